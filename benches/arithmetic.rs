@@ -0,0 +1,84 @@
+//! Times tight arithmetic and call loops through the whole lex/parse/run pipeline,
+//! walking the AST and as bytecode.
+//! Run with `cargo bench --bench arithmetic`.
+
+use rattlesnake::interpreter::Interpreter;
+use rattlesnake::lexer::Lexer;
+use rattlesnake::parser::Parser;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: i64 = 200_000;
+
+fn run(name: &str, source: &str) {
+    let tokens = Lexer::new(source.to_string(), format!("<{}>", name))
+        .lex()
+        .expect("benchmark should lex");
+    let ast = Parser::new(tokens).parse().expect("benchmark should parse");
+
+    for (backend, vm) in [("", false), (" (vm)", true)] {
+        let mut best = Duration::MAX;
+        for _ in 0..5 {
+            let start = Instant::now();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_vm(vm);
+            interpreter.execute(&ast).expect("benchmark should run");
+            best = best.min(start.elapsed());
+        }
+        println!(
+            "{:<21} {:>10.2?} total, {:>8.1} ns/iteration",
+            format!("{}{}", name, backend),
+            best,
+            best.as_nanos() as f64 / ITERATIONS as f64
+        );
+    }
+}
+
+fn main() {
+    run(
+        "integer loop",
+        &format!(
+            "let i = 0
+            let total = 0
+            while i < {ITERATIONS} {{
+                total = total + i * 2 - 1
+                i = i + 1
+            }}"
+        ),
+    );
+    run(
+        "float loop",
+        &format!(
+            "let i = 0
+            let total = 0.5
+            while i < {ITERATIONS} {{
+                total = total * 1.0000001 + 0.25 / 2.0
+                i = i + 1
+            }}"
+        ),
+    );
+    run(
+        "mixed loop",
+        &format!(
+            "let i = 0
+            let total = 0
+            while i < {ITERATIONS} {{
+                total = total + i * 0.5
+                i = i + 1
+            }}"
+        ),
+    );
+    run(
+        "call loop",
+        &format!(
+            "let i = 0
+            let total = 0
+            def step(n) {{
+                return n + len(\"ab\")
+            }}
+            while i < {ITERATIONS} {{
+                total = step(total)
+                i = i + 1
+            }}"
+        ),
+    );
+}
@@ -0,0 +1,60 @@
+//! Runs scripts from a host program: hands values in through globals and
+//! host functions, reads results back through `Value`'s conversions and as
+//! JSON, and keeps two interpreters whose globals don't see each other.
+//!
+//! ```text
+//! cargo run --example embed
+//! ```
+
+use rattlesnake::error::{Error, Result};
+use rattlesnake::interpreter::Interpreter;
+use rattlesnake::json;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let mut pricing = Interpreter::new();
+    let mut greeting = Interpreter::new();
+
+    pricing.set_global("prices", json::from_json("[3, 4.5]")?);
+    pricing.set_global("discount", 0.5);
+    greeting.set_global("name", "world");
+    pricing.register_typed_fn("tax", |(price, rate): (f64, f64)| price * rate);
+    greeting.register_fn("shout", |args| match args {
+        [text] => Ok(text.as_str().unwrap_or_default().to_uppercase().into()),
+        _ => Err(Error::Other(format!(
+            "takes 1 argument, got {}",
+            args.len()
+        ))),
+    });
+
+    let total: f64 = pricing
+        .eval_str(
+            "let total = 0\nfor price in prices {\n    total = total + price\n}\ntotal * discount + tax(total, 0.1)\n",
+        )?
+        .try_into()?;
+    let message: String = greeting
+        .eval_str("shout(\"Hello, \" + name)\n")?
+        .try_into()?;
+
+    println!("total: {}", total);
+    println!("message: {}", message);
+    if let Some(prices) = pricing.global("prices") {
+        println!("prices as JSON: {}", json::to_json(&prices)?);
+    }
+    // each interpreter has its own globals
+    println!(
+        "greeting sees total: {}",
+        greeting.global("total").is_some()
+    );
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Checks random programs for parser round-tripping, interpreter panics and
+//! differences between the tree-walker, the VM and `-O`.
+//!
+//! ```text
+//! cargo run --example fuzz --features generator -- [count] [first seed]
+//! ```
+
+use rattlesnake::generator::{check_round_trip, check_runs, unparse, Generator};
+use std::process::exit;
+
+const MAX_STEPS: u64 = 100_000;
+
+fn main() {
+    let mut args = std::env::args()
+        .skip(1)
+        .map(|arg| match arg.parse::<u64>() {
+            Ok(number) => number,
+            Err(_) => {
+                eprintln!("Usage: fuzz [count] [first seed]");
+                exit(2);
+            }
+        });
+    let count = args.next().unwrap_or(1000);
+    let first = args.next().unwrap_or(0);
+
+    // the checks report panics themselves
+    std::panic::set_hook(Box::new(|_| {}));
+    let mut failures = 0;
+    for seed in first..first + count {
+        let ast = Generator::new(seed).program();
+        let result = check_round_trip(&ast).and_then(|()| check_runs(&ast, MAX_STEPS));
+        if let Err(err) = result {
+            failures += 1;
+            println!("seed {}: {}\n{}", seed, err, unparse(&ast));
+        }
+    }
+    println!("{} of {} programs failed", failures, count);
+    if failures > 0 {
+        exit(1);
+    }
+}
@@ -0,0 +1,52 @@
+//! Checks the diagnostics in `snapshots/` against their recorded output.
+//!
+//! ```text
+//! cargo run --example snapshot [-- --bless]
+//! ```
+//!
+//! `--bless` records the current output of every case that differs.
+
+use rattlesnake::snapshot::check;
+use std::path::Path;
+use std::process::exit;
+
+fn main() {
+    let bless = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("--bless") => true,
+        Some(_) => {
+            eprintln!("Usage: snapshot [--bless]");
+            exit(2);
+        }
+    };
+    // panics are part of the snapshots
+    std::panic::set_hook(Box::new(|_| {}));
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots");
+    let mismatches = match check(&dir, bless) {
+        Ok(mismatches) => mismatches,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    };
+    for mismatch in &mismatches {
+        match &mismatch.expected {
+            Some(expected) => println!(
+                "{} changed\n--- expected\n{}+++ actual\n{}",
+                mismatch.name, expected, mismatch.actual
+            ),
+            None => println!("{} is new\n{}", mismatch.name, mismatch.actual),
+        }
+    }
+    match (mismatches.len(), bless) {
+        (0, _) => println!("All snapshots match"),
+        (count, true) => println!("Blessed {} snapshots", count),
+        (count, false) => {
+            println!(
+                "{} snapshots differ, rerun with --bless to accept them",
+                count
+            );
+            exit(1);
+        }
+    }
+}
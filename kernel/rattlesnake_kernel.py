@@ -0,0 +1,95 @@
+"""Jupyter kernel for rattlesnake.
+
+Speaks the Jupyter messaging protocol through ipykernel and forwards each cell
+to `rattlesnake --kernel` (built with `--features kernel`). See src/kernel.rs
+for the protocol between the two.
+
+Install with `jupyter kernelspec install kernel --user --name rattlesnake`
+from the repository root, with `rattlesnake` on the PATH or its path in the
+RATTLESNAKE environment variable.
+"""
+
+import os
+import subprocess
+
+from ipykernel.kernelapp import IPKernelApp
+from ipykernel.kernelbase import Kernel
+
+REPLY_START = b"\x01"
+
+
+class RattlesnakeKernel(Kernel):
+    implementation = "rattlesnake"
+    implementation_version = "0.1.0"
+    banner = "rattlesnake"
+    language_info = {
+        "name": "rattlesnake",
+        "mimetype": "text/x-rattlesnake",
+        "file_extension": ".rat",
+    }
+
+    def __init__(self, **kwargs):
+        super().__init__(**kwargs)
+        self.process = subprocess.Popen(
+            [os.environ.get("RATTLESNAKE", "rattlesnake"), "--kernel"],
+            stdin=subprocess.PIPE,
+            stdout=subprocess.PIPE,
+        )
+
+    def stream(self, text, silent):
+        if text and not silent:
+            self.send_response(self.iopub_socket, "stream", {"name": "stdout", "text": text})
+
+    def do_execute(self, code, silent, store_history=True, user_expressions=None, allow_stdin=False):
+        data = code.encode()
+        self.process.stdin.write(b"execute %d\n" % len(data) + data)
+        self.process.stdin.flush()
+
+        # pass printed output through line by line until the reply starts
+        line = bytearray()
+        while True:
+            byte = self.process.stdout.read(1)
+            if not byte:
+                raise RuntimeError("rattlesnake exited unexpectedly")
+            if byte == REPLY_START:
+                break
+            line += byte
+            if byte == b"\n":
+                self.stream(line.decode(), silent)
+                line.clear()
+        self.stream(line.decode(), silent)
+
+        status, length = self.process.stdout.readline().decode().split()
+        payload = self.process.stdout.read(int(length)).decode()
+
+        if status == "error":
+            error = {"ename": "Error", "evalue": payload, "traceback": [payload]}
+            if not silent:
+                self.send_response(self.iopub_socket, "error", error)
+            return {"status": "error", "execution_count": self.execution_count, **error}
+
+        if payload and not silent:
+            self.send_response(
+                self.iopub_socket,
+                "execute_result",
+                {
+                    "execution_count": self.execution_count,
+                    "data": {"text/plain": payload},
+                    "metadata": {},
+                },
+            )
+        return {
+            "status": "ok",
+            "execution_count": self.execution_count,
+            "payload": [],
+            "user_expressions": {},
+        }
+
+    def do_shutdown(self, restart):
+        self.process.stdin.close()
+        self.process.wait()
+        return {"status": "ok", "restart": restart}
+
+
+if __name__ == "__main__":
+    IPKernelApp.launch_instance(kernel_class=RattlesnakeKernel)
@@ -1,51 +1,728 @@
+use crate::symbol::Symbol;
 use crate::token::Location;
-use std::rc::Rc;
+use std::ops::Range;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub enum AST {
-    And(Location, Rc<AST>, Rc<AST>),
-    Assert(Location, Rc<AST>),
-    Assignment(Location, Rc<AST>, Rc<AST>),
-    Block(Location, Vec<Rc<AST>>),
+    And(Location, Arc<AST>, Arc<AST>),
+    Assert(Location, Arc<AST>),
+    Assignment(Location, Arc<AST>, Arc<AST>),
+    BitAnd(Location, Arc<AST>, Arc<AST>),
+    /// `~a`, every bit of `a` flipped.
+    BitNot(Location, Arc<AST>),
+    BitOr(Location, Arc<AST>, Arc<AST>),
+    BitXor(Location, Arc<AST>, Arc<AST>),
+    Block(Location, Vec<Arc<AST>>),
     BooleanLiteral(Location, bool),
-    Call(Location, Rc<AST>, Vec<Rc<AST>>),
-    Divide(Location, Rc<AST>, Rc<AST>),
+    Call(Location, Arc<AST>, Vec<Arc<AST>>),
+    /// A `{key: value}` literal.
+    Dict(Location, Vec<(Arc<AST>, Arc<AST>)>),
+    Divide(Location, Arc<AST>, Arc<AST>),
+    /// `enum Name { variants methods }`, which declares `Name` as a type
+    /// whose values are one of its variants. The methods are `Function`s,
+    /// shared by every variant.
+    Enum {
+        loc: Location,
+        name: Symbol,
+        variants: Vec<Variant>,
+        methods: Vec<Arc<AST>>,
+    },
     FloatLiteral(Location, f64),
+    /// A `def` or lambda. The defaults are the values of the last
+    /// arguments when a call leaves them out, which run in the call. The
+    /// signature holds the types of the arguments and return value, when
+    /// any were written. The captures are the
+    /// variables of the scopes around it that the body uses, as the
+    /// resolver finds them, and are all the function keeps of those scopes.
+    /// A `move` lambda copies their values instead of sharing them. A
+    /// `variadic` function's last argument is a `...rest` parameter, which
+    /// collects the positional arguments left over into a list.
     Function {
         loc: Location,
-        name: Option<String>,
-        args: Vec<String>,
-        body: Rc<AST>,
+        name: Option<Symbol>,
+        args: Vec<Symbol>,
+        variadic: bool,
+        defaults: Vec<Arc<AST>>,
+        signature: Option<Arc<Signature>>,
+        captures: Vec<Capture>,
+        moves: bool,
+        body: Arc<AST>,
     },
-    If(Location, Rc<AST>, Rc<AST>, Option<Rc<AST>>),
-    Index(Location, Rc<AST>, Rc<AST>),
+    /// The body of a function that `yield`s. Running it returns an iterator
+    /// that runs the body a `yield` at a time, instead of running it.
+    Generator(Location, Arc<AST>),
+    If(Location, Arc<AST>, Arc<AST>, Option<Arc<AST>>),
+    /// `import name`, `import a.b` or `import "path/to/file"`, which binds
+    /// the module to its last name, or the name of the file. The path is
+    /// where the module is looked for, without the `.rat`: `a/b` for
+    /// `import a.b`.
+    Import(Location, Symbol, Arc<str>),
+    /// `item in collection`.
+    In(Location, Arc<AST>, Arc<AST>),
+    Index(Location, Arc<AST>, Arc<AST>),
     IntegerLiteral(Location, i64),
-    Minus(Location, Rc<AST>, Rc<AST>),
-    Multiply(Location, Rc<AST>, Rc<AST>),
-    Not(Location, Rc<AST>),
+    /// A `[a, b, c]` literal.
+    List(Location, Vec<Arc<AST>>),
+    /// `match subject { arms }`, which runs the first arm whose pattern
+    /// matches.
+    Match(Location, Arc<AST>, Vec<MatchArm>),
+    Member(Location, Arc<AST>, Symbol),
+    Minus(Location, Arc<AST>, Arc<AST>),
+    /// `a % b`, the remainder of `a / b`.
+    Modulo(Location, Arc<AST>, Arc<AST>),
+    Multiply(Location, Arc<AST>, Arc<AST>),
+    /// A `name: value` or `name = value` argument of a call.
+    NamedArgument(Location, Symbol, Arc<AST>),
+    Negate(Location, Arc<AST>),
+    Not(Location, Arc<AST>),
     Nothing(Location),
-    Or(Location, Rc<AST>, Rc<AST>),
-    Plus(Location, Rc<AST>, Rc<AST>),
-    Return(Location, Rc<AST>),
+    Or(Location, Arc<AST>, Arc<AST>),
+    Plus(Location, Arc<AST>, Arc<AST>),
+    /// `a ** b`, `a` to the power of `b`.
+    Power(Location, Arc<AST>, Arc<AST>),
+    Pub(Location, Arc<AST>),
+    Return(Location, Arc<AST>),
+    /// `throw value`, which unwinds to the nearest `catch`.
+    Throw(Location, Arc<AST>),
+    /// `try { } catch name { } finally { }`, with at least one of `catch`
+    /// and `finally`.
+    Try {
+        loc: Location,
+        body: Arc<AST>,
+        catch: Option<(Symbol, Arc<AST>)>,
+        finally: Option<Arc<AST>>,
+    },
+    /// A `(a, b, c)` literal, or `a, b, c` on either side of an assignment.
+    Tuple(Location, Vec<Arc<AST>>),
+    /// `let a, b = value`, which declares each name in the pattern from
+    /// the items of a tuple or list.
+    Unpack(Location, Arc<AST>, Arc<AST>),
+    ShiftLeft(Location, Arc<AST>, Arc<AST>),
+    ShiftRight(Location, Arc<AST>, Arc<AST>),
+    /// A `...items` argument of a call, which passes each item of a tuple
+    /// or list as its own positional argument.
+    Spread(Location, Arc<AST>),
     Slice {
         loc: Location,
-        lhs: Rc<AST>,
-        start: Option<Rc<AST>>,
-        end: Option<Rc<AST>>,
-        step: Option<Rc<AST>>,
+        lhs: Arc<AST>,
+        start: Option<Arc<AST>>,
+        end: Option<Arc<AST>>,
+        step: Option<Arc<AST>>,
+    },
+    StringLiteral(Location, Arc<str>),
+    /// `struct Name { fields methods }`, which declares `Name` as a struct
+    /// with those fields, whose instances have those methods. The methods
+    /// are `Function`s, and those named like `__add__` overload operators.
+    Struct {
+        loc: Location,
+        name: Symbol,
+        fields: Vec<Symbol>,
+        methods: Vec<Arc<AST>>,
     },
-    StringLiteral(Location, String),
-    VarDeclaration(Location, String, Rc<AST>),
-    Variable(Location, String),
-    Equals(Location, Rc<AST>, Rc<AST>),
-    NotEquals(Location, Rc<AST>, Rc<AST>),
-    LessThan(Location, Rc<AST>, Rc<AST>),
-    GreaterThan(Location, Rc<AST>, Rc<AST>),
-    LessThanEquals(Location, Rc<AST>, Rc<AST>),
-    GreaterThanEquals(Location, Rc<AST>, Rc<AST>),
-    While(Location, Rc<AST>, Rc<AST>),
+    /// `let name: Type = value`, where the type is optional.
+    VarDeclaration(Location, Symbol, Option<Arc<TypeExpr>>, Arc<AST>),
+    Variable(Location, Symbol),
+    /// A `Variable` that the resolver found a local declaration for, which
+    /// is read from its slot instead of being looked up by name.
+    Local(Location, Symbol, Slot),
+    Equals(Location, Arc<AST>, Arc<AST>),
+    NotEquals(Location, Arc<AST>, Arc<AST>),
+    LessThan(Location, Arc<AST>, Arc<AST>),
+    GreaterThan(Location, Arc<AST>, Arc<AST>),
+    LessThanEquals(Location, Arc<AST>, Arc<AST>),
+    GreaterThanEquals(Location, Arc<AST>, Arc<AST>),
+    While(Location, Arc<AST>, Arc<AST>),
     Continue(Location),
     Break(Location),
-    For(Location, String, Rc<AST>, Rc<AST>),
-    Range(Location, Rc<AST>, Rc<AST>),
+    For(Location, Symbol, Arc<AST>, Arc<AST>),
+    /// `start..end`, or `start..=end` when the bool is set.
+    Range(Location, Arc<AST>, Arc<AST>, bool),
+    /// `yield value`, which hands `value` to whatever is stepping through
+    /// the generator and pauses it until the next value is asked for.
+    Yield(Location, Arc<AST>),
+}
+
+/// The location, function and arguments of a call.
+pub type PlainCall<'a> = (&'a Location, &'a Arc<AST>, &'a [Arc<AST>]);
+
+/// Where a local variable is: `index` in the order of declarations of the
+/// scope `depth` scopes up from the one reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+/// A variable that a function uses from the scopes around it: `name`, in
+/// `slot` of the scope the function is created in, or the global `name`
+/// when there's no slot, which only `move` functions capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capture {
+    pub name: Symbol,
+    pub slot: Option<Slot>,
+}
+
+/// One `pattern if guard => body` arm of a `match`. A pattern is a literal,
+/// a `Variable` that binds the value, `_` that matches anything, a `List`
+/// or `Tuple` of patterns that matches a list or tuple of the same length,
+/// or a `Call` of a struct or variant, as in `Shape.Circle(r)`, with a
+/// pattern for each field that matches its instances. A variant without
+/// fields, as in `Shape.Empty`, is compared like a literal.
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Arc<AST>,
+    pub guard: Option<Arc<AST>>,
+    pub body: Arc<AST>,
+}
+
+/// A `Name(fields)` variant of an `enum`, or just `Name` for one without
+/// fields.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub loc: Location,
+    pub name: Symbol,
+    pub fields: Vec<Symbol>,
+}
+
+/// A type as written in an annotation: a name with optional types in
+/// brackets, as in `list[int]`, or a union of types, as in `int | str`.
+#[derive(Debug, Clone)]
+pub enum TypeExpr {
+    Named(Location, Symbol, Vec<TypeExpr>),
+    Union(Location, Vec<TypeExpr>),
+}
+
+/// The annotated types of a function's arguments, in order, and of what
+/// it returns.
+#[derive(Debug, Clone, Default)]
+pub struct Signature {
+    pub args: Vec<Option<TypeExpr>>,
+    pub returns: Option<TypeExpr>,
+}
+
+impl Signature {
+    /// The parameters `args` with the types that `signature` gives them,
+    /// and the last ones with `defaults`, as in `a: int, b = 1`, or the
+    /// last one as `...rest` if the function is `variadic`. For the
+    /// `|parameters|` of a `lambda`, unions are put in parentheses.
+    pub fn parameters(
+        signature: Option<&Signature>,
+        args: &[Symbol],
+        variadic: bool,
+        defaults: &[String],
+        lambda: bool,
+    ) -> String {
+        let annotation = |i: usize| signature.and_then(|signature| signature.args.get(i)?.as_ref());
+        let first_default = args.len() - defaults.len();
+        let args = args.iter().enumerate().map(|(i, arg)| {
+            let dots = if variadic && i == args.len() - 1 {
+                "..."
+            } else {
+                ""
+            };
+            let param = match annotation(i) {
+                Some(ty @ TypeExpr::Union(..)) if lambda => format!("{}{}: ({})", dots, arg, ty),
+                Some(ty) => format!("{}{}: {}", dots, arg, ty),
+                None => format!("{}{}", dots, arg),
+            };
+            match i.checked_sub(first_default) {
+                Some(i) => format!("{} = {}", param, defaults[i]),
+                None => param,
+            }
+        });
+        args.collect::<Vec<_>>().join(", ")
+    }
+
+    /// ` -> Type` if `signature` gives the return type, otherwise nothing.
+    pub fn return_annotation(signature: Option<&Signature>) -> String {
+        match signature.and_then(|signature| signature.returns.as_ref()) {
+            Some(ty) => format!(" -> {}", ty),
+            None => String::new(),
+        }
+    }
+}
+
+impl TypeExpr {
+    pub fn loc(&self) -> &Location {
+        match self {
+            TypeExpr::Named(loc, ..) | TypeExpr::Union(loc, ..) => loc,
+        }
+    }
+}
+
+impl std::fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypeExpr::Named(_, name, items) if items.is_empty() => write!(f, "{}", name),
+            TypeExpr::Named(_, name, items) => {
+                let items: Vec<_> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "{}[{}]", name, items.join(", "))
+            }
+            TypeExpr::Union(_, types) => {
+                let types: Vec<_> = types.iter().map(|ty| ty.to_string()).collect();
+                write!(f, "{}", types.join(" | "))
+            }
+        }
+    }
+}
+
+impl MatchArm {
+    /// The names the pattern binds, in source order.
+    pub fn bindings(&self) -> Vec<Symbol> {
+        self.pattern.bindings()
+    }
+}
+
+impl AST {
+    /// The names this node binds when used as a pattern, in source order.
+    pub fn bindings(&self) -> Vec<Symbol> {
+        fn collect(pattern: &AST, names: &mut Vec<Symbol>) {
+            match pattern {
+                AST::Variable(_, name) if *name != "_" => names.push(*name),
+                AST::List(_, items) | AST::Tuple(_, items) | AST::Call(_, _, items) => {
+                    items.iter().for_each(|item| collect(item, names))
+                }
+                _ => {}
+            }
+        }
+        let mut names = vec![];
+        collect(self, &mut names);
+        names
+    }
+
+    pub fn loc(&self) -> &Location {
+        match self {
+            AST::And(loc, ..)
+            | AST::Assert(loc, ..)
+            | AST::Assignment(loc, ..)
+            | AST::BitAnd(loc, ..)
+            | AST::BitNot(loc, ..)
+            | AST::BitOr(loc, ..)
+            | AST::BitXor(loc, ..)
+            | AST::Block(loc, ..)
+            | AST::BooleanLiteral(loc, ..)
+            | AST::Call(loc, ..)
+            | AST::Dict(loc, ..)
+            | AST::Divide(loc, ..)
+            | AST::Enum { loc, .. }
+            | AST::FloatLiteral(loc, ..)
+            | AST::Function { loc, .. }
+            | AST::If(loc, ..)
+            | AST::Import(loc, ..)
+            | AST::In(loc, ..)
+            | AST::Index(loc, ..)
+            | AST::IntegerLiteral(loc, ..)
+            | AST::List(loc, ..)
+            | AST::Match(loc, ..)
+            | AST::Member(loc, ..)
+            | AST::Minus(loc, ..)
+            | AST::Modulo(loc, ..)
+            | AST::Multiply(loc, ..)
+            | AST::NamedArgument(loc, ..)
+            | AST::Spread(loc, ..)
+            | AST::Negate(loc, ..)
+            | AST::Not(loc, ..)
+            | AST::Nothing(loc)
+            | AST::Or(loc, ..)
+            | AST::Plus(loc, ..)
+            | AST::Power(loc, ..)
+            | AST::Pub(loc, ..)
+            | AST::Return(loc, ..)
+            | AST::Throw(loc, ..)
+            | AST::Try { loc, .. }
+            | AST::Tuple(loc, ..)
+            | AST::Unpack(loc, ..)
+            | AST::ShiftLeft(loc, ..)
+            | AST::ShiftRight(loc, ..)
+            | AST::Slice { loc, .. }
+            | AST::StringLiteral(loc, ..)
+            | AST::Struct { loc, .. }
+            | AST::VarDeclaration(loc, ..)
+            | AST::Variable(loc, ..)
+            | AST::Local(loc, ..)
+            | AST::Equals(loc, ..)
+            | AST::NotEquals(loc, ..)
+            | AST::LessThan(loc, ..)
+            | AST::GreaterThan(loc, ..)
+            | AST::LessThanEquals(loc, ..)
+            | AST::GreaterThanEquals(loc, ..)
+            | AST::While(loc, ..)
+            | AST::Continue(loc)
+            | AST::Break(loc)
+            | AST::For(loc, ..)
+            | AST::Range(loc, ..)
+            | AST::Generator(loc, ..)
+            | AST::Yield(loc, ..) => loc,
+        }
+    }
+
+    /// The byte offsets of the source text the node covers, from its first
+    /// token to its last. A closing bracket, brace or quote after the last
+    /// subexpression isn't included.
+    pub fn span(&self) -> Range<usize> {
+        let loc = self.loc();
+        self.children()
+            .into_iter()
+            .map(|child| child.span())
+            .fold(loc.start..loc.end, |span, child| {
+                span.start.min(child.start)..span.end.max(child.end)
+            })
+    }
+
+    /// The direct subexpressions and substatements of this node, in source order.
+    pub fn children(&self) -> Vec<&Arc<AST>> {
+        match self {
+            AST::And(_, a, b)
+            | AST::Assignment(_, a, b)
+            | AST::BitAnd(_, a, b)
+            | AST::BitOr(_, a, b)
+            | AST::BitXor(_, a, b)
+            | AST::Divide(_, a, b)
+            | AST::In(_, a, b)
+            | AST::Index(_, a, b)
+            | AST::Minus(_, a, b)
+            | AST::Modulo(_, a, b)
+            | AST::Multiply(_, a, b)
+            | AST::Or(_, a, b)
+            | AST::Plus(_, a, b)
+            | AST::Power(_, a, b)
+            | AST::ShiftLeft(_, a, b)
+            | AST::ShiftRight(_, a, b)
+            | AST::Equals(_, a, b)
+            | AST::NotEquals(_, a, b)
+            | AST::LessThan(_, a, b)
+            | AST::GreaterThan(_, a, b)
+            | AST::LessThanEquals(_, a, b)
+            | AST::GreaterThanEquals(_, a, b)
+            | AST::While(_, a, b)
+            | AST::For(_, _, a, b)
+            | AST::Unpack(_, a, b)
+            | AST::Range(_, a, b, _) => vec![a, b],
+            AST::Assert(_, a)
+            | AST::BitNot(_, a)
+            | AST::Member(_, a, _)
+            | AST::NamedArgument(_, _, a)
+            | AST::Spread(_, a)
+            | AST::Negate(_, a)
+            | AST::Not(_, a)
+            | AST::Pub(_, a)
+            | AST::Return(_, a)
+            | AST::Throw(_, a)
+            | AST::Generator(_, a)
+            | AST::Yield(_, a)
+            | AST::VarDeclaration(_, _, _, a) => vec![a],
+            AST::Function { defaults, body, .. } => defaults.iter().chain([body]).collect(),
+            AST::Block(_, stmts)
+            | AST::List(_, stmts)
+            | AST::Tuple(_, stmts)
+            | AST::Struct { methods: stmts, .. }
+            | AST::Enum { methods: stmts, .. } => stmts.iter().collect(),
+            AST::Call(_, func, args) => std::iter::once(func).chain(args).collect(),
+            AST::Dict(_, entries) => entries
+                .iter()
+                .flat_map(|(key, value)| [key, value])
+                .collect(),
+            AST::Match(_, subject, arms) => {
+                let mut children = vec![subject];
+                for arm in arms {
+                    children.push(&arm.pattern);
+                    children.extend(&arm.guard);
+                    children.push(&arm.body);
+                }
+                children
+            }
+            AST::If(_, cond, body, else_body) => {
+                let mut children = vec![cond, body];
+                children.extend(else_body);
+                children
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => std::iter::once(body)
+                .chain(catch.iter().map(|(_, handler)| handler))
+                .chain(finally)
+                .collect(),
+            AST::Slice {
+                lhs,
+                start,
+                end,
+                step,
+                ..
+            } => std::iter::once(lhs)
+                .chain(start)
+                .chain(end)
+                .chain(step)
+                .collect(),
+            AST::BooleanLiteral(..)
+            | AST::FloatLiteral(..)
+            | AST::Import(..)
+            | AST::IntegerLiteral(..)
+            | AST::Nothing(..)
+            | AST::StringLiteral(..)
+            | AST::Variable(..)
+            | AST::Local(..)
+            | AST::Continue(..)
+            | AST::Break(..) => vec![],
+        }
+    }
+
+    /// A copy of this node with each child replaced by what `f` makes of
+    /// it, called on the children in source order.
+    pub fn map_children(&self, mut f: impl FnMut(&Arc<AST>) -> Arc<AST>) -> AST {
+        match self {
+            AST::And(loc, a, b) => AST::And(loc.clone(), f(a), f(b)),
+            AST::Assert(loc, a) => AST::Assert(loc.clone(), f(a)),
+            AST::Assignment(loc, a, b) => AST::Assignment(loc.clone(), f(a), f(b)),
+            AST::BitAnd(loc, a, b) => AST::BitAnd(loc.clone(), f(a), f(b)),
+            AST::BitNot(loc, a) => AST::BitNot(loc.clone(), f(a)),
+            AST::BitOr(loc, a, b) => AST::BitOr(loc.clone(), f(a), f(b)),
+            AST::BitXor(loc, a, b) => AST::BitXor(loc.clone(), f(a), f(b)),
+            AST::Block(loc, stmts) => AST::Block(loc.clone(), stmts.iter().map(f).collect()),
+            AST::BooleanLiteral(loc, value) => AST::BooleanLiteral(loc.clone(), *value),
+            AST::Call(loc, func, args) => {
+                let func = f(func);
+                AST::Call(loc.clone(), func, args.iter().map(f).collect())
+            }
+            AST::Dict(loc, entries) => AST::Dict(
+                loc.clone(),
+                entries
+                    .iter()
+                    .map(|(key, value)| (f(key), f(value)))
+                    .collect(),
+            ),
+            AST::Divide(loc, a, b) => AST::Divide(loc.clone(), f(a), f(b)),
+            AST::FloatLiteral(loc, num) => AST::FloatLiteral(loc.clone(), *num),
+            AST::Function {
+                loc,
+                name,
+                args,
+                variadic,
+                defaults,
+                signature,
+                captures,
+                moves,
+                body,
+            } => AST::Function {
+                loc: loc.clone(),
+                name: *name,
+                args: args.clone(),
+                variadic: *variadic,
+                defaults: defaults.iter().map(&mut f).collect(),
+                signature: signature.clone(),
+                captures: captures.clone(),
+                moves: *moves,
+                body: f(body),
+            },
+            AST::Generator(loc, a) => AST::Generator(loc.clone(), f(a)),
+            AST::If(loc, cond, body, else_body) => {
+                let (cond, body) = (f(cond), f(body));
+                AST::If(loc.clone(), cond, body, else_body.as_ref().map(&mut f))
+            }
+            AST::Import(loc, name, path) => AST::Import(loc.clone(), *name, path.clone()),
+            AST::In(loc, a, b) => AST::In(loc.clone(), f(a), f(b)),
+            AST::Index(loc, a, b) => AST::Index(loc.clone(), f(a), f(b)),
+            AST::IntegerLiteral(loc, num) => AST::IntegerLiteral(loc.clone(), *num),
+            AST::List(loc, items) => AST::List(loc.clone(), items.iter().map(f).collect()),
+            AST::Match(loc, subject, arms) => {
+                let subject = f(subject);
+                let arms = arms
+                    .iter()
+                    .map(|arm| MatchArm {
+                        pattern: f(&arm.pattern),
+                        guard: arm.guard.as_ref().map(&mut f),
+                        body: f(&arm.body),
+                    })
+                    .collect();
+                AST::Match(loc.clone(), subject, arms)
+            }
+            AST::Member(loc, a, name) => AST::Member(loc.clone(), f(a), *name),
+            AST::Minus(loc, a, b) => AST::Minus(loc.clone(), f(a), f(b)),
+            AST::Modulo(loc, a, b) => AST::Modulo(loc.clone(), f(a), f(b)),
+            AST::Multiply(loc, a, b) => AST::Multiply(loc.clone(), f(a), f(b)),
+            AST::NamedArgument(loc, name, a) => AST::NamedArgument(loc.clone(), *name, f(a)),
+            AST::Spread(loc, a) => AST::Spread(loc.clone(), f(a)),
+            AST::Negate(loc, a) => AST::Negate(loc.clone(), f(a)),
+            AST::Not(loc, a) => AST::Not(loc.clone(), f(a)),
+            AST::Nothing(loc) => AST::Nothing(loc.clone()),
+            AST::Or(loc, a, b) => AST::Or(loc.clone(), f(a), f(b)),
+            AST::Plus(loc, a, b) => AST::Plus(loc.clone(), f(a), f(b)),
+            AST::Power(loc, a, b) => AST::Power(loc.clone(), f(a), f(b)),
+            AST::Pub(loc, a) => AST::Pub(loc.clone(), f(a)),
+            AST::Return(loc, a) => AST::Return(loc.clone(), f(a)),
+            AST::Throw(loc, a) => AST::Throw(loc.clone(), f(a)),
+            AST::Try {
+                loc,
+                body,
+                catch,
+                finally,
+            } => AST::Try {
+                loc: loc.clone(),
+                body: f(body),
+                catch: catch.as_ref().map(|(name, handler)| (*name, f(handler))),
+                finally: finally.as_ref().map(&mut f),
+            },
+            AST::Tuple(loc, items) => AST::Tuple(loc.clone(), items.iter().map(f).collect()),
+            AST::Unpack(loc, a, b) => AST::Unpack(loc.clone(), f(a), f(b)),
+            AST::ShiftLeft(loc, a, b) => AST::ShiftLeft(loc.clone(), f(a), f(b)),
+            AST::ShiftRight(loc, a, b) => AST::ShiftRight(loc.clone(), f(a), f(b)),
+            AST::Slice {
+                loc,
+                lhs,
+                start,
+                end,
+                step,
+            } => AST::Slice {
+                loc: loc.clone(),
+                lhs: f(lhs),
+                start: start.as_ref().map(&mut f),
+                end: end.as_ref().map(&mut f),
+                step: step.as_ref().map(&mut f),
+            },
+            AST::StringLiteral(loc, text) => AST::StringLiteral(loc.clone(), text.clone()),
+            AST::Struct {
+                loc,
+                name,
+                fields,
+                methods,
+            } => AST::Struct {
+                loc: loc.clone(),
+                name: *name,
+                fields: fields.clone(),
+                methods: methods.iter().map(f).collect(),
+            },
+            AST::Enum {
+                loc,
+                name,
+                variants,
+                methods,
+            } => AST::Enum {
+                loc: loc.clone(),
+                name: *name,
+                variants: variants.clone(),
+                methods: methods.iter().map(f).collect(),
+            },
+            AST::VarDeclaration(loc, name, ty, a) => {
+                AST::VarDeclaration(loc.clone(), *name, ty.clone(), f(a))
+            }
+            AST::Variable(loc, name) => AST::Variable(loc.clone(), *name),
+            AST::Local(loc, name, slot) => AST::Local(loc.clone(), *name, *slot),
+            AST::Equals(loc, a, b) => AST::Equals(loc.clone(), f(a), f(b)),
+            AST::NotEquals(loc, a, b) => AST::NotEquals(loc.clone(), f(a), f(b)),
+            AST::LessThan(loc, a, b) => AST::LessThan(loc.clone(), f(a), f(b)),
+            AST::GreaterThan(loc, a, b) => AST::GreaterThan(loc.clone(), f(a), f(b)),
+            AST::LessThanEquals(loc, a, b) => AST::LessThanEquals(loc.clone(), f(a), f(b)),
+            AST::GreaterThanEquals(loc, a, b) => AST::GreaterThanEquals(loc.clone(), f(a), f(b)),
+            AST::While(loc, a, b) => AST::While(loc.clone(), f(a), f(b)),
+            AST::Continue(loc) => AST::Continue(loc.clone()),
+            AST::Break(loc) => AST::Break(loc.clone()),
+            AST::For(loc, name, a, b) => AST::For(loc.clone(), *name, f(a), f(b)),
+            AST::Range(loc, a, b, inclusive) => AST::Range(loc.clone(), f(a), f(b), *inclusive),
+            AST::Yield(loc, a) => AST::Yield(loc.clone(), f(a)),
+        }
+    }
+
+    /// The name of the node's variant, for listings.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AST::And(..) => "And",
+            AST::Assert(..) => "Assert",
+            AST::Assignment(..) => "Assignment",
+            AST::BitAnd(..) => "BitAnd",
+            AST::BitNot(..) => "BitNot",
+            AST::BitOr(..) => "BitOr",
+            AST::BitXor(..) => "BitXor",
+            AST::Block(..) => "Block",
+            AST::BooleanLiteral(..) => "BooleanLiteral",
+            AST::Call(..) => "Call",
+            AST::Dict(..) => "Dict",
+            AST::Divide(..) => "Divide",
+            AST::FloatLiteral(..) => "FloatLiteral",
+            AST::Function { .. } => "Function",
+            AST::If(..) => "If",
+            AST::Import(..) => "Import",
+            AST::In(..) => "In",
+            AST::Index(..) => "Index",
+            AST::IntegerLiteral(..) => "IntegerLiteral",
+            AST::List(..) => "List",
+            AST::Match(..) => "Match",
+            AST::Member(..) => "Member",
+            AST::Minus(..) => "Minus",
+            AST::Modulo(..) => "Modulo",
+            AST::Multiply(..) => "Multiply",
+            AST::NamedArgument(..) => "NamedArgument",
+            AST::Spread(..) => "Spread",
+            AST::Negate(..) => "Negate",
+            AST::Not(..) => "Not",
+            AST::Nothing(..) => "Nothing",
+            AST::Or(..) => "Or",
+            AST::Plus(..) => "Plus",
+            AST::Power(..) => "Power",
+            AST::Pub(..) => "Pub",
+            AST::Return(..) => "Return",
+            AST::Throw(..) => "Throw",
+            AST::Generator(..) => "Generator",
+            AST::Yield(..) => "Yield",
+            AST::Try { .. } => "Try",
+            AST::Tuple(..) => "Tuple",
+            AST::Unpack(..) => "Unpack",
+            AST::ShiftLeft(..) => "ShiftLeft",
+            AST::ShiftRight(..) => "ShiftRight",
+            AST::Slice { .. } => "Slice",
+            AST::StringLiteral(..) => "StringLiteral",
+            AST::Struct { .. } => "Struct",
+            AST::Enum { .. } => "Enum",
+            AST::VarDeclaration(..) => "VarDeclaration",
+            AST::Variable(..) => "Variable",
+            AST::Local(..) => "Local",
+            AST::Equals(..) => "Equals",
+            AST::NotEquals(..) => "NotEquals",
+            AST::LessThan(..) => "LessThan",
+            AST::GreaterThan(..) => "GreaterThan",
+            AST::LessThanEquals(..) => "LessThanEquals",
+            AST::GreaterThanEquals(..) => "GreaterThanEquals",
+            AST::While(..) => "While",
+            AST::Continue(..) => "Continue",
+            AST::Break(..) => "Break",
+            AST::For(..) => "For",
+            AST::Range(..) => "Range",
+        }
+    }
+
+    /// The function and arguments of a call that isn't a method call and
+    /// has no named or spread arguments.
+    pub fn plain_call(&self) -> Option<PlainCall<'_>> {
+        match self {
+            AST::Call(loc, func, args)
+                if !matches!(func.as_ref(), AST::Member(..))
+                    && !args.iter().any(|arg| {
+                        matches!(arg.as_ref(), AST::NamedArgument(..) | AST::Spread(..))
+                    }) =>
+            {
+                Some((loc, func, args))
+            }
+            _ => None,
+        }
+    }
+
+    /// The name a declaration statement binds, if it is one.
+    pub fn declared_name(&self) -> Option<Symbol> {
+        match self {
+            AST::VarDeclaration(_, name, ..)
+            | AST::Import(_, name, _)
+            | AST::Struct { name, .. }
+            | AST::Enum { name, .. } => Some(*name),
+            AST::Function { name, .. } => *name,
+            // decorated functions are desugared to an assignment
+            AST::Assignment(_, lhs, _) => match lhs.as_ref() {
+                AST::Variable(_, name) | AST::Local(_, name, _) => Some(*name),
+                _ => None,
+            },
+            AST::Pub(_, decl) => decl.declared_name(),
+            _ => None,
+        }
+    }
 }
@@ -1,50 +1,1197 @@
-use crate::error::{runtime_error as error, Result};
+use crate::datetime;
+use crate::edition::Edition;
+use crate::error::{runtime_error as error, Error, Result};
+use crate::interpreter::{Interpreter, Ref, Scope};
+use crate::iterator::{Enumerate, Filter, Lines, Map, Skip, Take, Zip};
+use crate::json;
+use crate::signal;
+use crate::template;
 use crate::token::Location;
-use crate::value::Value;
+use crate::value::{
+    char_count, parse_float, parse_int, Dict, HashKey, IntegerOp, IteratorValue, Memoized,
+    RangeValue, Value,
+};
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub fn print(_loc: &Location, args: Vec<Value>) -> Result<Value> {
+/// Writes `args` separated by the `sep` argument, a space by default, and
+/// followed by the `end` argument.
+fn write_args(
+    interpreter: &mut Interpreter,
+    loc: &Location,
+    args: Vec<Value>,
+    end: &str,
+    stderr: bool,
+) -> Result<Value> {
+    let mut option = |name: &str, default: &str| -> Result<String> {
+        Ok(match interpreter.named_argument(name) {
+            None => default.to_string(),
+            Some(Value::String(string)) => string.to_string(),
+            Some(other) => error!(loc, "`{}` has to be a string, got {}", name, other.repr()),
+        })
+    };
+    let sep = option("sep", " ")?;
+    let end = option("end", end)?;
+    interpreter.no_more_named_arguments(loc)?;
+
+    let mut text = String::new();
     for (i, arg) in args.iter().enumerate() {
         if i != 0 {
-            print!(" ");
+            text.push_str(&sep);
         }
         match arg {
-            Value::Integer(num) => print!("{}", num),
-            Value::Float(num) => print!("{}", num),
-            Value::String(string) => print!("{}", string),
-            Value::Boolean(boolean) => print!("{}", boolean),
-            Value::Nothing => print!("nothing"),
-            Value::Iterator(_) => print!("<iterator>"),
-            Value::Range(start, end) => print!("{}..{}", start, end),
-            _ => print!("{:?}", arg),
+            Value::String(string) => text.push_str(string),
+            _ => text.push_str(&arg.repr()),
         }
     }
-    println!();
+    text.push_str(&end);
+
+    let out = match stderr {
+        true => interpreter.stderr(),
+        false => interpreter.stdout(),
+    };
+    if let Err(err) = out.write_all(text.as_bytes()).and_then(|_| out.flush()) {
+        error!(loc, "Couldn't print: {}", err);
+    }
     Ok(Value::Nothing)
 }
 
-pub fn len(loc: &Location, args: Vec<Value>) -> Result<Value> {
+pub fn print(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    write_args(interpreter, loc, args, "\n", false)
+}
+
+pub fn eprint(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    write_args(interpreter, loc, args, "", true)
+}
+
+pub fn eprintln(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    write_args(interpreter, loc, args, "\n", true)
+}
+
+/// Fills in the template that `args` start with from the rest of them,
+/// for `format` and `printf`.
+fn fill_template(name: &str, loc: &Location, args: &[Value]) -> Result<String> {
+    let Some((Value::String(template), args)) = args.split_first() else {
+        error!(loc, "{}() takes a template string and its arguments", name)
+    };
+    match template::format(template, args) {
+        Ok(text) => Ok(text),
+        Err(msg) => error!(loc, "{}", msg),
+    }
+}
+
+/// `format(template, args...)`: the template with each `{}` replaced by
+/// the next argument, written as its spec says, as in `{:>6.2}` or
+/// `{:#x}`.
+pub fn format(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    Ok(fill_template("format", loc, &args)?.into())
+}
+
+/// `printf(template, args...)`: prints what `format` would make, with no
+/// newline after it unless it's given as `end`.
+pub fn printf(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let text = fill_template("printf", loc, &args)?;
+    write_args(interpreter, loc, vec![text.into()], "", false)
+}
+
+pub fn len(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
     if args.len() != 1 {
         error!(loc, "len() takes exactly one argument");
     }
 
     Ok(match &args[0] {
-        Value::String(string) => Value::Integer(string.len() as i64),
-        other => error!(loc, "len() does not support {:?}", other),
+        // the edition of the call, as it was parsed
+        Value::String(string) => match loc.edition {
+            Edition::E2024 => Value::Integer(string.len() as i64),
+            _ => Value::Integer(char_count(string) as i64),
+        },
+        Value::List(list) => Value::Integer(list.borrow().len() as i64),
+        Value::Tuple(items) => Value::Integer(items.len() as i64),
+        Value::Dict(dict) => Value::Integer(dict.borrow().len() as i64),
+        Value::Range(range) => match range.count() {
+            Some(count) => Value::Integer(i64::try_from(count).unwrap_or(i64::MAX)),
+            None => error!(loc, "len() does not support {}", range.repr()),
+        },
+        other => error!(loc, "len() does not support {}", other.repr()),
+    })
+}
+
+/// `step_by(range, step)`: the same range counting by `step`, which goes
+/// backwards when negative, as in `step_by(10..0, -1)`.
+pub fn step_by(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::Range(_), Value::Integer(0)] => error!(loc, "step_by() can't step by 0"),
+        [Value::Range(range), Value::Integer(step)] => Ok(Value::Range(RangeValue {
+            step: *step,
+            ..*range
+        })),
+        _ => error!(loc, "step_by() takes a range and an integer"),
+    }
+}
+
+pub fn str(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "str() takes exactly one argument");
+    }
+
+    Ok(match &args[0] {
+        Value::String(_) => args[0].clone(),
+        other => Value::String(other.repr().into()),
+    })
+}
+
+pub fn float(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "float() takes exactly one argument");
+    }
+
+    Ok(match &args[0] {
+        Value::Float(_) => args[0].clone(),
+        Value::Integer(i) => Value::Float(*i as f64),
+        Value::String(string) => match parse_float(string) {
+            Some(f) => Value::Float(f),
+            None => error!(loc, "float() can't parse {}", args[0].repr()),
+        },
+        other => error!(loc, "float() does not support {}", other.repr()),
     })
 }
 
-pub fn exit(loc: &Location, args: Vec<Value>) -> Result<Value> {
-    let code = match args.get(0) {
+/// `int(value)`: an integer from a string written as in a script, from a
+/// float rounded toward zero, or from a boolean as 0 or 1.
+pub fn int(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "int() takes exactly one argument");
+    }
+
+    Ok(match &args[0] {
+        Value::Integer(_) => args[0].clone(),
+        Value::Boolean(b) => Value::Integer(*b as i64),
+        // the cast saturates, so the range is checked first
+        Value::Float(f) if f.is_finite() && f.trunc().abs() < i64::MAX as f64 => {
+            Value::Integer(*f as i64)
+        }
+        Value::Float(_) => error!(loc, "int() can't convert {} to an integer", args[0].repr()),
+        Value::String(string) => match parse_int(string) {
+            Some(i) => Value::Integer(i),
+            None => error!(loc, "int() can't parse {}", args[0].repr()),
+        },
+        other => error!(loc, "int() does not support {}", other.repr()),
+    })
+}
+
+/// `bool(value)`: whether the value counts as true in `and`, `or` and
+/// `not`.
+pub fn bool(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [value] => Ok(Value::Boolean(value.is_truthy())),
+        _ => error!(loc, "bool() takes exactly one argument"),
+    }
+}
+
+fn integer_operands(name: &str, loc: &Location, args: &[Value]) -> Result<(i64, i64)> {
+    match args {
+        [Value::Integer(left), Value::Integer(right)] => Ok((*left, *right)),
+        _ => error!(loc, "{}() takes exactly two integers", name),
+    }
+}
+
+/// Defines a built-in that does one integer operation with the given
+/// overflow behavior, whatever the interpreter's setting is.
+macro_rules! integer_op {
+    ($name:ident, $op:expr, checked) => {
+        pub fn $name(
+            _interpreter: &mut Interpreter,
+            _scope: &Ref<Scope>,
+            loc: &Location,
+            args: Vec<Value>,
+        ) -> Result<Value> {
+            let (left, right) = integer_operands(stringify!($name), loc, &args)?;
+            $op.check_operand(right, loc)?;
+            // overflowing is not an error here, it's the answer
+            Ok($op
+                .checked(left, right)
+                .map_or(Value::Nothing, Value::Integer))
+        }
+    };
+    ($name:ident, $op:expr, $method:ident) => {
+        pub fn $name(
+            _interpreter: &mut Interpreter,
+            _scope: &Ref<Scope>,
+            loc: &Location,
+            args: Vec<Value>,
+        ) -> Result<Value> {
+            let (left, right) = integer_operands(stringify!($name), loc, &args)?;
+            $op.check_operand(right, loc)?;
+            Ok(Value::Integer($op.$method(left, right)))
+        }
+    };
+}
+
+integer_op!(wrapping_add, IntegerOp::Add, wrapping);
+integer_op!(wrapping_sub, IntegerOp::Sub, wrapping);
+integer_op!(wrapping_mul, IntegerOp::Mul, wrapping);
+integer_op!(wrapping_pow, IntegerOp::Pow, wrapping);
+integer_op!(wrapping_shl, IntegerOp::Shl, wrapping);
+integer_op!(saturating_add, IntegerOp::Add, saturating);
+integer_op!(saturating_sub, IntegerOp::Sub, saturating);
+integer_op!(saturating_mul, IntegerOp::Mul, saturating);
+integer_op!(saturating_pow, IntegerOp::Pow, saturating);
+integer_op!(saturating_shl, IntegerOp::Shl, saturating);
+integer_op!(checked_add, IntegerOp::Add, checked);
+integer_op!(checked_sub, IntegerOp::Sub, checked);
+integer_op!(checked_mul, IntegerOp::Mul, checked);
+integer_op!(checked_pow, IntegerOp::Pow, checked);
+integer_op!(checked_shl, IntegerOp::Shl, checked);
+
+pub fn exit(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let code = match args.first() {
         Some(Value::Integer(i)) => *i,
         Some(_) => error!(loc, "exit() may only take an integer as argument"),
         None => 0,
     };
 
     match code.try_into() {
-        Ok(code) => std::process::exit(code),
+        Ok(code) => {
+            // process::exit skips destructors
+            interpreter.close_resources();
+            std::process::exit(code)
+        }
         Err(_) => error!(
             loc,
             "argument to exit() is too large, {code} does not fit into an i32"
         ),
     }
 }
+
+/// Collects the bindings of `scope` and its ancestors into a dict, stopping
+/// at the global scope unless `include_globals` is set. Inner bindings shadow
+/// outer ones.
+fn collect_scope(scope: &Ref<Scope>, include_globals: bool, loc: &Location) -> Result<Value> {
+    let mut chain = vec![];
+    let mut current = Some(scope.clone());
+    while let Some(scope) = current {
+        current = scope.borrow().parent.clone();
+        if current.is_some() || include_globals {
+            chain.push(scope);
+        }
+    }
+
+    let mut dict = Dict::new();
+    for scope in chain.iter().rev() {
+        let scope = scope.borrow();
+        let mut vars = scope.vars().collect::<Vec<_>>();
+        vars.sort_by_key(|&(name, _)| name.as_str());
+        for (name, value) in vars {
+            dict.insert(Value::String(name.as_str().into()), value.clone(), loc)?;
+        }
+    }
+    Ok(Value::Dict(Rc::new(RefCell::new(dict))))
+}
+
+pub(crate) fn global_scope(scope: &Ref<Scope>) -> Ref<Scope> {
+    let mut scope = scope.clone();
+    loop {
+        let parent = scope.borrow().parent.clone();
+        match parent {
+            Some(parent) => scope = parent,
+            None => return scope,
+        }
+    }
+}
+
+pub fn locals(
+    _interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(loc, "locals() takes no arguments");
+    }
+
+    // at the top level, the locals are the globals
+    let is_global = scope.borrow().parent.is_none();
+    collect_scope(scope, is_global, loc)
+}
+
+pub fn globals(
+    _interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(loc, "globals() takes no arguments");
+    }
+
+    collect_scope(&global_scope(scope), true, loc)
+}
+
+/// The function underneath any `memoize()` wrappers.
+fn unwrap_memoized(func: &Value) -> Value {
+    match func {
+        Value::Memoized(memo) => unwrap_memoized(&memo.func),
+        _ => func.clone(),
+    }
+}
+
+pub fn arity(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "arity() takes exactly one argument");
+    }
+
+    Ok(match &unwrap_memoized(&args[0]) {
+        Value::Function {
+            args,
+            variadic: false,
+            ..
+        } => Value::Integer(args.len() as i64),
+        // built-ins check their own arguments, and variadic functions take
+        // any number of them, so they have no fixed arity
+        Value::Function { .. } => Value::Nothing,
+        Value::BuiltInFunction(..) | Value::NativeFunction { .. } | Value::HostFunction(_) => {
+            Value::Nothing
+        }
+        other => error!(loc, "arity() expects a function, got {}", other.repr()),
+    })
+}
+
+/// `type(value)`: the name of the value's type, like `"integer"` or
+/// `"list"`, which is what errors call it.
+pub fn type_of(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [value] => Ok(Value::String(value.type_name().into())),
+        _ => error!(loc, "type() takes exactly one argument"),
+    }
+}
+
+/// Defines a built-in that tells whether its one argument matches `$pattern`.
+macro_rules! type_test {
+    ($name:ident, $pattern:pat) => {
+        pub fn $name(
+            _interpreter: &mut Interpreter,
+            _scope: &Ref<Scope>,
+            loc: &Location,
+            args: Vec<Value>,
+        ) -> Result<Value> {
+            match args.as_slice() {
+                [value] => Ok(Value::Boolean(matches!(value, $pattern))),
+                _ => error!(loc, "{}() takes exactly one argument", stringify!($name)),
+            }
+        }
+    };
+}
+
+type_test!(is_int, Value::Integer(_));
+type_test!(is_float, Value::Float(_));
+type_test!(is_number, Value::Integer(_) | Value::Float(_));
+type_test!(is_string, Value::String(_));
+type_test!(is_bool, Value::Boolean(_));
+type_test!(is_list, Value::List(_));
+type_test!(is_tuple, Value::Tuple(_));
+type_test!(is_dict, Value::Dict(_));
+type_test!(is_nothing, Value::Nothing);
+type_test!(
+    callable,
+    Value::Function { .. }
+        | Value::BuiltInFunction(..)
+        | Value::NativeFunction { .. }
+        | Value::HostFunction(_)
+        | Value::Memoized(_)
+        | Value::Struct(_)
+);
+
+pub fn source_location(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "source_location() takes exactly one argument");
+    }
+
+    let func = unwrap_memoized(&args[0]);
+    let def_loc = match &func {
+        Value::Function { loc, .. } => loc,
+        Value::Struct(of) => &of.loc,
+        Value::Enum(of) => &of.loc,
+        Value::BuiltInFunction(..) | Value::NativeFunction { .. } | Value::HostFunction(_) => {
+            return Ok(Value::Nothing)
+        }
+        other => error!(
+            loc,
+            "source_location() expects a function, struct or enum, got {}",
+            other.repr()
+        ),
+    };
+    let mut dict = Dict::new();
+    dict.insert(
+        Value::String("file".into()),
+        Value::String(def_loc.filename()),
+        loc,
+    )?;
+    dict.insert(
+        Value::String("line".into()),
+        Value::Integer(def_loc.line as i64),
+        loc,
+    )?;
+    dict.insert(
+        Value::String("column".into()),
+        Value::Integer(def_loc.column as i64),
+        loc,
+    )?;
+    Ok(Value::Dict(Rc::new(RefCell::new(dict))))
+}
+
+/// `fields(value)`: the names of the fields of a struct or an instance of
+/// one, in the order they are declared.
+pub fn fields(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let of = match args.as_slice() {
+        [Value::Struct(of) | Value::Instance(of, _)] => of,
+        [other] => error!(loc, "fields() expects a struct, got {}", other.repr()),
+        _ => error!(loc, "fields() takes exactly one argument"),
+    };
+    let names = of.fields.iter().map(|name| Value::from(name.as_str()));
+    Ok(names.collect::<Vec<_>>().into())
+}
+
+pub fn memoize(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    mut args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "memoize() takes exactly one argument");
+    }
+
+    let func = function_argument("memoize", args.remove(0), loc)?;
+    Ok(Value::Memoized(Rc::new(Memoized {
+        func,
+        cache: RefCell::default(),
+    })))
+}
+
+pub fn close(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "close() takes exactly one argument");
+    }
+
+    match &args[0] {
+        Value::Resource(handle) => match handle.close() {
+            Ok(()) => Ok(Value::Nothing),
+            Err(err) => error!(loc, "Couldn't close {}: {}", handle.name, err),
+        },
+        other => error!(loc, "close() expects a resource, got {}", other.repr()),
+    }
+}
+
+/// `func`, if it can be called.
+fn function_argument(name: &str, func: Value, loc: &Location) -> Result<Value> {
+    match func {
+        Value::Function { .. }
+        | Value::BuiltInFunction(..)
+        | Value::NativeFunction { .. }
+        | Value::HostFunction(_)
+        | Value::Memoized(_) => Ok(func),
+        other => error!(loc, "{}() expects a function, got {}", name, other.repr()),
+    }
+}
+
+/// An iterator over `value`, which may already be one.
+pub(crate) fn iterator_argument(name: &str, value: Value, loc: &Location) -> Result<IteratorValue> {
+    match value.iterator(loc) {
+        Value::Iterator(iterator) => Ok(iterator),
+        other => error!(loc, "{}() can't iterate over {}", name, other.repr()),
+    }
+}
+
+pub fn map(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([func, iterable]) = <[Value; 2]>::try_from(args) else {
+        error!(loc, "map() takes a function and something to iterate over");
+    };
+    Ok(Value::Iterator(IteratorValue::new(Map {
+        func: function_argument("map", func, loc)?,
+        inner: iterator_argument("map", iterable, loc)?,
+    })))
+}
+
+pub fn filter(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([func, iterable]) = <[Value; 2]>::try_from(args) else {
+        error!(
+            loc,
+            "filter() takes a function and something to iterate over"
+        );
+    };
+    Ok(Value::Iterator(IteratorValue::new(Filter {
+        func: function_argument("filter", func, loc)?,
+        inner: iterator_argument("filter", iterable, loc)?,
+    })))
+}
+
+pub fn take(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([iterable, Value::Integer(count)]) = <[Value; 2]>::try_from(args) else {
+        error!(loc, "take() takes something to iterate over and an integer");
+    };
+    Ok(Value::Iterator(IteratorValue::new(Take {
+        inner: iterator_argument("take", iterable, loc)?,
+        remaining: count,
+    })))
+}
+
+pub fn skip(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([iterable, Value::Integer(count)]) = <[Value; 2]>::try_from(args) else {
+        error!(loc, "skip() takes something to iterate over and an integer");
+    };
+    Ok(Value::Iterator(IteratorValue::new(Skip {
+        inner: iterator_argument("skip", iterable, loc)?,
+        remaining: count,
+    })))
+}
+
+pub fn zip(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.is_empty() {
+        error!(loc, "zip() takes at least one thing to iterate over");
+    }
+    let inners = args
+        .into_iter()
+        .map(|iterable| iterator_argument("zip", iterable, loc))
+        .collect::<Result<_>>()?;
+    Ok(Value::Iterator(IteratorValue::new(Zip { inners })))
+}
+
+pub fn enumerate(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([iterable]) = <[Value; 1]>::try_from(args) else {
+        error!(loc, "enumerate() takes exactly one argument");
+    };
+    Ok(Value::Iterator(IteratorValue::new(Enumerate {
+        inner: iterator_argument("enumerate", iterable, loc)?,
+        index: 0,
+    })))
+}
+
+/// `list(iterable)`: every remaining value of the iterable, in a new list.
+pub fn list(
+    interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([iterable]) = <[Value; 1]>::try_from(args) else {
+        error!(loc, "list() takes exactly one argument");
+    };
+    let iterator = iterator_argument("list", iterable, loc)?;
+    let mut items = vec![];
+    while let Some(value) = iterator.next(interpreter, scope, loc)? {
+        items.push(value);
+    }
+    Ok(items.into())
+}
+
+/// `read_file(path)`: the whole file as a string.
+pub fn read_file(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(path)] = args.as_slice() else {
+        error!(loc, "read_file() takes a path");
+    };
+    match fs::read_to_string(path.as_ref()) {
+        Ok(contents) => Ok(contents.into()),
+        Err(err) => error!(loc, "Couldn't read {}: {}", path, err),
+    }
+}
+
+/// `read_lines(path)`: an iterator over the lines of the file, without
+/// their line endings, which reads the file as it goes.
+pub fn read_lines(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(path)] = args.as_slice() else {
+        error!(loc, "read_lines() takes a path");
+    };
+    match File::open(path.as_ref()) {
+        Ok(file) => Ok(Value::Iterator(IteratorValue::new(Lines {
+            path: path.clone(),
+            lines: Some(BufReader::new(file).lines()),
+        }))),
+        Err(err) => error!(loc, "Couldn't read {}: {}", path, err),
+    }
+}
+
+/// Writes `contents` to the file at `path`, replacing what's there unless
+/// `append` is set.
+fn write(name: &str, args: &[Value], loc: &Location, append: bool) -> Result<Value> {
+    let [Value::String(path), Value::String(contents)] = args else {
+        error!(loc, "{}() takes a path and a string", name);
+    };
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path.as_ref());
+    match file.and_then(|mut file| file.write_all(contents.as_bytes())) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(err) => error!(loc, "Couldn't write {}: {}", path, err),
+    }
+}
+
+/// `write_file(path, contents)`, which creates the file or replaces it.
+pub fn write_file(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    write("write_file", &args, loc, false)
+}
+
+/// `append_file(path, contents)`, which creates the file or adds to its end.
+pub fn append_file(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    write("append_file", &args, loc, true)
+}
+
+/// `file_exists(path)`, which is false for a path that can't be looked at.
+pub fn file_exists(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(path)] = args.as_slice() else {
+        error!(loc, "file_exists() takes a path");
+    };
+    Ok(Path::new(path.as_ref()).exists().into())
+}
+
+/// The error of a JSON conversion, which doesn't know where it was asked for.
+fn json_error(err: Error, loc: &Location) -> Error {
+    match err {
+        Error::Other(msg) => Error::Runtime(loc.clone(), msg),
+        err => err,
+    }
+}
+
+/// `json_parse(text)`: the value the JSON text describes.
+pub fn json_parse(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(text)] = args.as_slice() else {
+        error!(loc, "json_parse() takes a string");
+    };
+    json::from_json(text).map_err(|err| json_error(err, loc))
+}
+
+/// `json_stringify(value, pretty)`: `value` as JSON, on one line unless
+/// `pretty` is true.
+pub fn json_stringify(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let json = match args.as_slice() {
+        [value] | [value, Value::Boolean(false)] => json::to_json(value),
+        [value, Value::Boolean(true)] => json::to_json_pretty(value),
+        _ => error!(
+            loc,
+            "json_stringify() takes a value and an optional boolean"
+        ),
+    };
+    json.map(Value::from).map_err(|err| json_error(err, loc))
+}
+
+/// `random()`: a float from 0 up to but not including 1.
+pub fn random(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(loc, "random() takes no arguments");
+    }
+    Ok(Value::Float(interpreter.rng().float()))
+}
+
+/// `rand_int(lo, hi)`: an integer from `lo` to `hi`, both included.
+pub fn rand_int(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (lo, hi) = integer_operands("rand_int", loc, &args)?;
+    if lo > hi {
+        error!(loc, "rand_int() needs lo <= hi, got {} and {}", lo, hi);
+    }
+    // the span of every i64 is one more than a u64 can hold
+    let offset = match (hi.abs_diff(lo)).checked_add(1) {
+        Some(span) => interpreter.rng().below(span),
+        None => interpreter.rng().next_u64(),
+    };
+    Ok(Value::Integer(lo.wrapping_add_unsigned(offset)))
+}
+
+/// `rand_choice(items)`: one of the items of a list or tuple.
+pub fn rand_choice(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let items = match args.as_slice() {
+        [Value::List(list)] => list.borrow().clone(),
+        [Value::Tuple(items)] => items.to_vec(),
+        _ => error!(loc, "rand_choice() takes a list or tuple"),
+    };
+    if items.is_empty() {
+        error!(loc, "rand_choice() can't choose from nothing");
+    }
+    let index = interpreter.rng().below(items.len() as u64);
+    Ok(items[index as usize].clone())
+}
+
+/// `shuffle(list)`: puts the items of the list in a random order.
+pub fn shuffle(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::List(list)] = args.as_slice() else {
+        error!(loc, "shuffle() takes a list");
+    };
+    let mut list = list.borrow_mut();
+    for i in (1..list.len()).rev() {
+        let j = interpreter.rng().below(i as u64 + 1);
+        list.swap(i, j as usize);
+    }
+    Ok(Value::Nothing)
+}
+
+/// `seed(n)`: makes the random built-ins give the same numbers on every run.
+pub fn seed(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::Integer(seed)] = args.as_slice() else {
+        error!(loc, "seed() takes an integer");
+    };
+    interpreter.seed_random(*seed as u64);
+    Ok(Value::Nothing)
+}
+
+/// `now()`: the seconds since 1970-01-01 UTC, with a fraction.
+pub fn now(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(loc, "now() takes no arguments");
+    }
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => Ok(Value::Float(time.as_secs_f64())),
+        Err(err) => Ok(Value::Float(-err.duration().as_secs_f64())),
+    }
+}
+
+/// `clock()`: seconds from a fixed point in the past, which only ever go
+/// up, for timing how long something takes.
+pub fn clock(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    static START: OnceLock<Instant> = OnceLock::new();
+    if !args.is_empty() {
+        error!(loc, "clock() takes no arguments");
+    }
+    let start = START.get_or_init(Instant::now);
+    Ok(Value::Float(start.elapsed().as_secs_f64()))
+}
+
+/// `sleep(seconds)`, which Ctrl-C interrupts like any other statement.
+pub fn sleep(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let seconds = match args.as_slice() {
+        [value] => value.as_float(),
+        _ => None,
+    };
+    let duration = match seconds.map(Duration::try_from_secs_f64) {
+        Some(Ok(duration)) => duration,
+        _ => error!(loc, "sleep() takes a number of seconds that isn't negative"),
+    };
+    let end = Instant::now() + duration;
+    loop {
+        if signal::take_interrupt() {
+            return Err(Error::Interrupted(loc.clone()));
+        }
+        interpreter.check_deadline(loc)?;
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(Value::Nothing);
+        }
+        thread::sleep(left.min(Duration::from_millis(50)));
+    }
+}
+
+/// `format_time(timestamp, pattern)`: the UTC date and time of the
+/// timestamp, as `"%Y-%m-%d %H:%M:%S"` unless another pattern is given.
+pub fn format_time(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (timestamp, pattern) = match args.as_slice() {
+        [timestamp] => (timestamp, datetime::DEFAULT_PATTERN),
+        [timestamp, Value::String(pattern)] => (timestamp, pattern.as_ref()),
+        _ => error!(
+            loc,
+            "format_time() takes a timestamp and an optional pattern"
+        ),
+    };
+    let seconds = match timestamp {
+        Value::Integer(seconds) => *seconds,
+        Value::Float(seconds) if (i64::MIN as f64..i64::MAX as f64).contains(seconds) => {
+            seconds.floor() as i64
+        }
+        other => error!(loc, "format_time() can't format {}", other.repr()),
+    };
+    match datetime::format(seconds, pattern) {
+        Ok(text) => Ok(text.into()),
+        Err(msg) => error!(loc, "{}", msg),
+    }
+}
+
+/// `parse_time(text, pattern)`: the timestamp of a UTC date and time, the
+/// reverse of `format_time`.
+pub fn parse_time(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (text, pattern) = match args.as_slice() {
+        [Value::String(text)] => (text, datetime::DEFAULT_PATTERN),
+        [Value::String(text), Value::String(pattern)] => (text, pattern.as_ref()),
+        _ => error!(loc, "parse_time() takes a string and an optional pattern"),
+    };
+    match datetime::parse(text, pattern) {
+        Ok(seconds) => Ok(Value::Integer(seconds)),
+        Err(msg) => error!(loc, "{}", msg),
+    }
+}
+
+/// `exec(cmd, args)`: runs a program with a list of string arguments and
+/// waits for it, giving a dict of its exit `status`, which is `nothing` if
+/// a signal ended it, and what it wrote to `stdout` and `stderr`.
+pub fn exec(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (program, program_args) = match args.as_slice() {
+        [Value::String(program)] => (program, vec![]),
+        [Value::String(program), Value::List(list)] => (program, list.borrow().clone()),
+        [Value::String(program), Value::Tuple(items)] => (program, items.to_vec()),
+        _ => error!(
+            loc,
+            "exec() takes a program and an optional list of arguments"
+        ),
+    };
+    let mut command = Command::new(program.as_ref());
+    for arg in program_args {
+        match arg {
+            Value::String(arg) => command.arg(arg.as_ref()),
+            other => error!(
+                loc,
+                "exec() arguments must be strings, got {}",
+                other.repr()
+            ),
+        };
+    }
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => error!(loc, "Couldn't run {}: {}", program, err),
+    };
+    let status = output
+        .status
+        .code()
+        .map_or(Value::Nothing, |code| code.into());
+    let mut result = Dict::new();
+    for (key, value) in [
+        ("status", status),
+        (
+            "stdout",
+            String::from_utf8_lossy(&output.stdout).as_ref().into(),
+        ),
+        (
+            "stderr",
+            String::from_utf8_lossy(&output.stderr).as_ref().into(),
+        ),
+    ] {
+        let key: Arc<str> = key.into();
+        result.insert_hashed(HashKey::String(key.clone()), Value::String(key), value);
+    }
+    Ok(Value::Dict(Rc::new(RefCell::new(result))))
+}
+
+/// `env(name)`: the environment variable, or `nothing` if it isn't set.
+pub fn env(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(name)] = args.as_slice() else {
+        error!(loc, "env() takes a name");
+    };
+    Ok(match std::env::var_os(name.as_ref()) {
+        Some(value) => value.to_string_lossy().as_ref().into(),
+        None => Value::Nothing,
+    })
+}
+
+/// `set_env(name, value)`, for this process and the programs it runs.
+pub fn set_env(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(name), Value::String(value)] = args.as_slice() else {
+        error!(loc, "set_env() takes a name and a string");
+    };
+    if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+        error!(
+            loc,
+            "set_env() can't set {} to {}",
+            args[0].repr(),
+            args[1].repr()
+        );
+    }
+    std::env::set_var(name.as_ref(), value.as_ref());
+    Ok(Value::Nothing)
+}
+
+/// `cwd()`: the directory that relative paths start from.
+pub fn cwd(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(loc, "cwd() takes no arguments");
+    }
+    match std::env::current_dir() {
+        Ok(dir) => Ok(dir.to_string_lossy().as_ref().into()),
+        Err(err) => error!(loc, "Couldn't get the current directory: {}", err),
+    }
+}
+
+/// `chdir(path)`: changes the directory that relative paths start from.
+pub fn chdir(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let [Value::String(path)] = args.as_slice() else {
+        error!(loc, "chdir() takes a path");
+    };
+    match std::env::set_current_dir(path.as_ref()) {
+        Ok(()) => Ok(Value::Nothing),
+        Err(err) => error!(loc, "Couldn't change directory to {}: {}", path, err),
+    }
+}
+
+/// `input(prompt)`: prints the prompt, if any, and reads a line of input
+/// without its line ending, or gives `nothing` once the input has ended.
+pub fn input(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [] => {}
+        [Value::String(prompt)] => {
+            let out = interpreter.stdout();
+            if let Err(err) = out.write_all(prompt.as_bytes()).and_then(|_| out.flush()) {
+                error!(loc, "Couldn't print: {}", err);
+            }
+        }
+        _ => error!(loc, "input() takes an optional prompt string"),
+    }
+    let mut line = String::new();
+    match interpreter.read_line(&mut line) {
+        Ok(0) => Ok(Value::Nothing),
+        Ok(_) => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            Ok(line.strip_suffix('\r').unwrap_or(line).into())
+        }
+        Err(err) => error!(loc, "Couldn't read input: {}", err),
+    }
+}
+
+/// `read_stdin()`: the rest of the input, as one string.
+pub fn read_stdin(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(loc, "read_stdin() takes no arguments");
+    }
+    let mut text = String::new();
+    match interpreter.read_to_string(&mut text) {
+        Ok(_) => Ok(text.into()),
+        Err(err) => error!(loc, "Couldn't read input: {}", err),
+    }
+}
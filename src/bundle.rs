@@ -0,0 +1,223 @@
+//! Standalone executables: a copy of the interpreter with a script and the
+//! modules it imports appended to the end of the binary.
+//!
+//! Files are stored under their path relative to the entry point's
+//! directory, and every import is recorded with the file it resolved to when
+//! bundling. Running a bundle never looks at the disk, so it behaves the same
+//! wherever it's copied to.
+//!
+//! The payload is made of strings, each a little-endian `u32` length and the
+//! text. It starts with the default edition and a `u32` file count, then
+//! holds a path and a source per file, with the entry point first, then an
+//! importer, name and target per import. It is followed by its own length
+//! as a `u64` and [`MAGIC`].
+
+use crate::ast::AST;
+use crate::edition::Edition;
+use crate::error::{Error, Result};
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::manifest::Manifest;
+use crate::parser::Parser;
+use crate::project::{collect_imports, resolve_import, Project};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Marks the end of an executable that carries a bundle.
+const MAGIC: &[u8; 16] = b"rattlesnake\0bndl";
+
+/// The files embedded in an executable, entry point first, and which file
+/// each `import` in them refers to.
+#[derive(Debug)]
+pub struct Bundle {
+    /// The edition of files that don't pick their own.
+    pub edition: Edition,
+    pub files: Vec<(PathBuf, String)>,
+    pub imports: Vec<(PathBuf, String, PathBuf)>,
+}
+
+fn io_error(what: &str, path: &Path, err: std::io::Error) -> Error {
+    Error::Other(format!("Couldn't {} {}: {}", what, path.display(), err))
+}
+
+fn parse(path: &Path, source: String) -> Result<Arc<AST>> {
+    Parser::from_lexer(Lexer::new(source, path.display().to_string())).parse()
+}
+
+impl Bundle {
+    /// Gathers `entry` and every script it imports, directly or not.
+    pub fn collect(entry: &Path, manifest: Option<&Manifest>) -> Result<Bundle> {
+        let entry = entry
+            .canonicalize()
+            .map_err(|err| io_error("read", entry, err))?;
+        let base = entry.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let mut seen = HashSet::from([entry.clone()]);
+        let mut queue = vec![entry];
+        let mut bundle = Bundle {
+            edition: manifest
+                .and_then(|manifest| manifest.edition)
+                .unwrap_or_else(Edition::default_edition),
+            files: vec![],
+            imports: vec![],
+        };
+        while let Some(path) = queue.pop() {
+            let source = fs::read_to_string(&path).map_err(|err| io_error("read", &path, err))?;
+            let ast = parse(&path, source.clone())?;
+            let name = relative_to(&path, &base);
+            let mut imports = vec![];
+            collect_imports(&ast, &mut imports);
+            for import in imports {
+                // anything that isn't a file may be a host module
+                let Ok(import_path) = resolve_import(&path, import, manifest).canonicalize() else {
+                    continue;
+                };
+                let import_name = relative_to(&import_path, &base);
+                bundle
+                    .imports
+                    .push((name.clone(), import.to_string(), import_name));
+                if seen.insert(import_path.clone()) {
+                    queue.push(import_path);
+                }
+            }
+            bundle.files.push((name, source));
+        }
+        Ok(bundle)
+    }
+
+    /// Writes a copy of the running executable with this bundle attached.
+    pub fn write_executable(&self, output: &Path) -> Result<()> {
+        let exe = std::env::current_exe()
+            .map_err(|err| io_error("find", Path::new("rattlesnake"), err))?;
+
+        let mut payload = vec![];
+        push_string(&mut payload, self.edition.name());
+        payload.extend((self.files.len() as u32).to_le_bytes());
+        for (path, source) in &self.files {
+            push_string(&mut payload, &path.to_string_lossy());
+            push_string(&mut payload, source);
+        }
+        for (importer, name, path) in &self.imports {
+            push_string(&mut payload, &importer.to_string_lossy());
+            push_string(&mut payload, name);
+            push_string(&mut payload, &path.to_string_lossy());
+        }
+        payload.extend((payload.len() as u64).to_le_bytes());
+        payload.extend(MAGIC);
+
+        // copying keeps the executable's permissions
+        fs::copy(&exe, output).map_err(|err| io_error("write", output, err))?;
+        OpenOptions::new()
+            .append(true)
+            .open(output)
+            .and_then(|mut file| file.write_all(&payload))
+            .map_err(|err| io_error("write", output, err))
+    }
+
+    /// The bundle attached to the running executable, if there is one.
+    pub fn embedded() -> Result<Option<Bundle>> {
+        let Ok(exe) = std::env::current_exe() else {
+            return Ok(None);
+        };
+        let mut file = File::open(&exe).map_err(|err| io_error("read", &exe, err))?;
+        let mut trailer = [0; 8 + MAGIC.len()];
+        if file.seek(SeekFrom::End(-(trailer.len() as i64))).is_err()
+            || file.read_exact(&mut trailer).is_err()
+            || &trailer[8..] != MAGIC
+        {
+            return Ok(None);
+        }
+        let bytes = fs::read(&exe).map_err(|err| io_error("read", &exe, err))?;
+        let corrupt = || Error::Other(format!("Corrupt bundle in {}", exe.display()));
+        let (start, end) = payload_range(&bytes).ok_or_else(corrupt)?;
+
+        let mut payload = &bytes[start..end];
+        let edition = take_string(&mut payload).ok_or_else(corrupt)?;
+        let edition = Edition::from_name(&edition).ok_or_else(corrupt)?;
+        let (count, rest) = payload.split_first_chunk::<4>().ok_or_else(corrupt)?;
+        let count = u32::from_le_bytes(*count);
+        payload = rest;
+        let mut bundle = Bundle {
+            edition,
+            files: vec![],
+            imports: vec![],
+        };
+        for _ in 0..count {
+            let path = take_string(&mut payload).ok_or_else(corrupt)?;
+            let source = take_string(&mut payload).ok_or_else(corrupt)?;
+            bundle.files.push((PathBuf::from(path), source));
+        }
+        while !payload.is_empty() {
+            let importer = take_string(&mut payload).ok_or_else(corrupt)?;
+            let name = take_string(&mut payload).ok_or_else(corrupt)?;
+            let path = take_string(&mut payload).ok_or_else(corrupt)?;
+            bundle
+                .imports
+                .push((PathBuf::from(importer), name, PathBuf::from(path)));
+        }
+        Ok(Some(bundle))
+    }
+
+    /// Runs the entry point, with imports served from the bundle.
+    pub fn run(self) -> Result<()> {
+        Edition::set_default(self.edition);
+        let mut project = Project::default();
+        for (i, (path, source)) in self.files.into_iter().enumerate() {
+            if i == 0 {
+                project.entry = path.clone();
+            }
+            let ast = parse(&path, source)?;
+            project.modules.insert(path, ast);
+        }
+        let mut interpreter = Interpreter::new();
+        interpreter.add_parsed_modules(&project);
+        let mut args = std::env::args();
+        interpreter.set_args(args.next().unwrap_or_default(), args.collect());
+        for (importer, name, path) in self.imports {
+            interpreter.pin_import(importer, name, path);
+        }
+        interpreter.execute(&project.modules[&project.entry])?;
+        Ok(())
+    }
+}
+
+/// `path` as seen from `base`, e.g. `../lib/shapes.rat`.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let mut path = path.components().peekable();
+    let mut base = base.components().peekable();
+    while path.peek().is_some() && path.peek() == base.peek() {
+        path.next();
+        base.next();
+    }
+    base.map(|_| Component::ParentDir).chain(path).collect()
+}
+
+fn push_string(payload: &mut Vec<u8>, string: &str) {
+    payload.extend((string.len() as u32).to_le_bytes());
+    payload.extend(string.as_bytes());
+}
+
+/// Reads a length-prefixed string off the front of `bytes`.
+fn take_string(bytes: &mut &[u8]) -> Option<String> {
+    let (len, rest) = bytes.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (string, rest) = rest.split_at(len);
+    *bytes = rest;
+    String::from_utf8(string.to_vec()).ok()
+}
+
+/// Where the payload sits in an executable's bytes, if it has one.
+fn payload_range(bytes: &[u8]) -> Option<(usize, usize)> {
+    let trailer_start = bytes.len().checked_sub(8 + MAGIC.len())?;
+    if &bytes[trailer_start + 8..] != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(bytes[trailer_start..trailer_start + 8].try_into().unwrap());
+    let start = trailer_start.checked_sub(len as usize)?;
+    Some((start, trailer_start))
+}
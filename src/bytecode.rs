@@ -0,0 +1,587 @@
+//! Bytecode for `--vm`, which [`Interpreter`] runs instead of walking the
+//! AST. A [`Chunk`] is compiled once per function body and per script, and
+//! its instructions work on a stack of values and a stack of scopes.
+//!
+//! Only the common nodes have instructions of their own. The rest, such as
+//! `match`, `try` and function definitions, are run by the tree-walker from
+//! an [`Op::Eval`], so both backends always agree on what they mean.
+//!
+//! [`Interpreter`]: crate::interpreter::Interpreter
+
+use crate::ast::AST;
+use crate::error::Result;
+use crate::token::Location;
+use crate::value::{Overflow, Value};
+use std::sync::Arc;
+
+/// An instruction. Indices refer to the tables of the chunk it's in, and
+/// jumps to the position of an instruction in its code.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    /// Pushes `constants[i]`.
+    Constant(u32),
+    /// Pushes the value of the variable `nodes[i]`.
+    Load(u32),
+    /// Pops a value and declares it with the `let` of `nodes[i]`.
+    Declare(u32),
+    /// Pops a value and assigns it to the left side of `nodes[i]`.
+    Assign(u32),
+    Pop,
+    /// Pops the right and then the left operand of `nodes[i]` and pushes
+    /// the result.
+    Binary(BinaryOp, u32),
+    Unary(UnaryOp, u32),
+    /// Pops the index and then the value being indexed.
+    Index(u32),
+    /// Pops the collection and then the item looked for in it.
+    In(u32),
+    /// Pops `n` items and pushes them as a list.
+    List(u32),
+    Tuple(u32),
+    /// Pops `n` arguments and then the function, and pushes what the call
+    /// `nodes[i]` returns.
+    Call(u32, u32),
+    /// Runs the following instructions in a new block scope.
+    PushScope,
+    PopScope,
+    Jump(u32),
+    /// Pops the condition of the `if` or `while` `nodes[i]`, jumping if it's
+    /// false.
+    JumpIfFalse(u32, u32),
+    /// Ends the `and` (`false`) or `or` (`true`) `nodes[i]` early, jumping
+    /// if the value on top is as truthy as the bool and leaving it as the
+    /// result, or else popping it.
+    ShortCircuit(u32, bool, u32),
+    /// Replaces the value on top with an iterator over it, for the `for`
+    /// loop `nodes[i]`.
+    IntoIter(u32),
+    /// Takes the next value of the iterator on top into a new scope with
+    /// the loop variable of `nodes[i]`, or jumps once there are none left.
+    ForNext(u32, u32),
+    /// Pops a value and returns it from the function.
+    Return,
+    /// Pops `n` arguments and then the function of the call `nodes[i]`,
+    /// which the function returns, leaving the call to whatever called it.
+    TailCall(u32, u32),
+    /// Runs `nodes[i]` with the tree-walker and pushes its value. A `break`
+    /// or `continue` in it leaves `loops[j]`, the innermost loop around it
+    /// in this chunk, if there is one.
+    Eval(u32, Option<u32>),
+}
+
+impl Op {
+    /// The node the instruction runs for, if it stands for one.
+    pub fn node(self) -> Option<u32> {
+        match self {
+            Op::Load(i)
+            | Op::Declare(i)
+            | Op::Assign(i)
+            | Op::Binary(_, i)
+            | Op::Unary(_, i)
+            | Op::Index(i)
+            | Op::In(i)
+            | Op::Call(_, i)
+            | Op::TailCall(_, i)
+            | Op::JumpIfFalse(_, i)
+            | Op::ShortCircuit(_, _, i)
+            | Op::IntoIter(i)
+            | Op::ForNext(_, i) => Some(i),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BinaryOp {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    ShiftLeft,
+    ShiftRight,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Equals,
+    NotEquals,
+    LessThan,
+    GreaterThan,
+    LessThanEquals,
+    GreaterThanEquals,
+}
+
+impl BinaryOp {
+    pub fn of(node: &AST) -> Option<(BinaryOp, &Arc<AST>, &Arc<AST>)> {
+        Some(match node {
+            AST::Plus(_, left, right) => (BinaryOp::Plus, left, right),
+            AST::Minus(_, left, right) => (BinaryOp::Minus, left, right),
+            AST::Multiply(_, left, right) => (BinaryOp::Multiply, left, right),
+            AST::Divide(_, left, right) => (BinaryOp::Divide, left, right),
+            AST::Modulo(_, left, right) => (BinaryOp::Modulo, left, right),
+            AST::Power(_, left, right) => (BinaryOp::Power, left, right),
+            AST::ShiftLeft(_, left, right) => (BinaryOp::ShiftLeft, left, right),
+            AST::ShiftRight(_, left, right) => (BinaryOp::ShiftRight, left, right),
+            AST::BitAnd(_, left, right) => (BinaryOp::BitAnd, left, right),
+            AST::BitOr(_, left, right) => (BinaryOp::BitOr, left, right),
+            AST::BitXor(_, left, right) => (BinaryOp::BitXor, left, right),
+            AST::Equals(_, left, right) => (BinaryOp::Equals, left, right),
+            AST::NotEquals(_, left, right) => (BinaryOp::NotEquals, left, right),
+            AST::LessThan(_, left, right) => (BinaryOp::LessThan, left, right),
+            AST::GreaterThan(_, left, right) => (BinaryOp::GreaterThan, left, right),
+            AST::LessThanEquals(_, left, right) => (BinaryOp::LessThanEquals, left, right),
+            AST::GreaterThanEquals(_, left, right) => (BinaryOp::GreaterThanEquals, left, right),
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Power => "**",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Equals => "==",
+            BinaryOp::NotEquals => "!=",
+            BinaryOp::LessThan => "<",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::LessThanEquals => "<=",
+            BinaryOp::GreaterThanEquals => ">=",
+        }
+    }
+
+    /// The operator's symbol, for the operators strict mode restricts.
+    fn strict_symbol(self) -> Option<&'static str> {
+        match self {
+            BinaryOp::ShiftRight | BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => None,
+            _ => Some(self.symbol()),
+        }
+    }
+
+    /// Applies the operator the way the tree-walker does.
+    pub fn apply(
+        self,
+        left: &Value,
+        right: &Value,
+        strict: bool,
+        overflow: Overflow,
+        loc: &Location,
+    ) -> Result<Value> {
+        if let (true, Some(symbol)) = (strict, self.strict_symbol()) {
+            left.check_strict(right, symbol, loc)?;
+        }
+        match self {
+            BinaryOp::Plus => left.plus(right, overflow, loc),
+            BinaryOp::Minus => left.minus(right, overflow, loc),
+            BinaryOp::Multiply => left.multiply(right, overflow, loc),
+            BinaryOp::Divide => left.divide(right, overflow, loc),
+            BinaryOp::Modulo => left.modulo(right, overflow, loc),
+            BinaryOp::Power => left.power(right, overflow, loc),
+            BinaryOp::ShiftLeft => left.shift_left(right, overflow, loc),
+            BinaryOp::ShiftRight => left.shift_right(right, loc),
+            BinaryOp::BitAnd => left.bit_and(right, loc),
+            BinaryOp::BitOr => left.bit_or(right, loc),
+            BinaryOp::BitXor => left.bit_xor(right, loc),
+            BinaryOp::Equals => left.equals(right, loc),
+            BinaryOp::NotEquals => left.not_equals(right, loc),
+            BinaryOp::LessThan => left.less_than(right, loc),
+            BinaryOp::GreaterThan => left.greater_than(right, loc),
+            BinaryOp::LessThanEquals => left.less_than_equals(right, loc),
+            BinaryOp::GreaterThanEquals => left.greater_than_equals(right, loc),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+    BitNot,
+}
+
+impl UnaryOp {
+    pub fn of(node: &AST) -> Option<(UnaryOp, &Arc<AST>)> {
+        Some(match node {
+            AST::Not(_, value) => (UnaryOp::Not, value),
+            AST::Negate(_, value) => (UnaryOp::Negate, value),
+            AST::BitNot(_, value) => (UnaryOp::BitNot, value),
+            _ => return None,
+        })
+    }
+
+    /// The method a struct overloads the operator with, if it can.
+    pub fn method(self) -> Option<&'static str> {
+        match self {
+            UnaryOp::Not => None,
+            UnaryOp::Negate => Some("__neg__"),
+            UnaryOp::BitNot => Some("__invert__"),
+        }
+    }
+
+    pub fn apply(
+        self,
+        value: &Value,
+        strict: bool,
+        overflow: Overflow,
+        loc: &Location,
+    ) -> Result<Value> {
+        match self {
+            UnaryOp::Not => value.not(strict, loc),
+            UnaryOp::Negate => value.negate(overflow, loc),
+            UnaryOp::BitNot => value.bit_not(loc),
+        }
+    }
+}
+
+/// Where a `break` or `continue` run by the tree-walker inside a loop goes.
+#[derive(Debug)]
+pub struct Loop {
+    pub break_to: usize,
+    pub continue_to: usize,
+    /// How many block scopes are open outside the loop.
+    pub scopes: usize,
+    /// How many values are on the stack outside the loop, counting the
+    /// iterator of a `for` loop.
+    pub stack: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Value>,
+    pub nodes: Vec<Arc<AST>>,
+    pub loops: Vec<Loop>,
+}
+
+impl Chunk {
+    /// Compiles the body of a function.
+    pub fn function(body: &Arc<AST>) -> Chunk {
+        let mut compiler = Compiler::new(true);
+        compiler.statement(body);
+        compiler.chunk
+    }
+
+    /// Compiles a script, whose value is that of its last statement.
+    pub fn script(block: &Arc<AST>) -> Chunk {
+        let mut compiler = Compiler::new(false);
+        let AST::Block(_, stmts) = block.as_ref() else {
+            unreachable!("Chunk::script called on non-block")
+        };
+        if let Some((last, rest)) = stmts.split_last() {
+            for stmt in rest {
+                compiler.statement(stmt);
+            }
+            match last.as_ref() {
+                AST::While(..) | AST::For(..) => {
+                    compiler.statement(last);
+                    compiler.constant(Value::Nothing);
+                }
+                _ => compiler.expression(last),
+            }
+        }
+        compiler.chunk
+    }
+}
+
+struct OpenLoop {
+    index: usize,
+    breaks: Vec<usize>,
+}
+
+struct Compiler {
+    chunk: Chunk,
+    /// Whether `return` is allowed, which the tree-walker reports otherwise.
+    in_function: bool,
+    scopes: usize,
+    /// How many values are on the stack, which is known for each
+    /// instruction since every path to it pushes the same ones.
+    stack: usize,
+    loops: Vec<OpenLoop>,
+}
+
+impl Compiler {
+    fn new(in_function: bool) -> Compiler {
+        Compiler {
+            chunk: Chunk::default(),
+            in_function,
+            scopes: 0,
+            stack: 0,
+            loops: vec![],
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.stack = match op {
+            Op::Constant(_) | Op::Load(_) | Op::Eval(..) => self.stack + 1,
+            Op::Declare(_)
+            | Op::Assign(_)
+            | Op::Pop
+            | Op::Binary(..)
+            | Op::Index(_)
+            | Op::In(_)
+            | Op::JumpIfFalse(..)
+            | Op::ShortCircuit(..)
+            | Op::Return => self.stack - 1,
+            Op::List(n) | Op::Tuple(n) => self.stack + 1 - n as usize,
+            Op::Call(argc, _) => self.stack - argc as usize,
+            Op::TailCall(argc, _) => self.stack - argc as usize - 1,
+            Op::Unary(..)
+            | Op::PushScope
+            | Op::PopScope
+            | Op::Jump(_)
+            | Op::IntoIter(_)
+            | Op::ForNext(..) => self.stack,
+        };
+        self.chunk.code.push(op);
+        self.chunk.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+        match &mut self.chunk.code[at] {
+            Op::Jump(to)
+            | Op::JumpIfFalse(to, _)
+            | Op::ShortCircuit(to, ..)
+            | Op::ForNext(to, _) => *to = here,
+            op => unreachable!("patching {:?}", op),
+        }
+    }
+
+    fn node(&mut self, node: &Arc<AST>) -> u32 {
+        self.chunk.nodes.push(node.clone());
+        self.chunk.nodes.len() as u32 - 1
+    }
+
+    fn constant(&mut self, value: Value) {
+        self.chunk.constants.push(value);
+        let index = self.chunk.constants.len() as u32 - 1;
+        self.emit(Op::Constant(index));
+    }
+
+    fn eval(&mut self, node: &Arc<AST>) {
+        let node = self.node(node);
+        let inner = self.loops.last().map(|open| open.index as u32);
+        self.emit(Op::Eval(node, inner));
+    }
+
+    fn pop_scopes(&mut self, to: usize) {
+        for _ in to..self.scopes {
+            self.emit(Op::PopScope);
+        }
+    }
+
+    fn block(&mut self, stmts: &[Arc<AST>]) {
+        self.emit(Op::PushScope);
+        self.scopes += 1;
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+        self.scopes -= 1;
+        self.emit(Op::PopScope);
+    }
+
+    /// Starts a loop whose `continue` goes to `continue_to`.
+    fn open_loop(&mut self, continue_to: usize) {
+        self.chunk.loops.push(Loop {
+            break_to: 0,
+            continue_to,
+            scopes: self.scopes,
+            stack: self.stack,
+        });
+        self.loops.push(OpenLoop {
+            index: self.chunk.loops.len() - 1,
+            breaks: vec![],
+        });
+    }
+
+    /// Ends the innermost loop, whose `break` goes to the next instruction.
+    fn close_loop(&mut self) {
+        let open = self.loops.pop().expect("no loop to close");
+        for at in open.breaks {
+            self.patch(at);
+        }
+        self.chunk.loops[open.index].break_to = self.here() as usize;
+    }
+
+    fn statement(&mut self, stmt: &Arc<AST>) {
+        match stmt.as_ref() {
+            AST::Block(_, stmts) => self.block(stmts),
+            AST::If(_, cond, body, else_body) => {
+                self.expression(cond);
+                let node = self.node(stmt);
+                let to_else = self.emit(Op::JumpIfFalse(0, node));
+                self.statement(body);
+                match else_body {
+                    Some(else_body) => {
+                        let to_end = self.emit(Op::Jump(0));
+                        self.patch(to_else);
+                        self.statement(else_body);
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            AST::While(_, cond, body) => {
+                let start = self.here();
+                self.open_loop(start as usize);
+                self.expression(cond);
+                let node = self.node(stmt);
+                let to_end = self.emit(Op::JumpIfFalse(0, node));
+                self.statement(body);
+                self.emit(Op::Jump(start));
+                self.patch(to_end);
+                self.close_loop();
+            }
+            AST::For(_, _, iter, body) => {
+                self.expression(iter);
+                let node = self.node(stmt);
+                self.emit(Op::IntoIter(node));
+                let start = self.here();
+                self.open_loop(start as usize);
+                let to_end = self.emit(Op::ForNext(0, node));
+                self.scopes += 1;
+                self.statement(body);
+                self.scopes -= 1;
+                self.emit(Op::PopScope);
+                self.emit(Op::Jump(start));
+                self.patch(to_end);
+                self.close_loop();
+                // the iterator
+                self.emit(Op::Pop);
+            }
+            AST::Break(_) => {
+                let Some(open) = self.loops.last() else {
+                    return self.eval_statement(stmt);
+                };
+                self.pop_scopes(self.chunk.loops[open.index].scopes);
+                let at = self.emit(Op::Jump(0));
+                let open = self.loops.last_mut().expect("checked above");
+                open.breaks.push(at);
+            }
+            AST::Continue(_) => {
+                let Some(open) = self.loops.last() else {
+                    return self.eval_statement(stmt);
+                };
+                let Loop {
+                    continue_to,
+                    scopes,
+                    ..
+                } = self.chunk.loops[open.index];
+                self.pop_scopes(scopes);
+                self.emit(Op::Jump(continue_to as u32));
+            }
+            AST::Return(_, value) if self.in_function => match value.plain_call() {
+                Some((_, func, args)) => {
+                    self.call_operands(func, args);
+                    let node = self.node(value);
+                    self.emit(Op::TailCall(args.len() as u32, node));
+                }
+                None => {
+                    self.expression(value);
+                    self.emit(Op::Return);
+                }
+            },
+            AST::VarDeclaration(_, _, _, value) => {
+                self.expression(value);
+                let node = self.node(stmt);
+                self.emit(Op::Declare(node));
+            }
+            AST::Assignment(_, _, value) => {
+                self.expression(value);
+                let node = self.node(stmt);
+                self.emit(Op::Assign(node));
+            }
+            _ => {
+                self.expression(stmt);
+                self.emit(Op::Pop);
+            }
+        }
+    }
+
+    fn eval_statement(&mut self, stmt: &Arc<AST>) {
+        self.eval(stmt);
+        self.emit(Op::Pop);
+    }
+
+    fn call_operands(&mut self, func: &Arc<AST>, args: &[Arc<AST>]) {
+        self.expression(func);
+        for arg in args {
+            self.expression(arg);
+        }
+    }
+
+    fn expression(&mut self, expr: &Arc<AST>) {
+        if let Some((op, left, right)) = BinaryOp::of(expr) {
+            self.expression(left);
+            self.expression(right);
+            let node = self.node(expr);
+            self.emit(Op::Binary(op, node));
+            return;
+        }
+        // method calls and named arguments are left to the tree-walker
+        if let Some((_, func, args)) = expr.plain_call() {
+            self.call_operands(func, args);
+            let node = self.node(expr);
+            self.emit(Op::Call(args.len() as u32, node));
+            return;
+        }
+        if let Some((op, value)) = UnaryOp::of(expr) {
+            self.expression(value);
+            let node = self.node(expr);
+            self.emit(Op::Unary(op, node));
+            return;
+        }
+        match expr.as_ref() {
+            AST::BooleanLiteral(_, value) => self.constant(Value::Boolean(*value)),
+            AST::IntegerLiteral(_, num) => self.constant(Value::Integer(*num)),
+            AST::FloatLiteral(_, num) => self.constant(Value::Float(*num)),
+            AST::StringLiteral(_, string) => self.constant(Value::String(string.clone())),
+            AST::Nothing(_) => self.constant(Value::Nothing),
+            AST::Variable(..) | AST::Local(..) => {
+                let node = self.node(expr);
+                self.emit(Op::Load(node));
+            }
+            AST::List(_, items) | AST::Tuple(_, items) => {
+                for item in items {
+                    self.expression(item);
+                }
+                let n = items.len() as u32;
+                self.emit(match expr.as_ref() {
+                    AST::List(..) => Op::List(n),
+                    _ => Op::Tuple(n),
+                });
+            }
+            AST::And(_, left, right) | AST::Or(_, left, right) => {
+                self.expression(left);
+                let or = matches!(expr.as_ref(), AST::Or(..));
+                let node = self.node(expr);
+                let to_end = self.emit(Op::ShortCircuit(0, or, node));
+                self.expression(right);
+                self.patch(to_end);
+            }
+            AST::Index(_, left, right) | AST::In(_, left, right) => {
+                self.expression(left);
+                self.expression(right);
+                let node = self.node(expr);
+                self.emit(match expr.as_ref() {
+                    AST::Index(..) => Op::Index(node),
+                    _ => Op::In(node),
+                });
+            }
+            _ => self.eval(expr),
+        }
+    }
+}
@@ -0,0 +1,1322 @@
+//! The optional static type checker behind `rattlesnake check`, which finds
+//! type errors before a script runs.
+//!
+//! Checking is gradual. The types of `let`s, parameters and return values
+//! come from their annotations, as in `def add(a: int, b: int) -> int`, and
+//! the types of expressions are inferred where that's obvious: from
+//! literals, arithmetic, calls of annotated functions and the built-ins.
+//! Everything else is `any`, which goes with every type, so code without
+//! annotations stays as dynamic as it runs, and only what would certainly
+//! fail is reported.
+//!
+//! Variables take the type of what was last assigned to them, joined over
+//! the branches that could have assigned it. Loops, `try`s and functions
+//! that assign to the variables around them make those variables `any`
+//! again, unless they're annotated.
+
+use crate::ast::{MatchArm, TypeExpr, AST};
+use crate::error::Error;
+use crate::interpreter::{describe, match_arguments, with_suggestions, Argument};
+use crate::symbol::Symbol;
+use crate::token::Location;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The names of the types that annotations can use, besides those of
+/// structs and enums.
+const TYPE_NAMES: &[&str] = &[
+    "any", "int", "float", "number", "str", "bool", "nothing", "range", "tuple", "list", "dict",
+    "function",
+];
+
+/// Finds the type errors in a parsed script, in the order the checker
+/// comes across them.
+pub fn check(ast: &AST) -> Vec<Error> {
+    let mut checker = Checker::default();
+    checker.survey(ast);
+    checker.expr(ast);
+    checker.errors
+}
+
+/// The type of a value, as far as the checker knows it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// Any value at all, which is what isn't known.
+    Any,
+    Int,
+    Float,
+    Str,
+    Bool,
+    Nothing,
+    Range,
+    Tuple,
+    List(Box<Type>),
+    Dict(Box<Type>, Box<Type>),
+    Function(Rc<FunctionType>),
+    /// A struct itself, which makes its instances when called.
+    Struct(Symbol),
+    /// An enum itself, whose members are its variants.
+    Enum(Symbol),
+    /// An instance of a struct, or one of the variants of an enum.
+    Instance(Symbol),
+    /// A value of any of these types.
+    Union(Vec<Type>),
+}
+
+/// The arguments a function takes, which are `None` when they aren't
+/// known, as for most built-ins, and what it returns. The last `defaults`
+/// arguments have default values, before the `...rest` one of a `variadic`
+/// function, which is a list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub name: Option<Symbol>,
+    pub args: Option<Vec<(Symbol, Type)>>,
+    pub variadic: bool,
+    pub defaults: usize,
+    pub returns: Type,
+}
+
+impl Type {
+    fn function(args: Option<Vec<(Symbol, Type)>>, returns: Type) -> Type {
+        Type::Function(Rc::new(FunctionType {
+            name: None,
+            args,
+            variadic: false,
+            defaults: 0,
+            returns,
+        }))
+    }
+
+    /// Whether a value of this type can be used where `expected` is. An
+    /// `int` goes where a `float` is expected, since it works everywhere a
+    /// float does.
+    pub fn fits(&self, expected: &Type) -> bool {
+        match (self, expected) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Union(types), _) => types.iter().all(|ty| ty.fits(expected)),
+            (_, Type::Union(types)) => types.iter().any(|ty| self.fits(ty)),
+            (Type::Int, Type::Float) => true,
+            (Type::List(item), Type::List(expected)) => item.fits(expected),
+            (Type::Dict(key, value), Type::Dict(expected_key, expected_value)) => {
+                key.fits(expected_key) && value.fits(expected_value)
+            }
+            (Type::Function(func), Type::Function(expected)) => {
+                let arity = match (&func.args, &expected.args) {
+                    (Some(args), Some(expected)) => args.len() == expected.len(),
+                    _ => true,
+                };
+                arity && func.returns.fits(&expected.returns)
+            }
+            _ => self == expected,
+        }
+    }
+
+    /// The type of a value that has either this type or `other`.
+    pub fn join(self, other: Type) -> Type {
+        if self == other {
+            return self;
+        }
+        if self == Type::Any || other == Type::Any {
+            return Type::Any;
+        }
+        let mut types = vec![];
+        for ty in [self, other] {
+            let members = match ty {
+                Type::Union(members) => members,
+                ty => vec![ty],
+            };
+            for member in members {
+                if !types.contains(&member) {
+                    types.push(member);
+                }
+            }
+        }
+        Type::Union(types)
+    }
+
+    /// The type without what's known about the items of lists and dicts,
+    /// which can change without the variable holding them being assigned.
+    fn erased(self) -> Type {
+        match self {
+            Type::List(_) => Type::List(Box::new(Type::Any)),
+            Type::Dict(..) => Type::Dict(Box::new(Type::Any), Box::new(Type::Any)),
+            Type::Union(types) => types
+                .into_iter()
+                .map(Type::erased)
+                .reduce(Type::join)
+                .unwrap_or(Type::Any),
+            ty => ty,
+        }
+    }
+
+    /// Whether this is a single type whose operators can't be overloaded,
+    /// so that what they do with it is known.
+    fn is_known(&self) -> bool {
+        !matches!(self, Type::Any | Type::Instance(_) | Type::Union(_))
+    }
+
+    /// Whether a variable narrows to this type when a value of it is
+    /// assigned, instead of keeping its annotated type.
+    fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            Type::Int | Type::Float | Type::Str | Type::Bool | Type::Nothing
+        )
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nothing => write!(f, "nothing"),
+            Type::Range => write!(f, "range"),
+            Type::Tuple => write!(f, "tuple"),
+            Type::List(item) if **item == Type::Any => write!(f, "list"),
+            Type::List(item) => write!(f, "list[{}]", item),
+            Type::Dict(key, value) if **key == Type::Any && **value == Type::Any => {
+                write!(f, "dict")
+            }
+            Type::Dict(key, value) => write!(f, "dict[{}, {}]", key, value),
+            Type::Function(_) => write!(f, "function"),
+            Type::Struct(name) => write!(f, "struct {}", name),
+            Type::Enum(name) => write!(f, "enum {}", name),
+            Type::Instance(name) => write!(f, "{}", name),
+            Type::Union(types) => {
+                let types: Vec<_> = types.iter().map(|ty| ty.to_string()).collect();
+                write!(f, "{}", types.join(" | "))
+            }
+        }
+    }
+}
+
+/// What the return types of the built-ins are, for those that always
+/// return the same type.
+fn builtin(name: &str) -> Option<Type> {
+    let returns = match name {
+        "print" | "eprint" | "eprintln" | "printf" => Type::Nothing,
+        "str" | "format" | "type" | "cwd" | "read_file" | "json_stringify" => Type::Str,
+        "len" | "int" => Type::Int,
+        "float" | "random" | "clock" => Type::Float,
+        "bool" | "callable" | "file_exists" => Type::Bool,
+        name if name.starts_with("is_") => Type::Bool,
+        "read_lines" => Type::List(Box::new(Type::Str)),
+        "list" => Type::List(Box::new(Type::Any)),
+        _ => return None,
+    };
+    Some(Type::function(None, returns))
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    /// The annotated type, which every value assigned to it must fit.
+    declared: Option<Type>,
+    /// The type of its value at this point of the script.
+    current: Type,
+    /// Whether it's a `def`, `struct` or `enum`, whose type is the same
+    /// wherever it's read unless it's assigned to.
+    fixed: bool,
+}
+
+type Scope = HashMap<Symbol, Binding>;
+
+/// The fields and methods of a struct.
+struct StructInfo {
+    fields: Vec<Symbol>,
+    methods: Vec<Arc<AST>>,
+}
+
+/// The variants of an enum, with how many fields each has, and its
+/// methods.
+struct EnumInfo {
+    variants: Vec<(Symbol, usize)>,
+    methods: Vec<Symbol>,
+}
+
+#[derive(Default)]
+struct Checker {
+    errors: Vec<Error>,
+    scopes: Vec<Scope>,
+    /// How many of `scopes` are outside the function being checked, whose
+    /// variables could have any value by the time the function is called.
+    function_start: usize,
+    /// The function being checked, and what it's declared to return.
+    returns: Option<(Option<Symbol>, Type)>,
+    /// The names that are assigned to anywhere.
+    assigned: HashSet<Symbol>,
+    /// The names that are declared anywhere, which shadow built-ins.
+    declared: HashSet<Symbol>,
+    /// The names that functions assign to without declaring them, whose
+    /// variables can change whenever a function is called.
+    escaping: HashSet<Symbol>,
+    /// The names of every struct and enum, which annotations can use.
+    types: HashSet<Symbol>,
+    /// The structs and enums that are declared once, so that their names
+    /// say which one they are.
+    structs: HashMap<Symbol, StructInfo>,
+    enums: HashMap<Symbol, EnumInfo>,
+    /// The types of the functions checked so far, by the address of their
+    /// node, so that hoisting a `def` doesn't report its annotations twice.
+    signatures: HashMap<*const AST, Rc<FunctionType>>,
+}
+
+impl Checker {
+    fn error(&mut self, loc: &Location, msg: String) {
+        self.errors.push(Error::Type(loc.clone(), msg));
+    }
+
+    /// Collects what the script declares and assigns, before any of it is
+    /// checked.
+    fn survey(&mut self, ast: &AST) {
+        self.declared.extend(declared_names(ast));
+        match ast {
+            AST::Assignment(_, target, _) => target_names(target, &mut self.assigned),
+            AST::Function { args, body, .. } => {
+                self.escaping.extend(free_assignments(args, body));
+            }
+            AST::Struct {
+                name,
+                fields,
+                methods,
+                ..
+            } => {
+                if self.types.insert(*name) {
+                    let info = StructInfo {
+                        fields: fields.clone(),
+                        methods: methods.clone(),
+                    };
+                    self.structs.insert(*name, info);
+                } else {
+                    self.structs.remove(name);
+                    self.enums.remove(name);
+                }
+            }
+            AST::Enum {
+                name,
+                variants,
+                methods,
+                ..
+            } => {
+                if self.types.insert(*name) {
+                    let variants = variants
+                        .iter()
+                        .map(|variant| (variant.name, variant.fields.len()))
+                        .collect();
+                    let methods = methods.iter().filter_map(|method| method.declared_name());
+                    let methods = methods.collect();
+                    self.enums.insert(*name, EnumInfo { variants, methods });
+                } else {
+                    self.structs.remove(name);
+                    self.enums.remove(name);
+                }
+            }
+            _ => {}
+        }
+        ast.children()
+            .into_iter()
+            .for_each(|child| self.survey(child));
+    }
+
+    fn declare(&mut self, name: Symbol, binding: Binding) {
+        let scope = self.scopes.last_mut().expect("checks inside a scope");
+        scope.insert(name, binding);
+    }
+
+    /// Declares `name` from a `let`, annotated with `declared` if it's
+    /// `Some`.
+    fn declare_variable(&mut self, name: Symbol, declared: Option<Type>, value: Type) {
+        let current = self.narrowed(name, declared.as_ref(), value);
+        let fixed = false;
+        self.declare(
+            name,
+            Binding {
+                declared,
+                current,
+                fixed,
+            },
+        );
+    }
+
+    /// The type of `name` right after a value of type `value` is assigned
+    /// to it.
+    fn narrowed(&self, name: Symbol, declared: Option<&Type>, value: Type) -> Type {
+        match declared {
+            Some(_) if value.is_scalar() && !self.escaping.contains(&name) => value,
+            Some(declared) => declared.clone(),
+            None if self.escaping.contains(&name) => Type::Any,
+            None => value.erased(),
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Type {
+        for (depth, scope) in self.scopes.iter().enumerate().rev() {
+            let Some(binding) = scope.get(&name) else {
+                continue;
+            };
+            if depth >= self.function_start {
+                return binding.current.clone();
+            }
+            return match &binding.declared {
+                Some(declared) => declared.clone(),
+                None if binding.fixed && !self.assigned.contains(&name) => binding.current.clone(),
+                None => Type::Any,
+            };
+        }
+        match self.declared.contains(&name) {
+            true => Type::Any,
+            false => builtin(name.as_str()).unwrap_or(Type::Any),
+        }
+    }
+
+    /// Makes the unannotated variables among `names` `any`, and the others
+    /// their annotated types, since they could have been assigned anything.
+    fn widen(&mut self, names: &HashSet<Symbol>) {
+        let start = self.function_start;
+        for scope in &mut self.scopes[start..] {
+            for (name, binding) in scope.iter_mut() {
+                if names.contains(name) {
+                    binding.current = binding.declared.clone().unwrap_or(Type::Any);
+                }
+            }
+        }
+    }
+
+    /// Joins the types of the variables with those they have in `other`,
+    /// where another branch left them.
+    fn merge(&mut self, other: Vec<Scope>) {
+        for (scope, other) in self.scopes.iter_mut().zip(other) {
+            for (name, binding) in scope.iter_mut() {
+                if let Some(other) = other.get(name) {
+                    let current = std::mem::replace(&mut binding.current, Type::Any);
+                    binding.current = current.join(other.current.clone());
+                }
+            }
+        }
+    }
+
+    /// The type that the annotation `ty` stands for.
+    fn resolve(&mut self, ty: &TypeExpr) -> Type {
+        let (loc, name, items) = match ty {
+            TypeExpr::Union(_, types) => {
+                let types = types.iter().map(|ty| self.resolve(ty)).collect::<Vec<_>>();
+                return types.into_iter().reduce(Type::join).unwrap_or(Type::Any);
+            }
+            TypeExpr::Named(loc, name, items) => (loc, *name, items),
+        };
+        let known = TYPE_NAMES.contains(&name.as_str()) || self.types.contains(&name);
+        // a type from an imported module, which isn't checked
+        if name.as_str().contains('.') {
+            return Type::Any;
+        } else if !known {
+            let error = Error::Type(loc.clone(), format!("Unknown type `{}`", name));
+            let mut types = self.types.iter().map(|ty| ty.as_str()).collect::<Vec<_>>();
+            types.sort();
+            let candidates = TYPE_NAMES.iter().copied().chain(types);
+            self.errors
+                .push(with_suggestions(error, name.as_str(), candidates));
+            return Type::Any;
+        }
+        let items = items
+            .iter()
+            .map(|item| self.resolve(item))
+            .collect::<Vec<_>>();
+        let takes = match name.as_str() {
+            "list" => 1,
+            "dict" => 2,
+            _ => 0,
+        };
+        if !items.is_empty() && items.len() != takes {
+            let msg = match takes {
+                0 => format!("`{}` doesn't take types in brackets", name),
+                1 => format!("`{}` takes 1 type in brackets, got {}", name, items.len()),
+                _ => format!(
+                    "`{}` takes {} types in brackets, got {}",
+                    name,
+                    takes,
+                    items.len()
+                ),
+            };
+            self.error(loc, msg);
+            return Type::Any;
+        }
+        let mut items = items.into_iter();
+        let mut item = || Box::new(items.next().unwrap_or(Type::Any));
+        match name.as_str() {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "number" => Type::Union(vec![Type::Int, Type::Float]),
+            "str" => Type::Str,
+            "bool" => Type::Bool,
+            "nothing" => Type::Nothing,
+            "range" => Type::Range,
+            "tuple" => Type::Tuple,
+            "list" => Type::List(item()),
+            "dict" => Type::Dict(item(), item()),
+            "function" => Type::function(None, Type::Any),
+            "any" => Type::Any,
+            _ => Type::Instance(name),
+        }
+    }
+
+    /// The type of the function `ast`, whose first argument is `receiver`
+    /// for a method.
+    fn signature(&mut self, ast: &AST, receiver: Option<Symbol>) -> Rc<FunctionType> {
+        if let Some(signature) = self.signatures.get(&(ast as *const AST)) {
+            return signature.clone();
+        }
+        let AST::Function {
+            name,
+            args,
+            variadic,
+            defaults,
+            signature,
+            ..
+        } = ast
+        else {
+            unreachable!("signature of {}", ast.kind())
+        };
+        let annotations = signature.as_ref().map(|signature| &signature.args);
+        let mut types = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            let annotation = annotations
+                .and_then(|args| args.get(i))
+                .and_then(Option::as_ref);
+            let ty = match (annotation, receiver) {
+                (Some(ty), _) => self.resolve(ty),
+                (None, Some(receiver)) if i == 0 => Type::Instance(receiver),
+                (None, _) => Type::Any,
+            };
+            // the annotation of a `...rest` parameter is that of its items
+            let ty = match *variadic && i == args.len() - 1 {
+                true => Type::List(Box::new(ty)),
+                false => ty,
+            };
+            types.push((*arg, ty));
+        }
+        let returns = signature
+            .as_ref()
+            .and_then(|signature| signature.returns.as_ref());
+        let returns = match returns {
+            Some(ty) => self.resolve(ty),
+            None => Type::Any,
+        };
+        let signature = Rc::new(FunctionType {
+            name: *name,
+            args: Some(types),
+            variadic: *variadic,
+            defaults: defaults.len(),
+            returns,
+        });
+        self.signatures.insert(ast as *const AST, signature.clone());
+        signature
+    }
+
+    /// Declares the `def`s, structs and enums of a block before checking
+    /// it, so that functions can call those declared after them.
+    fn hoist(&mut self, stmts: &[Arc<AST>]) {
+        for stmt in stmts {
+            let stmt = match stmt.as_ref() {
+                AST::Pub(_, decl) => decl,
+                _ => stmt,
+            };
+            let (name, ty) = match stmt.as_ref() {
+                AST::Function {
+                    name: Some(name), ..
+                } => (*name, Type::Function(self.signature(stmt, None))),
+                AST::Struct { name, .. } => (*name, Type::Struct(*name)),
+                AST::Enum { name, .. } => (*name, Type::Enum(*name)),
+                _ => continue,
+            };
+            self.declare(name, fixed(ty));
+        }
+    }
+
+    /// Checks the body of the function `ast`, returning its type.
+    fn function(&mut self, ast: &AST, receiver: Option<Symbol>) -> Rc<FunctionType> {
+        let AST::Function {
+            name,
+            args,
+            variadic,
+            defaults,
+            signature,
+            body,
+            ..
+        } = ast
+        else {
+            unreachable!("function called on {}", ast.kind())
+        };
+        let ty = self.signature(ast, receiver);
+        let annotated = |i: usize| {
+            let args = signature.as_ref().map(|signature| &signature.args);
+            args.and_then(|args| args.get(i))
+                .is_some_and(Option::is_some)
+        };
+        let mut scope = Scope::new();
+        for (i, (arg, arg_ty)) in ty.args.iter().flatten().enumerate() {
+            let declared = annotated(i).then(|| arg_ty.clone());
+            let current = self.narrowed(*arg, declared.as_ref(), arg_ty.clone());
+            let binding = Binding {
+                declared,
+                current,
+                fixed: false,
+            };
+            scope.insert(*arg, binding);
+        }
+        // a generator's body returns to whatever is stepping through it
+        let returns = match body.as_ref() {
+            AST::Generator(..) => Type::Any,
+            _ => ty.returns.clone(),
+        };
+        if !Type::Nothing.fits(&returns) && !always_returns(body) {
+            let msg = format!(
+                "{} is declared to return {}, but can end without returning",
+                describe(*name),
+                returns
+            );
+            self.error(ast.loc(), msg);
+        }
+        let function_start = std::mem::replace(&mut self.function_start, self.scopes.len());
+        let outer = self.returns.replace((*name, returns));
+        self.scopes.push(scope);
+        let params = args.iter().zip(ty.args.iter().flatten());
+        let params = params.skip(args.len() - *variadic as usize - defaults.len());
+        for ((arg, (_, arg_ty)), default) in params.zip(defaults) {
+            let default_ty = self.expr(default);
+            if !default_ty.fits(arg_ty) {
+                let msg = format!(
+                    "`{}` is declared as {}, but defaults to {}",
+                    arg, arg_ty, default_ty
+                );
+                self.error(default.loc(), msg);
+            }
+        }
+        self.expr(body);
+        self.scopes.pop();
+        self.returns = outer;
+        self.function_start = function_start;
+        ty
+    }
+
+    fn methods(&mut self, methods: &[Arc<AST>], of: Symbol) {
+        for method in methods {
+            self.function(method, Some(of));
+        }
+    }
+
+    /// Checks a node and everything in it, returning the type of its value.
+    fn expr(&mut self, ast: &AST) -> Type {
+        match ast {
+            AST::IntegerLiteral(..) => Type::Int,
+            AST::FloatLiteral(..) => Type::Float,
+            AST::StringLiteral(..) => Type::Str,
+            AST::BooleanLiteral(..) => Type::Bool,
+            AST::Nothing(_) => Type::Nothing,
+            AST::List(_, items) => {
+                let items = items.iter().map(|item| self.expr(item)).collect::<Vec<_>>();
+                let item = items.into_iter().reduce(Type::join).unwrap_or(Type::Any);
+                Type::List(Box::new(item))
+            }
+            AST::Dict(_, entries) => {
+                let (mut keys, mut values) = (vec![], vec![]);
+                for (key, value) in entries {
+                    keys.push(self.expr(key));
+                    values.push(self.expr(value));
+                }
+                let key = keys.into_iter().reduce(Type::join).unwrap_or(Type::Any);
+                let value = values.into_iter().reduce(Type::join).unwrap_or(Type::Any);
+                Type::Dict(Box::new(key), Box::new(value))
+            }
+            AST::Tuple(_, items) => {
+                items.iter().for_each(|item| _ = self.expr(item));
+                Type::Tuple
+            }
+            AST::Range(_, start, end, _) => {
+                let (start, end) = (self.expr(start), self.expr(end));
+                match (start, end) {
+                    (Type::Int, Type::Int) => Type::Range,
+                    _ => Type::Any,
+                }
+            }
+            AST::Variable(_, name) | AST::Local(_, name, _) => self.lookup(*name),
+            AST::Block(_, stmts) => {
+                self.scopes.push(Scope::new());
+                self.hoist(stmts);
+                stmts.iter().for_each(|stmt| _ = self.expr(stmt));
+                self.scopes.pop();
+                Type::Any
+            }
+            AST::VarDeclaration(_, name, ty, value) => {
+                let value_ty = self.expr(value);
+                let declared = ty.as_ref().map(|ty| self.resolve(ty));
+                if let Some(declared) = &declared {
+                    if !value_ty.fits(declared) {
+                        let msg = format!(
+                            "`{}` is declared as {}, but is given {}",
+                            name, declared, value_ty
+                        );
+                        self.error(value.loc(), msg);
+                    }
+                }
+                self.declare_variable(*name, declared, value_ty);
+                Type::Nothing
+            }
+            AST::Unpack(_, pattern, value) => {
+                self.expr(value);
+                for name in pattern.bindings() {
+                    self.declare_variable(name, None, Type::Any);
+                }
+                Type::Nothing
+            }
+            AST::Assignment(_, target, value) => {
+                let value_ty = self.expr(value);
+                self.assign(target, value, value_ty);
+                Type::Nothing
+            }
+            AST::Function { name, .. } => {
+                let ty = self.function(ast, None);
+                if let Some(name) = name {
+                    self.declare(*name, fixed(Type::Function(ty.clone())));
+                }
+                Type::Function(ty)
+            }
+            AST::Struct { name, methods, .. } => {
+                self.declare(*name, fixed(Type::Struct(*name)));
+                self.methods(methods, *name);
+                Type::Struct(*name)
+            }
+            AST::Enum { name, methods, .. } => {
+                self.declare(*name, fixed(Type::Enum(*name)));
+                self.methods(methods, *name);
+                Type::Enum(*name)
+            }
+            AST::Import(_, name, _) => {
+                self.declare_variable(*name, None, Type::Any);
+                Type::Nothing
+            }
+            AST::Return(_, value) => {
+                let value_ty = self.expr(value);
+                if let Some((name, returns)) = &self.returns {
+                    if !value_ty.fits(returns) {
+                        let msg = format!(
+                            "{} is declared to return {}, but this is {}",
+                            describe(*name),
+                            returns,
+                            value_ty
+                        );
+                        self.error(value.loc(), msg);
+                    }
+                }
+                Type::Nothing
+            }
+            AST::Call(loc, func, args) => self.call(loc, func, args),
+            AST::NamedArgument(_, _, value) => self.expr(value),
+            AST::Spread(loc, value) => {
+                let ty = self.expr(value);
+                if !matches!(ty, Type::Any | Type::Tuple | Type::List(_)) {
+                    let msg = format!("Can only spread a tuple or list into arguments, not {}", ty);
+                    self.error(loc, msg);
+                }
+                Type::Any
+            }
+            AST::Member(loc, lhs, name) => {
+                let of = self.expr(lhs);
+                self.member(loc, &of, *name)
+            }
+            AST::Plus(loc, a, b) => self.arithmetic(loc, "+", a, b),
+            AST::Minus(loc, a, b) => self.arithmetic(loc, "-", a, b),
+            AST::Multiply(loc, a, b) => self.arithmetic(loc, "*", a, b),
+            AST::Divide(loc, a, b) => self.arithmetic(loc, "/", a, b),
+            AST::Modulo(loc, a, b) => self.arithmetic(loc, "%", a, b),
+            AST::Power(loc, a, b) => self.arithmetic(loc, "**", a, b),
+            AST::BitAnd(loc, a, b) => self.bitwise(loc, "&", a, b),
+            AST::BitOr(loc, a, b) => self.bitwise(loc, "|", a, b),
+            AST::BitXor(loc, a, b) => self.bitwise(loc, "^", a, b),
+            AST::ShiftLeft(loc, a, b) => self.bitwise(loc, "<<", a, b),
+            AST::ShiftRight(loc, a, b) => self.bitwise(loc, ">>", a, b),
+            AST::LessThan(loc, a, b)
+            | AST::GreaterThan(loc, a, b)
+            | AST::LessThanEquals(loc, a, b)
+            | AST::GreaterThanEquals(loc, a, b) => self.comparison(loc, a, b),
+            AST::Equals(_, a, b) | AST::NotEquals(_, a, b) | AST::In(_, a, b) => {
+                let (a, b) = (self.expr(a), self.expr(b));
+                match (a, b) {
+                    (Type::Instance(_), _) | (_, Type::Instance(_)) => Type::Any,
+                    _ => Type::Bool,
+                }
+            }
+            AST::And(_, a, b) | AST::Or(_, a, b) => {
+                let a = self.expr(a);
+                a.join(self.expr(b))
+            }
+            AST::Not(_, value) => {
+                self.expr(value);
+                Type::Bool
+            }
+            AST::Negate(loc, value) => match self.expr(value) {
+                ty @ (Type::Int | Type::Float) => ty,
+                ty if ty.is_known() => {
+                    self.error(loc, format!("Invalid type for negation: {}", ty));
+                    Type::Any
+                }
+                _ => Type::Any,
+            },
+            AST::BitNot(loc, value) => match self.expr(value) {
+                Type::Int => Type::Int,
+                ty if ty.is_known() => {
+                    self.error(loc, format!("Invalid type for `~`: {}", ty));
+                    Type::Any
+                }
+                _ => Type::Any,
+            },
+            AST::Index(_, lhs, index) => {
+                let lhs = self.expr(lhs);
+                self.expr(index);
+                match lhs {
+                    Type::List(item) => *item,
+                    Type::Dict(_, value) => *value,
+                    Type::Str => Type::Str,
+                    Type::Range => Type::Int,
+                    _ => Type::Any,
+                }
+            }
+            AST::Slice {
+                lhs,
+                start,
+                end,
+                step,
+                ..
+            } => {
+                let lhs = self.expr(lhs);
+                for bound in [start, end, step].into_iter().flatten() {
+                    self.expr(bound);
+                }
+                match lhs {
+                    ty @ (Type::Str | Type::List(_) | Type::Tuple) => ty,
+                    _ => Type::Any,
+                }
+            }
+            AST::If(_, cond, body, else_body) => {
+                self.expr(cond);
+                let before = self.scopes.clone();
+                self.expr(body);
+                let after_body = std::mem::replace(&mut self.scopes, before);
+                if let Some(else_body) = else_body {
+                    self.expr(else_body);
+                }
+                self.merge(after_body);
+                Type::Any
+            }
+            AST::While(_, cond, body) => {
+                let assigned = assigned_names(body);
+                self.widen(&assigned);
+                self.expr(cond);
+                self.expr(body);
+                self.widen(&assigned);
+                Type::Nothing
+            }
+            AST::For(_, name, iter, body) => {
+                let item = match self.expr(iter) {
+                    Type::List(item) => *item,
+                    Type::Dict(key, _) => *key,
+                    Type::Str => Type::Str,
+                    Type::Range => Type::Int,
+                    _ => Type::Any,
+                };
+                let assigned = assigned_names(body);
+                self.widen(&assigned);
+                self.scopes.push(Scope::new());
+                self.declare_variable(*name, None, item);
+                self.expr(body);
+                self.scopes.pop();
+                self.widen(&assigned);
+                Type::Nothing
+            }
+            AST::Match(_, subject, arms) => {
+                self.expr(subject);
+                let before = self.scopes.clone();
+                let mut after_arms = vec![];
+                for arm in arms {
+                    self.scopes = before.clone();
+                    self.arm(arm);
+                    after_arms.push(std::mem::take(&mut self.scopes));
+                }
+                self.scopes = before;
+                after_arms.into_iter().for_each(|after| self.merge(after));
+                Type::Any
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                let mut assigned = assigned_names(body);
+                for block in catch.iter().map(|(_, block)| block).chain(finally) {
+                    assigned.extend(assigned_names(block));
+                }
+                self.widen(&assigned);
+                self.expr(body);
+                self.widen(&assigned);
+                if let Some((name, block)) = catch {
+                    self.scopes.push(Scope::new());
+                    self.declare_variable(*name, None, Type::Any);
+                    self.expr(block);
+                    self.scopes.pop();
+                    self.widen(&assigned);
+                }
+                if let Some(finally) = finally {
+                    self.expr(finally);
+                    self.widen(&assigned);
+                }
+                Type::Any
+            }
+            _ => {
+                ast.children()
+                    .into_iter()
+                    .for_each(|child| _ = self.expr(child));
+                Type::Any
+            }
+        }
+    }
+
+    fn arm(&mut self, arm: &MatchArm) {
+        self.scopes.push(Scope::new());
+        for name in arm.bindings() {
+            self.declare_variable(name, None, Type::Any);
+        }
+        // the pattern's literals and constructors are expressions too
+        if !matches!(arm.pattern.as_ref(), AST::Variable(..)) {
+            self.pattern(&arm.pattern);
+        }
+        if let Some(guard) = &arm.guard {
+            self.expr(guard);
+        }
+        self.expr(&arm.body);
+        self.scopes.pop();
+    }
+
+    /// Checks the parts of a pattern that are evaluated.
+    fn pattern(&mut self, pattern: &AST) {
+        match pattern {
+            AST::Variable(..) => {}
+            AST::List(_, items) | AST::Tuple(_, items) => {
+                items.iter().for_each(|item| self.pattern(item));
+            }
+            AST::Call(_, ctor, items) => {
+                self.expr(ctor);
+                items.iter().for_each(|item| self.pattern(item));
+            }
+            pattern => _ = self.expr(pattern),
+        }
+    }
+
+    /// Checks assigning a value of type `value_ty` to `target`.
+    fn assign(&mut self, target: &AST, value: &AST, value_ty: Type) {
+        match target {
+            AST::Variable(_, name) | AST::Local(_, name, _) => {
+                let found = self
+                    .scopes
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find_map(|(depth, scope)| {
+                        scope
+                            .get(name)
+                            .map(|binding| (depth, binding.declared.clone()))
+                    });
+                let Some((depth, declared)) = found else {
+                    return;
+                };
+                if let Some(declared) = &declared {
+                    if !value_ty.fits(declared) {
+                        let msg = format!(
+                            "`{}` is declared as {}, but is assigned {}",
+                            name, declared, value_ty
+                        );
+                        self.error(value.loc(), msg);
+                    }
+                }
+                // the function could be called anywhere, so it can't change
+                // what's known of the variables around it
+                if depth < self.function_start {
+                    return;
+                }
+                let current = self.narrowed(*name, declared.as_ref(), value_ty);
+                let binding = self.scopes[depth].get_mut(name).expect("was found");
+                binding.current = current;
+            }
+            AST::Tuple(_, items) | AST::List(_, items) => {
+                for item in items {
+                    self.assign(item, value, Type::Any);
+                }
+            }
+            AST::Index(_, lhs, index) => {
+                let lhs_ty = self.expr(lhs);
+                let index_ty = self.expr(index);
+                let (key, item) = match &lhs_ty {
+                    Type::List(item) => (Type::Any, item.as_ref()),
+                    Type::Dict(key, value) => (*key.clone(), value.as_ref()),
+                    _ => return,
+                };
+                if !index_ty.fits(&key) {
+                    let msg = format!("Can't use {} as a key of {}", index_ty, lhs_ty);
+                    self.error(index.loc(), msg);
+                } else if !value_ty.fits(item) {
+                    let msg = format!("Can't store {} in {}", value_ty, lhs_ty);
+                    self.error(value.loc(), msg);
+                }
+            }
+            AST::Member(loc, lhs, name) => {
+                let of = self.expr(lhs);
+                self.member(loc, &of, *name);
+            }
+            target => _ = self.expr(target),
+        }
+    }
+
+    fn call(&mut self, loc: &Location, func: &AST, args: &[Arc<AST>]) -> Type {
+        let callee = self.expr(func);
+        let types = args.iter().map(|arg| self.expr(arg)).collect::<Vec<_>>();
+        // how many arguments a spread one passes isn't known
+        if args
+            .iter()
+            .any(|arg| matches!(arg.as_ref(), AST::Spread(..)))
+        {
+            return match callee {
+                Type::Function(func) => func.returns.clone(),
+                Type::Struct(name) => Type::Instance(name),
+                _ => Type::Any,
+            };
+        }
+        match callee {
+            Type::Function(func) => {
+                self.arguments(loc, args, &types, &func);
+                func.returns.clone()
+            }
+            Type::Struct(name) => {
+                let fields = self.structs.get(&name).map(|info| info.fields.len());
+                if let Some(fields) = fields.filter(|fields| args.len() > *fields) {
+                    let msg = format!(
+                        "{} has {} fields, but got {} arguments",
+                        name,
+                        fields,
+                        args.len()
+                    );
+                    self.error(loc, msg);
+                }
+                Type::Instance(name)
+            }
+            callee @ (Type::Int
+            | Type::Float
+            | Type::Str
+            | Type::Bool
+            | Type::Nothing
+            | Type::Range
+            | Type::Tuple
+            | Type::List(_)
+            | Type::Dict(..)) => {
+                self.error(loc, format!("Can't call {}", callee));
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+
+    /// Checks the arguments of a call against those of the function.
+    fn arguments(
+        &mut self,
+        loc: &Location,
+        args: &[Arc<AST>],
+        types: &[Type],
+        func: &FunctionType,
+    ) {
+        let Some(expected) = &func.args else {
+            return;
+        };
+        // named arguments come after the positional ones
+        let named = args
+            .iter()
+            .filter_map(|arg| match arg.as_ref() {
+                AST::NamedArgument(_, name, _) => Some(*name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let positional = args.len() - named.len();
+        // a `...rest` parameter takes the positional arguments left over
+        let (expected, rest) = match expected.split_last() {
+            Some(((_, Type::List(item)), fixed)) if func.variadic => (fixed, Some(item)),
+            _ => (&expected[..], None),
+        };
+        if let Some(item) = rest {
+            for (arg, ty) in args.iter().zip(types).take(positional).skip(expected.len()) {
+                if !ty.fits(item) {
+                    let msg = format!("Expected {} for each extra argument, got {}", item, ty);
+                    self.error(arg.loc(), msg);
+                }
+            }
+        }
+        let params = expected.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        let sources = match_arguments(
+            loc,
+            func.name,
+            &params,
+            func.defaults,
+            match rest {
+                Some(_) => positional.min(params.len()),
+                None => positional,
+            },
+            &named,
+            Error::Type,
+        );
+        let sources = match sources {
+            Ok(sources) => sources,
+            Err(error) => return self.errors.push(error),
+        };
+        for ((name, expected), source) in expected.iter().zip(sources) {
+            let i = match source {
+                Argument::Positional(i) => i,
+                Argument::Named(j) => positional + j,
+                Argument::Default => continue,
+            };
+            if !types[i].fits(expected) {
+                let msg = format!("Expected {} for `{}`, got {}", expected, name, types[i]);
+                self.error(args[i].loc(), msg);
+            }
+        }
+    }
+
+    /// The type of the member `name` of a value of type `of`, reporting
+    /// the members that structs and enums don't have.
+    fn member(&mut self, loc: &Location, of: &Type, name: Symbol) -> Type {
+        match of {
+            Type::Instance(of) if self.structs.contains_key(of) => {
+                let info = &self.structs[of];
+                if info.fields.contains(&name) {
+                    return Type::Any;
+                }
+                let method = info
+                    .methods
+                    .iter()
+                    .find(|method| method.declared_name() == Some(name))
+                    .cloned();
+                if let Some(method) = method {
+                    let ty = self.signature(&method, Some(*of));
+                    // called with the instance first
+                    let args = ty.args.as_ref().map(|args| args[1..].to_vec());
+                    return Type::Function(Rc::new(FunctionType {
+                        args,
+                        ..(*ty).clone()
+                    }));
+                }
+                let names = info.fields.iter().copied();
+                let names = names.chain(info.methods.iter().filter_map(|m| m.declared_name()));
+                let names = names.map(Symbol::as_str).collect::<Vec<_>>();
+                let msg = format!("{} has no field or method `{}`", of, name);
+                let error = Error::Type(loc.clone(), msg);
+                let error = with_suggestions(error, name.as_str(), names.into_iter());
+                self.errors.push(error);
+                Type::Any
+            }
+            Type::Enum(of) if self.enums.contains_key(of) => {
+                let info = &self.enums[of];
+                let variant = info.variants.iter().find(|(variant, _)| *variant == name);
+                match variant {
+                    Some((_, 0)) => return Type::Instance(*of),
+                    Some(_) => return Type::function(None, Type::Instance(*of)),
+                    None if info.methods.contains(&name) => return Type::Any,
+                    None => {}
+                }
+                let names = info.variants.iter().map(|(variant, _)| *variant);
+                let names = names.chain(info.methods.iter().copied());
+                let names = names.map(Symbol::as_str).collect::<Vec<_>>();
+                let msg = format!("{} has no variant or method `{}`", of, name);
+                let error = Error::Type(loc.clone(), msg);
+                let error = with_suggestions(error, name.as_str(), names.into_iter());
+                self.errors.push(error);
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+
+    /// The type of `a symbol b` for an arithmetic operator, following the
+    /// rules of [`crate::value::Value::plus`] and the others.
+    fn arithmetic(&mut self, loc: &Location, symbol: &str, a: &AST, b: &AST) -> Type {
+        let (a, b) = (self.expr(a), self.expr(b));
+        match (symbol, &a, &b) {
+            (_, Type::Int, Type::Int) => Type::Int,
+            (_, Type::Int | Type::Float, Type::Int | Type::Float) => Type::Float,
+            ("+", Type::Str, Type::Str) | ("*", Type::Str, Type::Int) => Type::Str,
+            ("+", Type::List(a), Type::List(b)) => {
+                Type::List(Box::new(a.as_ref().clone().join(b.as_ref().clone())))
+            }
+            ("*", Type::List(item), Type::Int) => Type::List(item.clone()),
+            ("+", Type::Tuple, Type::Tuple) => Type::Tuple,
+            _ if a.is_known() && b.is_known() => {
+                let operation = match symbol {
+                    "+" => "addition",
+                    "-" => "subtraction",
+                    "*" => "multiplication",
+                    "/" => "division",
+                    "%" => "modulo",
+                    _ => "power",
+                };
+                let msg = format!("Invalid types for {}: {} and {}", operation, a, b);
+                self.error(loc, msg);
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn bitwise(&mut self, loc: &Location, symbol: &str, a: &AST, b: &AST) -> Type {
+        match (self.expr(a), self.expr(b)) {
+            (Type::Int, Type::Int) => Type::Int,
+            (a, b) if a.is_known() && b.is_known() => {
+                let msg = format!("Invalid types for `{}`: {} and {}", symbol, a, b);
+                self.error(loc, msg);
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+
+    /// The type of `<`, `>`, `<=` or `>=`, which only order numbers,
+    /// strings, bools, lists and tuples, by [`crate::value::Value::compare`].
+    fn comparison(&mut self, loc: &Location, a: &AST, b: &AST) -> Type {
+        match (self.expr(a), self.expr(b)) {
+            (Type::Int | Type::Float, Type::Int | Type::Float)
+            | (Type::Str, Type::Str)
+            | (Type::Bool, Type::Bool)
+            | (Type::List(_), Type::List(_))
+            | (Type::Tuple, Type::Tuple) => Type::Bool,
+            (a, b) if a.is_known() && b.is_known() => {
+                self.error(loc, format!("Can't compare {} and {}", a, b));
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+}
+
+fn fixed(ty: Type) -> Binding {
+    Binding {
+        declared: None,
+        current: ty,
+        fixed: true,
+    }
+}
+
+/// The names that `ast` itself declares in the scope it runs in.
+fn declared_names(ast: &AST) -> Vec<Symbol> {
+    match ast {
+        AST::VarDeclaration(_, name, ..)
+        | AST::Import(_, name, _)
+        | AST::For(_, name, ..)
+        | AST::Struct { name, .. }
+        | AST::Enum { name, .. } => vec![*name],
+        AST::Function { name, args, .. } => name.iter().chain(args).copied().collect(),
+        AST::Unpack(_, pattern, _) => pattern.bindings(),
+        AST::Match(_, _, arms) => arms.iter().flat_map(MatchArm::bindings).collect(),
+        AST::Try {
+            catch: Some((name, _)),
+            ..
+        } => vec![*name],
+        _ => vec![],
+    }
+}
+
+/// The variables an assignment to `target` assigns.
+fn target_names(target: &AST, names: &mut HashSet<Symbol>) {
+    match target {
+        AST::Variable(_, name) | AST::Local(_, name, _) => _ = names.insert(*name),
+        AST::Tuple(_, items) | AST::List(_, items) => {
+            items.iter().for_each(|item| target_names(item, names));
+        }
+        _ => {}
+    }
+}
+
+/// The variables that `ast` assigns to, outside of the functions in it.
+fn assigned_names(ast: &AST) -> HashSet<Symbol> {
+    fn collect(ast: &AST, names: &mut HashSet<Symbol>) {
+        match ast {
+            AST::Function { .. } | AST::Struct { .. } | AST::Enum { .. } => return,
+            AST::Assignment(_, target, _) => target_names(target, names),
+            _ => {}
+        }
+        ast.children()
+            .into_iter()
+            .for_each(|child| collect(child, names));
+    }
+    let mut names = HashSet::new();
+    collect(ast, &mut names);
+    names
+}
+
+/// The names that a function with `args` and `body` assigns to without
+/// declaring them, which are the variables of the scopes around it.
+fn free_assignments(args: &[Symbol], body: &AST) -> HashSet<Symbol> {
+    fn scan(ast: &AST, assigned: &mut HashSet<Symbol>, declared: &mut HashSet<Symbol>) {
+        match ast {
+            AST::Function {
+                name, args, body, ..
+            } => {
+                declared.extend(name);
+                assigned.extend(free_assignments(args, body));
+                return;
+            }
+            AST::Struct { name, methods, .. } | AST::Enum { name, methods, .. } => {
+                declared.insert(*name);
+                for method in methods {
+                    if let AST::Function { args, body, .. } = method.as_ref() {
+                        assigned.extend(free_assignments(args, body));
+                    }
+                }
+                return;
+            }
+            AST::Assignment(_, target, _) => target_names(target, assigned),
+            _ => {}
+        }
+        declared.extend(declared_names(ast));
+        ast.children()
+            .into_iter()
+            .for_each(|child| scan(child, assigned, declared));
+    }
+    let mut assigned = HashSet::new();
+    let mut declared = args.iter().copied().collect();
+    scan(body, &mut assigned, &mut declared);
+    assigned.retain(|name| !declared.contains(name));
+    assigned
+}
+
+/// Whether running `ast` always ends in a `return` or `throw`, or never
+/// ends, so a function whose body it is never returns `nothing` by
+/// running off its end.
+fn always_returns(ast: &AST) -> bool {
+    match ast {
+        AST::Return(..) | AST::Throw(..) => true,
+        AST::Block(_, stmts) => stmts.iter().any(|stmt| always_returns(stmt)),
+        AST::If(_, _, body, Some(else_body)) => always_returns(body) && always_returns(else_body),
+        AST::Match(_, _, arms) => arms.iter().all(|arm| always_returns(&arm.body)),
+        AST::While(_, cond, _) => matches!(cond.as_ref(), AST::BooleanLiteral(_, true)),
+        AST::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            let caught = catch
+                .as_ref()
+                .is_none_or(|(_, block)| always_returns(block));
+            (always_returns(body) && caught) || finally.as_deref().is_some_and(always_returns)
+        }
+        _ => false,
+    }
+}
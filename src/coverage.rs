@@ -0,0 +1,243 @@
+//! Statement and branch coverage, counted while the interpreter runs and
+//! reported as an lcov tracefile or a standalone HTML page.
+//!
+//! Hits are counted per AST node. A statement's hits are reported on the
+//! line it starts on, and a line with several statements reports the most
+//! any of them ran. `if` and `while` conditions are branches, each with a
+//! count of how often it came out true and how often false.
+
+use crate::ast::AST;
+use crate::interpreter::{node_key, NodeMap};
+use crate::source_map::{FileId, SourceMap};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct Coverage {
+    /// The scripts and modules that ran, whose statements make up the report.
+    roots: Vec<Arc<AST>>,
+    statements: NodeMap<u64>,
+    /// Times each condition was true and false.
+    branches: NodeMap<[u64; 2]>,
+}
+
+/// What ran in a single file.
+struct FileReport {
+    file: FileId,
+    /// Hits of every line that starts a statement.
+    lines: BTreeMap<usize, u64>,
+    /// The line of every condition, with its counts, or `None` if it never
+    /// ran at all.
+    branches: Vec<(usize, Option<[u64; 2]>)>,
+}
+
+impl FileReport {
+    fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|&&hits| hits > 0).count()
+    }
+
+    fn branches_hit(&self) -> usize {
+        self.branches
+            .iter()
+            .flat_map(|(_, counts)| counts.unwrap_or_default())
+            .filter(|&taken| taken > 0)
+            .count()
+    }
+}
+
+impl Coverage {
+    /// Adds a script or module to the report, including what in it never
+    /// runs.
+    pub fn add_root(&mut self, ast: &Arc<AST>) {
+        let key = node_key(ast);
+        if !self.roots.iter().any(|root| node_key(root) == key) {
+            self.roots.push(ast.clone());
+        }
+    }
+
+    pub fn hit_statement(&mut self, stmt: &Arc<AST>) {
+        *self.statements.entry(stmt, || 0) += 1;
+    }
+
+    pub fn hit_branch(&mut self, node: &Arc<AST>, taken: bool) {
+        let counts = self.branches.entry(node, || [0, 0]);
+        counts[usize::from(!taken)] += 1;
+    }
+
+    fn reports(&self) -> Vec<FileReport> {
+        let mut reports: Vec<FileReport> = vec![];
+        for root in &self.roots {
+            let file = root.loc().file;
+            let index = match reports.iter().position(|report| report.file == file) {
+                Some(index) => index,
+                None => {
+                    reports.push(FileReport {
+                        file,
+                        lines: BTreeMap::new(),
+                        branches: vec![],
+                    });
+                    reports.len() - 1
+                }
+            };
+            self.walk(root, &mut reports[index]);
+        }
+        reports
+    }
+
+    fn walk(&self, node: &Arc<AST>, report: &mut FileReport) {
+        match node.as_ref() {
+            AST::Block(_, stmts) => {
+                for stmt in stmts {
+                    let hits = self.statements.get(stmt).copied().unwrap_or(0);
+                    let line = report.lines.entry(stmt.loc().line).or_default();
+                    *line = (*line).max(hits);
+                }
+            }
+            AST::If(loc, ..) | AST::While(loc, ..) => {
+                let counts = self.branches.get(node).copied();
+                report.branches.push((loc.line, counts));
+            }
+            _ => {}
+        }
+        for child in node.children() {
+            self.walk(child, report);
+        }
+    }
+
+    /// The report in lcov's tracefile format, for `genhtml` and coverage
+    /// services.
+    pub fn lcov(&self) -> String {
+        let mut out = String::new();
+        for report in self.reports() {
+            writeln!(out, "TN:").unwrap();
+            writeln!(out, "SF:{}", report.file.name()).unwrap();
+            for (block, (line, counts)) in report.branches.iter().enumerate() {
+                for branch in 0..2 {
+                    match counts {
+                        Some(counts) => {
+                            writeln!(out, "BRDA:{},{},{},{}", line, block, branch, counts[branch])
+                        }
+                        None => writeln!(out, "BRDA:{},{},{},-", line, block, branch),
+                    }
+                    .unwrap();
+                }
+            }
+            writeln!(out, "BRF:{}", report.branches.len() * 2).unwrap();
+            writeln!(out, "BRH:{}", report.branches_hit()).unwrap();
+            for (line, hits) in &report.lines {
+                writeln!(out, "DA:{},{}", line, hits).unwrap();
+            }
+            writeln!(out, "LF:{}", report.lines.len()).unwrap();
+            writeln!(out, "LH:{}", report.lines_hit()).unwrap();
+            writeln!(out, "end_of_record").unwrap();
+        }
+        out
+    }
+
+    /// The report as a single HTML page: a summary, then every file's
+    /// source with the lines that ran and didn't highlighted.
+    pub fn html(&self) -> String {
+        let reports = self.reports();
+        let mut out = String::from(HTML_HEADER);
+        out.push_str("<table>\n<tr><th>File</th><th>Lines</th><th>Branches</th></tr>\n");
+        for (i, report) in reports.iter().enumerate() {
+            writeln!(
+                out,
+                "<tr><td><a href=\"#file{}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+                i,
+                escape(&report.file.name()),
+                ratio(report.lines_hit(), report.lines.len()),
+                ratio(report.branches_hit(), report.branches.len() * 2),
+            )
+            .unwrap();
+        }
+        out.push_str("</table>\n");
+
+        for (i, report) in reports.iter().enumerate() {
+            writeln!(
+                out,
+                "<h2 id=\"file{}\">{}</h2>",
+                i,
+                escape(&report.file.name())
+            )
+            .unwrap();
+            let Some(source) = SourceMap::source(report.file) else {
+                out.push_str("<p>Source not available.</p>\n");
+                continue;
+            };
+            let mut branches = BTreeMap::<usize, Vec<Option<[u64; 2]>>>::new();
+            for (line, counts) in &report.branches {
+                branches.entry(*line).or_default().push(*counts);
+            }
+            out.push_str("<table class=\"source\">\n");
+            for (number, code) in source.lines().enumerate().map(|(i, code)| (i + 1, code)) {
+                let (class, hits) = match report.lines.get(&number) {
+                    Some(0) => ("miss", "0".to_string()),
+                    Some(hits) => ("hit", hits.to_string()),
+                    None => ("", String::new()),
+                };
+                let taken = branches.get(&number).map_or(String::new(), |counts| {
+                    counts
+                        .iter()
+                        .map(|counts| match counts {
+                            Some([yes, no]) => format!("{}/{}", yes, no),
+                            None => "-/-".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                });
+                writeln!(
+                    out,
+                    "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td><pre>{}</pre></td></tr>",
+                    class,
+                    number,
+                    hits,
+                    taken,
+                    escape(code)
+                )
+                .unwrap();
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+const HTML_HEADER: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Coverage</title>
+<style>
+body { font-family: sans-serif; }
+td { padding: 0 0.5em; }
+pre { margin: 0; }
+.source td:nth-child(-n+3) { color: #888; text-align: right; }
+.hit { background: #dfd; }
+.miss { background: #fdd; }
+</style>
+</head>
+<body>
+<h1>Coverage</h1>
+";
+
+fn ratio(hit: usize, found: usize) -> String {
+    match found {
+        0 => "-".to_string(),
+        _ => format!(
+            "{}/{} ({:.1}%)",
+            hit,
+            found,
+            hit as f64 * 100.0 / found as f64
+        ),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,109 @@
+use crate::edition::Edition;
+use crate::token::{FileId, Location};
+use std::rc::Rc;
+
+/// Steps through source text one char at a time, keeping the byte offset
+/// and the line/column location in sync.
+#[derive(Debug)]
+pub struct Cursor {
+    input: Rc<str>,
+    pos: usize,
+    location: Location,
+}
+
+impl Cursor {
+    pub fn new(input: Rc<str>, filename: String) -> Cursor {
+        Cursor::in_file(input, FileId::new(&filename))
+    }
+
+    /// A cursor over `input`, which is the text of `file`.
+    pub fn in_file(input: Rc<str>, file: FileId) -> Cursor {
+        Cursor {
+            input,
+            pos: 0,
+            location: Location {
+                line: 1,
+                column: 1,
+                file,
+                start: 0,
+                end: 0,
+                edition: Edition::default_edition(),
+            },
+        }
+    }
+
+    pub fn cur(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    pub fn peek(&self, offset: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(offset)
+    }
+
+    pub fn starts_with(&self, text: &str) -> bool {
+        self.input[self.pos..].starts_with(text)
+    }
+
+    /// Moves past the current char, returning it.
+    pub fn bump(&mut self) -> Option<char> {
+        let c = self.cur()?;
+        self.pos += c.len_utf8();
+        self.location.start += c.len_utf8();
+        self.location.end = self.location.start;
+        if c == '\n' {
+            self.location.line += 1;
+            self.location.column = 1;
+        } else {
+            self.location.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Jumps ahead to byte offset `pos`, which must lie on a char boundary,
+    /// updating the location for everything skipped over. Combined with
+    /// `str::find`, which searches for single bytes with memchr, this skips
+    /// long comments and strings far faster than stepping char by char.
+    pub fn advance_to(&mut self, pos: usize) {
+        let skipped = &self.input[self.pos..pos];
+        match skipped.rfind('\n') {
+            Some(last_newline) => {
+                self.location.line += skipped.bytes().filter(|&b| b == b'\n').count();
+                self.location.column = skipped[last_newline + 1..].chars().count() + 1;
+            }
+            None => self.location.column += skipped.chars().count(),
+        }
+        self.location.start += pos - self.pos;
+        self.location.end = self.location.start;
+        self.pos = pos;
+    }
+
+    /// Continues in `input`, keeping the location where the previous input
+    /// left off.
+    pub fn refill(&mut self, input: Rc<str>) {
+        self.input = input;
+        self.pos = 0;
+    }
+
+    /// The input from the current char onwards.
+    pub fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    /// Byte offset of the current char.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
+    /// Makes the text from here on follow `edition`.
+    pub fn set_edition(&mut self, edition: Edition) {
+        self.location.edition = edition;
+    }
+
+    pub fn source(&self) -> &Rc<str> {
+        &self.input
+    }
+}
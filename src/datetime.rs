@@ -0,0 +1,133 @@
+//! Converting between Unix timestamps and calendar dates, for the
+//! `format_time` and `parse_time` built-ins. Dates are in UTC, since there's
+//! no time zone database to look other zones up in.
+//!
+//! Patterns are text with `%Y` for the year, `%m`, `%d`, `%H`, `%M` and `%S`
+//! for the two-digit month, day, hour, minute and second, and `%%` for a
+//! percent sign.
+
+pub const DEFAULT_PATTERN: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The days from 1970-01-01 to the given date, which may be before it. The
+/// calendar is the Gregorian one, carried on back before it was adopted.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // years start in March, so that leap days are at the end of them
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day that are `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    days_from_civil(year + month / 12, month % 12 + 1, 1) - days_from_civil(year, month, 1)
+}
+
+/// Takes a number of exactly `digits` digits from the start of `rest`, after
+/// a `-` if it's `signed`.
+fn take_number(rest: &mut &str, digits: usize, signed: bool) -> Option<i64> {
+    let sign = usize::from(signed && rest.starts_with('-'));
+    let end = sign + digits;
+    let part = rest.get(..end)?;
+    if !part[sign..].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    *rest = &rest[end..];
+    part.parse().ok()
+}
+
+/// `timestamp`, in whole seconds since 1970-01-01 UTC, written out as
+/// `pattern` says.
+pub fn format(timestamp: i64, pattern: &str) -> Result<String, String> {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86_400));
+    let seconds = timestamp.rem_euclid(86_400);
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", seconds / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", seconds / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", seconds % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => return Err(format!("Unknown format `%{}`", other)),
+            None => return Err("The pattern ends in a lone `%`".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// The timestamp of `text`, which has to match `pattern` exactly. Parts the
+/// pattern leaves out are those of 1970-01-01 00:00:00.
+pub fn parse(text: &str, pattern: &str) -> Result<i64, String> {
+    let mismatch = || format!("\"{}\" doesn't match the pattern \"{}\"", text, pattern);
+    let mut rest = text;
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        let field = match (c, chars.clone().next()) {
+            ('%', Some(field @ ('Y' | 'm' | 'd' | 'H' | 'M' | 'S'))) => field,
+            ('%', Some('%')) => '%',
+            ('%', Some(other)) => return Err(format!("Unknown format `%{}`", other)),
+            ('%', None) => return Err("The pattern ends in a lone `%`".to_string()),
+            (c, _) => {
+                rest = rest.strip_prefix(c).ok_or_else(mismatch)?;
+                continue;
+            }
+        };
+        chars.next();
+        let value = match field {
+            '%' => {
+                rest = rest.strip_prefix('%').ok_or_else(mismatch)?;
+                continue;
+            }
+            'Y' => take_number(&mut rest, 4, true),
+            _ => take_number(&mut rest, 2, false),
+        };
+        let value = value.ok_or_else(mismatch)?;
+        match field {
+            'Y' => year = value,
+            'm' => month = value,
+            'd' => day = value,
+            'H' => hour = value,
+            'M' => minute = value,
+            _ => second = value,
+        }
+    }
+    if !rest.is_empty() {
+        return Err(mismatch());
+    }
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(format!("\"{}\" isn't a valid date and time", text));
+    }
+    let days = days_from_civil(year, month, day);
+    Ok(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
@@ -0,0 +1,68 @@
+//! Language editions, so that changes which would break existing scripts
+//! only apply to files that opt in to them. A file picks its edition with a
+//! comment such as `// edition: 2026` before any code. Files that don't get
+//! the default edition, which `--edition` and the manifest's `edition` key
+//! set, and which is otherwise the oldest one.
+//!
+//! Edition 2026:
+//! - parameters have to be separated by commas, `def f(a b)` is an error
+//! - `len` counts the characters of a string rather than its bytes
+//! - strings interpolate `{expression}`s, and `{{` and `}}` are literal braces
+//! - `match` is a keyword, so it can't be used as a name
+//! - so are `try`, `catch`, `finally` and `throw`
+//! - and `yield`, which makes a function a generator
+//! - and `struct`, which declares a struct type
+//! - and `enum`, which declares a type with variants
+//! - and `move`, which makes a lambda capture by value, globals included
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    E2024,
+    E2026,
+}
+
+static DEFAULT: AtomicU8 = AtomicU8::new(Edition::E2024 as u8);
+
+impl Edition {
+    pub const ALL: [Edition; 2] = [Edition::E2024, Edition::E2026];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Edition::E2024 => "2024",
+            Edition::E2026 => "2026",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Edition> {
+        Edition::ALL
+            .into_iter()
+            .find(|edition| edition.name() == name)
+    }
+
+    /// The edition of files that don't pick one.
+    pub fn default_edition() -> Edition {
+        Edition::ALL[DEFAULT.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set_default(edition: Edition) {
+        DEFAULT.store(edition as u8, Ordering::Relaxed);
+    }
+
+    /// The edition named by a `// edition: <name>` comment, if that's what
+    /// `comment` is.
+    pub fn pragma(comment: &str) -> Option<&str> {
+        let name = comment
+            .strip_prefix("//")?
+            .trim()
+            .strip_prefix("edition:")?
+            .trim();
+        Some(name)
+    }
+
+    /// The names of all editions, for error messages.
+    pub fn names() -> String {
+        Edition::ALL.map(Edition::name).join(", ")
+    }
+}
@@ -0,0 +1,289 @@
+//! A minimal line editor for the REPL, with tab completion, history and
+//! Ctrl-R to search it. The REPL reads through the [`LineEditor`] trait, so
+//! it can be given another editor instead.
+//!
+//! The terminal is switched out of canonical mode with `stty` for as long as
+//! a line is being read, so that keys arrive one at a time. When stdin isn't
+//! a terminal, or `stty` isn't available, lines are read as they are.
+
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+/// What the REPL offers for the text before the cursor: the byte offset the
+/// completed word starts at, and the candidates for it.
+pub type Completer<'a> = dyn Fn(&str) -> (usize, Vec<String>) + 'a;
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_G: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const CTRL_R: u8 = 0x12;
+const TAB: u8 = b'\t';
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+pub enum Line {
+    Text(String),
+    /// Ctrl-C, which abandons the current input.
+    Interrupted,
+    Eof,
+}
+
+/// Where the REPL gets its input from.
+pub trait LineEditor {
+    /// Reads a line after showing `prompt`. `history` holds the earlier
+    /// lines, oldest first, and `complete` offers completions.
+    fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &[String],
+        complete: &Completer,
+    ) -> io::Result<Line>;
+}
+
+/// The editor of this module, reading from stdin.
+pub struct Terminal;
+
+impl LineEditor for Terminal {
+    fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &[String],
+        complete: &Completer,
+    ) -> io::Result<Line> {
+        read_line(prompt, history, complete)
+    }
+}
+
+/// Keeps the terminal in a key-at-a-time mode until dropped.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Reads a line, with Up and Down stepping through `history`, oldest first.
+pub fn read_line(prompt: &str, history: &[String], complete: &Completer) -> io::Result<Line> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let raw_mode = match io::stdin().is_terminal() {
+        true => RawMode::enable(),
+        false => None,
+    };
+    let Some(_raw_mode) = raw_mode else {
+        let mut line = String::new();
+        return Ok(match io::stdin().lock().read_line(&mut line)? {
+            0 => Line::Eof,
+            _ => Line::Text(line),
+        });
+    };
+
+    let mut line = String::new();
+    // the line being typed is kept while browsing the history
+    let mut draft = String::new();
+    let mut history_index = history.len();
+    let mut pending = vec![];
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut byte = [0];
+    loop {
+        if stdin.read(&mut byte)? == 0 {
+            return Ok(Line::Eof);
+        }
+        match byte[0] {
+            b'\r' | b'\n' => {
+                writeln!(stdout)?;
+                line.push('\n');
+                return Ok(Line::Text(line));
+            }
+            CTRL_C => {
+                writeln!(stdout, "^C")?;
+                return Ok(Line::Interrupted);
+            }
+            CTRL_D if line.is_empty() => {
+                writeln!(stdout)?;
+                return Ok(Line::Eof);
+            }
+            CTRL_R => {
+                let (found, run) = search_history(&mut stdin, &mut stdout, history, &line)?;
+                line = found;
+                history_index = history.len();
+                write!(stdout, "\r\x1b[K{}{}", prompt, line)?;
+                if run {
+                    writeln!(stdout)?;
+                    line.push('\n');
+                    return Ok(Line::Text(line));
+                }
+            }
+            BACKSPACE | DELETE => {
+                if line.pop().is_some() {
+                    write!(stdout, "\x08 \x08")?;
+                }
+            }
+            TAB => {
+                let (start, candidates) = complete(&line);
+                let word = &line[start..];
+                let common = common_prefix(&candidates);
+                if common.len() > word.len() {
+                    let rest = common[word.len()..].to_string();
+                    write!(stdout, "{}", rest)?;
+                    line.push_str(&rest);
+                } else if candidates.len() > 1 {
+                    writeln!(stdout)?;
+                    writeln!(stdout, "{}", candidates.join("  "))?;
+                    write!(stdout, "{}{}", prompt, line)?;
+                }
+            }
+            ESCAPE => {
+                let key = read_escape_sequence(&mut stdin)?;
+                let index = match key {
+                    b'A' if history_index > 0 => history_index - 1,
+                    b'B' if history_index < history.len() => history_index + 1,
+                    // everything else, like Left and Right, isn't supported
+                    _ => continue,
+                };
+                if history_index == history.len() {
+                    draft = line.clone();
+                }
+                history_index = index;
+                line = match history.get(index) {
+                    Some(entry) => entry.clone(),
+                    None => draft.clone(),
+                };
+                write!(stdout, "\r\x1b[K{}{}", prompt, line)?;
+            }
+            byte if byte < 0x20 => {}
+            byte => {
+                pending.push(byte);
+                if let Ok(text) = std::str::from_utf8(&pending) {
+                    write!(stdout, "{}", text)?;
+                    line.push_str(text);
+                    pending.clear();
+                }
+            }
+        }
+        stdout.flush()?;
+    }
+}
+
+/// Ctrl-R's search of the history for the newest entry containing what's
+/// typed, where Ctrl-R again finds the next older one. Returns the entry
+/// found, or `line` if the search was cancelled with Ctrl-C or Ctrl-G, and
+/// whether Enter was pressed to run it. Any other key ends the search to
+/// edit the entry.
+fn search_history(
+    stdin: &mut impl Read,
+    stdout: &mut impl Write,
+    history: &[String],
+    line: &str,
+) -> io::Result<(String, bool)> {
+    let mut query = String::new();
+    let mut found = None;
+    let mut pending = vec![];
+    let mut byte = [0];
+    loop {
+        let shown = found.map_or(line, |i: usize| history[i].as_str());
+        write!(stdout, "\r\x1b[K(reverse-i-search)`{}': {}", query, shown)?;
+        stdout.flush()?;
+        if stdin.read(&mut byte)? == 0 {
+            return Ok((shown.to_string(), false));
+        }
+        match byte[0] {
+            b'\r' | b'\n' => return Ok((shown.to_string(), true)),
+            CTRL_C | CTRL_G => return Ok((line.to_string(), false)),
+            CTRL_R => {
+                let before = found.unwrap_or(history.len());
+                found = find_in_history(history, &query, before).or(found);
+            }
+            BACKSPACE | DELETE => {
+                query.pop();
+                found = find_in_history(history, &query, history.len());
+            }
+            ESCAPE => {
+                read_escape_sequence(stdin)?;
+                return Ok((shown.to_string(), false));
+            }
+            byte if byte < 0x20 => return Ok((shown.to_string(), false)),
+            byte => {
+                pending.push(byte);
+                if let Ok(text) = std::str::from_utf8(&pending) {
+                    query.push_str(text);
+                    pending.clear();
+                    // the entry found may still contain the longer query
+                    let before = found.map_or(history.len(), |i| i + 1);
+                    found = find_in_history(history, &query, before);
+                }
+            }
+        }
+    }
+}
+
+/// The index of the newest entry before `before` that contains `query`.
+fn find_in_history(history: &[String], query: &str, before: usize) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    history[..before]
+        .iter()
+        .rposition(|entry| entry.contains(query))
+}
+
+/// Reads the rest of an escape sequence such as `\x1b[A`, returning its
+/// final byte.
+fn read_escape_sequence(stdin: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    stdin.read_exact(&mut byte)?;
+    if byte[0] != b'[' {
+        return Ok(byte[0]);
+    }
+    loop {
+        stdin.read_exact(&mut byte)?;
+        if (0x40..=0x7e).contains(&byte[0]) {
+            return Ok(byte[0]);
+        }
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> &str {
+    let Some((first, rest)) = candidates.split_first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for candidate in rest {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    &first[..len]
+}
@@ -1,17 +1,296 @@
+use crate::lexer::UNTERMINATED_BLOCK_COMMENT;
+use crate::source_map::SourceMap;
 use crate::token::Location;
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::io::IsTerminal;
+use std::rc::Rc;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     Lexer(Location, String),
     Parser(Location, String),
     UnexpectedEOF(Location, String),
     Runtime(Location, String),
+    Manifest(Location, String),
+    /// A mistake the type checker found before the script ran.
+    Type(Location, String),
+    /// A `throw` that no `catch` caught, with the repr of the value thrown.
+    Thrown(Location, String),
+    /// The host's progress callback asked for the script to stop.
+    Cancelled(Location),
+    /// Ctrl-C was pressed while the script ran.
+    Interrupted(Location),
+    /// The script used up the fuel or time the host gave it. Scripts can't
+    /// catch it, so they can't keep running past their budget.
+    ExecutionLimitExceeded(Location, String),
     Other(String),
+    /// An error that propagated out of function calls, with the calls it
+    /// went through, innermost first.
+    Traced(Box<Error>, Vec<Frame>),
+    /// An error with a hint on how to fix it.
+    Help(Box<Error>, String),
+}
+
+/// A function call that an error propagated out of.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The function called, `None` for lambdas.
+    pub function: Option<String>,
+    pub call: Location,
+}
+
+impl Error {
+    pub fn loc(&self) -> Option<&Location> {
+        match self {
+            Error::Lexer(loc, _)
+            | Error::Parser(loc, _)
+            | Error::UnexpectedEOF(loc, _)
+            | Error::Runtime(loc, _)
+            | Error::Manifest(loc, _)
+            | Error::Type(loc, _)
+            | Error::Thrown(loc, _)
+            | Error::Cancelled(loc)
+            | Error::Interrupted(loc)
+            | Error::ExecutionLimitExceeded(loc, _) => Some(loc),
+            Error::Other(_) => None,
+            Error::Traced(error, _) | Error::Help(error, _) => error.loc(),
+        }
+    }
+
+    /// Adds a hint on how to fix the error, shown after it.
+    pub fn with_help(self, help: impl Into<String>) -> Error {
+        Error::Help(Box::new(self), help.into())
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        match self {
+            Error::Help(_, help) => Some(help),
+            Error::Traced(error, _) => error.help(),
+            _ => None,
+        }
+    }
+
+    /// The error as shown to a person: the message, the line it points at
+    /// with its span underlined, and the help if it has any. With `color`,
+    /// which is for terminals, the kind of error, the underline and the help
+    /// are highlighted.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |text: &str, style: &str| match color {
+            true => format!("\x1b[{}m{}\x1b[0m", style, text),
+            false => text.to_string(),
+        };
+        let snippet = |loc: &Location| {
+            let snippet = snippet(loc)?;
+            let (code, carets) = snippet.trim_end().rsplit_once('\n')?;
+            Some(format!("{}\n{}\n", code, paint(carets, RED)))
+        };
+        let mut out = String::new();
+        if let Error::Traced(error, frames) = self {
+            out.push_str("Traceback, most recent call last:\n");
+            for (loc, function, repeats) in traceback(error, frames) {
+                out.push_str(&format!("  {} in {}\n", loc, function));
+                out.extend(snippet(loc));
+                out.extend(repeated(repeats).map(|line| line + "\n"));
+            }
+        }
+        let bold_red = format!("1;{}", RED);
+        out.push_str(&self.headline(|kind| paint(kind, &bold_red)));
+        out.push('\n');
+        // tracebacks already quote every line they go through
+        if !matches!(self, Error::Traced(..)) {
+            out.extend(self.loc().and_then(snippet));
+        }
+        if let Some(help) = self.help() {
+            out.push_str(&format!("{}: {}\n", paint("help", "1;36"), help));
+        }
+        out
+    }
+
+    /// The error's one line message, without a traceback or help, with the
+    /// kind of error passed through `kind`.
+    fn headline(&self, kind: impl Fn(&str) -> String) -> String {
+        let (name, message) = match self {
+            Error::Lexer(_, msg) => ("Lexer error", Some(msg)),
+            Error::Parser(_, msg) | Error::UnexpectedEOF(_, msg) => ("Parser error", Some(msg)),
+            Error::Runtime(_, msg) => ("Runtime error", Some(msg)),
+            Error::Manifest(_, msg) => ("Manifest error", Some(msg)),
+            Error::Type(_, msg) => ("Type error", Some(msg)),
+            Error::Thrown(_, repr) => ("Uncaught exception", Some(repr)),
+            Error::Cancelled(_) => ("Cancelled", None),
+            Error::Interrupted(_) => ("KeyboardInterrupt", None),
+            Error::ExecutionLimitExceeded(_, msg) => ("Execution limit exceeded", Some(msg)),
+            Error::Other(msg) => ("Error", Some(msg)),
+            Error::Traced(error, _) | Error::Help(error, _) => return error.headline(kind),
+        };
+        let mut headline = match self.loc() {
+            Some(loc) => format!("{}: {}", loc, kind(name)),
+            None => kind(name),
+        };
+        if let Some(message) = message {
+            headline.push_str(": ");
+            headline.push_str(message);
+        }
+        headline
+    }
+
+    /// Whether the input ended too soon, so that more of it might fix the
+    /// error, as the REPL asks for.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            Error::UnexpectedEOF(..) => true,
+            Error::Lexer(_, msg) => msg == UNTERMINATED_BLOCK_COMMENT,
+            Error::Help(error, _) => error.is_incomplete(),
+            _ => false,
+        }
+    }
+
+    /// Records that the error propagated out of a call.
+    pub fn with_frame(self, frame: Frame) -> Error {
+        match self {
+            Error::Traced(error, mut frames) => {
+                frames.push(frame);
+                Error::Traced(error, frames)
+            }
+            error => Error::Traced(Box::new(error), vec![frame]),
+        }
+    }
+}
+
+/// The line `loc` points at with carets under the text it spans, as far as
+/// the end of the line, indented for showing under a message.
+pub(crate) fn snippet(loc: &Location) -> Option<String> {
+    let line = SourceMap::line(loc.file, loc.line)?;
+    let code = line.trim_start().trim_end();
+    let indent = line.len() - line.trim_start().len();
+    let indent = line[..indent].chars().count();
+    let caret = loc.column.saturating_sub(1 + indent);
+    let mut width = 0;
+    let mut bytes = 0;
+    for c in code.chars().skip(caret) {
+        if bytes >= loc.end - loc.start {
+            break;
+        }
+        bytes += c.len_utf8();
+        width += 1;
+    }
+    Some(format!(
+        "    {}\n    {}{}\n",
+        code,
+        " ".repeat(caret),
+        "^".repeat(width.max(1))
+    ))
+}
+
+/// Whether errors written to `stream` should be in color: when it's a
+/// terminal, unless `NO_COLOR` is set.
+pub fn use_color(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// The escape code of the color errors are highlighted with.
+const RED: &str = "31";
+
+/// The places a traced error went through, outermost first, with the
+/// function each one is in and how many times it's repeated right after,
+/// as it is in a recursion.
+fn traceback<'a>(error: &'a Error, frames: &'a [Frame]) -> Vec<(&'a Location, &'a str, usize)> {
+    let callers = frames
+        .iter()
+        .skip(1)
+        .map(|frame| frame.function.as_deref().unwrap_or("<lambda>"))
+        .chain(["<module>"]);
+    let calls = frames.iter().map(|frame| &frame.call);
+    let mut places = calls.zip(callers).collect::<Vec<_>>();
+    places.reverse();
+    let innermost = frames[0].function.as_deref().unwrap_or("<lambda>");
+    places.extend(error.loc().map(|loc| (loc, innermost)));
+    let mut entries: Vec<(&Location, &str, usize)> = vec![];
+    for (loc, function) in places {
+        match entries.last_mut() {
+            Some((last, caller, repeats)) if *last == loc && *caller == function => *repeats += 1,
+            _ => entries.push((loc, function, 0)),
+        }
+    }
+    entries
+}
+
+/// The traceback line standing for `repeats` more of the entry above it.
+fn repeated(repeats: usize) -> Option<String> {
+    match repeats {
+        0 => None,
+        1 => Some("  [the line above is repeated once more]".to_string()),
+        _ => Some(format!(
+            "  [the line above is repeated {} more times]",
+            repeats
+        )),
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Traced(error, frames) => {
+                writeln!(f, "Traceback, most recent call last:")?;
+                for (loc, function, repeats) in traceback(error, frames) {
+                    writeln!(f, "  {} in {}", loc, function)?;
+                    write!(f, "{}", snippet(loc).unwrap_or_default())?;
+                    if let Some(line) = repeated(repeats) {
+                        writeln!(f, "{}", line)?;
+                    }
+                }
+                write!(f, "{}", error)
+            }
+            Error::Help(error, help) => write!(f, "{}\nhelp: {}", error, help),
+            error => write!(f, "{}", error.headline(str::to_string)),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Where the lexer and parser record the errors they recover from, so that
+/// one pass reports every syntax problem instead of only the first. Clones
+/// share the same list.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics(Rc<RefCell<Vec<Error>>>);
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&self, error: Error) {
+        self.0.borrow_mut().push(error);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    /// The error that comes first in the source.
+    pub fn first(&self) -> Option<Error> {
+        let errors = self.0.borrow();
+        errors
+            .iter()
+            .min_by_key(|error| error.loc().map(|loc| loc.start))
+            .cloned()
+    }
+
+    /// The errors recorded so far, in the order they appear in the source,
+    /// leaving none behind.
+    pub fn take(&self) -> Vec<Error> {
+        let mut errors = std::mem::take(&mut *self.0.borrow_mut());
+        errors.sort_by_key(|error| error.loc().map(|loc| loc.start));
+        errors
+    }
+}
+
 macro_rules! lexer_error {
     ($loc:expr, $($arg:tt)*) => {
         return Err(crate::error::Error::Lexer($loc.clone(), format!($($arg)*)))
@@ -40,38 +319,31 @@ macro_rules! runtime_error {
 }
 pub(crate) use runtime_error;
 
-// TODO: refactor/remove
-/*
-macro_rules! _error {
-    ($loc:expr, $($arg:tt)*) => {
-        {
-            let msg = format!($($arg)*);
-            let filename = &$loc.filename;
-            let file_content = std::fs::read_to_string(filename).expect("couldn't open input file");
-            let lines = file_content.lines().collect::<Vec<&str>>();
-            let context = 3;
-            let min_line = if $loc.line <= context {
-                1
-            } else {
-                $loc.line - context - 1
-            };
-            let max_line = lines.len().min($loc.line + context);
-
-            println!("╭───────────────────────────────────────────────────────────────");
-            println!("│ {}: Error: {}", $loc.clone(), msg);
-            println!("├────┬──────────────────────────────────────────────────────────");
-
-            for line_no in min_line..max_line {
-                let line = lines[line_no];
-                println!("│{:>3} │ {}", line_no, line);
-                if line_no == $loc.line - 1 {
-                    println!("│    ├─{}┘ \x1b[0;31m{}\x1b[0m", "─".repeat($loc.column - 1), msg);
-                }
-            }
+/// Returns the candidates that are within a small edit distance of `name`,
+/// closest first, for "did you mean" style hints.
+pub fn close_matches<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut matches = candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
 
-            println!("╰────┴──────────────────────────────────────────────────────────");
-            panic!();
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            cur.push(substitute.min(prev[j + 1] + 1).min(cur[j] + 1));
         }
+        prev = cur;
     }
+    prev[b.len()]
 }
-*/
@@ -0,0 +1,282 @@
+//! Collects the reference cycles that reference counting can't free. A
+//! function holds on to the scope of its captures, which shares cells with
+//! the scope it was declared in, and a cell can hold the function itself,
+//! as a scope does a struct and so the struct's methods. Lists, dicts and
+//! instances in those scopes can hold each other too.
+//!
+//! Collecting starts from the scopes functions were declared in and the
+//! scopes of their captures, and finds every scope, cell, list, dict,
+//! tuple, struct, enum and instance reachable from them. The references
+//! they hold to each other are subtracted from their reference counts, and
+//! whatever has references left is held from somewhere else, such as the
+//! interpreter or a native frame, so it's kept along with everything it
+//! reaches. The rest is only held by itself, and is emptied, which lets
+//! reference counting free it. Values that hide what they hold, like
+//! iterators, count as references from somewhere else, so what they hold
+//! is never collected early, only sometimes late.
+
+use crate::interpreter::{Binding, Ref, Scope};
+use crate::value::{Dict, EnumType, StructType, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// How many functions are declared before the first collection.
+const FIRST_COLLECTION: usize = 1000;
+
+/// The scopes functions were declared in since they were last collected,
+/// which are where cycles start.
+pub struct Collector {
+    candidates: Vec<Weak<RefCell<Scope>>>,
+    /// How many candidates wait for a collection, which grows with what the
+    /// last collection kept so that collecting stays a small part of the
+    /// running time.
+    threshold: usize,
+}
+
+impl Default for Collector {
+    fn default() -> Collector {
+        Collector {
+            candidates: vec![],
+            threshold: FIRST_COLLECTION,
+        }
+    }
+}
+
+impl Collector {
+    /// Notes that a function or struct was declared in `scope`, returning
+    /// whether it's time to collect.
+    pub fn declared_in(&mut self, scope: &Ref<Scope>) -> bool {
+        let duplicate = self
+            .candidates
+            .last()
+            .is_some_and(|last| last.as_ptr() == Rc::as_ptr(scope));
+        if !duplicate {
+            self.candidates.push(Rc::downgrade(scope));
+        }
+        self.candidates.len() >= self.threshold
+    }
+
+    /// Frees the cycles reachable from the candidates, returning how many
+    /// values were emptied.
+    pub fn collect(&mut self) -> usize {
+        self.candidates.retain(|scope| scope.strong_count() > 0);
+        let mut graph = Graph::default();
+        for scope in &self.candidates {
+            if let Some(scope) = scope.upgrade() {
+                graph.add(Node::Scope(Rc::downgrade(&scope)));
+            }
+        }
+        graph.explore();
+        let garbage = graph.garbage();
+        garbage.iter().for_each(Node::empty);
+        self.candidates.retain(|scope| scope.strong_count() > 0);
+        // collecting takes about as long as the garbage and what was kept
+        let kept = graph.nodes.len() - garbage.len();
+        self.threshold = kept.max(FIRST_COLLECTION);
+        garbage.len()
+    }
+}
+
+/// A value that holds other values.
+#[derive(Clone)]
+enum Node {
+    Scope(Weak<RefCell<Scope>>),
+    /// A variable that a scope shares with the functions capturing it.
+    Cell(Weak<RefCell<Option<Value>>>),
+    List(Weak<RefCell<Vec<Value>>>),
+    Dict(Weak<RefCell<Dict>>),
+    Tuple(Weak<[Value]>),
+    /// A struct, which holds its methods.
+    Struct(Weak<StructType>),
+    /// An enum, which holds its variants.
+    Enum(Weak<EnumType>),
+    /// The fields of an instance, and its struct.
+    Instance(Weak<StructType>, Weak<RefCell<Vec<Value>>>),
+}
+
+impl Node {
+    fn of(value: &Value) -> Option<Node> {
+        Some(match value {
+            Value::List(list) => Node::List(Rc::downgrade(list)),
+            Value::Dict(dict) => Node::Dict(Rc::downgrade(dict)),
+            Value::Tuple(items) => Node::Tuple(Rc::downgrade(items)),
+            Value::Function { scope, .. } => Node::Scope(Rc::downgrade(scope)),
+            Value::Struct(of) => Node::Struct(Rc::downgrade(of)),
+            Value::Enum(of) => Node::Enum(Rc::downgrade(of)),
+            Value::Instance(of, fields) => Node::Instance(Rc::downgrade(of), Rc::downgrade(fields)),
+            _ => return None,
+        })
+    }
+
+    fn key(&self) -> usize {
+        match self {
+            Node::Scope(scope) => scope.as_ptr() as *const () as usize,
+            Node::Cell(cell) => cell.as_ptr() as *const () as usize,
+            Node::List(list) => list.as_ptr() as *const () as usize,
+            Node::Dict(dict) => dict.as_ptr() as *const () as usize,
+            Node::Tuple(items) => items.as_ptr() as *const () as usize,
+            Node::Struct(of) => of.as_ptr() as *const () as usize,
+            Node::Enum(of) => of.as_ptr() as *const () as usize,
+            Node::Instance(_, fields) => fields.as_ptr() as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Scope(scope) => scope.strong_count(),
+            Node::Cell(cell) => cell.strong_count(),
+            Node::List(list) => list.strong_count(),
+            Node::Dict(dict) => dict.strong_count(),
+            Node::Tuple(items) => items.strong_count(),
+            Node::Struct(of) => of.strong_count(),
+            Node::Enum(of) => of.strong_count(),
+            Node::Instance(_, fields) => fields.strong_count(),
+        }
+    }
+
+    /// The nodes this one holds, once for each reference, or `None` if it
+    /// is being changed and can't be looked into.
+    fn children(&self) -> Option<Vec<Node>> {
+        let mut children = vec![];
+        match self {
+            Node::Scope(scope) => {
+                let scope = scope.upgrade()?;
+                let scope = scope.try_borrow().ok()?;
+                if let Some(parent) = &scope.parent {
+                    children.push(Node::Scope(Rc::downgrade(parent)));
+                }
+                children.extend(scope.bindings().filter_map(|binding| match binding {
+                    Binding::Value(value) => Node::of(value),
+                    Binding::Shared(cell) => Some(Node::Cell(Rc::downgrade(cell))),
+                }));
+            }
+            Node::Cell(cell) => {
+                let cell = cell.upgrade()?;
+                let value = cell.try_borrow().ok()?;
+                children.extend(value.as_ref().and_then(Node::of));
+            }
+            Node::List(list) => {
+                let list = list.upgrade()?;
+                let list = list.try_borrow().ok()?;
+                children.extend(list.iter().filter_map(Node::of));
+            }
+            Node::Dict(dict) => {
+                let dict = dict.upgrade()?;
+                let dict = dict.try_borrow().ok()?;
+                let entries = dict.entries().flat_map(|(key, value)| [key, value]);
+                children.extend(entries.filter_map(Node::of));
+            }
+            Node::Tuple(items) => {
+                let items = items.upgrade()?;
+                children.extend(items.iter().filter_map(Node::of));
+            }
+            Node::Struct(of) => {
+                let of = of.upgrade()?;
+                let methods = of.methods.try_borrow().ok()?;
+                children.extend(methods.values().filter_map(Node::of));
+            }
+            Node::Enum(of) => {
+                let of = of.upgrade()?;
+                let variants = of.variants.iter().map(Rc::downgrade);
+                children.extend(variants.map(Node::Struct));
+            }
+            Node::Instance(of, fields) => {
+                let fields = fields.upgrade()?;
+                let fields = fields.try_borrow().ok()?;
+                children.push(Node::Struct(of.clone()));
+                children.extend(fields.iter().filter_map(Node::of));
+            }
+        }
+        Some(children)
+    }
+
+    /// Drops what the node holds once it's no longer borrowed, since that
+    /// may drop other nodes.
+    fn empty(&self) {
+        match self {
+            Node::Scope(scope) => drop(scope.upgrade().and_then(take)),
+            Node::Cell(cell) => drop(cell.upgrade().and_then(take)),
+            Node::List(list) => drop(list.upgrade().and_then(take)),
+            Node::Dict(dict) => drop(dict.upgrade().and_then(take)),
+            Node::Instance(_, fields) => drop(fields.upgrade().and_then(take)),
+            Node::Struct(of) => {
+                let of = of.upgrade();
+                let methods = of.as_ref().and_then(|of| of.methods.try_borrow_mut().ok());
+                drop(methods.map(|mut methods| std::mem::take(&mut *methods)))
+            }
+            // tuples and enums can't be changed, so they are freed once
+            // what they are in is emptied
+            Node::Tuple(_) | Node::Enum(_) => {}
+        }
+    }
+}
+
+fn take<T: Default>(cell: Rc<RefCell<T>>) -> Option<T> {
+    let mut contents = cell.try_borrow_mut().ok()?;
+    Some(std::mem::take(&mut *contents))
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    index: HashMap<usize, usize>,
+    /// The nodes each node holds, or `None` for a node that couldn't be
+    /// looked into.
+    edges: Vec<Option<Vec<usize>>>,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        let key = node.key();
+        if let Some(&i) = self.index.get(&key) {
+            return i;
+        }
+        self.index.insert(key, self.nodes.len());
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Adds everything the nodes reach.
+    fn explore(&mut self) {
+        let mut next = 0;
+        while next < self.nodes.len() {
+            let children = self.nodes[next].children();
+            let edges = children.map(|children| {
+                children
+                    .into_iter()
+                    .map(|child| self.add(child))
+                    .collect::<Vec<_>>()
+            });
+            self.edges.push(edges);
+            next += 1;
+        }
+    }
+
+    /// The nodes that nothing outside the graph reaches.
+    fn garbage(&self) -> Vec<Node> {
+        let mut outside = self
+            .nodes
+            .iter()
+            .map(|node| node.strong_count() as isize)
+            .collect::<Vec<_>>();
+        for edges in self.edges.iter().flatten() {
+            for &child in edges {
+                outside[child] -= 1;
+            }
+        }
+        let mut kept = vec![false; self.nodes.len()];
+        let mut reached = (0..self.nodes.len())
+            // a node that couldn't be looked into may hold anything
+            .filter(|&i| outside[i] > 0 || self.edges[i].is_none())
+            .collect::<Vec<_>>();
+        while let Some(i) = reached.pop() {
+            if std::mem::replace(&mut kept[i], true) {
+                continue;
+            }
+            reached.extend(self.edges[i].iter().flatten());
+        }
+        let garbage = self.nodes.iter().zip(kept).filter(|(_, kept)| !kept);
+        garbage.map(|(node, _)| node.clone()).collect()
+    }
+}
@@ -0,0 +1,738 @@
+//! Random well-formed programs, for differential testing and fuzzing.
+//!
+//! [`Generator`] builds ASTs straight from the grammar and [`unparse`] turns
+//! them back into source, so a program can be checked to come back unchanged
+//! from the lexer and parser ([`check_round_trip`]) and to run without
+//! panicking ([`check_runs`]). Generated programs only call functions defined
+//! before them, so they never recurse, but their loops may run for a long
+//! time.
+
+use crate::ast::{Signature, AST};
+use crate::edition::Edition;
+use crate::interpreter::{Interpreter, Progress};
+use crate::lexer::{escape, Lexer};
+use crate::parser::Parser;
+use crate::symbol::Symbol;
+use crate::token::{FileId, Location};
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// How deeply statements and expressions nest.
+const MAX_DEPTH: usize = 4;
+
+pub struct Generator {
+    state: u64,
+    loc: Location,
+    /// The variables at each level of nesting, innermost last.
+    variables: Vec<Vec<Symbol>>,
+    /// The functions at each level of nesting, with their arity.
+    functions: Vec<Vec<(Symbol, usize)>>,
+    depth: usize,
+    in_loop: bool,
+    in_function: bool,
+    names: usize,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator {
+            // xorshift gets stuck on zero
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            loc: Location {
+                line: 1,
+                column: 1,
+                file: FileId::new("<generated>"),
+                start: 0,
+                end: 0,
+                edition: Edition::default_edition(),
+            },
+            variables: vec![],
+            functions: vec![],
+            depth: 0,
+            in_loop: false,
+            in_function: false,
+            names: 0,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> Option<T> {
+        match items.len() {
+            0 => None,
+            len => Some(items[self.below(len)].clone()),
+        }
+    }
+
+    /// Names are numbered, so they never clash with each other or keywords.
+    fn name(&mut self, prefix: &str) -> Symbol {
+        self.names += 1;
+        Symbol::intern(&format!("{}{}", prefix, self.names))
+    }
+
+    fn node(&self, make: impl FnOnce(Location) -> AST) -> Arc<AST> {
+        Arc::new(make(self.loc.clone()))
+    }
+
+    /// A whole program.
+    pub fn program(&mut self) -> Arc<AST> {
+        self.variables = vec![vec![]];
+        self.functions = vec![vec![]];
+        let stmts = (0..1 + self.below(8)).map(|_| self.statement()).collect();
+        self.node(|loc| AST::Block(loc, stmts))
+    }
+
+    /// A block, with `variables` declared in it up front.
+    fn block(&mut self, variables: Vec<Symbol>) -> Arc<AST> {
+        self.variables.push(variables);
+        self.functions.push(vec![]);
+        self.depth += 1;
+        let stmts = (0..self.below(4)).map(|_| self.statement()).collect();
+        self.depth -= 1;
+        self.functions.pop();
+        self.variables.pop();
+        self.node(|loc| AST::Block(loc, stmts))
+    }
+
+    fn statement(&mut self) -> Arc<AST> {
+        let nested = self.depth < MAX_DEPTH;
+        loop {
+            return match self.below(10) {
+                0 | 1 => {
+                    let value = self.expression(MAX_DEPTH);
+                    let name = self.name("v");
+                    self.variables.last_mut().unwrap().push(name);
+                    self.node(|loc| AST::VarDeclaration(loc, name, None, value))
+                }
+                2 => {
+                    let Some(name) = self.pick(&self.variables.concat()) else {
+                        continue;
+                    };
+                    let target = self.node(|loc| AST::Variable(loc, name));
+                    let value = self.expression(MAX_DEPTH);
+                    self.node(|loc| AST::Assignment(loc, target, value))
+                }
+                3 if nested => {
+                    let cond = self.expression(2);
+                    let body = self.block(vec![]);
+                    let else_body = match self.below(3) {
+                        0 => None,
+                        1 => Some(self.block(vec![])),
+                        _ => Some(self.statement_if()),
+                    };
+                    self.node(|loc| AST::If(loc, cond, body, else_body))
+                }
+                4 if nested => {
+                    let cond = self.expression(2);
+                    let body = self.loop_body(vec![]);
+                    self.node(|loc| AST::While(loc, cond, body))
+                }
+                5 if nested => {
+                    let start = self.literal_integer();
+                    let end = self.literal_integer();
+                    let range = self.node(|loc| AST::Range(loc, start, end, false));
+                    let name = self.name("i");
+                    let body = self.loop_body(vec![name]);
+                    self.node(|loc| AST::For(loc, name, range, body))
+                }
+                6 if nested => self.function(),
+                7 if self.in_loop => match self.below(2) {
+                    0 => self.node(AST::Break),
+                    _ => self.node(AST::Continue),
+                },
+                8 if self.in_function => {
+                    let value = self.expression(MAX_DEPTH);
+                    self.node(|loc| AST::Return(loc, value))
+                }
+                9 => self.expression(MAX_DEPTH),
+                _ => continue,
+            };
+        }
+    }
+
+    /// An `if` to follow an `else`.
+    fn statement_if(&mut self) -> Arc<AST> {
+        let cond = self.expression(2);
+        let body = self.block(vec![]);
+        self.node(|loc| AST::If(loc, cond, body, None))
+    }
+
+    fn loop_body(&mut self, variables: Vec<Symbol>) -> Arc<AST> {
+        let in_loop = std::mem::replace(&mut self.in_loop, true);
+        let body = self.block(variables);
+        self.in_loop = in_loop;
+        body
+    }
+
+    fn function(&mut self) -> Arc<AST> {
+        let name = self.name("f");
+        let args = (0..self.below(3))
+            .map(|_| self.name("a"))
+            .collect::<Vec<_>>();
+        let in_loop = std::mem::replace(&mut self.in_loop, false);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        let body = match self.chance(20) {
+            true => {
+                self.variables.push(args.clone());
+                let value = self.expression(MAX_DEPTH);
+                self.variables.pop();
+                self.node(|loc| AST::Return(loc, value))
+            }
+            false => self.block(args.clone()),
+        };
+        self.in_function = in_function;
+        self.in_loop = in_loop;
+        // only declared after its body, so that it can't call itself
+        self.functions.last_mut().unwrap().push((name, args.len()));
+        self.node(|loc| AST::Function {
+            loc,
+            name: Some(name),
+            args,
+            variadic: false,
+            defaults: vec![],
+            signature: None,
+            captures: vec![],
+            moves: false,
+            body,
+        })
+    }
+
+    fn literal_integer(&mut self) -> Arc<AST> {
+        let value = self.below(20) as i64;
+        self.node(|loc| AST::IntegerLiteral(loc, value))
+    }
+
+    fn leaf(&mut self) -> Arc<AST> {
+        loop {
+            return match self.below(7) {
+                0 => self.literal_integer(),
+                1 => {
+                    let value = self.below(40) as f64 / 4.0;
+                    self.node(|loc| AST::FloatLiteral(loc, value))
+                }
+                2 => {
+                    let text = (0..self.below(6))
+                        .map(|_| b"abc xyz"[self.below(7)] as char)
+                        .collect::<String>();
+                    self.node(|loc| AST::StringLiteral(loc, text.into()))
+                }
+                3 => {
+                    let value = self.chance(50);
+                    self.node(|loc| AST::BooleanLiteral(loc, value))
+                }
+                4 => self.node(AST::Nothing),
+                _ => {
+                    let Some(name) = self.pick(&self.variables.concat()) else {
+                        continue;
+                    };
+                    self.node(|loc| AST::Variable(loc, name))
+                }
+            };
+        }
+    }
+
+    fn expression(&mut self, depth: usize) -> Arc<AST> {
+        if depth == 0 || self.chance(30) {
+            return self.leaf();
+        }
+        let depth = depth - 1;
+        loop {
+            return match self.below(10) {
+                0 | 1 => {
+                    let left = self.expression(depth);
+                    let right = self.expression(depth);
+                    let make = [
+                        AST::Plus,
+                        AST::Minus,
+                        AST::Multiply,
+                        AST::Divide,
+                        AST::Modulo,
+                        AST::Power,
+                        AST::BitAnd,
+                        AST::BitOr,
+                        AST::BitXor,
+                        AST::ShiftLeft,
+                        AST::ShiftRight,
+                        AST::And,
+                        AST::Or,
+                        AST::Equals,
+                        AST::NotEquals,
+                        AST::LessThan,
+                        AST::GreaterThan,
+                        AST::LessThanEquals,
+                        AST::GreaterThanEquals,
+                        AST::In,
+                    ][self.below(20)];
+                    self.node(|loc| make(loc, left, right))
+                }
+                2 => {
+                    let value = self.expression(depth);
+                    let make = [AST::Not, AST::Negate, AST::BitNot][self.below(3)];
+                    self.node(|loc| make(loc, value))
+                }
+                3 => {
+                    let Some((name, arity)) = self.pick(&self.functions.concat()) else {
+                        continue;
+                    };
+                    let func = self.node(|loc| AST::Variable(loc, name));
+                    let args = (0..arity).map(|_| self.expression(depth)).collect();
+                    self.node(|loc| AST::Call(loc, func, args))
+                }
+                4 => {
+                    let func = self.node(|loc| AST::Variable(loc, Symbol::intern("len")));
+                    let args = vec![self.expression(depth)];
+                    self.node(|loc| AST::Call(loc, func, args))
+                }
+                5 => {
+                    let lhs = self.expression(depth);
+                    let index = self.expression(depth);
+                    self.node(|loc| AST::Index(loc, lhs, index))
+                }
+                6 => {
+                    let lhs = self.expression(depth);
+                    let mut part = || match self.chance(50) {
+                        true => Some(self.literal_integer()),
+                        false => None,
+                    };
+                    let (start, end, step) = (part(), part(), part());
+                    self.node(|loc| AST::Slice {
+                        loc,
+                        lhs,
+                        start,
+                        end,
+                        step,
+                    })
+                }
+                7 => {
+                    let items = (0..self.below(4)).map(|_| self.expression(depth)).collect();
+                    self.node(|loc| AST::List(loc, items))
+                }
+                8 => {
+                    let entries = (0..self.below(3))
+                        .map(|_| (self.leaf(), self.expression(depth)))
+                        .collect();
+                    self.node(|loc| AST::Dict(loc, entries))
+                }
+                _ => {
+                    let args = (0..self.below(3))
+                        .map(|_| self.name("a"))
+                        .collect::<Vec<_>>();
+                    self.variables.push(args.clone());
+                    let value = self.expression(depth);
+                    self.variables.pop();
+                    let body = self.node(|loc| AST::Return(loc, value));
+                    let lambda = self.node(|loc| AST::Function {
+                        loc,
+                        name: None,
+                        args,
+                        variadic: false,
+                        defaults: vec![],
+                        signature: None,
+                        captures: vec![],
+                        moves: false,
+                        body,
+                    });
+                    if !self.chance(50) {
+                        return lambda;
+                    }
+                    let arity = match lambda.as_ref() {
+                        AST::Function { args, .. } => args.len(),
+                        _ => unreachable!(),
+                    };
+                    let args = (0..arity).map(|_| self.expression(depth)).collect();
+                    self.node(|loc| AST::Call(loc, lambda, args))
+                }
+            };
+        }
+    }
+}
+
+/// Source code that parses back into `ast`. Every compound expression is
+/// parenthesized and every statement that needs a line end gets a `;`, so
+/// the output doesn't depend on precedence or on where lines break.
+pub fn unparse(ast: &AST) -> String {
+    let mut out = String::new();
+    match ast {
+        AST::Block(_, stmts) => {
+            for stmt in stmts {
+                statement(&mut out, stmt, 0);
+            }
+        }
+        _ => statement(&mut out, ast, 0),
+    }
+    out
+}
+
+fn block(out: &mut String, ast: &AST, indent: usize) {
+    out.push_str("{\n");
+    if let AST::Block(_, stmts) = ast {
+        for stmt in stmts {
+            statement(out, stmt, indent + 1);
+        }
+    }
+    out.push_str(&"    ".repeat(indent));
+    out.push('}');
+}
+
+/// A function's body after its parameters, either `=> value` or a block.
+fn function_body(out: &mut String, body: &AST, indent: usize) {
+    match body {
+        AST::Return(_, value) => out.push_str(&format!("=> {}", expression(value))),
+        AST::Generator(_, body) => block(out, body, indent),
+        _ => block(out, body, indent),
+    }
+}
+
+/// The decorators and function of `name = deco(other(def name ...))`, as
+/// the parser desugars decorated definitions.
+fn decorated(name: Symbol, value: &AST) -> Option<(Vec<&AST>, &AST)> {
+    let mut decorators = vec![];
+    let mut value = value;
+    while let AST::Call(_, deco, args) = value {
+        let [arg] = args.as_slice() else {
+            return None;
+        };
+        decorators.push(deco.as_ref());
+        value = arg;
+    }
+    match value {
+        AST::Function {
+            name: Some(func), ..
+        } if *func == name && !decorators.is_empty() => Some((decorators, value)),
+        _ => None,
+    }
+}
+
+fn statement(out: &mut String, ast: &AST, indent: usize) {
+    out.push_str(&"    ".repeat(indent));
+    match ast {
+        AST::VarDeclaration(_, name, None, value) => {
+            out.push_str(&format!("let {} = {};", name, expression(value)))
+        }
+        AST::VarDeclaration(_, name, Some(ty), value) => {
+            out.push_str(&format!("let {}: {} = {};", name, ty, expression(value)))
+        }
+        AST::Unpack(_, pattern, value) => out.push_str(&format!(
+            "let {} = {};",
+            expression(pattern),
+            expression(value)
+        )),
+        AST::Assignment(_, target, value) => match (target.as_ref(), value) {
+            (AST::Variable(_, name) | AST::Local(_, name, _), value)
+                if decorated(*name, value).is_some() =>
+            {
+                let (decorators, func) = decorated(*name, value).unwrap();
+                for deco in decorators {
+                    out.push_str(&format!("@{};\n", expression(deco)));
+                    out.push_str(&"    ".repeat(indent));
+                }
+                statement(out, func, indent);
+                return;
+            }
+            _ => out.push_str(&format!("{} = {};", expression(target), expression(value))),
+        },
+        AST::If(_, cond, body, else_body) => {
+            out.push_str(&format!("if {} ", expression(cond)));
+            block(out, body, indent);
+            match else_body.as_deref() {
+                Some(else_if @ AST::If(..)) => {
+                    out.push_str(" else ");
+                    let mut rest = String::new();
+                    statement(&mut rest, else_if, indent);
+                    out.push_str(rest.trim_start());
+                    return;
+                }
+                Some(else_body) => {
+                    out.push_str(" else ");
+                    block(out, else_body, indent);
+                }
+                None => {}
+            }
+        }
+        AST::While(_, cond, body) => {
+            out.push_str(&format!("while {} ", expression(cond)));
+            block(out, body, indent);
+        }
+        AST::For(_, name, iter, body) => {
+            out.push_str(&format!("for {} in {} ", name, expression(iter)));
+            block(out, body, indent);
+        }
+        AST::Function {
+            name,
+            args,
+            variadic,
+            defaults,
+            signature,
+            body,
+            ..
+        } => {
+            let name = name.map_or("_", Symbol::as_str);
+            let signature = signature.as_deref();
+            let defaults = defaults.iter().map(|value| expression(value));
+            let defaults = defaults.collect::<Vec<_>>();
+            out.push_str(&format!(
+                "def {}({}){} ",
+                name,
+                Signature::parameters(signature, args, *variadic, &defaults, false),
+                Signature::return_annotation(signature)
+            ));
+            function_body(out, body, indent);
+            out.push(';');
+        }
+        AST::Pub(_, decl) => {
+            out.push_str("pub ");
+            let mut rest = String::new();
+            statement(&mut rest, decl, indent);
+            out.push_str(rest.trim_start());
+            return;
+        }
+        AST::Import(_, name, _) => out.push_str(&format!("import {};", name)),
+        AST::Return(_, value) => out.push_str(&format!("return {};", expression(value))),
+        AST::Yield(_, value) => out.push_str(&format!("yield {};", expression(value))),
+        AST::Assert(_, cond) => out.push_str(&format!("assert {};", expression(cond))),
+        AST::Continue(_) => out.push_str("continue;"),
+        AST::Break(_) => out.push_str("break;"),
+        _ => out.push_str(&format!("{};", expression(ast))),
+    }
+    out.push('\n');
+}
+
+/// Whether `ast` prints as a single token or a parenthesized expression,
+/// which the parser accepts anywhere an operand is expected.
+fn is_atom(ast: &AST) -> bool {
+    !matches!(
+        ast,
+        AST::Call(..) | AST::Index(..) | AST::Member(..) | AST::Slice { .. }
+    )
+}
+
+fn expression(ast: &AST) -> String {
+    let binary = |op: &str, left: &AST, right: &AST| {
+        format!("({} {} {})", expression(left), op, expression(right))
+    };
+    match ast {
+        AST::IntegerLiteral(_, value) => value.to_string(),
+        AST::FloatLiteral(_, value) => format!("{:?}", value),
+        AST::StringLiteral(_, text) => format!("\"{}\"", escape(text)),
+        AST::BooleanLiteral(_, value) => value.to_string(),
+        AST::Nothing(_) => "nothing".to_string(),
+        AST::Variable(_, name) | AST::Local(_, name, _) => name.to_string(),
+        AST::Plus(_, left, right) => binary("+", left, right),
+        AST::Minus(_, left, right) => binary("-", left, right),
+        AST::Multiply(_, left, right) => binary("*", left, right),
+        AST::Divide(_, left, right) => binary("/", left, right),
+        AST::Modulo(_, left, right) => binary("%", left, right),
+        AST::Power(_, left, right) => binary("**", left, right),
+        AST::BitAnd(_, left, right) => binary("&", left, right),
+        AST::BitOr(_, left, right) => binary("|", left, right),
+        AST::BitXor(_, left, right) => binary("^", left, right),
+        AST::ShiftLeft(_, left, right) => binary("<<", left, right),
+        AST::ShiftRight(_, left, right) => binary(">>", left, right),
+        AST::And(_, left, right) => binary("and", left, right),
+        AST::Or(_, left, right) => binary("or", left, right),
+        AST::Equals(_, left, right) => binary("==", left, right),
+        AST::NotEquals(_, left, right) => binary("!=", left, right),
+        AST::LessThan(_, left, right) => binary("<", left, right),
+        AST::GreaterThan(_, left, right) => binary(">", left, right),
+        AST::LessThanEquals(_, left, right) => binary("<=", left, right),
+        AST::GreaterThanEquals(_, left, right) => binary(">=", left, right),
+        AST::In(_, left, right) => binary("in", left, right),
+        AST::Assignment(_, left, right) => binary("=", left, right),
+        AST::Not(_, value) => format!("(not {})", expression(value)),
+        AST::Negate(_, value) => format!("(-{})", expression(value)),
+        AST::BitNot(_, value) => format!("(~{})", expression(value)),
+        AST::NamedArgument(_, name, value) => format!("{}: {}", name, expression(value)),
+        AST::Spread(_, value) => format!("...{}", expression(value)),
+        AST::Call(_, func, args) => {
+            let args = args.iter().map(|arg| expression(arg)).collect::<Vec<_>>();
+            format!("{}({})", expression(func), args.join(", "))
+        }
+        AST::List(_, items) => {
+            let items = items
+                .iter()
+                .map(|item| expression(item))
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        AST::Tuple(_, items) => {
+            let items = items
+                .iter()
+                .map(|item| expression(item))
+                .collect::<Vec<_>>();
+            match items.len() {
+                1 => format!("({},)", items[0]),
+                _ => format!("({})", items.join(", ")),
+            }
+        }
+        AST::Dict(_, entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", expression(key), expression(value)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", entries.join(", "))
+        }
+        AST::Index(_, lhs, index) => format!("{}[{}]", expression(lhs), expression(index)),
+        AST::Member(_, lhs, name) => format!("{}.{}", expression(lhs), name),
+        AST::Slice {
+            lhs,
+            start,
+            end,
+            step,
+            ..
+        } => {
+            let part = |part: &Option<Arc<AST>>| part.as_deref().map_or(String::new(), expression);
+            let mut slice = format!("{}[{}:{}", expression(lhs), part(start), part(end));
+            if step.is_some() {
+                slice.push_str(&format!(":{}", part(step)));
+            }
+            slice + "]"
+        }
+        AST::Range(_, start, end, inclusive) => {
+            let dots = if *inclusive { "..=" } else { ".." };
+            match is_atom(end) {
+                true => format!("({}{}{})", expression(start), dots, expression(end)),
+                false => format!("({}{}({}))", expression(start), dots, expression(end)),
+            }
+        }
+        AST::Function {
+            args,
+            variadic,
+            signature,
+            moves,
+            body,
+            ..
+        } => {
+            let signature = signature.as_deref();
+            let mut lambda = format!(
+                "({}|{}|{} ",
+                if *moves { "move " } else { "" },
+                Signature::parameters(signature, args, *variadic, &[], true),
+                Signature::return_annotation(signature)
+            );
+            function_body(&mut lambda, body, 0);
+            lambda + ")"
+        }
+        _ => {
+            // not an expression, so this won't parse back
+            let mut text = String::new();
+            statement(&mut text, ast, 0);
+            text.trim_end().to_string()
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Arc<AST>, String> {
+    let parse =
+        || Parser::from_lexer(Lexer::new(source.to_string(), "<generated>".to_string())).parse();
+    match catch_unwind(AssertUnwindSafe(parse)) {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(panic) => Err(format!("Parser panicked: {}", panic_message(&panic))),
+    }
+}
+
+fn panic_message(panic: &Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "<no message>".to_string(),
+        },
+    }
+}
+
+/// Checks that `ast` comes back the same from unparsing and parsing it.
+pub fn check_round_trip(ast: &AST) -> Result<(), String> {
+    let source = unparse(ast);
+    let reparsed = parse(&source).map_err(|err| format!("{}\n{}", err, source))?;
+    let again = unparse(&reparsed);
+    match again == source {
+        true => Ok(()),
+        false => Err(format!(
+            "Parsed differently:\n{}\nbecame\n{}",
+            source, again
+        )),
+    }
+}
+
+/// Runs `ast` for up to `max_steps` steps, walking the AST, as bytecode and
+/// with `-O`, which must all print the same and fail the same way. Runtime errors
+/// are expected from random programs, but panics aren't.
+pub fn check_runs(ast: &Arc<AST>, max_steps: u64) -> Result<(), String> {
+    let walked = run_captured(ast, max_steps, false, false)?;
+    let compiled = run_captured(ast, max_steps, true, false)?;
+    let optimized = run_captured(ast, max_steps, false, true)?;
+    for (name, other) in [("The VM", compiled), ("-O", optimized)] {
+        if let (Some(walked), Some(other)) = (&walked, other) {
+            if *walked != other {
+                return Err(format!(
+                    "{} ran differently:\n{}\nbecame\n{}",
+                    name, walked, other
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What running `ast` printed and how it failed, or `None` if it ran out of
+/// steps, which the backends count differently.
+fn run_captured(
+    ast: &Arc<AST>,
+    max_steps: u64,
+    vm: bool,
+    optimize: bool,
+) -> Result<Option<String>, String> {
+    let run = || {
+        let output = Captured::default();
+        let cancelled = Rc::new(Cell::new(false));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_vm(vm);
+        interpreter.set_optimize(optimize);
+        interpreter.set_stdout(output.clone());
+        interpreter.set_stderr(output.clone());
+        let flag = cancelled.clone();
+        interpreter.set_progress_callback(max_steps, move |_| {
+            flag.set(true);
+            Progress::Cancel
+        });
+        let result = interpreter.execute(ast);
+        drop(interpreter);
+        let mut output = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+        if let Err(err) = result {
+            output += &err.to_string();
+        }
+        (!cancelled.get()).then_some(output)
+    };
+    catch_unwind(AssertUnwindSafe(run))
+        .map_err(|panic| format!("Interpreter panicked: {}", panic_message(&panic)))
+}
@@ -0,0 +1,237 @@
+//! Editor syntax definitions, generated from the lexer's own tables so that
+//! highlighting can't drift from what the lexer actually accepts.
+
+use crate::interpreter::Interpreter;
+use crate::lexer::OPERATORS;
+use crate::token::{TokenKind, KEYWORDS};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Category {
+    Conditional,
+    Repeat,
+    Statement,
+    Operator,
+    Boolean,
+    Constant,
+}
+
+const CATEGORIES: [Category; 6] = [
+    Category::Conditional,
+    Category::Repeat,
+    Category::Statement,
+    Category::Operator,
+    Category::Boolean,
+    Category::Constant,
+];
+
+impl Category {
+    fn of(kind: &TokenKind) -> Category {
+        match kind {
+            TokenKind::If | TokenKind::Else | TokenKind::Match => Category::Conditional,
+            TokenKind::While
+            | TokenKind::For
+            | TokenKind::In
+            | TokenKind::Break
+            | TokenKind::Continue => Category::Repeat,
+            TokenKind::And | TokenKind::Or | TokenKind::Not => Category::Operator,
+            TokenKind::True | TokenKind::False => Category::Boolean,
+            TokenKind::Nothing => Category::Constant,
+            _ => Category::Statement,
+        }
+    }
+
+    fn textmate_scope(self) -> &'static str {
+        match self {
+            Category::Conditional | Category::Repeat => "keyword.control.rattlesnake",
+            Category::Statement => "keyword.other.rattlesnake",
+            Category::Operator => "keyword.operator.logical.rattlesnake",
+            Category::Boolean | Category::Constant => "constant.language.rattlesnake",
+        }
+    }
+
+    fn vim_group(self) -> &'static str {
+        match self {
+            Category::Conditional => "Conditional",
+            Category::Repeat => "Repeat",
+            Category::Statement => "Statement",
+            Category::Operator => "Operator",
+            Category::Boolean => "Boolean",
+            Category::Constant => "Constant",
+        }
+    }
+}
+
+fn keywords(category: Category) -> Vec<&'static str> {
+    KEYWORDS
+        .iter()
+        .filter(|(_, kind)| Category::of(kind) == category)
+        .map(|(keyword, _)| *keyword)
+        .collect()
+}
+
+fn builtins() -> Vec<&'static str> {
+    let mut names = Interpreter::new().builtin_names().collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// Brackets and separators are punctuation, everything else an operator.
+fn is_punctuation(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LeftParen
+            | TokenKind::RightParen
+            | TokenKind::LeftBracket
+            | TokenKind::RightBracket
+            | TokenKind::LeftBrace
+            | TokenKind::RightBrace
+            | TokenKind::Comma
+            | TokenKind::Colon
+            | TokenKind::SemiColon
+            | TokenKind::At
+    )
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn json_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn textmate_rule(scope: &str, regex: &str) -> String {
+    format!(
+        "    {{ \"name\": {}, \"match\": {} }}",
+        json_string(scope),
+        json_string(regex)
+    )
+}
+
+/// A TextMate grammar, as used by VS Code and Sublime Text.
+pub fn textmate() -> String {
+    let mut rules = vec![
+        textmate_rule("comment.line.double-slash.rattlesnake", "//.*$"),
+        "    { \"include\": \"#block-comment\" }".to_string(),
+        textmate_rule(
+            "string.quoted.double.rattlesnake",
+            "\"(?:[^\"\\\\\\n]|\\\\.)*\"?",
+        ),
+        format!(
+            "    {{ \"match\": {}, \"captures\": {{ \"1\": {{ \"name\": \"keyword.other.rattlesnake\" }}, \"2\": {{ \"name\": \"entity.name.function.rattlesnake\" }} }} }}",
+            json_string("\\b(def)\\s+([A-Za-z_][A-Za-z0-9_]*)")
+        ),
+        format!(
+            "    {{ \"match\": {}, \"captures\": {{ \"1\": {{ \"name\": \"keyword.other.rattlesnake\" }}, \"2\": {{ \"name\": \"entity.name.type.rattlesnake\" }} }} }}",
+            json_string("\\b(struct|enum)\\s+([A-Za-z_][A-Za-z0-9_]*)")
+        ),
+        textmate_rule(
+            "entity.name.function.decorator.rattlesnake",
+            "@[A-Za-z_][A-Za-z0-9_]*",
+        ),
+        textmate_rule(
+            "constant.numeric.rattlesnake",
+            "\\b(0b[01_]+|0o[0-7_]+|0x[0-9A-Fa-f_]+|[0-9][0-9_]*(\\.[0-9_]+)?)\\b",
+        ),
+    ];
+    for category in CATEGORIES {
+        rules.push(textmate_rule(
+            category.textmate_scope(),
+            &format!("\\b({})\\b", keywords(category).join("|")),
+        ));
+    }
+    rules.push(textmate_rule(
+        "support.function.builtin.rattlesnake",
+        &format!("\\b({})\\b", builtins().join("|")),
+    ));
+    let (punctuation, operators): (Vec<_>, Vec<_>) =
+        OPERATORS.iter().partition(|(_, kind)| is_punctuation(kind));
+    let alternatives = |ops: Vec<&(&str, TokenKind)>| {
+        ops.iter()
+            .map(|(op, _)| regex_escape(op))
+            .collect::<Vec<_>>()
+            .join("|")
+    };
+    rules.push(textmate_rule(
+        "keyword.operator.rattlesnake",
+        &alternatives(operators),
+    ));
+    rules.push(textmate_rule(
+        "punctuation.rattlesnake",
+        &alternatives(punctuation),
+    ));
+
+    // block comments nest, so their rule includes itself
+    let block_comment = format!(
+        "    \"block-comment\": {{ \"name\": \"comment.block.rattlesnake\", \"begin\": {}, \"end\": {}, \"patterns\": [{{ \"include\": \"#block-comment\" }}] }}",
+        json_string("/\\*"),
+        json_string("\\*/")
+    );
+    format!(
+        "{{\n  \"name\": \"Rattlesnake\",\n  \"scopeName\": \"source.rattlesnake\",\n  \"fileTypes\": [\"rat\"],\n  \"patterns\": [\n{}\n  ],\n  \"repository\": {{\n{}\n  }}\n}}\n",
+        rules.join(",\n"),
+        block_comment
+    )
+}
+
+/// A Vim syntax file.
+pub fn vim() -> String {
+    let mut lines = vec![
+        "\" Vim syntax file for rattlesnake, generated by `rattlesnake --emit=grammar`".to_string(),
+        "if exists(\"b:current_syntax\")".to_string(),
+        "  finish".to_string(),
+        "endif".to_string(),
+        String::new(),
+    ];
+    for category in CATEGORIES {
+        lines.push(format!(
+            "syn keyword rattlesnake{} {}",
+            category.vim_group(),
+            keywords(category).join(" ")
+        ));
+    }
+    lines.push(format!(
+        "syn keyword rattlesnakeBuiltin {}",
+        builtins().join(" ")
+    ));
+    lines.extend(
+        [
+            "syn match rattlesnakeComment \"//.*$\"",
+            "syn region rattlesnakeComment start=\"/\\*\" end=\"\\*/\" contains=rattlesnakeComment",
+            "syn region rattlesnakeString start=+\"+ skip=+\\\\.+ end=+\"+ oneline",
+            "syn match rattlesnakeNumber \"\\<\\d[0-9_]*\\%(\\.[0-9_]\\+\\)\\?\\>\"",
+            "syn match rattlesnakeNumber \"\\<0b[01_]\\+\\>\"",
+            "syn match rattlesnakeNumber \"\\<0o[0-7_]\\+\\>\"",
+            "syn match rattlesnakeNumber \"\\<0x[0-9A-Fa-f_]\\+\\>\"",
+            "syn match rattlesnakeDecorator \"@\\h\\w*\"",
+            "",
+        ]
+        .map(String::from),
+    );
+    for category in CATEGORIES {
+        lines.push(format!(
+            "hi def link rattlesnake{0} {0}",
+            category.vim_group()
+        ));
+    }
+    lines.extend(
+        [
+            "hi def link rattlesnakeBuiltin Function",
+            "hi def link rattlesnakeComment Comment",
+            "hi def link rattlesnakeString String",
+            "hi def link rattlesnakeNumber Number",
+            "hi def link rattlesnakeDecorator PreProc",
+            "",
+            "let b:current_syntax = \"rattlesnake\"",
+        ]
+        .map(String::from),
+    );
+    lines.join("\n") + "\n"
+}
@@ -1,23 +1,145 @@
-use crate::ast::AST;
+use crate::ast::{Capture, MatchArm, Slot, Variant, AST};
 use crate::builtin;
-use crate::error::{runtime_error as error, Result};
+use crate::bytecode::{Chunk, Op};
+use crate::coverage::Coverage;
+use crate::error::{close_matches, runtime_error as error, Error, Frame, Result};
+use crate::gc::Collector;
+use crate::iterator::{Generator, GeneratorFrame};
+use crate::lexer::Lexer;
+use crate::manifest::Manifest;
+use crate::math;
+use crate::memory;
+use crate::methods::{self, MethodTable};
+use crate::optimizer;
+use crate::parser::Parser;
+use crate::project::{resolve_import, Project};
+use crate::random::Rng;
+use crate::signal;
+use crate::symbol::Symbol;
 use crate::token::Location;
-use crate::value::{IteratorValue, Value};
+use crate::value::{
+    Dict, EnumType, FromArgs, HashKey, HostFunction, IntoValue, IteratorValue, Module, Overflow,
+    RangeValue, Resource, ResourceHandle, StructType, Value,
+};
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Scope {
-    pub vars: HashMap<String, Value>,
+    /// The variables in the order they were declared, which is the order
+    /// the resolver numbers their slots in.
+    slots: Vec<(Symbol, Binding)>,
+    /// Where each variable is in `slots`.
+    names: HashMap<Symbol, usize>,
+    /// The cells of variables that functions captured before this scope
+    /// declared them, which they are put in once it does.
+    pending: HashMap<Symbol, Ref<Option<Value>>>,
     pub parent: Option<Ref<Scope>>,
     pub in_function: bool,
 }
 
+/// What a scope holds for a variable: its value, or the cell it shares
+/// with the functions that capture it, which is empty until the variable
+/// is declared.
+#[derive(Debug, Clone)]
+pub(crate) enum Binding {
+    Value(Value),
+    Shared(Ref<Option<Value>>),
+}
+
+impl Binding {
+    fn get(&self) -> Option<Value> {
+        match self {
+            Binding::Value(value) => Some(value.clone()),
+            Binding::Shared(cell) => cell.borrow().clone(),
+        }
+    }
+
+    fn set(&mut self, value: Value) {
+        match self {
+            Binding::Value(old) => *old = value,
+            Binding::Shared(cell) => *cell.borrow_mut() = Some(value),
+        }
+    }
+}
+
 impl Scope {
-    fn insert(&mut self, name: String, value: Value, update: bool, loc: &Location) -> Result<()> {
-        if !update || self.vars.contains_key(&name) {
-            self.vars.insert(name, value);
+    pub fn new(parent: Option<Ref<Scope>>, in_function: bool) -> Scope {
+        Scope {
+            parent,
+            in_function,
+            ..Scope::default()
+        }
+    }
+
+    /// A scope nested in `parent`, which is in a function if `parent` is.
+    pub fn child(parent: &Ref<Scope>) -> Scope {
+        Scope::new(Some(parent.clone()), parent.borrow().in_function)
+    }
+
+    /// Declares `name` in this scope, or replaces its value if it's already
+    /// declared here.
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        match self.names.get(&name) {
+            Some(&index) => self.slots[index].1.set(value),
+            None => {
+                let binding = match self.pending.remove(&name) {
+                    Some(cell) => {
+                        *cell.borrow_mut() = Some(value);
+                        Binding::Shared(cell)
+                    }
+                    None => Binding::Value(value),
+                };
+                self.names.insert(name, self.slots.len());
+                self.slots.push((name, binding));
+            }
+        }
+    }
+
+    /// Declares `name` in this scope as a variable shared through `cell`.
+    fn define_shared(&mut self, name: Symbol, cell: Ref<Option<Value>>) {
+        self.names.insert(name, self.slots.len());
+        self.slots.push((name, Binding::Shared(cell)));
+    }
+
+    /// The cell that `name` in `slot` is kept in from now on, so that a
+    /// function capturing it shares it with this scope. If the scope hasn't
+    /// declared it yet, the cell is empty until it does.
+    fn share(&mut self, slot: Slot, name: Symbol) -> Ref<Option<Value>> {
+        if slot.depth > 0 {
+            if let Some(parent) = &self.parent {
+                let slot = Slot {
+                    depth: slot.depth - 1,
+                    ..slot
+                };
+                return parent.borrow_mut().share(slot, name);
+            }
+        }
+        let Some(&index) = self.names.get(&name) else {
+            let cell = self.pending.entry(name).or_default();
+            return cell.clone();
+        };
+        let binding = &mut self.slots[index].1;
+        if let Binding::Value(value) = binding {
+            let value = std::mem::replace(value, Value::Nothing);
+            *binding = Binding::Shared(Rc::new(RefCell::new(Some(value))));
+        }
+        match binding {
+            Binding::Shared(cell) => cell.clone(),
+            Binding::Value(_) => unreachable!("the binding was just shared"),
+        }
+    }
+
+    fn insert(&mut self, name: Symbol, value: Value, update: bool, loc: &Location) -> Result<()> {
+        if !update || self.contains(name) {
+            self.define(name, value);
         } else {
             match &self.parent {
                 Some(parent) => parent.borrow_mut().insert(name, value, update, loc)?,
@@ -27,31 +149,303 @@ impl Scope {
         Ok(())
     }
 
-    fn get(&self, name: &str) -> Option<Value> {
-        if self.vars.contains_key(name) {
-            self.vars.get(name).cloned()
-        } else {
-            match &self.parent {
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        match self.local(name) {
+            Some(value) => Some(value.clone()),
+            None => match &self.parent {
                 Some(parent) => parent.borrow_mut().get(name),
                 None => None,
-            }
+            },
+        }
+    }
+
+    /// The value of `name` if it's declared in this scope itself.
+    pub fn local(&self, name: Symbol) -> Option<Value> {
+        let &index = self.names.get(&name)?;
+        self.slots[index].1.get()
+    }
+
+    pub fn contains(&self, name: Symbol) -> bool {
+        self.names.contains_key(&name)
+    }
+
+    /// The variables declared in this scope itself, in the order they were
+    /// declared.
+    pub fn vars(&self) -> impl Iterator<Item = (Symbol, Value)> + '_ {
+        let vars = self.slots.iter();
+        vars.filter_map(|(name, binding)| Some((*name, binding.get()?)))
+    }
+
+    /// What the scope holds for each of its variables.
+    pub(crate) fn bindings(&self) -> impl Iterator<Item = &Binding> {
+        self.slots.iter().map(|(_, binding)| binding)
+    }
+
+    /// The value in `slot`, if it holds `name`. It doesn't yet if a function
+    /// runs before the scope around it declares the name.
+    fn slot(&self, slot: Slot, name: Symbol) -> Option<Value> {
+        match slot.depth {
+            0 => match self.slots.get(slot.index) {
+                Some((held, binding)) if *held == name => binding.get(),
+                _ => None,
+            },
+            depth => self.parent.as_ref()?.borrow().slot(
+                Slot {
+                    depth: depth - 1,
+                    ..slot
+                },
+                name,
+            ),
+        }
+    }
+
+    /// Replaces the value in `slot` if it holds `name`, returning whether it
+    /// did.
+    fn set_slot(&mut self, slot: Slot, name: Symbol, value: &Value) -> bool {
+        match slot.depth {
+            0 => match self.slots.get_mut(slot.index) {
+                Some((held, old)) if *held == name => {
+                    old.set(value.clone());
+                    true
+                }
+                _ => false,
+            },
+            depth => match &self.parent {
+                Some(parent) => parent.borrow_mut().set_slot(
+                    Slot {
+                        depth: depth - 1,
+                        ..slot
+                    },
+                    name,
+                    value,
+                ),
+                None => false,
+            },
         }
     }
 }
 
-type BuiltInFunctionType = fn(&Location, Vec<Value>) -> Result<Value>;
+pub type BuiltInFunctionType =
+    fn(&mut Interpreter, &Ref<Scope>, &Location, Vec<Value>) -> Result<Value>;
 pub type Ref<T> = Rc<RefCell<T>>;
+/// The `name: value` arguments of a call, in the order they were passed.
+pub type NamedArguments = Vec<(Symbol, Value)>;
 
 enum ControlFlow {
     None,
     Continue,
     Break,
     Return(Value),
+    /// A `return f(args)`, which leaves the call to `f` to the caller of
+    /// the returning function, so it doesn't take up another native frame.
+    TailCall(Value, Vec<Value>, Location),
+}
+
+/// What a `Variable` node resolved to the last time it ran.
+enum InlineCache {
+    /// A built-in read by a `Variable`. Parameters and other bindings of the
+    /// name around the node make it a `Local` when resolving, and globals
+    /// don't shadow built-ins, so these never go stale. A `Local` falls
+    /// back to a built-in only while its slot is empty, which isn't cached.
+    BuiltIn(&'static str, BuiltInFunctionType),
+    /// A global found `depth` scopes up from where the node runs. Scope chains
+    /// follow the source's nesting, so this holds until a local is declared
+    /// under the same name, which bumps the interpreter's generation.
+    Global { depth: usize, generation: u64 },
+}
+
+/// Hashes AST node addresses, which are already unique, without the cost of
+/// the default hasher.
+#[derive(Default)]
+pub(crate) struct NodeHasher(u64);
+
+impl Hasher for NodeHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _: &[u8]) {
+        unreachable!("NodeHasher only hashes node addresses")
+    }
+
+    fn write_usize(&mut self, addr: usize) {
+        self.0 = (addr as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+/// Per-node state, keyed by node address. Each entry holds a weak reference
+/// to its node, so that the address can't be reused by another node while
+/// the entry is there, without keeping the node and its children alive.
+/// Entries whose node is gone are swept out as the map grows.
+pub(crate) struct NodeMap<T> {
+    entries: HashMap<usize, (std::sync::Weak<AST>, T), BuildHasherDefault<NodeHasher>>,
+    /// How many entries there can be before the next sweep.
+    sweep_at: usize,
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> NodeMap<T> {
+        NodeMap {
+            entries: HashMap::default(),
+            sweep_at: NodeMap::<T>::MIN_SWEEP,
+        }
+    }
+}
+
+impl<T> NodeMap<T> {
+    const MIN_SWEEP: usize = 64;
+
+    pub(crate) fn get(&self, node: &Arc<AST>) -> Option<&T> {
+        let (_, value) = self.entries.get(&node_key(node))?;
+        Some(value)
+    }
+
+    pub(crate) fn insert(&mut self, node: &Arc<AST>, value: T) {
+        self.sweep();
+        let entry = (Arc::downgrade(node), value);
+        self.entries.insert(node_key(node), entry);
+    }
+
+    /// The state of `node`, which starts out as `default()`.
+    pub(crate) fn entry(&mut self, node: &Arc<AST>, default: impl FnOnce() -> T) -> &mut T {
+        self.sweep();
+        let entry = self.entries.entry(node_key(node));
+        &mut entry.or_insert_with(|| (Arc::downgrade(node), default())).1
+    }
+
+    /// Drops the entries of nodes that are gone, once there are twice as
+    /// many entries as after the last sweep, so sweeping takes amortized
+    /// constant time.
+    fn sweep(&mut self) {
+        if self.entries.len() < self.sweep_at {
+            return;
+        }
+        self.entries.retain(|_, (node, _)| node.strong_count() > 0);
+        self.sweep_at = (self.entries.len() * 2).max(NodeMap::<T>::MIN_SWEEP);
+    }
+}
+
+pub(crate) fn node_key(node: &Arc<AST>) -> usize {
+    Arc::as_ptr(node) as usize
+}
+
+/// The global variables at some point, which [`Interpreter::restore`] goes
+/// back to. The copy is shallow: a dict changed in place after the snapshot
+/// is taken stays changed.
+#[derive(Clone)]
+pub struct ScopeSnapshot {
+    slots: Vec<(Symbol, Binding)>,
+    names: HashMap<Symbol, usize>,
+}
+
+/// What a progress callback wants the interpreter to do.
+pub enum Progress {
+    Continue,
+    /// Unwinds the running script with [`Error::Cancelled`].
+    Cancel,
+}
+
+/// Calls back into the host every `every` steps.
+struct Watchdog {
+    every: u64,
+    steps: u64,
+    callback: Box<dyn FnMut(u64) -> Progress>,
+}
+
+/// A file module that is currently being imported.
+struct Import {
+    name: String,
+    path: PathBuf,
+    loc: Location,
 }
 
 pub struct Interpreter {
     builtins: HashMap<&'static str, BuiltInFunctionType>,
+    /// The methods of built-in types, by type name and method name.
+    methods: MethodTable,
+    /// The scope that [`Interpreter::eval`] runs code in.
+    globals: Ref<Scope>,
+    modules: HashMap<String, Rc<Module>>,
+    imports: Vec<Import>,
+    /// File modules that finished running, so importing them again from
+    /// anywhere reuses the same module instead of running the file twice.
+    loaded: HashMap<PathBuf, Rc<Module>>,
+    manifest: Option<Manifest>,
+    parsed: HashMap<PathBuf, Arc<AST>>,
+    pinned_imports: HashMap<(PathBuf, String), PathBuf>,
+    inline_caches: NodeMap<InlineCache>,
+    /// Whether scripts and function bodies are compiled to bytecode, which
+    /// is kept here by node.
+    vm: bool,
+    chunks: NodeMap<Rc<Chunk>>,
+    /// Whether scripts are folded by [`optimizer::optimize`] before they
+    /// run.
+    optimize: bool,
+    cached_globals: HashSet<Symbol>,
+    generation: u64,
+    coverage: Option<Coverage>,
+    strict: bool,
+    overflow: Overflow,
+    watchdog: Option<Watchdog>,
+    /// How many more steps scripts may take.
+    fuel: Option<u64>,
+    /// How long each run may take, and when the current one must end by.
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    /// Steps since the clock was last read.
+    unclocked_steps: u32,
+    /// Whether [`memory::check`] has a limit to check.
+    memory_limit: bool,
+    gc: Collector,
+    resources: Vec<Weak<ResourceHandle>>,
+    /// Named arguments of the built-in being called, which haven't been
+    /// taken yet.
+    named_args: NamedArguments,
+    /// Where `input()` reads from, the process's standard input if unset.
+    /// That one isn't kept locked, since the REPL reads from it too.
+    stdin: Option<Box<dyn BufRead>>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
     control_flow: ControlFlow,
+    /// Whether a `return f(args)` may leave the function before calling
+    /// `f`, which it can't inside a `try` or a generator, where something
+    /// still runs after it.
+    tail_calls: bool,
+    /// The value of the `throw` that is unwinding. [`Error::Thrown`] only
+    /// carries its repr, since errors are sent between threads and values
+    /// can't be.
+    thrown: Option<Value>,
+    /// Where `random()` and the other random built-ins get their numbers,
+    /// seeded from the clock unless `seed()` or the host set a seed.
+    rng: Rng,
+    /// The script's name and the arguments after it, which every global
+    /// scope has as `argv0` and `args`.
+    argv0: String,
+    args: Vec<String>,
+    /// How many script function calls are running, and how many may be.
+    depth: usize,
+    max_depth: usize,
+}
+
+/// How deep script function calls may nest unless the host sets otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The native stack a thread needs for scripts to reach [`DEFAULT_MAX_DEPTH`],
+/// even in debug builds.
+pub const STACK_SIZE: usize = 256 << 20;
+
+/// How many steps pass between reads of the clock for a timeout.
+const CLOCK_EVERY: u32 = 1024;
+
+/// The names that every global scope starts out with.
+pub const GLOBAL_NAMES: [&str; 3] = [math::NAME, "argv0", "args"];
+
+/// A global scope, which starts out with the built-in modules.
+fn new_global_scope() -> Ref<Scope> {
+    let math = Value::Module(Rc::new(math::module()));
+    let mut scope = Scope::new(None, false);
+    scope.define(Symbol::intern(math::NAME), math);
+    Rc::new(RefCell::new(scope))
 }
 
 macro_rules! builtins {
@@ -62,29 +456,523 @@ macro_rules! builtins {
     };
 }
 
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        // a cycle through a function's scope is only freed by the next
+        // collection, and the host may still hold values, so what the
+        // scripts left open is closed here rather than whenever it's freed
+        self.close_resources();
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Interpreter {
     pub fn new() -> Interpreter {
-        let builtins = builtins!(print, len, exit);
-        Interpreter {
+        let mut builtins = builtins!(
+            print,
+            eprint,
+            eprintln,
+            printf,
+            format,
+            len,
+            step_by,
+            str,
+            int,
+            float,
+            bool,
+            wrapping_add,
+            wrapping_sub,
+            wrapping_mul,
+            wrapping_pow,
+            wrapping_shl,
+            saturating_add,
+            saturating_sub,
+            saturating_mul,
+            saturating_pow,
+            saturating_shl,
+            checked_add,
+            checked_sub,
+            checked_mul,
+            checked_pow,
+            checked_shl,
+            exit,
+            locals,
+            globals,
+            arity,
+            is_int,
+            is_float,
+            is_number,
+            is_string,
+            is_bool,
+            is_list,
+            is_tuple,
+            is_dict,
+            is_nothing,
+            callable,
+            source_location,
+            fields,
+            memoize,
+            close,
+            map,
+            filter,
+            take,
+            skip,
+            zip,
+            enumerate,
+            list,
+            read_file,
+            read_lines,
+            write_file,
+            append_file,
+            file_exists,
+            json_parse,
+            json_stringify,
+            random,
+            rand_int,
+            rand_choice,
+            shuffle,
+            seed,
+            now,
+            clock,
+            sleep,
+            format_time,
+            parse_time,
+            exec,
+            env,
+            set_env,
+            cwd,
+            chdir,
+            input,
+            read_stdin,
+        );
+        // `type` is a keyword in Rust, so it can't be named the same there
+        builtins.insert("type", builtin::type_of as BuiltInFunctionType);
+        let interpreter = Interpreter {
             builtins,
+            methods: methods::table(),
+            globals: new_global_scope(),
+            modules: HashMap::new(),
+            imports: vec![],
+            loaded: HashMap::new(),
+            manifest: None,
+            parsed: HashMap::new(),
+            pinned_imports: HashMap::new(),
+            inline_caches: NodeMap::default(),
+            vm: false,
+            chunks: NodeMap::default(),
+            optimize: false,
+            cached_globals: HashSet::new(),
+            generation: 0,
+            coverage: None,
+            strict: false,
+            overflow: Overflow::default(),
+            watchdog: None,
+            fuel: None,
+            timeout: None,
+            deadline: None,
+            unclocked_steps: 0,
+            memory_limit: false,
+            gc: Collector::default(),
+            resources: vec![],
+            named_args: vec![],
+            stdin: None,
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
             control_flow: ControlFlow::None,
+            tail_calls: false,
+            thrown: None,
+            rng: Rng::from_time(),
+            argv0: String::new(),
+            args: vec![],
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        };
+        interpreter.declare_args(&interpreter.globals);
+        interpreter
+    }
+
+    /// Starts counting which statements and branches run.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    /// What ran since [`Interpreter::enable_coverage`] was called.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Runs scripts and functions as bytecode instead of walking their
+    /// AST. Coverage is counted on the AST, so it keeps the tree-walker.
+    pub fn set_vm(&mut self, vm: bool) {
+        self.vm = vm;
+    }
+
+    /// Folds the constant parts of scripts and modules before running
+    /// them. Coverage is counted on the AST as written, so it keeps them
+    /// as they are.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// `ast`, folded if [`Interpreter::set_optimize`] asked for it.
+    fn optimized(&self, ast: &Arc<AST>) -> Arc<AST> {
+        match self.optimize && self.coverage.is_none() {
+            true => optimizer::optimize(ast, self.strict, self.overflow),
+            false => ast.clone(),
         }
     }
 
-    pub fn execute(&mut self, ast: &Rc<AST>) -> Result<Value> {
-        let scope = Rc::new(RefCell::new(Scope {
-            vars: HashMap::new(),
-            parent: None,
-            in_function: false,
-        }));
-        self.run(ast, scope)
+    /// In strict mode operators don't convert their operands: integers and
+    /// floats can't be mixed, strings and lists can't be repeated by
+    /// multiplying them with an integer, and the conditions of `if` and
+    /// `while` and the operands `and`, `or` and `not` decide on must be
+    /// booleans rather than going by truthiness.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// What integer arithmetic does when it overflows, which is to raise an
+    /// error unless set otherwise.
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// How deep script function calls may nest before a call fails with a
+    /// runtime error, [`DEFAULT_MAX_DEPTH`] unless set. Tail calls don't
+    /// nest. Every level takes native stack, around 10 KiB in release
+    /// builds and ten times that in debug builds, so hosts running scripts
+    /// on small threads should lower it.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    /// Makes scripts read input from `stdin` instead of the process's
+    /// standard input.
+    pub fn set_stdin(&mut self, stdin: impl BufRead + 'static) {
+        self.stdin = Some(Box::new(stdin));
+    }
+
+    /// Reads a line of input into `line`, with its line ending, returning
+    /// how many bytes were read, which is 0 at the end of the input.
+    pub fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.read_line(line),
+            None => io::stdin().read_line(line),
+        }
+    }
+
+    /// Reads the rest of the input into `text`.
+    pub fn read_to_string(&mut self, text: &mut String) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.read_to_string(text),
+            None => io::stdin().read_to_string(text),
+        }
+    }
+
+    /// Sends what scripts print to `stdout` instead of the process's
+    /// standard output.
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.stdout = Box::new(stdout);
+    }
+
+    /// Sends what scripts print to stderr to `stderr` instead of the
+    /// process's standard error.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.stderr = Box::new(stderr);
+    }
+
+    /// Sets what scripts see as `argv0`, the name of the script, and `args`,
+    /// the list of arguments that follow it on the command line.
+    pub fn set_args(&mut self, argv0: impl Into<String>, args: Vec<String>) {
+        self.argv0 = argv0.into();
+        self.args = args;
+        self.declare_args(&self.globals);
+    }
+
+    fn declare_args(&self, scope: &Ref<Scope>) {
+        let args = self.args.iter().map(|arg| Value::from(arg.as_str()));
+        let mut scope = scope.borrow_mut();
+        scope.define(Symbol::intern("argv0"), self.argv0.as_str().into());
+        scope.define(Symbol::intern("args"), args.collect::<Vec<_>>().into());
+    }
+
+    /// A global scope for a script or module, with the built-in modules and
+    /// the command line arguments.
+    fn fresh_globals(&self) -> Ref<Scope> {
+        let scope = new_global_scope();
+        self.declare_args(&scope);
+        scope
+    }
+
+    /// Makes the random built-ins give the same numbers on every run, as
+    /// `seed(n)` does from scripts.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        &mut self.stdout
+    }
+
+    pub fn stderr(&mut self) -> &mut dyn Write {
+        &mut self.stderr
+    }
+
+    /// Calls `callback` with the number of steps taken so far after every
+    /// `every` steps, where a step is the evaluation of a single node, or
+    /// with [`Interpreter::set_vm`] an instruction that stands for one. This
+    /// lets a host stay responsive while a script runs, and stop it cleanly
+    /// by returning [`Progress::Cancel`].
+    pub fn set_progress_callback(
+        &mut self,
+        every: u64,
+        callback: impl FnMut(u64) -> Progress + 'static,
+    ) {
+        self.watchdog = Some(Watchdog {
+            every: every.max(1),
+            steps: 0,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Lets scripts take `fuel` more steps, counted like the steps of
+    /// [`Interpreter::set_progress_callback`], after which they stop with
+    /// [`Error::ExecutionLimitExceeded`]. `None` lifts the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// How many more steps scripts may take, if there's a limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Stops each run of [`Interpreter::eval`], or the functions that
+    /// wrap it, with [`Error::ExecutionLimitExceeded`] once it has taken
+    /// longer than `timeout`. `None` lifts the limit.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Frees the values scripts made that only hold each other, like a
+    /// function and the scope it was declared in, returning how many values
+    /// were emptied. This also happens on its own as scripts declare
+    /// functions and structs.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.gc.collect()
+    }
+
+    /// Fails scripts with a runtime error once the thread they run on holds
+    /// `bytes` more of the heap than it does now, checked every step and
+    /// before repeating a string or list. `None` lifts the limit. The heap
+    /// is only counted with [`memory::CountingAllocator`] as the global
+    /// allocator. The budget is the thread's rather than the interpreter's:
+    /// other interpreters on the thread allocate from it too, and setting
+    /// it from any of them replaces it, as [`memory`] explains.
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        memory::set_limit(bytes);
+        self.memory_limit = bytes.is_some();
+    }
+
+    /// Fails if the running script is past its timeout, for built-ins that
+    /// wait without taking steps.
+    pub(crate) fn check_deadline(&self, loc: &Location) -> Result<()> {
+        match (self.timeout, self.deadline) {
+            (Some(timeout), Some(deadline)) if Instant::now() >= deadline => {
+                let msg = format!("ran for longer than {:?}", timeout);
+                Err(Error::ExecutionLimitExceeded(loc.clone(), msg))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Wraps a host resource for scripts. Whatever scripts haven't closed
+    /// when the interpreter is dropped is closed then.
+    pub fn add_resource(&mut self, name: impl Into<String>, resource: impl Resource) -> Value {
+        let handle = Rc::new(ResourceHandle::new(name, resource));
+        self.resources.retain(|handle| handle.strong_count() > 0);
+        self.resources.push(Rc::downgrade(&handle));
+        Value::Resource(handle)
+    }
+
+    /// Closes every resource that's still open, ignoring errors.
+    pub fn close_resources(&mut self) {
+        for handle in self.resources.drain(..) {
+            if let Some(handle) = handle.upgrade() {
+                let _ = handle.close();
+            }
+        }
+    }
+
+    /// The names of all built-in functions.
+    pub fn builtin_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.builtins.keys().copied()
+    }
+
+    /// Resolves imports that aren't next to the importing file through the
+    /// project's dependencies and source roots.
+    pub fn set_manifest(&mut self, manifest: Manifest) {
+        self.manifest = Some(manifest);
+    }
+
+    /// Lets imports of an already parsed project skip lexing and parsing.
+    pub fn add_parsed_modules(&mut self, project: &Project) {
+        self.parsed.extend(
+            project
+                .modules
+                .iter()
+                .map(|(path, ast)| (path.clone(), ast.clone())),
+        );
+    }
+
+    /// Fixes the module that `import name` inside `importer` refers to,
+    /// bypassing the usual lookup. The module needn't exist on disk as long
+    /// as its parsed source was added with [`Self::add_parsed_modules`].
+    pub fn pin_import(&mut self, importer: PathBuf, name: String, path: PathBuf) {
+        self.pinned_imports.insert((importer, name), path);
+    }
+
+    /// Makes `module` available to scripts through `import <name>`,
+    /// replacing any module previously registered under the same name.
+    pub fn register_module(&mut self, module: Module) {
+        self.modules.insert(module.name.clone(), Rc::new(module));
+    }
+
+    /// Runs a whole script in a fresh global scope, which becomes the one
+    /// [`Interpreter::globals`] returns.
+    pub fn execute(&mut self, ast: &Arc<AST>) -> Result<Value> {
+        self.globals = self.fresh_globals();
+        self.eval(ast)
+    }
+
+    /// Runs `ast` in the global scope, where what earlier calls declared is
+    /// still around, the way the REPL runs each statement.
+    pub fn eval(&mut self, ast: &Arc<AST>) -> Result<Value> {
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let ast = &self.optimized(ast);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add_root(ast);
+        } else if self.vm {
+            let chunk = self.chunk(ast, Chunk::script);
+            return self.run_chunk(&chunk, self.globals.clone());
+        }
+        self.run_block_without_scope(ast, self.globals.clone())
+    }
+
+    /// Lexes, parses and runs `source` the way [`Interpreter::eval`] does,
+    /// for hosts that embed scripts as text.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value> {
+        let lexer = Lexer::new(source.to_string(), String::from("<string>"));
+        let ast = self.parser(lexer).parse()?;
+        self.eval(&ast)
+    }
+
+    /// Reads, parses and runs the script at `path` the way
+    /// [`Interpreter::eval`] does, so it sees globals the host set. Its
+    /// imports are looked for next to it.
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Value> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| Error::Other(format!("Couldn't read {}: {}", path.display(), err)))?;
+        let lexer = Lexer::new(source, path.display().to_string());
+        let ast = self.parser(lexer).parse()?;
+        self.eval(&ast)
+    }
+
+    /// A parser for code that runs in the global scope, where the globals
+    /// declared so far are known to the resolver.
+    pub(crate) fn parser(&self, lexer: Lexer) -> Parser {
+        let globals = self.globals.borrow();
+        let names = globals.vars().map(|(name, _)| name);
+        Parser::from_lexer(lexer).with_globals(names)
     }
 
-    pub fn run_block_without_scope(&mut self, ast: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+    /// Declares or replaces the global `name`, for the host to hand values
+    /// to the scripts it runs.
+    pub fn set_global(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(&name.into()), value.into());
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let name = Symbol::lookup(name)?;
+        self.globals.borrow().local(name)
+    }
+
+    /// Makes the host function `func` a global, callable from scripts as
+    /// `name(...)`. Its arguments come as they were passed, and an
+    /// [`Error::Other`] it returns becomes a runtime error at the call.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        func: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) {
+        let name = name.into();
+        let func = Value::HostFunction(Rc::new(HostFunction::new(name.clone(), func)));
+        self.set_global(name, func);
+    }
+
+    /// Like [`Interpreter::register_fn`], for a function whose arguments and
+    /// result convert from and to values, such as `|(a, b): (i64, i64)| a + b`.
+    /// Arguments of the wrong number or type are runtime errors at the call.
+    pub fn register_typed_fn<Args: FromArgs, R: IntoValue>(
+        &mut self,
+        name: impl Into<String>,
+        func: impl Fn(Args) -> R + 'static,
+    ) {
+        self.register_fn(name, move |args| {
+            Args::from_args(args)
+                .map(|args| func(args).into_value())
+                .map_err(Error::Other)
+        });
+    }
+
+    pub fn globals(&self) -> Ref<Scope> {
+        self.globals.clone()
+    }
+
+    /// Takes note of the global variables, to go back to them later with
+    /// [`Interpreter::restore`].
+    pub fn snapshot_globals(&self) -> ScopeSnapshot {
+        let globals = self.globals.borrow();
+        ScopeSnapshot {
+            slots: globals.slots.clone(),
+            names: globals.names.clone(),
+        }
+    }
+
+    /// Puts the global variables back the way they were when `snapshot` was
+    /// taken. Functions declared since then are gone, but the scope itself is
+    /// kept, so functions from before the snapshot still see the globals.
+    pub fn restore(&mut self, snapshot: ScopeSnapshot) {
+        let mut globals = self.globals.borrow_mut();
+        globals.slots = snapshot.slots;
+        globals.names = snapshot.names;
+    }
+
+    pub fn run_block_without_scope(&mut self, ast: &Arc<AST>, scope: Ref<Scope>) -> Result<Value> {
         let mut last = Value::Nothing;
         match ast.as_ref() {
             AST::Block(_, stmts) => {
                 for stmt in stmts {
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.hit_statement(stmt);
+                    }
                     last = self.run(stmt, scope.clone())?;
                     match self.control_flow {
                         ControlFlow::None => {}
@@ -97,227 +985,261 @@ impl Interpreter {
         }
     }
 
-
-    pub fn run(&mut self, ast: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+    pub fn run(&mut self, ast: &Arc<AST>, scope: Ref<Scope>) -> Result<Value> {
         macro_rules! dispatch_op {
+            // operators that may convert their operands, which strict mode restricts
+            ($loc:expr, $op:path, $symbol:literal, $left:expr, $right:expr) => {{
+                self.run_comparison($loc, $op, $symbol, $left, $right, scope.clone())?
+            }};
+
             ($loc:expr, $op:path, $left:expr, $right:expr) => {{
                 let left = self.run($left, scope.clone())?;
                 let right = self.run($right, scope.clone())?;
-                $op(left, right, $loc)?
-            }};
-
-            ($loc:expr, $op:path, $val:expr) => {{
-                let val = self.run($val, scope.clone())?;
-                $op(val, $loc)?
+                $op(&left, &right, $loc)?
             }};
         }
 
+        self.step(ast.loc())?;
+
         Ok(match ast.as_ref() {
             AST::Block(..) => {
-                let block_scope = Rc::new(RefCell::new(Scope {
-                    vars: HashMap::new(),
-                    parent: Some(scope.clone()),
-                    in_function: scope.borrow_mut().in_function,
-                }));
+                let block_scope = Rc::new(RefCell::new(Scope::child(&scope)));
                 self.run_block_without_scope(ast, block_scope)?
             }
             AST::Call(loc, func, args) => self.handle_call(scope, loc, func, args)?,
+            AST::Match(loc, subject, arms) => self.run_match(loc, subject, arms, scope)?,
             AST::If(loc, cond, body, else_body) => {
-                let cond = self.run(cond, scope.clone())?;
-                match cond {
-                    Value::Boolean(true) => self.run(body, scope)?,
-                    Value::Boolean(false) => match else_body {
-                        Some(else_body) => self.run(else_body, scope)?,
-                        None => Value::Nothing,
-                    },
-                    _ => error!(loc, "If condition must be a boolean"),
+                let taken = self.run(cond, scope.clone())?;
+                let taken = taken.condition(self.strict, "if", loc)?;
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.hit_branch(ast, taken);
+                }
+                match (taken, else_body) {
+                    (true, _) => self.run(body, scope)?,
+                    (false, Some(else_body)) => self.run(else_body, scope)?,
+                    (false, None) => Value::Nothing,
                 }
             }
             AST::While(loc, cond, body) => {
                 loop {
-                    let cond = self.run(cond, scope.clone())?;
-                    match cond {
-                        Value::Boolean(true) => {
-                            self.run(body, scope.clone())?;
-                            match self.control_flow {
-                                ControlFlow::None => {}
-                                ControlFlow::Continue => self.control_flow = ControlFlow::None,
-                                ControlFlow::Break => {
-                                    self.control_flow = ControlFlow::None;
-                                    break;
-                                }
-                                ControlFlow::Return(_) => break,
-                            }
+                    let taken = self.run(cond, scope.clone())?;
+                    let taken = taken.condition(self.strict, "while", loc)?;
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.hit_branch(ast, taken);
+                    }
+                    if !taken {
+                        break;
+                    }
+                    self.run(body, scope.clone())?;
+                    match self.control_flow {
+                        ControlFlow::None => {}
+                        ControlFlow::Continue => self.control_flow = ControlFlow::None,
+                        ControlFlow::Break => {
+                            self.control_flow = ControlFlow::None;
+                            break;
                         }
-                        Value::Boolean(false) => break,
-                        _ => error!(loc, "While condition must be a boolean"),
+                        ControlFlow::Return(_) | ControlFlow::TailCall(..) => break,
                     }
                 }
                 Value::Nothing
             }
             AST::For(loc, loop_var, iter, body) => {
                 let iter = self.run(iter, scope.clone())?.iterator(loc);
-                match iter {
-                    Value::Iterator(IteratorValue(iter)) => {
-                        let iter = &mut *(*iter).borrow_mut();
-                        for val in iter {
-                            let mut loop_scope = Scope {
-                                vars: HashMap::new(),
-                                parent: Some(scope.clone()),
-                                in_function: scope.borrow_mut().in_function,
-                            };
-                            loop_scope.insert(loop_var.clone(), val.clone(), false, loc)?;
-                            self.run(body, Rc::new(RefCell::new(loop_scope)))?;
-                            match self.control_flow {
-                                ControlFlow::None => {}
-                                ControlFlow::Continue => self.control_flow = ControlFlow::None,
-                                ControlFlow::Break => {
-                                    self.control_flow = ControlFlow::None;
-                                    break;
-                                }
-                                ControlFlow::Return(_) => break,
-                            }
-                        }
-                    }
-                    _ => error!(loc, "For loop must iterate over an iterable"),
-                }
+                let Value::Iterator(iter) = iter else {
+                    error!(loc, "For loop must iterate over an iterable")
+                };
+                self.run_for(loc, *loop_var, &iter, body, scope)?;
                 Value::Nothing
             }
             AST::BooleanLiteral(_, value) => Value::Boolean(*value),
             AST::IntegerLiteral(_, num) => Value::Integer(*num),
             AST::FloatLiteral(_, num) => Value::Float(*num),
             AST::StringLiteral(_, string) => Value::String(string.clone()),
+            AST::List(_, items) => self.run_list(items, scope)?,
+            AST::Tuple(_, items) => self.run_tuple(items, scope)?,
+            AST::Dict(loc, entries) => self.run_dict(loc, entries, scope)?,
             AST::Nothing(_) => Value::Nothing,
-            AST::VarDeclaration(loc, name, value) => {
-                if scope.borrow_mut().vars.contains_key(name) {
-                    error!(loc, "Variable {} already exists in scope", name)
-                }
-                if self.builtins.contains_key(name.as_str()) {
-                    error!(
-                        loc,
-                        "`{}` is a built-in function, can't be used as a variable", name
-                    )
-                }
+            AST::VarDeclaration(loc, name, _, value) => {
                 let value = self.run(value, scope.clone())?;
-                scope
-                    .borrow_mut()
-                    .insert(name.clone(), value.clone(), false, loc)?;
+                self.declare_variable(loc, *name, value.clone(), &scope)?;
                 value
             }
-            AST::Assignment(loc, lhs, value) => {
+            AST::Unpack(_, pattern, value) => {
                 let value = self.run(value, scope.clone())?;
-                match lhs.as_ref() {
-                    AST::Variable(loc, name) => {
-                        if scope.borrow_mut().get(name).is_none() {
-                            error!(loc, "Variable {} doesn't exist", name)
-                        }
-                        if self.builtins.contains_key(name.as_str()) {
-                            error!(loc, "`{}` is a built-in function, can't override it", name)
-                        }
-                        scope
-                            .borrow_mut()
-                            .insert(name.clone(), value.clone(), true, loc)?;
-                        value
-                    }
-                    _ => error!(loc, "Can't assign to {:?}", lhs),
-                }
+                self.declare_pattern(pattern, value.clone(), &scope)?;
+                value
+            }
+            AST::Assignment(_, lhs, value) => {
+                let value = self.run(value, scope.clone())?;
+                self.assign(lhs, value.clone(), &scope)?;
+                value
             }
             AST::Index(loc, left, right) => {
                 let left = self.run(left, scope.clone())?;
-                let right = self.run(right, scope)?;
-                match (&left, &right) {
-                    (Value::String(left), Value::Integer(right)) => {
-                        match left.chars().nth(*right as usize) {
-                            Some(c) => Value::String(c.to_string()),
-                            None => error!(loc, "Index out of bounds"),
-                        }
-                    }
-                    _ => error!(loc, "Can't index {:?} with {:?}", left, right),
+                let right = self.run(right, scope.clone())?;
+                match self.run_overloaded(&scope, loc, "__index__", &left, &[&right]) {
+                    Some(result) => result?,
+                    None => left.index(&right, loc)?,
                 }
             }
-            AST::Variable(loc, name) => {
-                if self.builtins.get(name.as_str()).is_some() {
-                    Value::BuiltInFunction(name.clone())
-                } else if let Some(value) = scope.borrow_mut().get(name) {
-                    value
-                } else {
-                    error!(loc, "Variable {} not found", name)
-                }
+            AST::Member(loc, lhs, name) => {
+                let lhs = self.run(lhs, scope)?;
+                self.member(loc, &lhs, name.as_str())?
             }
+            AST::Variable(..) | AST::Local(..) => self.read(ast, &scope)?,
 
-            AST::Plus(loc, left, right) => dispatch_op!(loc, Value::plus, left, right),
-            AST::Minus(loc, left, right) => dispatch_op!(loc, Value::minus, left, right),
-            AST::Multiply(loc, left, right) => dispatch_op!(loc, Value::multiply, left, right),
-            AST::Divide(loc, left, right) => dispatch_op!(loc, Value::divide, left, right),
-
-            AST::Not(loc, expr) => dispatch_op!(loc, Value::not, expr),
-            AST::And(loc, left, right) => dispatch_op!(loc, Value::and, left, right),
-            AST::Or(loc, left, right) => dispatch_op!(loc, Value::or, left, right),
-
-            AST::Equals(loc, left, right) => dispatch_op!(loc, Value::equals, left, right),
-            AST::NotEquals(loc, left, right) => dispatch_op!(loc, Value::not_equals, left, right),
-            AST::LessThan(loc, left, right) => dispatch_op!(loc, Value::less_than, left, right),
-            AST::GreaterThan(loc, left, right) => {
-                dispatch_op!(loc, Value::greater_than, left, right)
+            AST::Plus(loc, left, right) => {
+                self.run_arithmetic(loc, Value::plus, "+", left, right, scope)?
             }
-            AST::LessThanEquals(loc, left, right) => {
-                dispatch_op!(loc, Value::less_than_equals, left, right)
+            AST::Minus(loc, left, right) => {
+                self.run_arithmetic(loc, Value::minus, "-", left, right, scope)?
             }
-            AST::GreaterThanEquals(loc, left, right) => {
-                dispatch_op!(loc, Value::greater_than_equals, left, right)
+            AST::Multiply(loc, left, right) => {
+                self.run_arithmetic(loc, Value::multiply, "*", left, right, scope)?
             }
-
-            AST::Slice {
-                loc,
-                lhs,
-                start,
+            AST::Divide(loc, left, right) => {
+                self.run_arithmetic(loc, Value::divide, "/", left, right, scope)?
+            }
+            AST::Modulo(loc, left, right) => {
+                self.run_arithmetic(loc, Value::modulo, "%", left, right, scope)?
+            }
+            AST::Power(loc, left, right) => {
+                self.run_arithmetic(loc, Value::power, "**", left, right, scope)?
+            }
+            AST::ShiftLeft(loc, left, right) => {
+                self.run_arithmetic(loc, Value::shift_left, "<<", left, right, scope)?
+            }
+            AST::ShiftRight(loc, left, right) => {
+                self.run_bitwise(loc, Value::shift_right, ">>", left, right, scope)?
+            }
+            AST::BitAnd(loc, left, right) => {
+                self.run_bitwise(loc, Value::bit_and, "&", left, right, scope)?
+            }
+            AST::BitOr(loc, left, right) => {
+                self.run_bitwise(loc, Value::bit_or, "|", left, right, scope)?
+            }
+            AST::BitXor(loc, left, right) => {
+                self.run_bitwise(loc, Value::bit_xor, "^", left, right, scope)?
+            }
+            AST::BitNot(loc, expr) => {
+                let val = self.run(expr, scope.clone())?;
+                match self.run_overloaded(&scope, loc, "__invert__", &val, &[]) {
+                    Some(result) => result?,
+                    None => val.bit_not(loc)?,
+                }
+            }
+
+            AST::Not(loc, expr) => self.run(expr, scope)?.not(self.strict, loc)?,
+            AST::NamedArgument(loc, ..) => {
+                error!(loc, "Named arguments can only be passed to calls")
+            }
+            AST::Spread(loc, _) => error!(loc, "`...` can only spread arguments of calls"),
+            AST::Negate(loc, expr) => {
+                let val = self.run(expr, scope.clone())?;
+                match self.run_overloaded(&scope, loc, "__neg__", &val, &[]) {
+                    Some(result) => result?,
+                    None => val.negate(self.overflow, loc)?,
+                }
+            }
+            AST::In(loc, item, collection) => {
+                let item = self.run(item, scope.clone())?;
+                let collection = self.run(collection, scope.clone())?;
+                let contains = "__contains__";
+                match self.run_overloaded(&scope, loc, contains, &collection, &[&item]) {
+                    Some(result) => result?,
+                    None => collection.contains(&item, loc)?,
+                }
+            }
+            // the right side only runs if the left doesn't decide the result
+            AST::And(loc, left, right) => match self.run(left, scope.clone())? {
+                left if left.condition(self.strict, "and", loc)? => self.run(right, scope)?,
+                left => left,
+            },
+            AST::Or(loc, left, right) => match self.run(left, scope.clone())? {
+                left if left.condition(self.strict, "or", loc)? => left,
+                _ => self.run(right, scope)?,
+            },
+
+            AST::Equals(loc, left, right) => dispatch_op!(loc, Value::equals, "==", left, right),
+            AST::NotEquals(loc, left, right) => {
+                dispatch_op!(loc, Value::not_equals, "!=", left, right)
+            }
+            AST::LessThan(loc, left, right) => {
+                dispatch_op!(loc, Value::less_than, "<", left, right)
+            }
+            AST::GreaterThan(loc, left, right) => {
+                dispatch_op!(loc, Value::greater_than, ">", left, right)
+            }
+            AST::LessThanEquals(loc, left, right) => {
+                dispatch_op!(loc, Value::less_than_equals, "<=", left, right)
+            }
+            AST::GreaterThanEquals(loc, left, right) => {
+                dispatch_op!(loc, Value::greater_than_equals, ">=", left, right)
+            }
+
+            AST::Slice {
+                loc,
+                lhs,
+                start,
                 end,
                 step,
-            } => {
-                let lhs = self.run(lhs, scope.clone())?;
-                let start = start
-                    .clone()
-                    .map(|start| self.run(&start, scope.clone()))
-                    .transpose()?;
-                let end = end
-                    .clone()
-                    .map(|end| self.run(&end, scope.clone()))
-                    .transpose()?;
-                let step = step
-                    .clone()
-                    .map(|step| self.run(&step, scope.clone()))
-                    .transpose()?;
-                lhs.slice(start, end, step, loc)?
-            }
+            } => self.run_slice(loc, lhs, [start, end, step], scope)?,
             AST::Function {
                 name,
                 args,
+                variadic,
+                defaults,
+                captures,
+                moves,
                 body,
                 loc,
                 ..
             } => {
                 let func = Value::Function {
+                    name: *name,
+                    loc: loc.clone(),
                     args: args.clone(),
+                    variadic: *variadic,
+                    defaults: defaults.clone(),
                     body: body.clone(),
-                    scope: scope.clone(),
+                    scope: self.closure(loc, captures, *moves, &scope)?,
                 };
-                match name {
-                    Some(name) => {
-                        scope
-                            .borrow_mut()
-                            .insert(name.clone(), func.clone(), false, loc)?
-                    }
-                    None => {}
+                if let Some(name) = name {
+                    self.declare(&mut scope.borrow_mut(), *name, func.clone(), loc)?
+                }
+                if self.gc.declared_in(&scope) {
+                    self.gc.collect();
                 }
                 func
             }
+            AST::Struct {
+                loc,
+                name,
+                fields,
+                methods,
+            } => self.declare_struct(loc, *name, fields, methods, &scope)?,
+            AST::Enum {
+                loc,
+                name,
+                variants,
+                methods,
+            } => self.declare_enum(loc, *name, variants, methods, &scope)?,
             AST::Return(loc, val) => {
                 if !scope.borrow_mut().in_function {
                     error!(loc, "Return statement outside of function")
                 }
-                self.control_flow = ControlFlow::Return(self.run(val, scope)?);
+                self.control_flow = self.run_return(val, scope)?;
                 Value::Nothing
             }
+            AST::Throw(loc, val) => return self.run_throw(loc, val, scope),
+            AST::Generator(_, body) => self.start_generator(body, scope),
+            AST::Yield(loc, _) => error!(loc, "Can't `yield` from inside a `try` or `match`"),
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => self.run_try(body, catch.as_ref(), finally.as_ref(), scope)?,
             AST::Break(_loc) => {
                 self.control_flow = ControlFlow::Break;
                 Value::Nothing
@@ -335,69 +1257,1884 @@ impl Interpreter {
                 }
                 Value::Nothing
             }
-            AST::Range(loc, start, end) => {
+            AST::Import(loc, name, path) => {
+                let module = self.load_module(loc, path)?;
+                let existing = scope.borrow().local(*name);
+                match existing {
+                    // importing the same module again changes nothing
+                    Some(Value::Module(existing)) if Rc::ptr_eq(&existing, &module) => {
+                        return Ok(Value::Nothing)
+                    }
+                    Some(_) => error!(loc, "Variable {} already exists in scope", name),
+                    None => {}
+                }
+                self.declare(&mut scope.borrow_mut(), *name, Value::Module(module), loc)?;
+                Value::Nothing
+            }
+            AST::Pub(loc, decl) => {
+                if scope.borrow().parent.is_some() {
+                    error!(loc, "`pub` is only allowed at the top level of a module")
+                }
+                self.run(decl, scope)?
+            }
+            AST::Range(loc, start, end, inclusive) => {
                 let start = self.run(start, scope.clone())?;
                 let end = self.run(end, scope)?;
                 match (start, end) {
-                    (Value::Integer(start), Value::Integer(end)) => Value::Range(start, end),
+                    (Value::Integer(start), Value::Integer(end)) => {
+                        Value::Range(RangeValue::new(start, end, *inclusive))
+                    }
                     _ => error!(loc, "Range must be between integers"),
                 }
             }
         })
     }
 
-    fn handle_call(
+    /// Counts a step for the watchdog and the fuel, and stops if the host,
+    /// an interrupt or a limit asks to.
+    fn step(&mut self, loc: &Location) -> Result<()> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                let msg = "ran out of fuel".to_string();
+                return Err(Error::ExecutionLimitExceeded(loc.clone(), msg));
+            }
+            *fuel -= 1;
+        }
+        if self.memory_limit && memory::check(0, loc).is_err() {
+            // cycles may be all that's in the way
+            self.gc.collect();
+            memory::check(0, loc)?;
+        }
+        if self.deadline.is_some() {
+            // reading the clock every step would slow scripts down
+            self.unclocked_steps += 1;
+            if self.unclocked_steps == CLOCK_EVERY {
+                self.unclocked_steps = 0;
+                self.check_deadline(loc)?;
+            }
+        }
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.steps += 1;
+            if watchdog.steps % watchdog.every == 0 {
+                if let Progress::Cancel = (watchdog.callback)(watchdog.steps) {
+                    return Err(Error::Cancelled(loc.clone()));
+                }
+            }
+        }
+        if signal::take_interrupt() {
+            return Err(Error::Interrupted(loc.clone()));
+        }
+        Ok(())
+    }
+
+    /// The bytecode of `node`, compiled the first time it runs.
+    fn chunk(&mut self, node: &Arc<AST>, compile: fn(&Arc<AST>) -> Chunk) -> Rc<Chunk> {
+        if let Some(chunk) = self.chunks.get(node) {
+            return chunk.clone();
+        }
+        let chunk = Rc::new(compile(node));
+        self.chunks.insert(node, chunk.clone());
+        chunk
+    }
+
+    /// Runs `chunk` in `scope`, returning what it returns, or otherwise the
+    /// value it leaves on the stack.
+    fn run_chunk(&mut self, chunk: &Chunk, scope: Ref<Scope>) -> Result<Value> {
+        fn pop(stack: &mut Vec<Value>) -> Value {
+            stack.pop().expect("chunks pop only what they pushed")
+        }
+
+        let mut stack = vec![];
+        let mut scopes = vec![scope];
+        let mut pc = 0;
+        while let Some(&op) = chunk.code.get(pc) {
+            pc += 1;
+            let node = op.node().map(|i| &chunk.nodes[i as usize]);
+            if let Some(node) = node {
+                self.step(node.loc())?;
+            }
+            let loc = node.map(|node| node.loc());
+            let scope = scopes
+                .last()
+                .expect("chunks close only the scopes they open");
+            match op {
+                Op::Constant(i) => stack.push(chunk.constants[i as usize].clone()),
+                Op::Load(_) => stack.push(self.read(node.expect("has a node"), scope)?),
+                Op::Declare(_) => {
+                    let AST::VarDeclaration(loc, name, ..) = node.expect("has a node").as_ref()
+                    else {
+                        unreachable!("declaration without a `let`")
+                    };
+                    self.declare_variable(loc, *name, pop(&mut stack), scope)?;
+                }
+                Op::Assign(_) => {
+                    let AST::Assignment(_, lhs, _) = node.expect("has a node").as_ref() else {
+                        unreachable!("assignment without an `=`")
+                    };
+                    self.assign(lhs, pop(&mut stack), scope)?;
+                }
+                Op::Pop => {
+                    pop(&mut stack);
+                }
+                Op::Binary(op, _) => {
+                    let right = pop(&mut stack);
+                    let left = pop(&mut stack);
+                    let loc = loc.expect("has a node");
+                    let method = operator_method(op.symbol());
+                    let value = match self.run_overloaded(scope, loc, method, &left, &[&right]) {
+                        Some(result) => result?,
+                        None => op.apply(&left, &right, self.strict, self.overflow, loc)?,
+                    };
+                    stack.push(value);
+                }
+                Op::Unary(op, _) => {
+                    let value = pop(&mut stack);
+                    let loc = loc.expect("has a node");
+                    let overloaded = match op.method() {
+                        Some(method) => self.run_overloaded(scope, loc, method, &value, &[]),
+                        None => None,
+                    };
+                    stack.push(match overloaded {
+                        Some(result) => result?,
+                        None => op.apply(&value, self.strict, self.overflow, loc)?,
+                    });
+                }
+                Op::Index(_) => {
+                    let index = pop(&mut stack);
+                    let value = pop(&mut stack);
+                    let loc = loc.expect("has a node");
+                    let item = match self.run_overloaded(scope, loc, "__index__", &value, &[&index])
+                    {
+                        Some(result) => result?,
+                        None => value.index(&index, loc)?,
+                    };
+                    stack.push(item);
+                }
+                Op::In(_) => {
+                    let collection = pop(&mut stack);
+                    let item = pop(&mut stack);
+                    let loc = loc.expect("has a node");
+                    let contains = "__contains__";
+                    let value =
+                        match self.run_overloaded(scope, loc, contains, &collection, &[&item]) {
+                            Some(result) => result?,
+                            None => collection.contains(&item, loc)?,
+                        };
+                    stack.push(value);
+                }
+                Op::List(n) => {
+                    let items = stack.split_off(stack.len() - n as usize);
+                    stack.push(Value::List(Rc::new(RefCell::new(items))));
+                }
+                Op::Tuple(n) => {
+                    let items = stack.split_off(stack.len() - n as usize);
+                    stack.push(Value::Tuple(items.into()));
+                }
+                Op::Call(argc, _) => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let func = pop(&mut stack);
+                    let loc = loc.expect("has a node");
+                    stack.push(self.call_with_named(scope, loc, &func, args, vec![])?);
+                }
+                Op::PushScope => {
+                    let block_scope = Scope::child(scope);
+                    scopes.push(Rc::new(RefCell::new(block_scope)));
+                }
+                Op::PopScope => {
+                    scopes.pop();
+                }
+                Op::Jump(to) => pc = to as usize,
+                Op::JumpIfFalse(to, _) => {
+                    let construct = match node.expect("has a node").as_ref() {
+                        AST::While(..) => "while",
+                        _ => "if",
+                    };
+                    let loc = loc.expect("has a node");
+                    if !pop(&mut stack).condition(self.strict, construct, loc)? {
+                        pc = to as usize;
+                    }
+                }
+                Op::ShortCircuit(to, when, _) => {
+                    let construct = if when { "or" } else { "and" };
+                    let left = stack.last().expect("the left operand is on the stack");
+                    let decided =
+                        left.condition(self.strict, construct, loc.expect("has a node"))?;
+                    match decided == when {
+                        true => pc = to as usize,
+                        false => drop(pop(&mut stack)),
+                    }
+                }
+                Op::IntoIter(_) => {
+                    let loc = loc.expect("has a node");
+                    let Value::Iterator(iter) = pop(&mut stack).iterator(loc) else {
+                        error!(loc, "For loop must iterate over an iterable")
+                    };
+                    stack.push(Value::Iterator(iter));
+                }
+                Op::ForNext(done, _) => {
+                    let AST::For(loc, loop_var, ..) = node.expect("has a node").as_ref() else {
+                        unreachable!("iteration outside of a `for`")
+                    };
+                    let Some(Value::Iterator(iter)) = stack.last() else {
+                        unreachable!("iteration without an iterator")
+                    };
+                    match iter.clone().next(self, scope, loc)? {
+                        Some(value) => {
+                            let mut loop_scope = Scope::child(scope);
+                            self.declare(&mut loop_scope, *loop_var, value, loc)?;
+                            scopes.push(Rc::new(RefCell::new(loop_scope)));
+                        }
+                        None => pc = done as usize,
+                    }
+                }
+                Op::Return => return Ok(pop(&mut stack)),
+                Op::TailCall(argc, _) => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let func = pop(&mut stack);
+                    let loc = loc.expect("has a node");
+                    match self.tail_call(scope, loc, func, args)? {
+                        ControlFlow::Return(value) => return Ok(value),
+                        flow => {
+                            self.control_flow = flow;
+                            return Ok(Value::Nothing);
+                        }
+                    }
+                }
+                Op::Eval(i, inner) => {
+                    let value = self.run(&chunk.nodes[i as usize], scope.clone())?;
+                    stack.push(value);
+                    let exits = inner.map(|i| &chunk.loops[i as usize]);
+                    match std::mem::replace(&mut self.control_flow, ControlFlow::None) {
+                        ControlFlow::None => {}
+                        ControlFlow::Return(value) => return Ok(value),
+                        flow @ ControlFlow::TailCall(..) => {
+                            self.control_flow = flow;
+                            return Ok(Value::Nothing);
+                        }
+                        flow => {
+                            let exits = exits.expect("`break` and `continue` are in loops");
+                            stack.truncate(exits.stack);
+                            scopes.truncate(exits.scopes + 1);
+                            pc = match flow {
+                                ControlFlow::Break => exits.break_to,
+                                _ => exits.continue_to,
+                            };
+                        }
+                    }
+                }
+            }
+        }
+        Ok(stack.pop().unwrap_or(Value::Nothing))
+    }
+
+    /// Binds a new variable in `scope`. A local sharing its name with a
+    /// cached global may shadow it, so that invalidates the global caches.
+    fn declare(
         &mut self,
+        scope: &mut Scope,
+        name: Symbol,
+        value: Value,
+        loc: &Location,
+    ) -> Result<()> {
+        if scope.parent.is_some() && self.cached_globals.contains(&name) {
+            self.generation += 1;
+        }
+        scope.insert(name, value, false, loc)
+    }
+
+    /// Reads the variable `node`, from its slot if it's a `Local` whose slot
+    /// is filled, or else by name.
+    fn read(&mut self, node: &Arc<AST>, scope: &Ref<Scope>) -> Result<Value> {
+        let (loc, name) = match node.as_ref() {
+            AST::Local(loc, name, slot) => match scope.borrow().slot(*slot, *name) {
+                Some(value) => return Ok(value),
+                None => (loc, name),
+            },
+            AST::Variable(loc, name) => (loc, name),
+            node => unreachable!("read of {}", node.kind()),
+        };
+        match self.lookup(node, scope, *name) {
+            Some(value) => Ok(value),
+            None => Err(self.not_found(loc, name.as_str(), scope)),
+        }
+    }
+
+    /// Resolves the variable `name` read by `node`, consulting and filling
+    /// the node's inline cache.
+    fn lookup(&mut self, node: &Arc<AST>, scope: &Ref<Scope>, name: Symbol) -> Option<Value> {
+        match self.inline_caches.get(node) {
+            Some(&InlineCache::BuiltIn(name, func)) => {
+                return Some(Value::BuiltInFunction(name, func))
+            }
+            Some(&InlineCache::Global { depth, generation }) if generation == self.generation => {
+                let mut current = scope.clone();
+                for _ in 0..depth {
+                    let parent = current.borrow().parent.clone();
+                    match parent {
+                        Some(parent) => current = parent,
+                        None => break,
+                    }
+                }
+                let value = current.borrow().local(name);
+                if value.is_some() {
+                    return value;
+                }
+            }
+            _ => {}
+        }
+
+        if let Some((&name, &func)) = self.builtins.get_key_value(name.as_str()) {
+            if let AST::Variable(..) = node.as_ref() {
+                self.inline_caches
+                    .insert(node, InlineCache::BuiltIn(name, func));
+            }
+            return Some(Value::BuiltInFunction(name, func));
+        }
+        let mut current = scope.clone();
+        let mut depth = 0;
+        loop {
+            let parent = {
+                let scope = current.borrow();
+                if let Some(value) = scope.local(name) {
+                    if scope.parent.is_none() {
+                        let generation = self.generation;
+                        let cache = InlineCache::Global { depth, generation };
+                        self.inline_caches.insert(node, cache);
+                        self.cached_globals.insert(name);
+                    }
+                    return Some(value);
+                }
+                scope.parent.clone()?
+            };
+            current = parent;
+            depth += 1;
+        }
+    }
+
+    /// Finds the module `name`, trying host-registered modules, then
+    /// `<name>.rat` next to the importing script, then the project manifest.
+    /// A file runs only the first time it is imported.
+    fn load_module(&mut self, loc: &Location, name: &str) -> Result<Rc<Module>> {
+        if let Some(module) = self.modules.get(name) {
+            return Ok(module.clone());
+        }
+
+        let importer = PathBuf::from(&*loc.filename());
+        let path = match self.pinned_imports.get(&(importer, name.to_string())) {
+            Some(path) => path.clone(),
+            None => {
+                let path =
+                    resolve_import(Path::new(&*loc.filename()), name, self.manifest.as_ref());
+                match path.canonicalize() {
+                    Ok(path) => path,
+                    Err(_) => error!(
+                        loc,
+                        "No module named `{}` (looked for {})",
+                        name,
+                        path.display()
+                    ),
+                }
+            }
+        };
+
+        if let Some(module) = self.loaded.get(&path) {
+            return Ok(module.clone());
+        }
+        if let Some(start) = self.imports.iter().position(|import| import.path == path) {
+            let trace = self.imports[start..]
+                .iter()
+                .map(|import| format!("  {} imports `{}`", import.loc, import.name))
+                .collect::<Vec<_>>()
+                .join("\n");
+            error!(
+                loc,
+                "Circular import of `{}`:\n{}\n  {} imports `{}` again", name, trace, loc, name
+            )
+        }
+
+        self.imports.push(Import {
+            name: name.to_string(),
+            path: path.clone(),
+            loc: loc.clone(),
+        });
+        let module = self.run_module(name, &path, loc);
+        self.imports.pop();
+        let module = Rc::new(module?);
+        self.loaded.insert(path, module.clone());
+        Ok(module)
+    }
+
+    fn run_module(&mut self, name: &str, path: &Path, loc: &Location) -> Result<Module> {
+        let ast = match self.parsed.get(path) {
+            Some(ast) => ast.clone(),
+            None => {
+                let file = match File::open(path) {
+                    Ok(file) => file,
+                    Err(err) => error!(loc, "Couldn't read module {}: {}", path.display(), err),
+                };
+                let lexer = Lexer::from_reader(BufReader::new(file), path.display().to_string());
+                Parser::from_lexer(lexer).parse()?
+            }
+        };
+        let ast = self.optimized(&ast);
+        let scope = self.fresh_globals();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add_root(&ast);
+        }
+        self.run_block_without_scope(&ast, scope.clone())?;
+
+        let mut module = Module::new(name);
+        if let AST::Block(_, stmts) = ast.as_ref() {
+            for stmt in stmts {
+                let Some(member) = stmt.declared_name() else {
+                    continue;
+                };
+                match stmt.as_ref() {
+                    AST::Pub(..) => {
+                        let value = scope.borrow().local(member);
+                        let value = value.expect("the module ran its declarations");
+                        module.add_constant(member.as_str(), value);
+                    }
+                    _ => {
+                        module
+                            .private
+                            .insert(member.to_string(), stmt.loc().clone());
+                    }
+                }
+            }
+        }
+        Ok(module)
+    }
+
+    /// Runs a binary arithmetic operator, whose integer overflow follows the
+    /// interpreter's setting. It's kept out of `run`, where every operator's
+    /// copy of it would make the stack frame of each nested call bigger.
+    fn run_arithmetic(
+        &mut self,
+        loc: &Location,
+        op: fn(&Value, &Value, Overflow, &Location) -> Result<Value>,
+        symbol: &str,
+        left: &Arc<AST>,
+        right: &Arc<AST>,
         scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let left = self.run(left, scope.clone())?;
+        let right = self.run(right, scope.clone())?;
+        let method = operator_method(symbol);
+        if let Some(result) = self.run_overloaded(&scope, loc, method, &left, &[&right]) {
+            return result;
+        }
+        if self.strict {
+            left.check_strict(&right, symbol, loc)?;
+        }
+        op(&left, &right, self.overflow, loc)
+    }
+
+    /// Runs a comparison, which strict mode restricts like arithmetic, kept
+    /// out of `run` like [`Self::run_arithmetic`].
+    fn run_comparison(
+        &mut self,
         loc: &Location,
-        func: &Rc<AST>,
-        args: &[Rc<AST>],
+        op: fn(&Value, &Value, &Location) -> Result<Value>,
+        symbol: &str,
+        left: &Arc<AST>,
+        right: &Arc<AST>,
+        scope: Ref<Scope>,
     ) -> Result<Value> {
-        let func = self.run(func, scope.clone())?;
-        let args = args
-            .iter()
-            .map(|arg| self.run(arg, scope.clone()))
-            .collect::<Result<Vec<_>>>()?;
+        let left = self.run(left, scope.clone())?;
+        let right = self.run(right, scope.clone())?;
+        let method = operator_method(symbol);
+        if let Some(result) = self.run_overloaded(&scope, loc, method, &left, &[&right]) {
+            return result;
+        }
+        if self.strict {
+            left.check_strict(&right, symbol, loc)?;
+        }
+        op(&left, &right, loc)
+    }
+
+    /// Calls the method `name` of `receiver` with `args`, if `receiver` is
+    /// an instance whose struct overloads an operator with it. `!=` is the
+    /// opposite of `__eq__` unless `__ne__` is overloaded too.
+    fn run_overloaded(
+        &mut self,
+        scope: &Ref<Scope>,
+        loc: &Location,
+        name: &str,
+        receiver: &Value,
+        args: &[&Value],
+    ) -> Option<Result<Value>> {
+        let Value::Instance(of, _) = receiver else {
+            return None;
+        };
+        let (method, negate) = match Symbol::lookup(name).and_then(|name| of.method(name)) {
+            Some(method) => (method, false),
+            None if name == "__ne__" => (of.method(Symbol::lookup("__eq__")?)?, true),
+            None => return None,
+        };
+        let args = std::iter::once(receiver).chain(args.iter().copied());
+        let args = args.cloned().collect();
+        let result = self.call(scope, loc, &method, args);
+        Some(if negate {
+            result.map(|equal| Value::Boolean(!equal.is_truthy()))
+        } else {
+            result
+        })
+    }
+
+    fn run_slice(
+        &mut self,
+        loc: &Location,
+        lhs: &Arc<AST>,
+        bounds: [&Option<Arc<AST>>; 3],
+        scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let lhs = self.run(lhs, scope.clone())?;
+        let mut values = [None, None, None];
+        for (value, bound) in values.iter_mut().zip(bounds) {
+            if let Some(bound) = bound {
+                match self.run(bound, scope.clone())? {
+                    Value::Integer(i) => *value = Some(i),
+                    _ => error!(loc, "Invalid types for slice"),
+                }
+            }
+        }
+        let [start, end, step] = values;
+        let range = RangeValue {
+            start,
+            end,
+            step: step.unwrap_or(1),
+            inclusive: false,
+        };
+        lhs.slice(range, loc)
+    }
+
+    /// The error for reading the undeclared variable `name`, suggesting the
+    /// names in reach that are spelled like it.
+    fn not_found(&self, loc: &Location, name: &str, scope: &Ref<Scope>) -> Error {
+        let mut names = self.builtin_names().map(String::from).collect::<Vec<_>>();
+        let mut current = Some(scope.clone());
+        while let Some(scope) = current {
+            names.extend(scope.borrow().vars().map(|(name, _)| name.to_string()));
+            current = scope.borrow().parent.clone();
+        }
+        names.sort();
+        names.dedup();
+        let error = Error::Runtime(loc.clone(), format!("Variable {} not found", name));
+        with_suggestions(error, name, names.iter().map(String::as_str))
+    }
 
-        Ok(match &func {
-            Value::BuiltInFunction(func) => match self.builtins.get(func.as_str()) {
-                Some(func) => func(loc, args)?,
-                None => error!(loc, "Built-in function {:?} not found", func),
+    /// Reads `lhs.name`, which only modules have.
+    fn member(&self, loc: &Location, lhs: &Value, name: &str) -> Result<Value> {
+        Ok(match lhs {
+            Value::Module(module) => match module.members.get(name) {
+                Some(value) => value.clone(),
+                None if module.private.contains_key(name) => {
+                    let msg = format!(
+                        "`{}` is private to module `{}` (defined at {})",
+                        name, module.name, module.private[name]
+                    );
+                    return Err(Error::Runtime(loc.clone(), msg)
+                        .with_help("declare it with `pub` to use it from here"));
+                }
+                None => {
+                    let msg = format!("Module `{}` has no member `{}`", module.name, name);
+                    let suggestions = module.members.keys().map(String::as_str);
+                    return Err(with_suggestions(
+                        Error::Runtime(loc.clone(), msg),
+                        name,
+                        suggestions,
+                    ));
+                }
             },
-            Value::Function {
+            Value::Instance(of, fields) => {
+                let symbol = Symbol::lookup(name);
+                match symbol.and_then(|symbol| of.field(symbol)) {
+                    Some(i) => fields.borrow()[i].clone(),
+                    None if symbol.and_then(|symbol| of.method(symbol)).is_some() => {
+                        let msg = format!("`{}` is a method of {}", name, lhs.type_name());
+                        let help = format!("call it as `.{}()`", name);
+                        return Err(Error::Runtime(loc.clone(), msg).with_help(help));
+                    }
+                    None => return Err(no_field(loc, of, name)),
+                }
+            }
+            // the methods of a struct can be called on an instance by
+            // passing it first, as in `Point.norm(p)`
+            Value::Struct(of) => match Symbol::lookup(name).and_then(|name| of.method(name)) {
+                Some(method) => method,
+                None => {
+                    let msg = format!("{} has no method `{}`", of, name);
+                    let methods = of.methods.borrow();
+                    let methods = methods.keys().map(|method| method.as_str());
+                    return Err(with_suggestions(
+                        Error::Runtime(loc.clone(), msg),
+                        name,
+                        methods,
+                    ));
+                }
+            },
+            // a variant without fields is a value rather than a constructor
+            Value::Enum(of) => {
+                let symbol = Symbol::lookup(name);
+                match symbol.and_then(|symbol| of.variant(symbol)) {
+                    Some(variant) if variant.fields.is_empty() => {
+                        Value::Instance(variant.clone(), Rc::new(RefCell::new(vec![])))
+                    }
+                    Some(variant) => Value::Struct(variant.clone()),
+                    None => match symbol.and_then(|symbol| of.method(symbol)) {
+                        Some(method) => method,
+                        None => return Err(no_variant(loc, of, name)),
+                    },
+                }
+            }
+            _ if self.methods.contains_key(&(lhs.type_name(), name)) => {
+                let msg = format!("`{}` is a method of {}", name, lhs.type_name());
+                return Err(
+                    Error::Runtime(loc.clone(), msg).with_help(format!("call it as `.{}()`", name))
+                );
+            }
+            _ => error!(loc, "{} has no member `{}`", lhs.repr(), name),
+        })
+    }
+
+    /// The scope that calls to a function created in `scope` run in, which
+    /// holds only the function's captures, over the global scope. They
+    /// share their variables with `scope`, or are copies of them if the
+    /// function `moves` them.
+    fn closure(
+        &mut self,
+        loc: &Location,
+        captures: &[Capture],
+        moves: bool,
+        scope: &Ref<Scope>,
+    ) -> Result<Ref<Scope>> {
+        let globals = builtin::global_scope(scope);
+        if captures.is_empty() {
+            return Ok(globals);
+        }
+        let mut closure = Scope::new(Some(globals.clone()), false);
+        for &Capture { name, slot } in captures {
+            if moves {
+                let value = match slot {
+                    Some(slot) => scope.borrow().slot(slot, name),
+                    None => globals.borrow().get(name),
+                };
+                let Some(value) = value else {
+                    error!(
+                        loc,
+                        "Can't move {} into the function before it's declared", name
+                    )
+                };
+                closure.define(name, value);
+            } else {
+                let slot = slot.expect("only `move` functions capture globals");
+                let cell = scope.borrow_mut().share(slot, name);
+                closure.define_shared(name, cell);
+            }
+        }
+        let closure = Rc::new(RefCell::new(closure));
+        // a captured cell may hold the function itself
+        if self.gc.declared_in(&closure) {
+            self.gc.collect();
+        }
+        Ok(closure)
+    }
+
+    /// The `def`s of a struct or enum as functions created in `scope`.
+    fn method_values(
+        &mut self,
+        methods: &[Arc<AST>],
+        scope: &Ref<Scope>,
+    ) -> Result<HashMap<Symbol, Value>> {
+        let mut values = HashMap::new();
+        for method in methods {
+            let AST::Function {
+                loc,
+                name: Some(name),
+                args,
+                variadic,
+                defaults,
+                captures,
+                moves,
                 body,
-                args: func_args,
-                scope: closure_scope,
                 ..
-            } => {
-                let new_scope = Rc::new(RefCell::new(Scope {
-                    vars: HashMap::new(),
-                    parent: Some(closure_scope.clone()),
-                    in_function: true,
-                }));
-                if args.len() != func_args.len() {
+            } = method.as_ref()
+            else {
+                continue;
+            };
+            let method = Value::Function {
+                name: Some(*name),
+                loc: loc.clone(),
+                args: args.clone(),
+                variadic: *variadic,
+                defaults: defaults.clone(),
+                body: body.clone(),
+                scope: self.closure(loc, captures, *moves, scope)?,
+            };
+            values.insert(*name, method);
+        }
+        Ok(values)
+    }
+
+    /// Declares the struct `name` in `scope`, with methods created there.
+    fn declare_struct(
+        &mut self,
+        loc: &Location,
+        name: Symbol,
+        fields: &[Symbol],
+        methods: &[Arc<AST>],
+        scope: &Ref<Scope>,
+    ) -> Result<Value> {
+        let of = Value::Struct(Rc::new(StructType {
+            name,
+            variant_of: None,
+            fields: fields.to_vec(),
+            methods: RefCell::new(self.method_values(methods, scope)?),
+            loc: loc.clone(),
+        }));
+        self.declare(&mut scope.borrow_mut(), name, of.clone(), loc)?;
+        if self.gc.declared_in(scope) {
+            self.gc.collect();
+        }
+        Ok(of)
+    }
+
+    /// Declares an enum, whose variants are structs that share its methods.
+    fn declare_enum(
+        &mut self,
+        loc: &Location,
+        name: Symbol,
+        variants: &[Variant],
+        methods: &[Arc<AST>],
+        scope: &Ref<Scope>,
+    ) -> Result<Value> {
+        let methods = self.method_values(methods, scope)?;
+        let variants = variants.iter().map(|variant| {
+            Rc::new(StructType {
+                name: variant.name,
+                variant_of: Some(name),
+                fields: variant.fields.clone(),
+                methods: RefCell::new(methods.clone()),
+                loc: variant.loc.clone(),
+            })
+        });
+        let of = Value::Enum(Rc::new(EnumType {
+            name,
+            variants: variants.collect(),
+            loc: loc.clone(),
+        }));
+        self.declare(&mut scope.borrow_mut(), name, of.clone(), loc)?;
+        if self.gc.declared_in(scope) {
+            self.gc.collect();
+        }
+        Ok(of)
+    }
+
+    /// Runs `&`, `|`, `^` or `>>`, which can't overflow, kept out of `run`
+    /// like [`Self::run_arithmetic`].
+    fn run_bitwise(
+        &mut self,
+        loc: &Location,
+        op: fn(&Value, &Value, &Location) -> Result<Value>,
+        symbol: &str,
+        left: &Arc<AST>,
+        right: &Arc<AST>,
+        scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let left = self.run(left, scope.clone())?;
+        let right = self.run(right, scope.clone())?;
+        let method = operator_method(symbol);
+        if let Some(result) = self.run_overloaded(&scope, loc, method, &left, &[&right]) {
+            return result;
+        }
+        op(&left, &right, loc)
+    }
+
+    fn run_list(&mut self, items: &[Arc<AST>], scope: Ref<Scope>) -> Result<Value> {
+        let items = items
+            .iter()
+            .map(|item| self.run(item, scope.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Value::List(Rc::new(RefCell::new(items))))
+    }
+
+    /// Runs `body` once for each value of `iter`, taking one value at a
+    /// time, so the body may step through the same iterator itself.
+    fn run_for(
+        &mut self,
+        loc: &Location,
+        loop_var: Symbol,
+        iter: &IteratorValue,
+        body: &Arc<AST>,
+        scope: Ref<Scope>,
+    ) -> Result<()> {
+        while let Some(val) = iter.next(self, &scope, loc)? {
+            let mut loop_scope = Scope::child(&scope);
+            self.declare(&mut loop_scope, loop_var, val, loc)?;
+            self.run(body, Rc::new(RefCell::new(loop_scope)))?;
+            match self.control_flow {
+                ControlFlow::None => {}
+                ControlFlow::Continue => self.control_flow = ControlFlow::None,
+                ControlFlow::Break => {
+                    self.control_flow = ControlFlow::None;
+                    break;
+                }
+                ControlFlow::Return(_) | ControlFlow::TailCall(..) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns from the running function with a generator that will run
+    /// `body` in a scope of its own inside `scope`, the function's arguments.
+    fn start_generator(&mut self, body: &Arc<AST>, scope: Ref<Scope>) -> Value {
+        let frame = GeneratorFrame::Block {
+            block: body.clone(),
+            next: 0,
+            scope: Rc::new(RefCell::new(Scope::child(&scope))),
+        };
+        let generator = Generator {
+            frames: vec![frame],
+        };
+        self.control_flow = ControlFlow::Return(Value::Iterator(IteratorValue::new(generator)));
+        Value::Nothing
+    }
+
+    /// Runs `generator` until its next `yield`, or until its body ends. A
+    /// generator that fails is finished.
+    pub(crate) fn resume(&mut self, generator: &mut Generator) -> Result<Option<Value>> {
+        let control_flow = std::mem::replace(&mut self.control_flow, ControlFlow::None);
+        let tail_calls = std::mem::replace(&mut self.tail_calls, false);
+        let value = self.resume_frames(&mut generator.frames);
+        self.tail_calls = tail_calls;
+        self.control_flow = control_flow;
+        if value.is_err() {
+            generator.frames.clear();
+        }
+        value
+    }
+
+    fn resume_frames(&mut self, frames: &mut Vec<GeneratorFrame>) -> Result<Option<Value>> {
+        while let Some(frame) = frames.last_mut() {
+            let value = match frame {
+                GeneratorFrame::Block { block, next, scope } => {
+                    let AST::Block(_, stmts) = block.as_ref() else {
+                        unreachable!("generator block frame on a non-block")
+                    };
+                    let Some(stmt) = stmts.get(*next).cloned() else {
+                        frames.pop();
+                        continue;
+                    };
+                    *next += 1;
+                    let scope = scope.clone();
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.hit_statement(&stmt);
+                    }
+                    self.step_generator(frames, &stmt, scope)?
+                }
+                GeneratorFrame::While { node, scope } => {
+                    let (node, scope) = (node.clone(), scope.clone());
+                    let AST::While(loc, cond, body) = node.as_ref() else {
+                        unreachable!("generator while frame on a non-while")
+                    };
+                    let taken = self.run(cond, scope.clone())?;
+                    let taken = taken.condition(self.strict, "while", loc)?;
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.hit_branch(&node, taken);
+                    }
+                    match taken {
+                        true => self.step_generator(frames, body, scope)?,
+                        false => {
+                            frames.pop();
+                            None
+                        }
+                    }
+                }
+                GeneratorFrame::For { node, iter, scope } => {
+                    let (node, iter, scope) = (node.clone(), iter.clone(), scope.clone());
+                    let AST::For(loc, loop_var, _, body) = node.as_ref() else {
+                        unreachable!("generator for frame on a non-for")
+                    };
+                    match iter.next(self, &scope, loc)? {
+                        Some(val) => {
+                            let mut loop_scope = Scope::new(Some(scope.clone()), true);
+                            self.declare(&mut loop_scope, *loop_var, val, loc)?;
+                            let loop_scope = Rc::new(RefCell::new(loop_scope));
+                            self.step_generator(frames, body, loop_scope)?
+                        }
+                        None => {
+                            frames.pop();
+                            None
+                        }
+                    }
+                }
+            };
+            if value.is_some() {
+                return Ok(value);
+            }
+            match std::mem::replace(&mut self.control_flow, ControlFlow::None) {
+                ControlFlow::None => {}
+                ControlFlow::Return(_) | ControlFlow::TailCall(..) => frames.clear(),
+                flow => {
+                    while let Some(GeneratorFrame::Block { .. }) = frames.last() {
+                        frames.pop();
+                    }
+                    if let ControlFlow::Break = flow {
+                        frames.pop();
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Starts running `stmt` in a generator. A `yield` gives its value, the
+    /// blocks and loops that may `yield` are pushed as frames to be stepped
+    /// through, and anything else is run as usual.
+    fn step_generator(
+        &mut self,
+        frames: &mut Vec<GeneratorFrame>,
+        stmt: &Arc<AST>,
+        scope: Ref<Scope>,
+    ) -> Result<Option<Value>> {
+        match stmt.as_ref() {
+            AST::Yield(_, value) => return self.run(value, scope).map(Some),
+            AST::Block(..) => frames.push(GeneratorFrame::Block {
+                block: stmt.clone(),
+                next: 0,
+                scope: Rc::new(RefCell::new(Scope::new(Some(scope), true))),
+            }),
+            AST::If(loc, cond, body, else_body) => {
+                let taken = self.run(cond, scope.clone())?;
+                let taken = taken.condition(self.strict, "if", loc)?;
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.hit_branch(stmt, taken);
+                }
+                match (taken, else_body) {
+                    (true, _) => return self.step_generator(frames, body, scope),
+                    (false, Some(else_body)) => {
+                        return self.step_generator(frames, else_body, scope)
+                    }
+                    (false, None) => {}
+                }
+            }
+            AST::While(..) => frames.push(GeneratorFrame::While {
+                node: stmt.clone(),
+                scope,
+            }),
+            AST::For(loc, _, iter, _) => {
+                let iter = self.run(iter, scope.clone())?.iterator(loc);
+                let Value::Iterator(iter) = iter else {
+                    error!(loc, "For loop must iterate over an iterable")
+                };
+                frames.push(GeneratorFrame::For {
+                    node: stmt.clone(),
+                    iter,
+                    scope,
+                });
+            }
+            _ => {
+                self.run(stmt, scope)?;
+            }
+        }
+        Ok(None)
+    }
+
+    fn run_tuple(&mut self, items: &[Arc<AST>], scope: Ref<Scope>) -> Result<Value> {
+        let items = items
+            .iter()
+            .map(|item| self.run(item, scope.clone()))
+            .collect::<Result<Rc<[Value]>>>()?;
+        Ok(Value::Tuple(items))
+    }
+
+    /// Declares `name` in `scope`, which mustn't already have it.
+    fn declare_variable(
+        &mut self,
+        loc: &Location,
+        name: Symbol,
+        value: Value,
+        scope: &Ref<Scope>,
+    ) -> Result<()> {
+        if scope.borrow().contains(name) {
+            error!(loc, "Variable {} already exists in scope", name)
+        }
+        if self.builtins.contains_key(name.as_str()) {
+            error!(
+                loc,
+                "`{}` is a built-in function, can't be used as a variable", name
+            )
+        }
+        self.declare(&mut scope.borrow_mut(), name, value, loc)
+    }
+
+    /// Declares the names in `pattern`, a name or a tuple of patterns, from
+    /// the parts of `value`. `_` in a tuple skips an item.
+    fn declare_pattern(&mut self, pattern: &AST, value: Value, scope: &Ref<Scope>) -> Result<()> {
+        match pattern {
+            AST::Variable(loc, name) => self.declare_variable(loc, *name, value, scope),
+            AST::Tuple(loc, patterns) => {
+                let items = unpack(loc, &value, patterns.len())?;
+                for (pattern, item) in patterns.iter().zip(items) {
+                    if !is_wildcard(pattern) {
+                        self.declare_pattern(pattern, item, scope)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => error!(pattern.loc(), "Can't declare {}", pattern.kind()),
+        }
+    }
+
+    /// Stores `value` in the variable, item, or tuple of targets that `lhs`
+    /// names. `_` in a tuple skips an item.
+    fn assign(&mut self, lhs: &Arc<AST>, value: Value, scope: &Ref<Scope>) -> Result<()> {
+        match lhs.as_ref() {
+            AST::Variable(loc, name) | AST::Local(loc, name, _) => {
+                if let AST::Local(_, _, slot) = lhs.as_ref() {
+                    if scope.borrow_mut().set_slot(*slot, *name, &value) {
+                        return Ok(());
+                    }
+                }
+                if scope.borrow_mut().get(*name).is_none() {
+                    error!(loc, "Variable {} doesn't exist", name)
+                }
+                if self.builtins.contains_key(name.as_str()) {
+                    error!(loc, "`{}` is a built-in function, can't override it", name)
+                }
+                scope.borrow_mut().insert(*name, value, true, loc)
+            }
+            AST::Index(loc, target, index) => {
+                let target = self.run(target, scope.clone())?;
+                let index = self.run(index, scope.clone())?;
+                let args = [&index, &value];
+                match self.run_overloaded(scope, loc, "__set_index__", &target, &args) {
+                    Some(result) => result.map(drop),
+                    None => target.set_index(index, value, loc),
+                }
+            }
+            AST::Member(loc, target, name) => match self.run(target, scope.clone())? {
+                Value::Instance(of, fields) => match of.field(*name) {
+                    Some(i) => {
+                        fields.borrow_mut()[i] = value;
+                        Ok(())
+                    }
+                    None => Err(no_field(loc, &of, name.as_str())),
+                },
+                other => error!(loc, "Can't set `{}` of {}", name, other.repr()),
+            },
+            AST::Tuple(loc, targets) => {
+                let items = unpack(loc, &value, targets.len())?;
+                for (target, item) in targets.iter().zip(items) {
+                    if !is_wildcard(target) {
+                        self.assign(target, item, scope)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => error!(lhs.loc(), "Can't assign to {}", lhs.kind()),
+        }
+    }
+
+    fn run_dict(
+        &mut self,
+        loc: &Location,
+        entries: &[(Arc<AST>, Arc<AST>)],
+        scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let mut dict = Dict::new();
+        for (key, value) in entries {
+            let key = self.run(key, scope.clone())?;
+            let value = self.run(value, scope.clone())?;
+            dict.insert(key, value, loc)?;
+        }
+        Ok(Value::Dict(Rc::new(RefCell::new(dict))))
+    }
+
+    fn run_match(
+        &mut self,
+        loc: &Location,
+        subject: &Arc<AST>,
+        arms: &[MatchArm],
+        scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let subject = self.run(subject, scope.clone())?;
+        for arm in arms {
+            let mut arm_scope = Scope::child(&scope);
+            if !self.bind_pattern(&arm.pattern, &subject, &mut arm_scope, &scope)? {
+                continue;
+            }
+            let arm_scope = Rc::new(RefCell::new(arm_scope));
+            if let Some(guard) = &arm.guard {
+                match self.run(guard, arm_scope.clone())? {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => continue,
+                    _ => error!(guard.loc(), "Match guard must be a boolean"),
+                }
+            }
+            return self.run(&arm.body, arm_scope);
+        }
+        error!(loc, "No arm of the match matched {}", subject.repr())
+    }
+
+    fn run_throw(&mut self, loc: &Location, value: &Arc<AST>, scope: Ref<Scope>) -> Result<Value> {
+        let value = self.run(value, scope)?;
+        let repr = value.repr();
+        self.thrown = Some(value);
+        Err(Error::Thrown(loc.clone(), repr))
+    }
+
+    /// Runs a `try`. Its `catch` gets the thrown value, or the message of a
+    /// runtime error. `finally` runs however the `try` ends, and a `return`,
+    /// `break`, `continue` or error of its own replaces how it ended, even
+    /// if it threw. Errors a `catch` couldn't catch aren't replaced.
+    fn run_try(
+        &mut self,
+        body: &Arc<AST>,
+        catch: Option<&(Symbol, Arc<AST>)>,
+        finally: Option<&Arc<AST>>,
+        scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let tail_calls = std::mem::replace(&mut self.tail_calls, false);
+        let result = self.run_try_without_tail_calls(body, catch, finally, scope);
+        self.tail_calls = tail_calls;
+        result
+    }
+
+    fn run_try_without_tail_calls(
+        &mut self,
+        body: &Arc<AST>,
+        catch: Option<&(Symbol, Arc<AST>)>,
+        finally: Option<&Arc<AST>>,
+        scope: Ref<Scope>,
+    ) -> Result<Value> {
+        let mut result = self.run(body, scope.clone());
+        if let (Err(err), Some((name, handler))) = (&result, catch) {
+            if let Some(caught) = self.caught(err) {
+                let mut catch_scope = Scope::child(&scope);
+                self.declare(&mut catch_scope, *name, caught, handler.loc())?;
+                result = self.run_block_without_scope(handler, Rc::new(RefCell::new(catch_scope)));
+            }
+        }
+        if let Some(finally) = finally {
+            let control_flow = std::mem::replace(&mut self.control_flow, ControlFlow::None);
+            self.run(finally, scope)?;
+            match self.control_flow {
+                ControlFlow::None => self.control_flow = control_flow,
+                _ if result.as_ref().is_err_and(catchable) => {
+                    self.thrown = None;
+                    result = Ok(Value::Nothing);
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+
+    /// The value a `catch` gets for `err`, `None` for errors that aren't the
+    /// script's to catch, like a cancellation.
+    fn caught(&mut self, err: &Error) -> Option<Value> {
+        match err {
+            Error::Traced(err, _) | Error::Help(err, _) => self.caught(err),
+            Error::Thrown(..) => self.thrown.take(),
+            Error::Runtime(_, msg) => Some(Value::String(msg.as_str().into())),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` matches `pattern`, binding the pattern's names in
+    /// `arm_scope` if it does. Literals and structs run in `scope`.
+    fn bind_pattern(
+        &mut self,
+        pattern: &Arc<AST>,
+        value: &Value,
+        arm_scope: &mut Scope,
+        scope: &Ref<Scope>,
+    ) -> Result<bool> {
+        Ok(match pattern.as_ref() {
+            AST::Variable(_, name) if name == "_" => true,
+            AST::Variable(loc, name) => {
+                self.declare(arm_scope, *name, value.clone(), loc)?;
+                true
+            }
+            AST::List(_, patterns) => {
+                let Value::List(items) = value else {
+                    return Ok(false);
+                };
+                self.bind_items(patterns, &items.borrow(), arm_scope, scope)?
+            }
+            AST::Tuple(_, patterns) => {
+                let Value::Tuple(items) = value else {
+                    return Ok(false);
+                };
+                self.bind_items(patterns, items, arm_scope, scope)?
+            }
+            AST::Call(loc, of, patterns) => {
+                let Value::Struct(of) = self.run(of, scope.clone())? else {
+                    error!(loc, "Only structs and variants can be matched with fields")
+                };
+                if patterns.len() != of.fields.len() {
+                    let plural = if of.fields.len() == 1 { "" } else { "s" };
                     error!(
                         loc,
-                        "Expected {} arguments, got {}",
-                        func_args.len(),
-                        args.len()
+                        "{} has {} field{}, but the pattern has {}",
+                        of,
+                        of.fields.len(),
+                        plural,
+                        patterns.len()
                     )
                 }
-                for (arg, value) in func_args.iter().zip(args) {
-                    new_scope
-                        .borrow_mut()
-                        .insert(arg.clone(), value, false, loc)?;
+                match value {
+                    Value::Instance(value_of, fields) if Rc::ptr_eq(&of, value_of) => {
+                        let fields = fields.borrow().clone();
+                        self.bind_items(patterns, &fields, arm_scope, scope)?
+                    }
+                    _ => false,
                 }
-                self.run(body, new_scope)?;
-                let value = if let ControlFlow::Return(value) = &self.control_flow {
-                    value.clone()
-                } else {
-                    Value::Nothing
-                };
-                self.control_flow = ControlFlow::None;
+            }
+            _ => self.run(pattern, scope.clone())?.is_equal(value),
+        })
+    }
+
+    /// Whether each item matches the pattern in the same place, like
+    /// [`Interpreter::bind_pattern`].
+    fn bind_items(
+        &mut self,
+        patterns: &[Arc<AST>],
+        items: &[Value],
+        arm_scope: &mut Scope,
+        scope: &Ref<Scope>,
+    ) -> Result<bool> {
+        if items.len() != patterns.len() {
+            return Ok(false);
+        }
+        for (pattern, item) in patterns.iter().zip(items) {
+            if !self.bind_pattern(pattern, item, arm_scope, scope)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn handle_call(
+        &mut self,
+        scope: Ref<Scope>,
+        loc: &Location,
+        func: &Arc<AST>,
+        args: &[Arc<AST>],
+    ) -> Result<Value> {
+        // calls to built-ins skip creating and matching on a function value
+        if let Some(&InlineCache::BuiltIn(_, builtin)) = self.inline_caches.get(func) {
+            let (args, named) = self.run_arguments(&scope, args)?;
+            return self.call_builtin(builtin, &scope, loc, args, named);
+        }
+
+        if let AST::Member(member_loc, lhs, name) = func.as_ref() {
+            return self.call_member(&scope, loc, member_loc, lhs, name.as_str(), args);
+        }
+
+        let func = self.run(func, scope.clone())?;
+        let (args, named) = self.run_arguments(&scope, args)?;
+        self.call_with_named(&scope, loc, &func, args, named)
+    }
+
+    /// Calls `lhs.name(args)`, a function of a module or a method of a
+    /// struct, a field of an instance, or otherwise the method `name` of
+    /// the type of `lhs`, which gets `lhs` as its first argument.
+    fn call_member(
+        &mut self,
+        scope: &Ref<Scope>,
+        loc: &Location,
+        member_loc: &Location,
+        lhs: &Arc<AST>,
+        name: &str,
+        args: &[Arc<AST>],
+    ) -> Result<Value> {
+        let lhs = self.run(lhs, scope.clone())?;
+        if let Value::Module(_) | Value::Struct(_) | Value::Enum(_) = lhs {
+            let func = self.member(member_loc, &lhs, name)?;
+            let (args, named) = self.run_arguments(scope, args)?;
+            return self.call_with_named(scope, loc, &func, args, named);
+        }
+        if let Value::Instance(of, fields) = &lhs {
+            // a field holding a function is called like a method, but
+            // without the instance
+            let symbol = Symbol::lookup(name);
+            let (func, receiver) = match symbol.and_then(|name| of.field(name)) {
+                Some(i) => (fields.borrow()[i].clone(), None),
+                None => match symbol.and_then(|name| of.method(name)) {
+                    Some(method) => (method, Some(lhs.clone())),
+                    None => return Err(no_member(member_loc, of, name)),
+                },
+            };
+            let (mut args, named) = self.run_arguments(scope, args)?;
+            args.splice(0..0, receiver);
+            return self.call_with_named(scope, loc, &func, args, named);
+        }
+        let type_name = lhs.type_name();
+        let Some(&method) = self.methods.get(&(type_name, name)) else {
+            let msg = format!("{} has no method `{}`", lhs.repr(), name);
+            let suggestions = self
+                .methods
+                .keys()
+                .filter(|(of, _)| *of == type_name)
+                .map(|(_, method)| *method);
+            return Err(with_suggestions(
+                Error::Runtime(member_loc.clone(), msg),
+                name,
+                suggestions,
+            ));
+        };
+        let (mut args, named) = self.run_arguments(scope, args)?;
+        args.insert(0, lhs);
+        self.call_builtin(method, scope, loc, args, named)
+    }
+
+    /// Evaluates the arguments of a call, setting the named ones aside and
+    /// passing the items of spread ones as positional arguments.
+    fn run_arguments(
+        &mut self,
+        scope: &Ref<Scope>,
+        args: &[Arc<AST>],
+    ) -> Result<(Vec<Value>, NamedArguments)> {
+        let mut positional = Vec::with_capacity(args.len());
+        let mut named: NamedArguments = vec![];
+        for arg in args {
+            match arg.as_ref() {
+                AST::NamedArgument(loc, name, value) => {
+                    if named.iter().any(|(other, _)| other == name) {
+                        error!(loc, "Argument `{}` is passed more than once", name);
+                    }
+                    named.push((*name, self.run(value, scope.clone())?));
+                }
+                AST::Spread(loc, value) => match self.run(value, scope.clone())? {
+                    Value::List(items) => positional.extend(items.borrow().iter().cloned()),
+                    Value::Tuple(items) => positional.extend(items.iter().cloned()),
+                    other => error!(
+                        loc,
+                        "Can only spread a tuple or list into arguments, not {}",
+                        other.repr()
+                    ),
+                },
+                _ => positional.push(self.run(arg, scope.clone())?),
+            }
+        }
+        Ok((positional, named))
+    }
+
+    /// Calls a built-in, which takes the named arguments it understands with
+    /// [`Interpreter::named_argument`]. Any left over are an error.
+    fn call_builtin(
+        &mut self,
+        func: BuiltInFunctionType,
+        scope: &Ref<Scope>,
+        loc: &Location,
+        args: Vec<Value>,
+        named: NamedArguments,
+    ) -> Result<Value> {
+        // built-ins can call back into the interpreter, so the caller's own
+        // named arguments have to survive the call
+        let outer = std::mem::replace(&mut self.named_args, named);
+        let result = func(self, scope, loc, args);
+        let unused = std::mem::replace(&mut self.named_args, outer);
+        let value = result?;
+        if let Some((name, _)) = unused.first() {
+            error!(loc, "Unexpected argument `{}`", name);
+        }
+        Ok(value)
+    }
+
+    fn call_host(&mut self, host: &HostFunction, loc: &Location, args: &[Value]) -> Result<Value> {
+        match host.call(args) {
+            Ok(value) => Ok(value),
+            // hosts have no location to give their errors
+            Err(Error::Other(msg)) => error!(loc, "{}() {}", host.name, msg),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Fails if the running built-in was passed named arguments it hasn't
+    /// taken, so that it can refuse them before doing anything.
+    pub fn no_more_named_arguments(&self, loc: &Location) -> Result<()> {
+        match self.named_args.first() {
+            Some((name, _)) => error!(loc, "Unexpected argument `{}`", name),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the argument named `name` from the call of the running built-in.
+    pub fn named_argument(&mut self, name: &str) -> Option<Value> {
+        let index = self.named_args.iter().position(|(arg, _)| arg == name)?;
+        Some(self.named_args.remove(index).1)
+    }
+
+    /// Calls `func` with already evaluated positional and named arguments.
+    pub fn call_with_named(
+        &mut self,
+        scope: &Ref<Scope>,
+        loc: &Location,
+        func: &Value,
+        args: Vec<Value>,
+        named: NamedArguments,
+    ) -> Result<Value> {
+        match func {
+            Value::BuiltInFunction(_, func) | Value::NativeFunction { func, .. } => {
+                self.call_builtin(*func, scope, loc, args, named)
+            }
+            Value::Struct(of) => construct(loc, of, args, named),
+            Value::Function { .. } => self.call_function(loc, func, args, named),
+            _ => match named.first() {
+                Some((name, _)) => error!(loc, "Unexpected argument `{}`", name),
+                None => self.call(scope, loc, func, args),
+            },
+        }
+    }
+
+    /// Calls `func` with already evaluated arguments.
+    pub fn call(
+        &mut self,
+        scope: &Ref<Scope>,
+        loc: &Location,
+        func: &Value,
+        args: Vec<Value>,
+    ) -> Result<Value> {
+        Ok(match func {
+            Value::BuiltInFunction(..) | Value::NativeFunction { .. } | Value::Struct(_) => {
+                self.call_with_named(scope, loc, func, args, vec![])?
+            }
+            Value::HostFunction(host) => self.call_host(host, loc, &args)?,
+            Value::Memoized(memo) => {
+                let key = args
+                    .iter()
+                    .map(|arg| HashKey::from_value(arg, loc))
+                    .collect::<Result<Vec<_>>>()?;
+                if let Some(value) = memo.cache.borrow().get(&key) {
+                    return Ok(value.clone());
+                }
+                let value = self.call(scope, loc, &memo.func, args)?;
+                memo.cache.borrow_mut().insert(key, value.clone());
                 value
             }
-            _ => error!(loc, "Can't call object {:?}", func),
+            Value::Function { .. } => self.call_function(loc, func, args, vec![])?,
+            _ => error!(loc, "Can't call object {}", func.repr()),
+        })
+    }
+
+    /// Calls the script function `func`, and then the functions it and
+    /// they tail call, one after the other. An error's traceback has the
+    /// call to `func` and the last tail call, but not the ones between.
+    fn call_function(
+        &mut self,
+        loc: &Location,
+        func: &Value,
+        args: Vec<Value>,
+        named: NamedArguments,
+    ) -> Result<Value> {
+        if self.depth >= self.max_depth {
+            error!(
+                loc,
+                "Maximum recursion depth of {} exceeded", self.max_depth
+            )
+        }
+        self.depth += 1;
+        let tail_calls = std::mem::replace(&mut self.tail_calls, true);
+        let mut result = self.run_function(loc, func, args, named);
+        let mut tail_called = false;
+        while let Ok(ControlFlow::TailCall(func, args, loc)) = result {
+            result = self.run_function(&loc, &func, args, vec![]);
+            tail_called = true;
+        }
+        self.tail_calls = tail_calls;
+        self.depth -= 1;
+        let flow = match (result, tail_called) {
+            (Err(err), true) => return Err(err.with_frame(call_frame(func, loc))),
+            (result, _) => result?,
+        };
+        Ok(match flow {
+            ControlFlow::Return(value) => value,
+            _ => Value::Nothing,
         })
     }
+
+    /// Runs the body of `func`, which returns a value or the call it ends
+    /// with.
+    fn run_function(
+        &mut self,
+        loc: &Location,
+        func: &Value,
+        args: Vec<Value>,
+        named: NamedArguments,
+    ) -> Result<ControlFlow> {
+        let Value::Function {
+            body,
+            args: func_args,
+            variadic,
+            scope: closure_scope,
+            ..
+        } = func
+        else {
+            unreachable!("run_function called on {}", func.repr())
+        };
+        let new_scope = Rc::new(RefCell::new(Scope::new(Some(closure_scope.clone()), true)));
+        if named.is_empty() && args.len() == func_args.len() && !variadic {
+            for (arg, value) in func_args.iter().zip(args) {
+                self.declare(&mut new_scope.borrow_mut(), *arg, value, loc)?;
+            }
+        } else {
+            self.bind_arguments(loc, func, args, named, &new_scope)?;
+        }
+        let result = if self.vm && self.coverage.is_none() {
+            let chunk = self.chunk(body, Chunk::function);
+            self.run_chunk(&chunk, new_scope)
+        } else {
+            self.run(body, new_scope).map(|_| Value::Nothing)
+        };
+        let value = result.map_err(|err| err.with_frame(call_frame(func, loc)))?;
+        Ok(
+            match std::mem::replace(&mut self.control_flow, ControlFlow::None) {
+                flow @ (ControlFlow::Return(_) | ControlFlow::TailCall(..)) => flow,
+                _ => ControlFlow::Return(value),
+            },
+        )
+    }
+
+    /// Declares the parameters of `func` in `scope`, the scope of its call
+    /// at `loc`, from the arguments `args` and `named` or else from their
+    /// defaults. A `...rest` parameter gets the positional arguments left
+    /// over as a list.
+    fn bind_arguments(
+        &mut self,
+        loc: &Location,
+        func: &Value,
+        mut args: Vec<Value>,
+        named: NamedArguments,
+        scope: &Ref<Scope>,
+    ) -> Result<()> {
+        let Value::Function {
+            name,
+            args: params,
+            variadic,
+            defaults,
+            ..
+        } = func
+        else {
+            unreachable!("bind_arguments called on {}", func.repr())
+        };
+        let (params, rest) = match variadic {
+            true => params.split_at(params.len() - 1),
+            false => (&params[..], &[][..]),
+        };
+        let extra = match variadic {
+            true => args.split_off(args.len().min(params.len())),
+            false => vec![],
+        };
+        let names = named.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        let kind = Error::Runtime;
+        let sources =
+            match_arguments(loc, *name, params, defaults.len(), args.len(), &names, kind)?;
+        let mut args = args.into_iter().map(Some).collect::<Vec<_>>();
+        let mut named = named
+            .into_iter()
+            .map(|(_, value)| Some(value))
+            .collect::<Vec<_>>();
+        let first_default = params.len() - defaults.len();
+        for (i, (param, source)) in params.iter().zip(sources).enumerate() {
+            let value = match source {
+                Argument::Positional(j) => args[j].take(),
+                Argument::Named(j) => named[j].take(),
+                Argument::Default => None,
+            };
+            let value = match value {
+                Some(value) => value,
+                None => self
+                    .run(&defaults[i - first_default], scope.clone())
+                    .map_err(|err| err.with_frame(call_frame(func, loc)))?,
+            };
+            self.declare(&mut scope.borrow_mut(), *param, value, loc)?;
+        }
+        if let Some(rest) = rest.first() {
+            self.declare(&mut scope.borrow_mut(), *rest, Value::from(extra), loc)?;
+        }
+        Ok(())
+    }
+
+    /// How `return value` leaves the running function. A call to a script
+    /// function is left to the caller when it can be.
+    fn run_return(&mut self, value: &Arc<AST>, scope: Ref<Scope>) -> Result<ControlFlow> {
+        match value.plain_call() {
+            Some((loc, func, args)) if self.tail_calls => {
+                self.step(loc)?;
+                let func = self.run(func, scope.clone())?;
+                let (args, _) = self.run_arguments(&scope, args)?;
+                self.tail_call(&scope, loc, func, args)
+            }
+            _ => Ok(ControlFlow::Return(self.run(value, scope)?)),
+        }
+    }
+
+    /// Returns the call `func(args)` at `loc` from the running function,
+    /// which makes the call right away unless it's to a script function.
+    fn tail_call(
+        &mut self,
+        scope: &Ref<Scope>,
+        loc: &Location,
+        func: Value,
+        args: Vec<Value>,
+    ) -> Result<ControlFlow> {
+        Ok(match func {
+            Value::Function { .. } if self.tail_calls => {
+                ControlFlow::TailCall(func, args, loc.clone())
+            }
+            _ => ControlFlow::Return(self.call_with_named(scope, loc, &func, args, vec![])?),
+        })
+    }
+}
+
+/// Where a parameter of a function gets its value from in a call.
+pub(crate) enum Argument {
+    /// The positional argument with this index.
+    Positional(usize),
+    /// The named argument with this index.
+    Named(usize),
+    /// The parameter's default value.
+    Default,
+}
+
+/// Matches the arguments of a call at `loc` to the parameters `params` of
+/// the function `name`, whose last `defaults` have default values. The call
+/// passes `positional` arguments and then those in `named`. A mismatch is
+/// reported as a `kind` of error that names the parameters at fault.
+pub(crate) fn match_arguments(
+    loc: &Location,
+    name: Option<Symbol>,
+    params: &[Symbol],
+    defaults: usize,
+    positional: usize,
+    named: &[Symbol],
+    kind: fn(Location, String) -> Error,
+) -> Result<Vec<Argument>> {
+    let required = params.len() - defaults;
+    if positional > params.len() {
+        let expected = match (required, params.len()) {
+            (_, 1) if defaults == 0 => "1 argument".to_string(),
+            (required, all) if required == all => format!("{} arguments", all),
+            (required, all) => format!("{} to {} arguments", required, all),
+        };
+        let msg = format!(
+            "{} takes {}, but got {}",
+            describe(name),
+            expected,
+            positional
+        );
+        return Err(kind(loc.clone(), msg));
+    }
+    let mut sources = (0..params.len())
+        .map(|i| (i < positional).then_some(Argument::Positional(i)))
+        .collect::<Vec<_>>();
+    for (j, arg) in named.iter().enumerate() {
+        let Some(i) = params.iter().position(|param| param == arg) else {
+            let msg = format!("{} has no parameter `{}`", describe(name), arg);
+            let candidates = params.iter().map(|param| param.as_str());
+            return Err(with_suggestions(
+                kind(loc.clone(), msg),
+                arg.as_str(),
+                candidates,
+            ));
+        };
+        if sources[i].replace(Argument::Named(j)).is_some() {
+            let msg = format!("{} got the argument `{}` twice", describe(name), arg);
+            return Err(kind(loc.clone(), msg));
+        }
+    }
+    let missing = params[..required]
+        .iter()
+        .zip(&sources)
+        .filter(|(_, source)| source.is_none())
+        .map(|(param, _)| format!("`{}`", param))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        let arguments = match missing.len() {
+            1 => "the argument",
+            _ => "the arguments",
+        };
+        let msg = format!(
+            "{} is missing {} {}",
+            describe(name),
+            arguments,
+            missing.join(", ")
+        );
+        return Err(kind(loc.clone(), msg));
+    }
+    let sources = sources
+        .into_iter()
+        .map(|source| source.unwrap_or(Argument::Default));
+    Ok(sources.collect())
+}
+
+/// How messages about the function `name` refer to it at the start of a
+/// sentence.
+pub(crate) fn describe(name: Option<Symbol>) -> String {
+    match name {
+        Some(name) => format!("`{}`", name),
+        None => "The lambda".to_string(),
+    }
+}
+
+/// The traceback frame of a call at `call` to the script function `func`.
+fn call_frame(func: &Value, call: &Location) -> Frame {
+    let Value::Function { name, .. } = func else {
+        unreachable!("call_frame called on {}", func.repr())
+    };
+    Frame {
+        function: name.map(|name| name.to_string()),
+        call: call.clone(),
+    }
+}
+
+/// The method a struct overloads the binary operator `symbol` with.
+fn operator_method(symbol: &str) -> &'static str {
+    match symbol {
+        "+" => "__add__",
+        "-" => "__sub__",
+        "*" => "__mul__",
+        "/" => "__div__",
+        "%" => "__mod__",
+        "**" => "__pow__",
+        "<<" => "__lshift__",
+        ">>" => "__rshift__",
+        "&" => "__and__",
+        "|" => "__or__",
+        "^" => "__xor__",
+        "==" => "__eq__",
+        "!=" => "__ne__",
+        "<" => "__lt__",
+        ">" => "__gt__",
+        "<=" => "__le__",
+        ">=" => "__ge__",
+        _ => unreachable!("{} isn't a binary operator", symbol),
+    }
+}
+
+/// An instance of the struct `of`, with its fields given in order by
+/// `args` and by name by `named`.
+fn construct(
+    loc: &Location,
+    of: &Rc<StructType>,
+    args: Vec<Value>,
+    named: NamedArguments,
+) -> Result<Value> {
+    if args.len() > of.fields.len() {
+        error!(
+            loc,
+            "{} has {} fields, but got {} arguments",
+            of,
+            of.fields.len(),
+            args.len()
+        )
+    }
+    let mut fields = args.into_iter().map(Some).collect::<Vec<_>>();
+    fields.resize(of.fields.len(), None);
+    for (name, value) in named {
+        let Some(i) = of.field(name) else {
+            return Err(no_field(loc, of, name.as_str()));
+        };
+        if fields[i].replace(value).is_some() {
+            error!(loc, "Field `{}` of {} is given twice", name, of)
+        }
+    }
+    let missing = of
+        .fields
+        .iter()
+        .zip(&fields)
+        .filter(|(_, value)| value.is_none())
+        .map(|(name, _)| format!("`{}`", name))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        error!(loc, "{} is missing {}", of, missing.join(", "))
+    }
+    let fields = fields.into_iter().flatten().collect();
+    Ok(Value::Instance(of.clone(), Rc::new(RefCell::new(fields))))
+}
+
+/// The items of the tuple or list `value`, which `count` targets are taking
+/// apart.
+fn unpack(loc: &Location, value: &Value, count: usize) -> Result<Vec<Value>> {
+    let items = match value {
+        Value::Tuple(items) => items.to_vec(),
+        Value::List(list) => list.borrow().clone(),
+        _ => error!(loc, "Can only unpack a tuple or list, not {}", value.repr()),
+    };
+    if items.len() != count {
+        error!(
+            loc,
+            "Expected {} values to unpack, but got {}",
+            count,
+            items.len()
+        )
+    }
+    Ok(items)
+}
+
+/// Whether a `catch` would catch `err`, as [`Interpreter::caught`] decides.
+fn catchable(err: &Error) -> bool {
+    match err {
+        Error::Traced(err, _) | Error::Help(err, _) => catchable(err),
+        Error::Thrown(..) | Error::Runtime(..) => true,
+        _ => false,
+    }
+}
+
+fn is_wildcard(pattern: &AST) -> bool {
+    matches!(pattern, AST::Variable(_, name) if name == "_")
+}
+
+/// The error for calling the missing method `name` of an instance of `of`.
+fn no_member(loc: &Location, of: &StructType, name: &str) -> Error {
+    let msg = format!("{} has no field or method `{}`", of, name);
+    let methods = of.methods.borrow();
+    let names = of.fields.iter().chain(methods.keys());
+    let names = names.map(|name| name.as_str()).collect::<Vec<_>>();
+    with_suggestions(Error::Runtime(loc.clone(), msg), name, names.into_iter())
+}
+
+/// The error for reading or setting the missing field `name` of an
+/// instance of `of`.
+fn no_field(loc: &Location, of: &StructType, name: &str) -> Error {
+    let msg = format!("{} has no field `{}`", of, name);
+    let fields = of.fields.iter().map(|field| field.as_str());
+    with_suggestions(Error::Runtime(loc.clone(), msg), name, fields)
+}
+
+/// The error for reading the missing variant `name` of the enum `of`.
+fn no_variant(loc: &Location, of: &EnumType, name: &str) -> Error {
+    let msg = format!("{} has no variant or method `{}`", of.name, name);
+    let methods = of.variants.first().map(|variant| variant.methods.borrow());
+    let methods = methods.iter().flat_map(|methods| methods.keys());
+    let names = of
+        .variants
+        .iter()
+        .map(|variant| &variant.name)
+        .chain(methods);
+    let names = names.map(|name| name.as_str()).collect::<Vec<_>>();
+    with_suggestions(Error::Runtime(loc.clone(), msg), name, names.into_iter())
+}
+
+/// Adds a "did you mean" help to `error` if any of `candidates` is spelled
+/// like `name`.
+pub(crate) fn with_suggestions<'a>(
+    error: Error,
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Error {
+    let suggestions = close_matches(name, candidates);
+    if suggestions.is_empty() {
+        return error;
+    }
+    let suggestions = suggestions
+        .iter()
+        .map(|s| format!("`{}`", s))
+        .collect::<Vec<_>>()
+        .join(", ");
+    error.with_help(format!("did you mean {}?", suggestions))
 }
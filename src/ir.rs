@@ -0,0 +1,535 @@
+//! A listing of what the interpreter runs, for `--emit=ir`. This is the AST
+//! that `--vm` compiles from: one numbered node per line, indented
+//! under its parent, with every variable marked with where it resolves to,
+//! every function with the variables it captures, and each source line
+//! quoted above the nodes it produced.
+//!
+//! ```text
+//! 2 | let y = x + 1
+//!    3  VarDeclaration y (global)
+//!    4    Plus
+//!    5      Variable x (global)
+//!    6      IntegerLiteral 1
+//! ```
+//!
+//! With `--vm`, it's the bytecode instead: the chunk of the script and then
+//! that of every function body, one instruction per line, with the line of
+//! the node an instruction runs for.
+//!
+//! ```text
+//! ; script
+//!    0  Load         x  ; line 2
+//!    1  Constant     1
+//!    2  Binary       +  ; line 2
+//!    3  Declare      y  ; line 2
+//! ```
+
+use crate::ast::{Signature, AST};
+use crate::bytecode::{Chunk, Op, UnaryOp};
+use crate::interpreter::GLOBAL_NAMES;
+use crate::source_map::{FileId, SourceMap};
+use crate::symbol::Symbol;
+use crate::value::format_float;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Where a name is looked up when a node runs.
+#[derive(Clone, Copy)]
+enum Resolution {
+    /// Declared in the running function, or in the running block at the top
+    /// level.
+    Local,
+    /// Declared in a function the running one is nested in.
+    Captured,
+    Global,
+    BuiltIn,
+    /// Not declared anywhere the node can see, so running it fails.
+    Unresolved,
+}
+
+impl Resolution {
+    fn name(self) -> &'static str {
+        match self {
+            Resolution::Local => "local",
+            Resolution::Captured => "captured",
+            Resolution::Global => "global",
+            Resolution::BuiltIn => "built-in",
+            Resolution::Unresolved => "unresolved",
+        }
+    }
+}
+
+struct Scope {
+    names: HashSet<Symbol>,
+    /// Whether this is the outermost scope of a function.
+    function: bool,
+}
+
+struct Listing<'a> {
+    out: String,
+    next: usize,
+    /// The last source line quoted.
+    line: Option<(FileId, usize)>,
+    scopes: Vec<Scope>,
+    builtins: &'a [&'a str],
+}
+
+/// Lists `ast`, resolving names against `builtins`.
+pub fn listing(ast: &Arc<AST>, builtins: &[&str]) -> String {
+    // functions can refer to globals declared after them
+    let mut globals = match ast.as_ref() {
+        AST::Block(_, stmts) => stmts
+            .iter()
+            .filter_map(|stmt| stmt.declared_name())
+            .collect(),
+        _ => HashSet::new(),
+    };
+    globals.extend(GLOBAL_NAMES.map(Symbol::intern));
+    let mut listing = Listing {
+        out: String::new(),
+        next: 0,
+        line: None,
+        scopes: vec![Scope {
+            names: globals,
+            function: false,
+        }],
+        builtins,
+    };
+    match ast.as_ref() {
+        // the top-level block runs in the global scope rather than a new one
+        AST::Block(_, stmts) => stmts.iter().for_each(|stmt| listing.node(stmt, 0)),
+        _ => listing.node(ast, 0),
+    }
+    listing.out
+}
+
+/// Disassembles the bytecode that `--vm` compiles the script `ast` and the
+/// bodies of its functions to.
+pub fn bytecode(ast: &Arc<AST>) -> String {
+    let mut out = String::from("; script\n");
+    disassemble(&mut out, &Chunk::script(ast));
+    let mut functions = vec![];
+    collect_functions(ast, &mut functions);
+    for function in functions {
+        let AST::Function { name, body, .. } = function.as_ref() else {
+            unreachable!("collected {}", function.kind())
+        };
+        let name = name.map_or("lambda".to_string(), |name| format!("function {}", name));
+        let _ = writeln!(out, "\n; {} at line {}", name, function.loc().line);
+        disassemble(&mut out, &Chunk::function(body));
+    }
+    out
+}
+
+/// The functions in `ast`, outermost first.
+fn collect_functions<'a>(ast: &'a Arc<AST>, functions: &mut Vec<&'a Arc<AST>>) {
+    if let AST::Function { .. } = ast.as_ref() {
+        functions.push(ast);
+    }
+    for child in ast.children() {
+        collect_functions(child, functions);
+    }
+}
+
+fn disassemble(out: &mut String, chunk: &Chunk) {
+    for (at, op) in chunk.code.iter().enumerate() {
+        let node = |i: u32| &chunk.nodes[i as usize];
+        let (name, operand) = match *op {
+            Op::Constant(i) => ("Constant", chunk.constants[i as usize].repr()),
+            Op::Load(i) => ("Load", operand(node(i))),
+            Op::Declare(i) => ("Declare", operand(node(i))),
+            Op::Assign(i) => ("Assign", operand(node(i))),
+            Op::Pop => ("Pop", String::new()),
+            Op::Binary(op, _) => ("Binary", op.symbol().to_string()),
+            Op::Unary(op, _) => {
+                let symbol = match op {
+                    UnaryOp::Not => "not",
+                    UnaryOp::Negate => "-",
+                    UnaryOp::BitNot => "~",
+                };
+                ("Unary", symbol.to_string())
+            }
+            Op::Index(_) => ("Index", String::new()),
+            Op::In(_) => ("In", String::new()),
+            Op::List(n) => ("List", n.to_string()),
+            Op::Tuple(n) => ("Tuple", n.to_string()),
+            Op::Call(argc, _) => ("Call", argc.to_string()),
+            Op::PushScope => ("PushScope", String::new()),
+            Op::PopScope => ("PopScope", String::new()),
+            Op::Jump(to) => ("Jump", format!("-> {}", to)),
+            Op::JumpIfFalse(to, _) => ("JumpIfFalse", format!("-> {}", to)),
+            Op::ShortCircuit(to, when, _) => ("ShortCircuit", format!("-> {} if {}", to, when)),
+            Op::IntoIter(_) => ("IntoIter", String::new()),
+            Op::ForNext(to, i) => ("ForNext", format!("{} else -> {}", operand(node(i)), to)),
+            Op::Return => ("Return", String::new()),
+            Op::TailCall(argc, _) => ("TailCall", argc.to_string()),
+            Op::Eval(i, _) => match node(i).as_ref() {
+                AST::Function {
+                    name: Some(name), ..
+                } => ("Eval", format!("Function {}", name)),
+                AST::VarDeclaration(_, name, ..) => ("Eval", format!("VarDeclaration {}", name)),
+                node => ("Eval", label(node)),
+            },
+        };
+        let line = match op.node().or(match *op {
+            Op::Eval(i, _) => Some(i),
+            _ => None,
+        }) {
+            Some(i) => format!("  ; line {}", node(i).loc().line),
+            None => String::new(),
+        };
+        let text = format!("{:>4}  {:<12} {}", at, name, operand);
+        let _ = writeln!(out, "{}{}", text.trim_end(), line);
+    }
+}
+
+/// The name an instruction works on, for the nodes that have one.
+fn operand(node: &AST) -> String {
+    match node {
+        AST::Variable(_, name)
+        | AST::VarDeclaration(_, name, ..)
+        | AST::For(_, name, ..)
+        | AST::Local(_, name, _) => name.to_string(),
+        AST::Assignment(_, target, _) => match target.as_ref() {
+            AST::Variable(_, name) | AST::Local(_, name, _) => name.to_string(),
+            target => label(target),
+        },
+        _ => label(node),
+    }
+}
+
+impl Listing<'_> {
+    fn resolve(&self, name: Symbol) -> Resolution {
+        let mut crossed_function = false;
+        for (depth, scope) in self.scopes.iter().enumerate().rev() {
+            if scope.names.contains(&name) {
+                return match (depth, crossed_function) {
+                    (0, _) => Resolution::Global,
+                    (_, true) => Resolution::Captured,
+                    (_, false) => Resolution::Local,
+                };
+            }
+            crossed_function |= scope.function;
+        }
+        match self.builtins.contains(&name.as_str()) {
+            true => Resolution::BuiltIn,
+            false => Resolution::Unresolved,
+        }
+    }
+
+    /// Where a name declared at this point lives.
+    fn declaration(&self) -> Resolution {
+        match self.scopes.len() {
+            1 => Resolution::Global,
+            _ => Resolution::Local,
+        }
+    }
+
+    fn declare(&mut self, name: Symbol) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.names.insert(name);
+    }
+
+    fn scoped(&mut self, names: &[Symbol], function: bool, body: impl FnOnce(&mut Self)) {
+        self.scopes.push(Scope {
+            names: names.iter().copied().collect(),
+            function,
+        });
+        body(self);
+        self.scopes.pop();
+    }
+
+    /// Quotes the source line `ast` starts on, unless it was just quoted.
+    fn quote_line(&mut self, ast: &AST) {
+        let loc = ast.loc();
+        let key = (loc.file, loc.line);
+        if self.line == Some(key) {
+            return;
+        }
+        self.line = Some(key);
+        let text = SourceMap::line(loc.file, loc.line).unwrap_or_default();
+        let _ = writeln!(self.out, "{} | {}", loc.line, text.trim_end());
+    }
+
+    fn emit(&mut self, ast: &AST, depth: usize, label: String) {
+        self.quote_line(ast);
+        let _ = writeln!(
+            self.out,
+            "{:>4}  {}{}",
+            self.next,
+            "  ".repeat(depth),
+            label
+        );
+        self.next += 1;
+    }
+
+    fn node(&mut self, ast: &Arc<AST>, depth: usize) {
+        let children = depth + 1;
+        match ast.as_ref() {
+            AST::Variable(_, name) => {
+                let label = format!("Variable {} ({})", name, self.resolve(*name).name());
+                self.emit(ast, depth, label);
+            }
+            AST::Local(_, name, slot) => {
+                let label = format!(
+                    "Local {} ({}, depth {}, slot {})",
+                    name,
+                    self.resolve(*name).name(),
+                    slot.depth,
+                    slot.index
+                );
+                self.emit(ast, depth, label);
+            }
+            AST::VarDeclaration(_, name, ty, value) => {
+                let resolution = self.declaration().name();
+                let ty = ty
+                    .as_ref()
+                    .map(|ty| format!(": {}", ty))
+                    .unwrap_or_default();
+                self.emit(
+                    ast,
+                    depth,
+                    format!("VarDeclaration {}{} ({})", name, ty, resolution),
+                );
+                // the value runs before the name exists
+                self.node(value, children);
+                self.declare(*name);
+            }
+            AST::Unpack(_, pattern, value) => {
+                self.emit(ast, depth, "Unpack".to_string());
+                self.node(value, children);
+                self.pattern(pattern, children);
+                pattern
+                    .bindings()
+                    .into_iter()
+                    .for_each(|name| self.declare(name));
+            }
+            AST::Import(_, name, path) => {
+                let resolution = self.declaration().name();
+                let from = match path.as_ref() == name.as_str() {
+                    true => String::new(),
+                    false => format!(" from {:?}", path),
+                };
+                self.emit(
+                    ast,
+                    depth,
+                    format!("Import {}{} ({})", name, from, resolution),
+                );
+                self.declare(*name);
+            }
+            AST::Struct {
+                name,
+                fields,
+                methods,
+                ..
+            } => {
+                let resolution = self.declaration().name();
+                let fields = Symbol::join(fields, ", ");
+                let label = format!("Struct {} {{ {} }} ({})", name, fields, resolution);
+                self.emit(ast, depth, label);
+                self.declare(*name);
+                self.methods(methods, children);
+            }
+            AST::Enum {
+                name,
+                variants,
+                methods,
+                ..
+            } => {
+                let resolution = self.declaration().name();
+                let variants = variants
+                    .iter()
+                    .map(|variant| match variant.fields.as_slice() {
+                        [] => variant.name.to_string(),
+                        fields => format!("{}({})", variant.name, Symbol::join(fields, ", ")),
+                    })
+                    .collect::<Vec<_>>();
+                let label = format!(
+                    "Enum {} {{ {} }} ({})",
+                    name,
+                    variants.join(", "),
+                    resolution
+                );
+                self.emit(ast, depth, label);
+                self.declare(*name);
+                self.methods(methods, children);
+            }
+            AST::Function {
+                name,
+                args,
+                variadic,
+                defaults,
+                signature,
+                captures,
+                moves,
+                body,
+                ..
+            } => {
+                let signature = signature.as_deref();
+                let args_list = format!(
+                    "({}){}",
+                    Signature::parameters(signature, args, *variadic, &[], name.is_none()),
+                    Signature::return_annotation(signature)
+                );
+                let mut label = format!("Function{}", args_list);
+                if let Some(name) = *name {
+                    let resolution = self.declaration().name();
+                    label = format!("Function {}{} ({})", name, args_list, resolution);
+                    self.declare(name);
+                }
+                if !captures.is_empty() {
+                    let names = captures.iter().map(|capture| capture.name);
+                    let names = Symbol::join(&names.collect::<Vec<_>>(), ", ");
+                    let how = if *moves { "moves" } else { "captures" };
+                    label = format!("{} {} {}", label, how, names);
+                }
+                self.emit(ast, depth, label);
+                self.scoped(args, true, |listing| {
+                    for default in defaults {
+                        listing.node(default, children);
+                    }
+                    listing.node(body, children)
+                });
+            }
+            AST::For(_, name, iter, body) => {
+                self.emit(ast, depth, format!("For {}", name));
+                self.node(iter, children);
+                self.scoped(std::slice::from_ref(name), false, |listing| {
+                    listing.node(body, children)
+                });
+            }
+            AST::Match(_, subject, arms) => {
+                let label = match arms.len() {
+                    1 => "Match (1 arm)".to_string(),
+                    arms => format!("Match ({} arms)", arms),
+                };
+                self.emit(ast, depth, label);
+                self.node(subject, children);
+                for arm in arms {
+                    let label = match arm.guard {
+                        Some(_) => "Arm (with guard)",
+                        None => "Arm",
+                    };
+                    self.emit(&arm.pattern, children, label.to_string());
+                    self.scoped(&arm.bindings(), false, |listing| {
+                        listing.pattern(&arm.pattern, children + 1);
+                        arm.guard
+                            .iter()
+                            .chain([&arm.body])
+                            .for_each(|node| listing.node(node, children + 1));
+                    });
+                }
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.emit(ast, depth, "Try".to_string());
+                self.node(body, children);
+                if let Some((name, handler)) = catch {
+                    self.emit(handler, children, format!("Catch {}", name));
+                    self.scoped(std::slice::from_ref(name), false, |listing| {
+                        listing.node(handler, children + 1)
+                    });
+                }
+                if let Some(finally) = finally {
+                    self.emit(finally, children, "Finally".to_string());
+                    self.node(finally, children + 1);
+                }
+            }
+            AST::Block(_, stmts) => {
+                self.emit(ast, depth, "Block".to_string());
+                self.scoped(&[], false, |listing| {
+                    stmts.iter().for_each(|stmt| listing.node(stmt, children))
+                });
+            }
+            _ => {
+                self.emit(ast, depth, label(ast));
+                for child in ast.children() {
+                    self.node(child, children);
+                }
+            }
+        }
+    }
+
+    /// The methods of a struct or enum, with their bodies.
+    fn methods(&mut self, methods: &[Arc<AST>], depth: usize) {
+        for method in methods {
+            let AST::Function {
+                name: Some(name),
+                args,
+                variadic,
+                defaults,
+                body,
+                ..
+            } = method.as_ref()
+            else {
+                continue;
+            };
+            let params = Signature::parameters(None, args, *variadic, &[], false);
+            let label = format!("Method {}({})", name, params);
+            self.emit(method, depth, label);
+            self.scoped(args, true, |listing| {
+                for default in defaults {
+                    listing.node(default, depth + 1);
+                }
+                listing.node(body, depth + 1)
+            });
+        }
+    }
+
+    fn pattern(&mut self, ast: &Arc<AST>, depth: usize) {
+        match ast.as_ref() {
+            AST::Variable(_, name) if *name == "_" => self.emit(ast, depth, "Wildcard".to_string()),
+            AST::Variable(_, name) => {
+                let label = format!("Binding {} ({})", name, self.declaration().name());
+                self.emit(ast, depth, label);
+            }
+            AST::List(_, items) | AST::Tuple(_, items) => {
+                self.emit(ast, depth, label(ast));
+                items.iter().for_each(|item| self.pattern(item, depth + 1));
+            }
+            // the struct or variant is an expression, and its fields patterns
+            AST::Call(_, of, items) => {
+                self.emit(ast, depth, label(ast));
+                self.node(of, depth + 1);
+                items.iter().for_each(|item| self.pattern(item, depth + 1));
+            }
+            _ => self.node(ast, depth),
+        }
+    }
+}
+
+/// The label of a node that doesn't declare or resolve a name.
+fn label(ast: &AST) -> String {
+    match ast {
+        AST::IntegerLiteral(_, value) => format!("IntegerLiteral {}", value),
+        AST::FloatLiteral(_, value) => format!("FloatLiteral {}", format_float(*value)),
+        AST::StringLiteral(_, text) => format!("StringLiteral {:?}", text),
+        AST::BooleanLiteral(_, value) => format!("BooleanLiteral {}", value),
+        AST::List(_, items) if items.len() == 1 => "List (1 item)".to_string(),
+        AST::List(_, items) => format!("List ({} items)", items.len()),
+        AST::Tuple(_, items) if items.len() == 1 => "Tuple (1 item)".to_string(),
+        AST::Tuple(_, items) => format!("Tuple ({} items)", items.len()),
+        AST::Dict(_, entries) if entries.len() == 1 => "Dict (1 entry)".to_string(),
+        AST::Dict(_, entries) => format!("Dict ({} entries)", entries.len()),
+        AST::Call(_, _, args) if args.len() == 1 => "Call (1 arg)".to_string(),
+        AST::Call(_, _, args) => format!("Call ({} args)", args.len()),
+        AST::Member(_, _, name) => format!("Member .{}", name),
+        AST::NamedArgument(_, name, _) => format!("NamedArgument {}", name),
+        AST::If(_, _, _, Some(_)) => "If (with else)".to_string(),
+        AST::Range(.., true) => "Range (inclusive)".to_string(),
+        AST::Slice {
+            start, end, step, ..
+        } => {
+            let part = |part: &Option<Arc<AST>>, name| part.as_ref().map(|_| name);
+            let parts = [part(start, "start"), part(end, "end"), part(step, "step")];
+            let parts = parts.into_iter().flatten().collect::<Vec<_>>();
+            format!("Slice ({})", parts.join(", "))
+        }
+        _ => ast.kind().to_string(),
+    }
+}
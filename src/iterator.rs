@@ -0,0 +1,218 @@
+//! The lazy iterators that `map`, `filter` and the other iterator built-ins
+//! return. Each steps through the iterators it wraps only as far as it's
+//! asked to, so a chain of them over a huge range never holds more than a
+//! value at a time.
+
+use crate::ast::AST;
+use crate::error::{runtime_error as error, Result};
+use crate::interpreter::{Interpreter, Ref, Scope};
+use crate::token::Location;
+use crate::value::{Iterate, IteratorValue, Value};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+
+/// `map(func, iterable)`: `func` of each value.
+pub struct Map {
+    pub inner: IteratorValue,
+    pub func: Value,
+}
+
+impl Iterate for Map {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        let Some(value) = self.inner.next(interpreter, scope, loc)? else {
+            return Ok(None);
+        };
+        interpreter
+            .call(scope, loc, &self.func, vec![value])
+            .map(Some)
+    }
+}
+
+/// `filter(func, iterable)`: the values that `func` returns true for.
+pub struct Filter {
+    pub inner: IteratorValue,
+    pub func: Value,
+}
+
+impl Iterate for Filter {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        while let Some(value) = self.inner.next(interpreter, scope, loc)? {
+            match interpreter.call(scope, loc, &self.func, vec![value.clone()])? {
+                Value::Boolean(true) => return Ok(Some(value)),
+                Value::Boolean(false) => {}
+                other => error!(
+                    loc,
+                    "filter() expects its function to return a boolean, got {}",
+                    other.repr()
+                ),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// `take(iterable, n)`: the first `n` values.
+pub struct Take {
+    pub inner: IteratorValue,
+    pub remaining: i64,
+}
+
+impl Iterate for Take {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        if self.remaining <= 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.inner.next(interpreter, scope, loc)
+    }
+}
+
+/// `skip(iterable, n)`: the values after the first `n`, which are stepped
+/// past when the first value is asked for.
+pub struct Skip {
+    pub inner: IteratorValue,
+    pub remaining: i64,
+}
+
+impl Iterate for Skip {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            if self.inner.next(interpreter, scope, loc)?.is_none() {
+                return Ok(None);
+            }
+        }
+        self.inner.next(interpreter, scope, loc)
+    }
+}
+
+/// `zip(a, b, ...)`: tuples of the values at the same place in each, until
+/// the shortest runs out.
+pub struct Zip {
+    pub inners: Vec<IteratorValue>,
+}
+
+impl Iterate for Zip {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        let mut values = Vec::with_capacity(self.inners.len());
+        for inner in &self.inners {
+            match inner.next(interpreter, scope, loc)? {
+                Some(value) => values.push(value),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(Value::Tuple(values.into())))
+    }
+}
+
+/// `enumerate(iterable)`: `(index, value)` tuples, counting from 0.
+pub struct Enumerate {
+    pub inner: IteratorValue,
+    pub index: i64,
+}
+
+impl Iterate for Enumerate {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        let Some(value) = self.inner.next(interpreter, scope, loc)? else {
+            return Ok(None);
+        };
+        let index = Value::Integer(self.index);
+        self.index += 1;
+        Ok(Some(Value::Tuple([index, value].into())))
+    }
+}
+
+/// `read_lines(path)`: the lines of a file, read as they're asked for. The
+/// file is closed once the last line is read.
+pub struct Lines {
+    pub path: Arc<str>,
+    pub lines: Option<io::Lines<BufReader<File>>>,
+}
+
+impl Iterate for Lines {
+    fn next(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        _scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        let Some(lines) = &mut self.lines else {
+            return Ok(None);
+        };
+        match lines.next() {
+            Some(Ok(line)) => Ok(Some(line.into())),
+            Some(Err(err)) => error!(loc, "Couldn't read {}: {}", self.path, err),
+            None => {
+                self.lines = None;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// A call of a function that `yield`s, which runs the body as far as its
+/// next `yield` each time a value is asked for. The blocks and loops it's
+/// partway through are kept as frames, innermost last, and it's finished
+/// once there are none left.
+pub struct Generator {
+    pub(crate) frames: Vec<GeneratorFrame>,
+}
+
+pub(crate) enum GeneratorFrame {
+    /// A block and the index of the statement to run next.
+    Block {
+        block: Arc<AST>,
+        next: usize,
+        scope: Ref<Scope>,
+    },
+    /// A `while` loop, whose condition is checked each time it's on top.
+    While { node: Arc<AST>, scope: Ref<Scope> },
+    /// A `for` loop and the iterator it's stepping through.
+    For {
+        node: Arc<AST>,
+        iter: IteratorValue,
+        scope: Ref<Scope>,
+    },
+}
+
+impl Iterate for Generator {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        _scope: &Ref<Scope>,
+        _loc: &Location,
+    ) -> Result<Option<Value>> {
+        interpreter.resume(self)
+    }
+}
@@ -0,0 +1,355 @@
+//! Converting values to and from JSON, for hosts that pass script results to
+//! other programs or hand structured data to scripts. Lists, tuples and
+//! dicts with string keys map to arrays and objects, and `nothing` to `null`. Values
+//! JSON has no form for, like functions or infinite floats, are errors.
+
+use crate::error::{Error, Result};
+use crate::value::{format_float, Dict, HashKey, Value};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// How deeply arrays and objects may nest in parsed JSON, which keeps
+/// hostile input from overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Writes `value` as compact JSON.
+pub fn to_json(value: &Value) -> Result<String> {
+    let mut out = String::new();
+    write_value(&mut out, value, &mut Vec::new(), None)?;
+    Ok(out)
+}
+
+/// Writes `value` as JSON with each item of an array or object on its own
+/// line, indented by two spaces for each level it's nested at.
+pub fn to_json_pretty(value: &Value) -> Result<String> {
+    let mut out = String::new();
+    write_value(&mut out, value, &mut Vec::new(), Some(0))?;
+    Ok(out)
+}
+
+/// Reads a single JSON value, allowing whitespace around it. Numbers without
+/// a fraction or exponent that fit an integer become integers.
+pub fn from_json(text: &str) -> Result<Value> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("Unexpected text after the JSON value")),
+    }
+}
+
+/// `outer` holds the lists and dicts being written, to refuse cycles.
+/// `indent` is how deeply the value is nested when writing pretty JSON.
+fn write_value(
+    out: &mut String,
+    value: &Value,
+    outer: &mut Vec<*const ()>,
+    indent: Option<usize>,
+) -> Result<()> {
+    match value {
+        Value::Nothing => out.push_str("null"),
+        Value::Boolean(b) => write!(out, "{}", b).unwrap(),
+        Value::Integer(i) => write!(out, "{}", i).unwrap(),
+        Value::Float(f) if f.is_finite() => out.push_str(&format_float(*f)),
+        Value::String(s) => write_string(out, s),
+        Value::List(list) => {
+            enter(outer, Rc::as_ptr(list).cast())?;
+            write_array(out, &list.borrow(), outer, indent)?;
+            outer.pop();
+        }
+        Value::Tuple(items) => write_array(out, items, outer, indent)?,
+        Value::Dict(dict) => {
+            enter(outer, Rc::as_ptr(dict).cast())?;
+            out.push('{');
+            let dict = dict.borrow();
+            for (i, (key, value)) in dict.entries().enumerate() {
+                let Value::String(key) = key else {
+                    return Err(Error::Other(format!(
+                        "JSON object keys must be strings, got {}",
+                        key.repr()
+                    )));
+                };
+                if i > 0 {
+                    out.push(',');
+                }
+                new_line(out, indent.map(|depth| depth + 1));
+                write_string(out, key);
+                out.push_str(if indent.is_some() { ": " } else { ":" });
+                write_value(out, value, outer, indent.map(|depth| depth + 1))?;
+            }
+            if !dict.is_empty() {
+                new_line(out, indent);
+            }
+            out.push('}');
+            outer.pop();
+        }
+        _ => {
+            return Err(Error::Other(format!(
+                "{} can't be converted to JSON",
+                value.repr()
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn write_array(
+    out: &mut String,
+    items: &[Value],
+    outer: &mut Vec<*const ()>,
+    indent: Option<usize>,
+) -> Result<()> {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        new_line(out, indent.map(|depth| depth + 1));
+        write_value(out, item, outer, indent.map(|depth| depth + 1))?;
+    }
+    if !items.is_empty() {
+        new_line(out, indent);
+    }
+    out.push(']');
+    Ok(())
+}
+
+/// Starts a line indented to `indent` when writing pretty JSON.
+fn new_line(out: &mut String, indent: Option<usize>) {
+    if let Some(depth) = indent {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+}
+
+fn enter(outer: &mut Vec<*const ()>, ptr: *const ()) -> Result<()> {
+    if outer.contains(&ptr) {
+        return Err(Error::Other(
+            "A list or dict that contains itself can't be converted to JSON".to_string(),
+        ));
+    }
+    outer.push(ptr);
+    Ok(())
+}
+
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        Error::Other(format!("{} at byte {} of the JSON", msg, self.pos))
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(found) if found == c => Ok(()),
+            _ => Err(self.error(&format!("Expected `{}`", c))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Parser::object),
+            Some('[') => self.nested(Parser::array),
+            Some('"') => Ok(Value::String(self.string()?.into())),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                for (word, value) in [
+                    ("null", Value::Nothing),
+                    ("true", Value::Boolean(true)),
+                    ("false", Value::Boolean(false)),
+                ] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("Expected a JSON value"))
+            }
+            None => Err(self.error("Unexpected end")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value>) -> Result<Value> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("Nested too deeply"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+        } else {
+            loop {
+                items.push(self.value()?);
+                self.skip_whitespace();
+                match self.next() {
+                    Some(',') => {}
+                    Some(']') => break,
+                    _ => return Err(self.error("Expected `,` or `]`")),
+                }
+            }
+        }
+        Ok(Value::List(Rc::new(RefCell::new(items))))
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut dict = Dict::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some('"') {
+                    return Err(self.error("Expected a string key"));
+                }
+                let key: Arc<str> = self.string()?.into();
+                self.expect(':')?;
+                let value = self.value()?;
+                dict.insert_hashed(HashKey::String(key.clone()), Value::String(key), value);
+                self.skip_whitespace();
+                match self.next() {
+                    Some(',') => {}
+                    Some('}') => break,
+                    _ => return Err(self.error("Expected `,` or `}`")),
+                }
+            }
+        }
+        Ok(Value::Dict(Rc::new(RefCell::new(dict))))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    out.push(c);
+                }
+                Some(c) if c < ' ' => return Err(self.error("Control character in a string")),
+                Some(c) => out.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                if !self.text[self.pos..].starts_with("\\u") {
+                    return Err(self.error("Unpaired surrogate"));
+                }
+                self.pos += 2;
+                let low = self.hex4()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(self.error("Unpaired surrogate"));
+                }
+                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+            }
+            code => code,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Unpaired surrogate"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self.text[self.pos..].get(..4);
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(code) if digits.is_some_and(|d| d.bytes().all(|b| b.is_ascii_hexdigit())) => {
+                self.pos += 4;
+                Ok(code)
+            }
+            _ => Err(self.error("Expected 4 hex digits")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let mut integral = true;
+        while let Some(c) = self.peek() {
+            match c {
+                '0'..='9' | '-' | '+' => {}
+                '.' | 'e' | 'E' => integral = false,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let text = &self.text[start..self.pos];
+        if integral {
+            if let Ok(i) = text.parse() {
+                return Ok(Value::Integer(i));
+            }
+        }
+        match text.parse() {
+            Ok(f) => Ok(Value::Float(f)),
+            Err(_) => Err(self.error(&format!("Invalid number {}", text))),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Drives the interpreter from a Jupyter notebook.
+//!
+//! Jupyter talks to kernels over ZeroMQ. `kernel/rattlesnake_kernel.py` handles
+//! that side and forwards each cell here over stdin/stdout using a small
+//! framed protocol:
+//!
+//! - a request is `execute <length>\n` followed by `length` bytes of code
+//! - whatever the code prints is passed through as is, as stream output
+//! - the reply starts with a `\x01` byte, then `ok <length>\n` followed by
+//!   the repr of the cell's value (empty for `nothing`), or `error <length>\n`
+//!   followed by the error message
+//!
+//! Cells share one global scope, as lines do in the REPL.
+
+use crate::ast::AST;
+use crate::error::{Error, Result};
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::value::Value;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::Arc;
+
+/// Marks the start of a reply, so it can't be mistaken for printed output.
+const REPLY_START: u8 = 0x01;
+
+pub struct Kernel {
+    interpreter: Interpreter,
+    execution_count: usize,
+}
+
+impl Default for Kernel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kernel {
+    pub fn new() -> Kernel {
+        Kernel {
+            interpreter: Interpreter::new(),
+            execution_count: 0,
+        }
+    }
+
+    /// Serves requests from stdin until it is closed.
+    pub fn run(&mut self) -> Result<()> {
+        let mut stdin = io::stdin().lock();
+        let mut header = String::new();
+        loop {
+            header.clear();
+            if stdin.read_line(&mut header).map_err(io_error)? == 0 {
+                return Ok(());
+            }
+            let code = match header.trim().split_once(' ') {
+                Some(("execute", length)) => {
+                    let length = length
+                        .parse::<usize>()
+                        .map_err(|_| protocol_error(&header))?;
+                    let mut code = vec![0; length];
+                    stdin.read_exact(&mut code).map_err(io_error)?;
+                    String::from_utf8(code).map_err(|_| protocol_error(&header))?
+                }
+                _ => return Err(protocol_error(&header)),
+            };
+            let reply = match self.execute(code) {
+                Ok(Value::Nothing) => ("ok", String::new()),
+                Ok(value) => ("ok", value.repr()),
+                Err(err) => ("error", err.render(false).trim_end().to_string()),
+            };
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&[REPLY_START]).map_err(io_error)?;
+            write!(stdout, "{} {}\n{}", reply.0, reply.1.len(), reply.1).map_err(io_error)?;
+            stdout.flush().map_err(io_error)?;
+        }
+    }
+
+    fn execute(&mut self, code: String) -> Result<Value> {
+        self.execution_count += 1;
+        let filename = format!("<cell {}>", self.execution_count);
+        let ast: Arc<AST> = self
+            .interpreter
+            .parser(Lexer::new(code, filename))
+            .parse()?;
+        self.interpreter.eval(&ast)
+    }
+}
+
+fn io_error(err: io::Error) -> Error {
+    Error::Other(format!("Kernel I/O failed: {}", err))
+}
+
+fn protocol_error(header: &str) -> Error {
+    Error::Other(format!("Malformed kernel request `{}`", header.trim()))
+}
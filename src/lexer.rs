@@ -1,82 +1,243 @@
-use crate::error::{lexer_error as error, Result};
-use crate::token::{Location, Token, TokenKind};
+use crate::cursor::Cursor;
+use crate::edition::Edition;
+use crate::error::{lexer_error as error, Diagnostics, Error, Result};
+use crate::token::{FileId, Location, StringPart, Token, TokenKind};
+use std::borrow::Cow;
+use std::fmt::{Debug, Formatter};
+use std::io::BufRead;
+use std::str::CharIndices;
+
+/// Punctuation tokens, longest first so that e.g. `==` wins over `=`.
+pub const OPERATORS: &[(&str, TokenKind)] = &[
+    ("=>", TokenKind::FatArrow),
+    ("->", TokenKind::Arrow),
+    ("==", TokenKind::EqualsEquals),
+    ("!=", TokenKind::BangEquals),
+    ("<=", TokenKind::LessThanEquals),
+    (">=", TokenKind::GreaterThanEquals),
+    ("<<", TokenKind::LessThanLessThan),
+    (">>", TokenKind::GreaterThanGreaterThan),
+    ("...", TokenKind::DotDotDot),
+    ("..=", TokenKind::DotDotEquals),
+    ("..", TokenKind::DotDot),
+    ("**", TokenKind::StarStar),
+    ("+", TokenKind::Plus),
+    ("-", TokenKind::Minus),
+    ("*", TokenKind::Star),
+    ("/", TokenKind::Slash),
+    ("%", TokenKind::Percent),
+    ("&", TokenKind::Ampersand),
+    ("^", TokenKind::Caret),
+    ("~", TokenKind::Tilde),
+    ("(", TokenKind::LeftParen),
+    (")", TokenKind::RightParen),
+    ("[", TokenKind::LeftBracket),
+    ("]", TokenKind::RightBracket),
+    ("{", TokenKind::LeftBrace),
+    ("}", TokenKind::RightBrace),
+    ("|", TokenKind::Pipe),
+    (":", TokenKind::Colon),
+    (";", TokenKind::SemiColon),
+    (",", TokenKind::Comma),
+    ("=", TokenKind::Equals),
+    ("<", TokenKind::LessThan),
+    (">", TokenKind::GreaterThan),
+    ("!", TokenKind::Bang),
+    ("@", TokenKind::At),
+    (".", TokenKind::Dot),
+];
+
+/// The error for a `/*` without its `*/`, which more input may yet close.
+pub(crate) const UNTERMINATED_BLOCK_COMMENT: &str = "Unterminated block comment";
+
+/// How much source a reader-backed lexer takes in at a time. Chunks are
+/// extended to the end of the line, so no token ever straddles two of them.
+const CHUNK_SIZE: usize = 64 * 1024;
 
-#[derive(Debug)]
 pub struct Lexer {
-    location: Location,
-    input: String,
-    current_index: usize,
+    cursor: Cursor,
     seen_newline: bool,
+    reader: Option<Box<dyn BufRead>>,
+    /// Whether a token has been lexed yet, before which comments may be
+    /// pragmas.
+    started: bool,
+    /// Whether the EOF token or an error has been returned, after which
+    /// there are no more tokens.
+    finished: bool,
+    /// Where to record unexpected characters and carry on, rather than
+    /// stopping at the first.
+    diagnostics: Option<Diagnostics>,
+}
+
+impl Debug for Lexer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lexer")
+            .field("cursor", &self.cursor)
+            .field("seen_newline", &self.seen_newline)
+            .field("started", &self.started)
+            .field("finished", &self.finished)
+            .field("diagnostics", &self.diagnostics)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Lexer {
     pub fn new(input: String, filename: String) -> Lexer {
+        let file = FileId::with_source(&filename, input.as_str().into());
+        let cursor = Cursor::in_file(input.into(), file);
         Lexer {
-            location: Location {
-                line: 1,
-                column: 1,
-                filename,
-            },
-            input,
-            current_index: 0,
+            cursor,
             seen_newline: false,
+            reader: None,
+            started: false,
+            finished: false,
+            diagnostics: None,
+        }
+    }
+
+    /// A lexer that reads its input from `reader` a chunk at a time, rather
+    /// than needing all of it up front. Tokens only keep alive the chunk
+    /// their text is in.
+    pub fn from_reader(reader: impl BufRead + 'static, filename: String) -> Lexer {
+        Lexer {
+            cursor: Cursor::new("".into(), filename),
+            seen_newline: false,
+            reader: Some(Box::new(reader)),
+            started: false,
+            finished: false,
+            diagnostics: None,
+        }
+    }
+
+    /// Records unexpected characters in `diagnostics` and skips past them,
+    /// instead of failing at the first.
+    pub fn with_diagnostics(mut self, diagnostics: Diagnostics) -> Lexer {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    pub(crate) fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Reads the next chunk of input, returning whether there was any.
+    fn refill(&mut self) -> Result<bool> {
+        let Some(reader) = &mut self.reader else {
+            return Ok(false);
+        };
+        let mut chunk = String::new();
+        while chunk.len() < CHUNK_SIZE {
+            match reader.read_line(&mut chunk) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => error!(
+                    self.cursor.location(),
+                    "Couldn't read {}: {}",
+                    self.cursor.location().filename(),
+                    err
+                ),
+            }
         }
+        if chunk.is_empty() {
+            self.reader = None;
+            return Ok(false);
+        }
+        self.cursor.refill(chunk.into());
+        Ok(true)
     }
 
     fn cur(&self) -> Option<char> {
-        self.input.chars().nth(self.current_index)
+        self.cursor.cur()
     }
 
     fn peek(&self, offset: usize) -> Option<char> {
-        self.input.chars().nth(self.current_index + offset)
+        self.cursor.peek(offset)
+    }
+
+    fn location(&self) -> Location {
+        self.cursor.location().clone()
     }
 
     fn increment(&mut self) {
-        match self.cur() {
-            Some('\n') => {
-                self.location.line += 1;
-                self.location.column = 1;
-                self.current_index += 1;
-                self.seen_newline = true;
-            }
-            Some(_) => {
-                self.current_index += 1;
-                self.location.column += 1;
-            }
-            None => {}
+        if let Some('\n') = self.cursor.bump() {
+            self.seen_newline = true;
         }
     }
 
-    fn push_simple(&mut self, tokens: &mut Vec<Token>, kind: TokenKind, len: usize) {
-        self.push(
-            tokens,
-            Token::new(
-                kind,
-                self.location.clone(),
-                self.input[self.current_index..self.current_index + len].to_string(),
-            ),
-        );
+    /// A token whose text runs from `start` up to the current position.
+    fn token_from(&self, kind: TokenKind, loc: Location, start: usize) -> Token {
+        Token::new(
+            kind,
+            loc,
+            self.cursor.source().clone(),
+            start..self.cursor.pos(),
+        )
+    }
+
+    /// A token of kind `kind` made of the next `len` chars.
+    fn simple(&mut self, kind: TokenKind, len: usize) -> Token {
+        let loc = self.location();
+        let start = self.cursor.pos();
         for _ in 0..len {
             self.increment();
         }
+        self.token_from(kind, loc, start)
     }
 
-    fn push(&mut self, tokens: &mut Vec<Token>, mut token: Token) {
+    /// Notes whether a newline came before `token`, which the parser needs
+    /// to find the ends of statements.
+    fn finish(&mut self, mut token: Token) -> Token {
         token.newline_before = self.seen_newline;
-        tokens.push(token);
         self.seen_newline = false;
+        self.started = true;
+        token
     }
 
     pub fn lex(&mut self) -> Result<Vec<Token>> {
-        let mut tokens: Vec<Token> = vec![];
-        while let Some(c) = self.cur() {
-            match c {
-                c if c.is_whitespace() => self.increment(),
+        // source code averages a little over four bytes per token
+        let mut tokens: Vec<Token> = Vec::with_capacity(self.cursor.source().len() / 4);
+        for token in self.tokens() {
+            tokens.push(token?);
+        }
+        Ok(tokens)
+    }
 
-                // base N literals, i.e. 0b_1101, 0o_567, 0x_ff
-                '0' if self.peek(1).map_or(false, |c| "box".contains(c)) => {
-                    let mut num = String::new();
+    /// The tokens of the input, lexed as they are asked for, ending with
+    /// [`TokenKind::EOF`] or the first error.
+    pub fn tokens(&mut self) -> impl Iterator<Item = Result<Token>> + '_ {
+        std::iter::from_fn(|| self.next_token().transpose())
+    }
+
+    /// Lexes the next token, or returns `None` once the EOF token or an error
+    /// has been returned.
+    pub fn next_token(&mut self) -> Result<Option<Token>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let token = self.lex_token();
+        self.finished = !matches!(&token, Ok(token) if token.kind != TokenKind::EOF);
+        token.map(Some)
+    }
 
+    fn lex_token(&mut self) -> Result<Token> {
+        loop {
+            let Some(c) = self.cur() else {
+                if self.refill()? {
+                    continue;
+                }
+                let token = self.simple(TokenKind::EOF, 0);
+                return Ok(self.finish(token));
+            };
+            let token = match c {
+                c if c.is_whitespace() => {
+                    self.increment();
+                    continue;
+                }
+
+                // base N literals, i.e. 0b_1101, 0o_567, 0x_ff
+                // the token text excludes the prefix but keeps the underscores
+                '0' if self.peek(1).is_some_and(|c| "box".contains(c)) => {
+                    let loc = self.location();
                     let base = match self.peek(1) {
                         Some('b') => Base::Bin,
                         Some('o') => Base::Oct,
@@ -87,146 +248,311 @@ impl Lexer {
                     self.increment();
                     self.increment();
 
-                    self.lex_num(&mut num, base)?;
-                    self.push(
-                        &mut tokens,
-                        Token::new(base.into(), self.location.clone(), num),
-                    );
+                    let start = self.cursor.pos();
+                    self.lex_num(base)?;
+                    self.token_from(base.into(), loc, start)
                 }
 
                 // decimal int/float literals
                 '0'..='9' => {
-                    let loc = self.location.clone();
-                    let mut num = String::new();
-
-                    self.lex_num(&mut num, Base::Dec)?;
-                    if let Some('.') = self.cur() {
-                        if let Some('.') = self.peek(1) {
-                            self.push(
-                                &mut tokens,
-                                Token::new(TokenKind::IntegerLiteralDec, loc.clone(), num),
-                            );
-                        } else {
-                            num.push('.');
+                    let loc = self.location();
+                    let start = self.cursor.pos();
+
+                    self.lex_num(Base::Dec)?;
+                    let mut kind = match (self.cur(), self.peek(1)) {
+                        (Some('.'), Some('.')) => TokenKind::IntegerLiteralDec,
+                        (Some('.'), _) => {
                             self.increment();
-                            self.lex_num(&mut num, Base::Dec)?;
-                            self.push(&mut tokens, Token::new(TokenKind::FloatLiteral, loc, num));
+                            self.lex_num(Base::Dec)?;
+                            TokenKind::FloatLiteral
                         }
-                    } else {
-                        self.push(
-                            &mut tokens,
-                            Token::new(TokenKind::IntegerLiteralDec, loc, num),
-                        );
+                        _ => TokenKind::IntegerLiteralDec,
+                    };
+                    if self.lex_exponent()? {
+                        kind = TokenKind::FloatLiteral;
                     }
+                    self.token_from(kind, loc, start)
                 }
-                '+' => self.push_simple(&mut tokens, TokenKind::Plus, 1),
-                '-' => self.push_simple(&mut tokens, TokenKind::Minus, 1),
-                '*' => self.push_simple(&mut tokens, TokenKind::Star, 1),
-                '/' => match self.peek(1) {
-                    Some('/') => {
-                        while let Some(c) = self.cur() {
-                            self.increment();
-                            if c == '\n' {
-                                break;
-                            }
+
+                // line comments
+                '/' if self.peek(1) == Some('/') => {
+                    let rest = self.cursor.rest();
+                    let comment = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                    let end = self.cursor.pos() + comment.len();
+                    if !self.started {
+                        if let Some(edition) = self.edition_pragma(comment)? {
+                            self.cursor.set_edition(edition);
                         }
                     }
-                    _ => self.push_simple(&mut tokens, TokenKind::Slash, 1),
-                },
-                '(' => self.push_simple(&mut tokens, TokenKind::LeftParen, 1),
-                ')' => self.push_simple(&mut tokens, TokenKind::RightParen, 1),
-                '[' => self.push_simple(&mut tokens, TokenKind::LeftBracket, 1),
-                ']' => self.push_simple(&mut tokens, TokenKind::RightBracket, 1),
-                '|' => self.push_simple(&mut tokens, TokenKind::Pipe, 1),
-                ':' => self.push_simple(&mut tokens, TokenKind::Colon, 1),
-                '=' => match self.peek(1) {
-                    Some('>') => self.push_simple(&mut tokens, TokenKind::FatArrow, 2),
-                    Some('=') => self.push_simple(&mut tokens, TokenKind::EqualsEquals, 2),
-                    _ => self.push_simple(&mut tokens, TokenKind::Equals, 1),
-                },
-                '<' => match self.peek(1) {
-                    Some('=') => self.push_simple(&mut tokens, TokenKind::LessThanEquals, 2),
-                    _ => self.push_simple(&mut tokens, TokenKind::LessThan, 1),
-                },
-                '>' => match self.peek(1) {
-                    Some('=') => self.push_simple(&mut tokens, TokenKind::GreaterThanEquals, 2),
-                    _ => self.push_simple(&mut tokens, TokenKind::GreaterThan, 1),
-                },
-                '!' => match self.peek(1) {
-                    Some('=') => self.push_simple(&mut tokens, TokenKind::BangEquals, 2),
-                    _ => self.push_simple(&mut tokens, TokenKind::Bang, 1),
-                },
-                ';' => self.push_simple(&mut tokens, TokenKind::SemiColon, 1),
-                ',' => self.push_simple(&mut tokens, TokenKind::Comma, 1),
-                '{' => self.push_simple(&mut tokens, TokenKind::LeftBrace, 1),
-                '}' => self.push_simple(&mut tokens, TokenKind::RightBrace, 1),
-                '@' => self.push_simple(&mut tokens, TokenKind::At, 1),
-                '"' => {
-                    let token = self.lex_string_literal()?;
-                    self.push(&mut tokens, token);
+                    self.cursor.advance_to(end);
+                    continue;
                 }
-                '.' => match self.peek(1) {
-                    Some('.') => self.push_simple(&mut tokens, TokenKind::DotDot, 2),
-                    _ => self.push_simple(&mut tokens, TokenKind::Dot, 1),
-                },
+                '/' if self.peek(1) == Some('*') => {
+                    self.skip_block_comment()?;
+                    continue;
+                }
+                '"' => self.lex_string_literal()?,
 
                 // identifiers
                 'a'..='z' | 'A'..='Z' | '_' => {
-                    let loc = self.location.clone();
-                    let mut ident = String::new();
-                    while let Some(c) = self.cur() {
-                        match c {
-                            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
-                                ident.push(c);
-                                self.increment();
-                            }
-                            _ => break,
-                        }
+                    let loc = self.location();
+                    let start = self.cursor.pos();
+                    while let Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_') = self.cur() {
+                        self.increment();
+                    }
+                    let edition = loc.edition;
+                    let mut token = Token::identifier_or_keyword(
+                        loc,
+                        self.cursor.source().clone(),
+                        start..self.cursor.pos(),
+                    );
+                    // older scripts may use these keywords as names
+                    if matches!(
+                        token.kind,
+                        TokenKind::Match
+                            | TokenKind::Try
+                            | TokenKind::Catch
+                            | TokenKind::Finally
+                            | TokenKind::Throw
+                            | TokenKind::Yield
+                            | TokenKind::Struct
+                            | TokenKind::Enum
+                            | TokenKind::Move
+                    ) && edition < Edition::E2026
+                    {
+                        token.kind = TokenKind::Identifier;
                     }
-                    self.push(&mut tokens, Token::from_str(ident, loc));
+                    token
+                }
+                _ => match OPERATORS.iter().find(|(op, _)| self.cursor.starts_with(op)) {
+                    Some((op, kind)) => self.simple(kind.clone(), op.chars().count()),
+                    None => {
+                        let err =
+                            Error::Lexer(self.location(), format!("Unexpected character {}", c));
+                        let Some(diagnostics) = &self.diagnostics else {
+                            return Err(err);
+                        };
+                        diagnostics.push(err);
+                        self.increment();
+                        continue;
+                    }
+                },
+            };
+            return Ok(self.finish(token));
+        }
+    }
+
+    /// Skips a `/* */` comment, which can span lines and have others nested
+    /// in it. A newline inside one ends a statement, like any other.
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let open = self.location();
+        self.increment();
+        self.increment();
+        let mut depth = 1;
+        while depth > 0 {
+            let rest = self.cursor.rest();
+            let Some(stop) = rest.find(['/', '*']) else {
+                self.seen_newline |= rest.contains('\n');
+                self.cursor.advance_to(self.cursor.pos() + rest.len());
+                if !self.refill()? {
+                    error!(open, "{}", UNTERMINATED_BLOCK_COMMENT);
                 }
-                _ => error!(self.location, "Unexpected character {}", c),
+                continue;
+            };
+            self.seen_newline |= rest[..stop].contains('\n');
+            self.cursor.advance_to(self.cursor.pos() + stop);
+            if self.cursor.starts_with("/*") {
+                depth += 1;
+                self.increment();
+            } else if self.cursor.starts_with("*/") {
+                depth -= 1;
+                self.increment();
             }
+            self.increment();
         }
-        self.push_simple(&mut tokens, TokenKind::EOF, 0);
-        Ok(tokens)
+        Ok(())
     }
 
+    /// The edition a `// edition: <name>` comment from before the first
+    /// token picks.
+    fn edition_pragma(&self, comment: &str) -> Result<Option<Edition>> {
+        let Some(name) = Edition::pragma(comment) else {
+            return Ok(None);
+        };
+        match Edition::from_name(name) {
+            Some(edition) => Ok(Some(edition)),
+            None => error!(
+                self.location(),
+                "Unknown edition `{}`, expected one of {}",
+                name,
+                Edition::names()
+            ),
+        }
+    }
+
+    /// The token text is the contents between the quotes, with escapes
+    /// left in; [`unescape`] decodes them. From edition 2026 on, a string
+    /// with `{expression}`s in it is an interpolated string instead, and
+    /// `{{` and `}}` stand for literal braces.
     fn lex_string_literal(&mut self) -> Result<Token> {
-        let loc = self.location.clone();
-        let mut string = String::new();
+        let loc = self.location();
+        let interpolates = loc.edition >= Edition::E2026;
+        let stops: &[char] = match interpolates {
+            true => &['"', '\\', '\n', '{', '}'],
+            false => &['"', '\\', '\n'],
+        };
+        // the tokens lexed inside the string mustn't take the newline
+        // before it, or make comments after it look like pragmas
+        let newline_before = std::mem::take(&mut self.seen_newline);
+        self.started = true;
         self.increment();
-        while let Some(c) = self.cur() {
-            match c {
-                '"' => {
+        let start = self.cursor.pos();
+        let mut parts = Vec::new();
+        let mut literal_start = start;
+        // where each escape starts, for pointing at a bad one
+        let mut escapes = Vec::new();
+        loop {
+            let rest = self.cursor.rest();
+            let Some(stop) = rest.find(stops) else {
+                // unterminated at the end of the input
+                self.cursor.advance_to(self.cursor.pos() + rest.len());
+                break;
+            };
+            self.cursor.advance_to(self.cursor.pos() + stop);
+            match self.cur() {
+                Some('\\') => {
+                    escapes.push((self.cursor.pos(), self.location()));
                     self.increment();
-                    break;
-                }
-                '\n' => {
-                    panic!("{loc} Unexpected newline in string literal");
+                    self.skip_escape();
                 }
-                _ => {
-                    string.push(c);
+                Some('\n') => error!(self.location(), "Unexpected newline in string literal"),
+                Some(brace @ ('{' | '}')) if self.peek(1) == Some(brace) => {
                     self.increment();
+                    parts.push(StringPart::Literal(literal_start..self.cursor.pos()));
+                    self.increment();
+                    literal_start = self.cursor.pos();
+                }
+                Some('}') => {
+                    let msg = "Unmatched `}` in string".to_string();
+                    return Err(Error::Lexer(self.location(), msg)
+                        .with_help("write `}}` for a literal one"));
+                }
+                Some('{') => {
+                    parts.push(StringPart::Literal(literal_start..self.cursor.pos()));
+                    parts.push(StringPart::Expression(self.lex_interpolation()?));
+                    literal_start = self.cursor.pos();
                 }
+                _ => break,
+            }
+        }
+        let end = self.cursor.pos();
+        parts.push(StringPart::Literal(literal_start..end));
+        let source = self.cursor.source().clone();
+        for part in &parts {
+            let StringPart::Literal(span) = part else {
+                continue;
+            };
+            if let Err((offset, message)) = unescape(&source[span.clone()]) {
+                let at = span.start + offset;
+                let loc = escapes
+                    .into_iter()
+                    .find(|(pos, _)| *pos == at)
+                    .map_or(loc, |(_, loc)| loc);
+                error!(loc, "{}", message);
             }
         }
-        Ok(Token::new(TokenKind::StringLiteral, loc, string))
+        if self.cur() == Some('"') {
+            self.increment();
+        }
+        self.seen_newline = newline_before;
+        let mut token = Token::new(TokenKind::StringLiteral, loc, source, start..end);
+        // the quotes are part of the token, if not of its text
+        token.loc.end = self.cursor.location().start;
+        if parts.len() > 1 {
+            parts.retain(|part| !matches!(part, StringPart::Literal(span) if span.is_empty()));
+            token.kind = TokenKind::InterpolatedString;
+            token.parts = Some(parts.into());
+        }
+        Ok(token)
     }
 
-    fn lex_num(&mut self, num: &mut String, base: Base) -> Result<()> {
+    /// Moves past the rest of an escape, after its backslash. Only how long
+    /// it is matters here, [`unescape`] checks it.
+    fn skip_escape(&mut self) {
+        if self.cursor.starts_with("u{") {
+            let rest = self.cursor.rest();
+            let len = match rest.find(['}', '"', '\n']) {
+                Some(end) if rest[end..].starts_with('}') => end + 1,
+                Some(end) => end,
+                None => rest.len(),
+            };
+            self.cursor.advance_to(self.cursor.pos() + len);
+        } else if self.cur().is_some_and(|c| c != '\n') {
+            // the escaped char can't end the literal, even if it's a quote
+            self.increment();
+        }
+    }
+
+    /// Lexes a `{expression}` in a string, from its opening brace up to
+    /// and including its closing one.
+    fn lex_interpolation(&mut self) -> Result<Vec<Token>> {
+        let open = self.location();
+        self.increment();
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        loop {
+            while self.cur().is_some_and(|c| c.is_whitespace() && c != '\n') {
+                self.increment();
+            }
+            match self.cur() {
+                None | Some('\n') => error!(open, "Unclosed `{{` in string"),
+                // with nothing left on the line to close it, a quote ends the
+                // outer string rather than starting one in the expression
+                Some('"')
+                    if !self.cursor.rest()[1..]
+                        .lines()
+                        .next()
+                        .unwrap_or("")
+                        .contains('"') =>
+                {
+                    error!(open, "Unclosed `{{` in string")
+                }
+                Some('}') if depth == 0 => break,
+                _ if self.cursor.starts_with("//") || self.cursor.starts_with("/*") => {
+                    error!(self.location(), "Comments can't go inside a string")
+                }
+                _ => {}
+            }
+            let token = self.lex_token()?;
+            match token.kind {
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightBrace => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+        if tokens.is_empty() {
+            error!(open, "Expected an expression between `{{` and `}}`");
+        }
+        tokens.push(self.simple(TokenKind::EOF, 0));
+        self.increment();
+        Ok(tokens)
+    }
+
+    fn lex_num(&mut self, base: Base) -> Result<()> {
         while let Some(mut c) = self.cur() {
             c = c.to_ascii_lowercase();
             match (base, c) {
+                // the exponent of a float, which lex_exponent takes
+                (Base::Dec, 'e') => break,
                 (Base::Bin, '0'..='1')
                 | (Base::Oct, '0'..='7')
                 | (Base::Dec, '0'..='9')
                 | (Base::Hex, '0'..='9' | 'a'..='f') => {
-                    num.push(c);
                     self.increment();
                 }
                 (_, '0'..='9' | 'a'..='f') => {
-                    error!(self.location, "Invalid numerical literal");
+                    error!(self.location(), "Invalid numerical literal");
                 }
                 (_, '_') => self.increment(),
                 _ => break,
@@ -234,6 +560,105 @@ impl Lexer {
         }
         Ok(())
     }
+
+    /// Lexes the `e10`, `e+10` or `e-10` that can end a decimal literal.
+    /// Returns whether there was one.
+    fn lex_exponent(&mut self) -> Result<bool> {
+        if !matches!(self.cur(), Some('e' | 'E')) {
+            return Ok(false);
+        }
+        let digits = match self.peek(1) {
+            Some('+' | '-') => 2,
+            _ => 1,
+        };
+        if !matches!(self.peek(digits), Some('0'..='9')) {
+            error!(self.location(), "Invalid numerical literal");
+        }
+        for _ in 0..digits {
+            self.increment();
+        }
+        self.lex_num(Base::Dec)?;
+        Ok(true)
+    }
+}
+
+/// Decodes the escapes in the text of a string literal: `\n`, `\t`, `\r`,
+/// `\\`, `\"`, `\0` and `\u{XXXX}`. Errors give the byte offset
+/// of the bad escape along with what's wrong with it.
+pub fn unescape(text: &str) -> std::result::Result<Cow<'_, str>, (usize, String)> {
+    if !text.contains('\\') {
+        return Ok(Cow::Borrowed(text));
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, 'r')) => '\r',
+            Some((_, '\\')) => '\\',
+            Some((_, '"')) => '"',
+            Some((_, '0')) => '\0',
+            Some((_, 'u')) => unicode_escape(&mut chars).ok_or_else(|| {
+                (
+                    i,
+                    "Invalid unicode escape, expected `\\u{` then 1 to 6 hex digits \
+                     of a char then `}`"
+                        .to_string(),
+                )
+            })?,
+            Some((_, c)) => return Err((i, format!("Unknown escape sequence `\\{}`", c))),
+            None => return Err((i, "Unfinished escape sequence".to_string())),
+        });
+    }
+    Ok(Cow::Owned(out))
+}
+
+/// The char of the `{XXXX}` after a `\u`.
+fn unicode_escape(chars: &mut CharIndices) -> Option<char> {
+    if chars.next()?.1 != '{' {
+        return None;
+    }
+    let mut code = 0;
+    let mut digits = 0;
+    loop {
+        let (_, c) = chars.next()?;
+        if c == '}' {
+            break;
+        }
+        code = code * 16 + c.to_digit(16)?;
+        digits += 1;
+        if digits > 6 {
+            return None;
+        }
+    }
+    match digits {
+        0 => None,
+        _ => char::from_u32(code),
+    }
+}
+
+/// The inverse of [`unescape`], for writing `text` back out as the contents
+/// of a string literal.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\0' => out.push_str("\\0"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
 }
 
 #[derive(Debug, Clone, Copy)]
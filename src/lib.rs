@@ -0,0 +1,43 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod ast;
+mod builtin;
+pub mod bundle;
+mod bytecode;
+pub mod checker;
+pub mod coverage;
+mod cursor;
+mod datetime;
+pub mod edition;
+pub mod editor;
+pub mod error;
+mod gc;
+#[cfg(feature = "generator")]
+pub mod generator;
+pub mod grammar;
+pub mod interpreter;
+pub mod ir;
+pub mod iterator;
+pub mod json;
+#[cfg(feature = "kernel")]
+pub mod kernel;
+pub mod lexer;
+pub mod manifest;
+mod math;
+pub mod memory;
+mod methods;
+mod optimizer;
+pub mod parser;
+pub mod project;
+pub mod random;
+pub mod repl;
+mod resolver;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod signal;
+pub mod snapshot;
+pub mod source_map;
+pub mod symbol;
+mod template;
+pub mod token;
+pub mod value;
@@ -1,20 +1,77 @@
-#![allow(clippy::upper_case_acronyms)]
-
-use crate::error::Result;
+use rattlesnake::bundle::Bundle;
+use rattlesnake::edition::Edition;
+use rattlesnake::error::{use_color, Diagnostics, Error, Result};
+use rattlesnake::manifest::Manifest;
+use rattlesnake::memory::CountingAllocator;
+use rattlesnake::value::Overflow;
+use rattlesnake::{checker, grammar, interpreter, ir, lexer, parser, project, repl, signal};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::Duration;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn main() {
+    let runner = std::thread::Builder::new()
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(run)
+        .expect("couldn't start the interpreter's thread");
+    // a panic has already been reported by the thread
+    let result = runner.join().unwrap_or_else(|_| exit(101));
+    if let Err(e) = result {
+        eprint!("{}", e.render(use_color(&std::io::stderr())));
+        exit(1);
+    }
+}
 
-mod ast;
-mod builtin;
-mod error;
-mod interpreter;
-mod lexer;
-mod parser;
-mod repl;
-mod token;
-mod value;
-
-fn main() -> Result<()> {
-    let args = Args::get_args();
+fn run() -> Result<()> {
+    if let Some(bundle) = Bundle::embedded()? {
+        return bundle.run();
+    }
+    let argv: Vec<String> = std::env::args().collect();
+    match argv.get(1).map(String::as_str) {
+        Some("bundle") => return bundle(&argv[2..]),
+        Some("check") => return check(&argv[2..]),
+        Some("tokens") => return tokens(&argv[2..]),
+        Some("ast") => return ast(&argv[2..]),
+        // `run` and `repl` are the same as running without them, or with --repl
+        _ => {}
+    }
+    let mut args = Args::get_args();
+    match args.emit.as_deref() {
+        // the listing needs the program, which is parsed below
+        Some("ir") | None => {}
+        Some(emit) => return emit_files(emit),
+    }
+    if args.kernel {
+        #[cfg(feature = "kernel")]
+        return rattlesnake::kernel::Kernel::new().run();
+        #[cfg(not(feature = "kernel"))]
+        return Err(Error::Other(
+            "--kernel needs rattlesnake to be built with the `kernel` feature".to_string(),
+        ));
+    }
+    let manifest = match &args.file {
+        Some(file) => Manifest::find(Path::new(file).parent().unwrap_or(Path::new("")))?,
+        None => Manifest::find(Path::new("."))?,
+    };
+    let edition = args
+        .edition
+        .or(manifest.as_ref().and_then(|manifest| manifest.edition));
+    if let Some(edition) = edition {
+        Edition::set_default(edition);
+    }
+    if !args.repl && args.file.is_none() && args.code.is_none() {
+        // without arguments, run the project's entry point, or fall back to the repl
+        match manifest
+            .as_ref()
+            .and_then(|manifest| manifest.entry.as_ref())
+        {
+            Some(entry) => args.file = Some(entry.display().to_string()),
+            None => args.repl = true,
+        }
+    }
     if args.repl && args.file.is_some() {
         println!("Cannot run file and repl at the same time.");
         exit(1);
@@ -24,50 +81,318 @@ fn main() -> Result<()> {
     }
     if args.repl {
         let mut repl = repl::Repl::new();
+        repl.interpreter_mut().set_strict(args.strict);
+        repl.interpreter_mut().set_overflow(args.overflow);
+        repl.interpreter_mut().set_max_depth(args.max_depth);
         repl.run();
         exit(0)
     }
-    let file = if let Some(ref file) = args.file {
-        std::fs::read_to_string(file).expect("Couldn't open input file")
+    let mut interpreter = interpreter::Interpreter::new();
+    let ast = if let Some(ref file) = args.file {
+        let project = project::parse_project(Path::new(file), manifest.as_ref());
+        if let Some((last, rest)) = project.errors.split_last() {
+            for err in rest {
+                eprint!("{}", err.render(use_color(&std::io::stderr())));
+            }
+            return Err(last.clone());
+        }
+        if args.emit.is_some() {
+            emit_ir(&interpreter, &project, args.vm);
+            return Ok(());
+        }
+        interpreter.add_parsed_modules(&project);
+        interpreter.set_args(file.clone(), args.script_args);
+        project.modules[&project.entry].clone()
     } else if let Some(code) = args.code {
-        code
+        let lex = lexer::Lexer::new(code, String::from("<input>"));
+        let mut parser = parser::Parser::from_lexer(lex);
+        let ast = parser.parse()?;
+        if args.emit.is_some() {
+            if args.vm {
+                print!("{}", ir::bytecode(&ast));
+            } else {
+                let builtins = interpreter.builtin_names().collect::<Vec<_>>();
+                print!("{}", ir::listing(&ast, &builtins));
+            }
+            return Ok(());
+        }
+        interpreter.set_args("-c", args.script_args);
+        ast
     } else {
         unreachable!()
     };
-    let mut lex = lexer::Lexer::new(file, args.file.unwrap_or(String::from("<input>")));
-    let tokens = lex.lex()?;
-    let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse()?;
-    let mut interpreter = interpreter::Interpreter::new();
-    interpreter.execute(&ast)?;
+    if let Some(manifest) = manifest {
+        interpreter.set_manifest(manifest);
+    }
+    if args.coverage {
+        interpreter.enable_coverage();
+    }
+    interpreter.set_strict(args.strict);
+    interpreter.set_vm(args.vm);
+    interpreter.set_optimize(args.optimize);
+    interpreter.set_max_depth(args.max_depth);
+    interpreter.set_fuel(args.fuel);
+    interpreter.set_timeout(args.timeout);
+    interpreter.set_memory_limit(args.memory_limit);
+    interpreter.set_overflow(args.overflow);
+    signal::install();
+    let result = interpreter.execute(&ast);
+    if let Some(coverage) = interpreter.coverage() {
+        // a failing script still gets a report of how far it got
+        write_files(&[
+            ("coverage/lcov.info", coverage.lcov()),
+            ("coverage/index.html", coverage.html()),
+        ])?;
+    }
+    result?;
+    Ok(())
+}
+
+/// `rattlesnake bundle <script> [-o <output>]`: builds a standalone
+/// executable that runs the script.
+fn bundle(args: &[String]) -> Result<()> {
+    let (script, output) = match args {
+        [script] => (script, None),
+        [script, flag, output] if flag == "-o" || flag == "--output" => (script, Some(output)),
+        _ => {
+            println!("Usage: rattlesnake bundle <script> [-o <output>]");
+            exit(1);
+        }
+    };
+    let script = Path::new(script);
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => {
+            let stem = script.file_stem().unwrap_or(script.as_os_str());
+            PathBuf::from(stem).with_extension(std::env::consts::EXE_EXTENSION)
+        }
+    };
+    let manifest = Manifest::find(script.parent().unwrap_or(Path::new("")))?;
+    let bundle = Bundle::collect(script, manifest.as_ref())?;
+    bundle.write_executable(&output)?;
+    println!("Wrote {} ({} files)", output.display(), bundle.files.len());
+    Ok(())
+}
+
+/// What `check`, `tokens` and `ast` read: a file, or code passed with `-c`.
+enum Input {
+    File(PathBuf),
+    Code(String),
+}
+
+impl Input {
+    fn from_args(command: &str, args: &[String]) -> Input {
+        match args {
+            [file] if !file.starts_with('-') => Input::File(PathBuf::from(file)),
+            [flag, code] if flag == "-c" || flag == "--code" => Input::Code(code.clone()),
+            _ => {
+                println!("Usage: rattlesnake {} <file> | -c <code>", command);
+                exit(1);
+            }
+        }
+    }
+
+    /// The manifest next to the file, whose edition is made the default.
+    fn manifest(&self) -> Result<Option<Manifest>> {
+        let dir = match self {
+            Input::File(file) => file.parent().unwrap_or(Path::new("")),
+            Input::Code(_) => Path::new("."),
+        };
+        let manifest = Manifest::find(dir)?;
+        if let Some(edition) = manifest.as_ref().and_then(|manifest| manifest.edition) {
+            Edition::set_default(edition);
+        }
+        Ok(manifest)
+    }
+
+    fn lexer(&self) -> Result<lexer::Lexer> {
+        self.manifest()?;
+        Ok(match self {
+            Input::File(file) => {
+                let source = std::fs::read_to_string(file).map_err(|err| {
+                    Error::Other(format!("Couldn't read {}: {}", file.display(), err))
+                })?;
+                lexer::Lexer::new(source, file.display().to_string())
+            }
+            Input::Code(code) => lexer::Lexer::new(code.clone(), String::from("<input>")),
+        })
+    }
+}
+
+/// `rattlesnake check <file>`: parses the file and everything it imports
+/// without running anything, and reports the error each file stops at,
+/// then the type errors that the checker finds in the files that parsed.
+fn check(args: &[String]) -> Result<()> {
+    let input = Input::from_args("check", args);
+    let (files, errors) = match &input {
+        Input::File(file) => {
+            let manifest = input.manifest()?;
+            let project = project::parse_project(file, manifest.as_ref());
+            let mut modules = project.modules.iter().collect::<Vec<_>>();
+            modules.sort_by_key(|(path, _)| *path);
+            let mut errors = project.errors;
+            for (_, ast) in modules {
+                errors.extend(checker::check(ast));
+            }
+            (project.modules.len(), errors)
+        }
+        Input::Code(_) => {
+            let diagnostics = Diagnostics::new();
+            let lexer = input.lexer()?.with_diagnostics(diagnostics.clone());
+            let ast = parser::Parser::from_lexer(lexer).parse();
+            let mut errors = diagnostics.take();
+            if let Ok(ast) = ast {
+                errors.extend(checker::check(&ast));
+            }
+            (1, errors)
+        }
+    };
+    let files = match files {
+        1 => "1 file".to_string(),
+        files => format!("{} files", files),
+    };
+    match errors.split_last() {
+        Some((last, rest)) => {
+            for err in rest {
+                eprint!("{}", err.render(use_color(&std::io::stderr())));
+            }
+            Err(last.clone())
+        }
+        None => {
+            println!("Checked {}, no errors", files);
+            Ok(())
+        }
+    }
+}
+
+/// `rattlesnake tokens <file>`: prints what the lexer makes of the file, a
+/// token per line with where it starts and the byte offsets it spans.
+fn tokens(args: &[String]) -> Result<()> {
+    let mut lexer = Input::from_args("tokens", args).lexer()?;
+    for token in lexer.tokens() {
+        let token = token?;
+        let loc = &token.loc;
+        println!(
+            "{}:{}\t{}..{}\t{}",
+            loc.line, loc.column, loc.start, loc.end, token
+        );
+    }
+    Ok(())
+}
+
+/// `rattlesnake ast <file>`: prints the parse tree of the file, as the
+/// listing of `--emit=ir` does.
+fn ast(args: &[String]) -> Result<()> {
+    let lexer = Input::from_args("ast", args).lexer()?;
+    let ast = parser::Parser::from_lexer(lexer).parse()?;
+    let builtins = interpreter::Interpreter::new()
+        .builtin_names()
+        .collect::<Vec<_>>();
+    print!("{}", ir::listing(&ast, &builtins));
+    Ok(())
+}
+
+/// `--emit=ir`: prints the listing of every module in the project, starting
+/// with the entry point, or with `--vm` their bytecode.
+fn emit_ir(interpreter: &interpreter::Interpreter, project: &project::Project, vm: bool) {
+    let builtins = interpreter.builtin_names().collect::<Vec<_>>();
+    let mut paths = project.modules.keys().collect::<Vec<_>>();
+    paths.sort_by_key(|path| (*path != &project.entry, *path));
+    for (i, path) in paths.into_iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        println!("; {}", path.display());
+        if vm {
+            print!("{}", ir::bytecode(&project.modules[path]));
+        } else {
+            print!("{}", ir::listing(&project.modules[path], &builtins));
+        }
+    }
+}
+
+/// Writes generated files for `--emit=<what>` into the current directory.
+fn emit_files(what: &str) -> Result<()> {
+    let files = match what {
+        "grammar" => [
+            ("rattlesnake.tmLanguage.json", grammar::textmate()),
+            ("rattlesnake.vim", grammar::vim()),
+        ],
+        _ => {
+            println!(
+                "Unknown --emit target \"{}\", expected \"grammar\" or \"ir\".",
+                what
+            );
+            exit(1);
+        }
+    };
+    write_files(&files)
+}
+
+fn write_files(files: &[(&str, String)]) -> Result<()> {
+    for (path, contents) in files {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir).map_err(|err| {
+                Error::Other(format!("Couldn't create {}: {}", dir.display(), err))
+            })?;
+        }
+        std::fs::write(path, contents)
+            .map_err(|err| Error::Other(format!("Couldn't write {}: {}", path, err)))?;
+        eprintln!("Wrote {}", path);
+    }
     Ok(())
 }
 
 #[derive(Debug)]
 struct Args {
     repl: bool,
+    kernel: bool,
+    coverage: bool,
+    strict: bool,
+    /// Whether to run the script as bytecode instead of walking its AST.
+    vm: bool,
+    /// Whether to fold the script's constants before running it.
+    optimize: bool,
+    max_depth: usize,
+    /// How many steps the script may take.
+    fuel: Option<u64>,
+    timeout: Option<Duration>,
+    /// How many bytes of the heap the script may use.
+    memory_limit: Option<usize>,
+    overflow: Overflow,
+    edition: Option<Edition>,
+    emit: Option<String>,
     file: Option<String>,
     code: Option<String>,
+    /// What comes after the script's path, or after `--`, for the script.
+    script_args: Vec<String>,
 }
 
 impl Args {
     fn get_args() -> Args {
         let args: Vec<String> = std::env::args().collect();
-        if args.len() == 1 {
-            return Args {
-                repl: true,
-                file: None,
-                code: None,
-            };
-        }
-
         let mut repl = None;
+        let mut kernel = false;
+        let mut coverage = false;
+        let mut strict = false;
+        let mut vm = false;
+        let mut optimize = false;
+        let mut max_depth = interpreter::DEFAULT_MAX_DEPTH;
+        let mut fuel = None;
+        let mut timeout = None;
+        let mut memory_limit = None;
+        let mut overflow = Overflow::default();
+        let mut edition = None;
+        let mut emit = None;
         let mut file = None;
         let mut code = None;
+        let mut script_args = vec![];
         let mut i: usize = 1;
         while i < args.len() {
             let item = &args[i];
             match item.as_str() {
+                "run" if i == 1 => {}
+                "repl" if i == 1 => repl = Some(true),
                 "--repl" | "-r" => {
                     repl = if repl.is_some() {
                         println!("Bad usage of repl param.");
@@ -77,6 +402,93 @@ impl Args {
                         Some(true)
                     }
                 }
+                "--kernel" => kernel = true,
+                "--coverage" => coverage = true,
+                "--strict" => strict = true,
+                "--vm" => vm = true,
+                "-O" => optimize = true,
+                "--max-depth" => {
+                    i += 1;
+                    let depth = args.get(i).map_or("", String::as_str);
+                    max_depth = match depth.parse() {
+                        Ok(depth) => depth,
+                        Err(_) => {
+                            println!("Invalid recursion depth \"{}\", expected a number.", depth);
+                            exit(1);
+                        }
+                    };
+                }
+                "--fuel" => {
+                    i += 1;
+                    let steps = args.get(i).map_or("", String::as_str);
+                    fuel = match steps.parse() {
+                        Ok(steps) => Some(steps),
+                        Err(_) => {
+                            println!("Invalid fuel \"{}\", expected a number of steps.", steps);
+                            exit(1);
+                        }
+                    };
+                }
+                "--timeout" => {
+                    i += 1;
+                    let seconds = args.get(i).map_or("", String::as_str);
+                    timeout = match seconds.parse().map(Duration::try_from_secs_f64) {
+                        Ok(Ok(timeout)) => Some(timeout),
+                        _ => {
+                            println!("Invalid timeout \"{}\", expected seconds.", seconds);
+                            exit(1);
+                        }
+                    };
+                }
+                "--memory-limit" => {
+                    i += 1;
+                    let megabytes = args.get(i).map_or("", String::as_str);
+                    let bytes = megabytes.parse::<usize>().map(|mb| mb.checked_mul(1 << 20));
+                    memory_limit = match bytes {
+                        Ok(Some(bytes)) => Some(bytes),
+                        Ok(None) => {
+                            println!("Memory limit of {} MiB is too big.", megabytes);
+                            exit(1);
+                        }
+                        Err(_) => {
+                            println!("Invalid memory limit \"{}\", expected MiB.", megabytes);
+                            exit(1);
+                        }
+                    };
+                }
+                "--overflow" => {
+                    i += 1;
+                    let name = args.get(i).map_or("", String::as_str);
+                    overflow = match Overflow::from_name(name) {
+                        Some(overflow) => overflow,
+                        None => {
+                            println!(
+                                "Unknown overflow behavior \"{}\", expected one of {}.",
+                                name,
+                                Overflow::names()
+                            );
+                            exit(1);
+                        }
+                    };
+                }
+                "--edition" => {
+                    i += 1;
+                    let name = args.get(i).map_or("", String::as_str);
+                    edition = match Edition::from_name(name) {
+                        Some(edition) => Some(edition),
+                        None => {
+                            println!(
+                                "Unknown edition \"{}\", expected one of {}.",
+                                name,
+                                Edition::names()
+                            );
+                            exit(1);
+                        }
+                    };
+                }
+                _ if item.starts_with("--emit=") => {
+                    emit = Some(item["--emit=".len()..].to_string());
+                }
                 "--file" | "-f" => {
                     file = if file.is_some() {
                         println!("Multiple usages of file param.");
@@ -84,7 +496,14 @@ impl Args {
                         exit(1);
                     } else {
                         i += 1;
-                        Some(args[i].clone())
+                        match args.get(i) {
+                            Some(file) => Some(file.clone()),
+                            None => {
+                                println!("Missing a file after \"{}\".", item);
+                                println!("Usage: rattlesnake [file] [args]");
+                                exit(1);
+                            }
+                        }
                     };
                 }
                 "--code" | "-c" => {
@@ -94,11 +513,24 @@ impl Args {
                         exit(1);
                     } else {
                         i += 1;
-                        Some(args[i].clone())
+                        match args.get(i) {
+                            Some(code) => Some(code.clone()),
+                            None => {
+                                println!("Missing code after \"{}\".", item);
+                                println!("Usage: rattlesnake [file] [args]");
+                                exit(1);
+                            }
+                        }
                     };
                 }
-                _ if !(item.starts_with('-') || item.starts_with("--")) && i == 1 => {
+                "--" => {
+                    script_args = args[i + 1..].to_vec();
+                    break;
+                }
+                _ if !item.starts_with('-') && file.is_none() => {
                     file = Some(item.clone());
+                    script_args = args[i + 1..].to_vec();
+                    break;
                 }
                 _ => {
                     println!("Unknown argument \"{}\".", item);
@@ -110,8 +542,21 @@ impl Args {
         }
         Args {
             repl: repl.unwrap_or(false),
+            kernel,
+            coverage,
+            strict,
+            vm,
+            optimize,
+            max_depth,
+            fuel,
+            timeout,
+            memory_limit,
+            overflow,
+            edition,
+            emit,
             file,
             code,
+            script_args,
         }
     }
 }
@@ -0,0 +1,220 @@
+use crate::edition::Edition;
+use crate::error::{Error, Result};
+use crate::token::{FileId, Location};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_NAME: &str = "snake.toml";
+
+/// A project manifest, read from a `snake.toml` such as
+///
+/// ```toml
+/// entry = "src/main.rat"
+/// roots = ["src", "lib"]
+/// edition = "2026"
+///
+/// [dependencies]
+/// utils = "../utils/utils.rat"
+/// shapes = { path = "../shapes" }
+/// ```
+///
+/// All paths are relative to the directory containing the manifest.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub dir: PathBuf,
+    pub entry: Option<PathBuf>,
+    pub roots: Vec<PathBuf>,
+    /// The edition of files that don't pick their own.
+    pub edition: Option<Edition>,
+    pub dependencies: HashMap<String, PathBuf>,
+}
+
+macro_rules! manifest_error {
+    ($loc:expr, $($arg:tt)*) => {
+        return Err(Error::Manifest($loc.clone(), format!($($arg)*)))
+    }
+}
+
+impl Manifest {
+    /// Looks for a manifest in `start` and each of its ancestors.
+    pub fn find(start: &Path) -> Result<Option<Manifest>> {
+        // the parent of a bare filename is the empty path
+        let start = if start.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            start
+        };
+        let start = start.canonicalize().unwrap_or(start.to_path_buf());
+        for dir in start.ancestors() {
+            let path = dir.join(MANIFEST_NAME);
+            if path.is_file() {
+                return Manifest::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| Error::Other(format!("Couldn't read {}: {}", path.display(), err)))?;
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Manifest::parse(&source, &path.display().to_string(), dir)
+    }
+
+    pub fn parse(source: &str, filename: &str, dir: PathBuf) -> Result<Manifest> {
+        let mut manifest = Manifest {
+            dir,
+            entry: None,
+            roots: vec![],
+            edition: None,
+            dependencies: HashMap::new(),
+        };
+        let file = FileId::new(filename);
+        let mut section = String::new();
+        let mut start = 0;
+        for (i, raw) in source.split_inclusive('\n').enumerate() {
+            let line = raw.trim_end_matches(['\n', '\r']);
+            let loc = Location {
+                line: i + 1,
+                column: 1,
+                file,
+                start,
+                end: start + line.len(),
+                edition: Edition::default_edition(),
+            };
+            start += raw.len();
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                match name.strip_suffix(']') {
+                    Some(name) => section = name.trim().to_string(),
+                    None => manifest_error!(loc, "Unterminated section header"),
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                manifest_error!(loc, "Expected `key = value`, got `{}`", line);
+            };
+            let key = key.trim();
+            let value = parse_value(value.trim(), &loc)?;
+            match (section.as_str(), key, value) {
+                ("", "entry", TomlValue::String(entry)) => {
+                    manifest.entry = Some(manifest.dir.join(entry))
+                }
+                ("", "roots", TomlValue::Array(roots)) => {
+                    manifest.roots = roots.iter().map(|root| manifest.dir.join(root)).collect()
+                }
+                ("dependencies", name, TomlValue::String(path))
+                | ("dependencies", name, TomlValue::Path(path)) => {
+                    manifest
+                        .dependencies
+                        .insert(name.to_string(), manifest.dir.join(path));
+                }
+                ("", "edition", TomlValue::String(name)) => match Edition::from_name(&name) {
+                    Some(edition) => manifest.edition = Some(edition),
+                    None => manifest_error!(
+                        loc,
+                        "Unknown edition `{}`, expected one of {}",
+                        name,
+                        Edition::names()
+                    ),
+                },
+                ("", "entry" | "roots" | "edition", _) => {
+                    manifest_error!(loc, "Invalid value for `{}`", key)
+                }
+                ("dependencies", _, _) => manifest_error!(
+                    loc,
+                    "Dependency `{}` must be a path string or `{{ path = \"...\" }}`",
+                    key
+                ),
+                _ => manifest_error!(loc, "Unknown manifest key `{}`", key),
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Finds the file for `import name`: dependencies first, then each source
+    /// root. The name is a path for `import a.b`, which is `a/b`.
+    pub fn resolve(&self, name: &str) -> Option<PathBuf> {
+        // `import shapes.circle` looks in the `shapes` dependency
+        if let Some((dependency, rest)) = name.split_once('/') {
+            if let Some(path) = self
+                .dependencies
+                .get(dependency)
+                .filter(|path| path.is_dir())
+            {
+                return Some(path.join(format!("{}.rat", rest)));
+            }
+        }
+        if let Some(path) = self.dependencies.get(name) {
+            if !path.is_dir() {
+                return Some(path.clone());
+            }
+            // a dependency directory is either a project of its own or holds `<name>.rat`
+            return match Manifest::load(&path.join(MANIFEST_NAME)) {
+                Ok(Manifest {
+                    entry: Some(entry), ..
+                }) => Some(entry),
+                _ => Some(path.join(format!("{}.rat", name))),
+            };
+        }
+        self.roots
+            .iter()
+            .map(|root| root.join(format!("{}.rat", name)))
+            .find(|path| path.is_file())
+    }
+}
+
+enum TomlValue {
+    String(String),
+    Array(Vec<String>),
+    Path(String),
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(value: &str, loc: &Location) -> Result<String> {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(string) => Ok(string.to_string()),
+        None => manifest_error!(loc, "Expected a string, got `{}`", value),
+    }
+}
+
+fn parse_value(value: &str, loc: &Location) -> Result<TomlValue> {
+    if let Some(items) = value.strip_prefix('[') {
+        let Some(items) = items.strip_suffix(']') else {
+            manifest_error!(loc, "Unterminated array");
+        };
+        return items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| parse_string(item, loc))
+            .collect::<Result<Vec<_>>>()
+            .map(TomlValue::Array);
+    }
+    if let Some(table) = value.strip_prefix('{') {
+        let Some(table) = table.strip_suffix('}') else {
+            manifest_error!(loc, "Unterminated inline table");
+        };
+        return match table.split_once('=') {
+            Some((key, path)) if key.trim() == "path" => {
+                Ok(TomlValue::Path(parse_string(path.trim(), loc)?))
+            }
+            _ => manifest_error!(loc, "Expected `{{ path = \"...\" }}`"),
+        };
+    }
+    parse_string(value, loc).map(TomlValue::String)
+}
@@ -0,0 +1,203 @@
+//! The `math` module, which every global scope starts out with.
+
+use crate::builtin::iterator_argument;
+use crate::error::{runtime_error as error, Result};
+use crate::interpreter::{Interpreter, Ref, Scope};
+use crate::token::Location;
+use crate::value::{IntegerOp, Module, Overflow, Value};
+
+pub const NAME: &str = "math";
+
+pub fn module() -> Module {
+    let mut module = Module::new(NAME);
+    module.add_function("sqrt", sqrt);
+    module.add_function("abs", abs);
+    module.add_function("floor", floor);
+    module.add_function("ceil", ceil);
+    module.add_function("round", round);
+    module.add_function("sin", sin);
+    module.add_function("cos", cos);
+    module.add_function("tan", tan);
+    module.add_function("log", log);
+    module.add_function("log2", log2);
+    module.add_function("log10", log10);
+    module.add_function("pow", pow);
+    module.add_function("min", min);
+    module.add_function("max", max);
+    module.add_constant("pi", Value::Float(std::f64::consts::PI));
+    module.add_constant("e", Value::Float(std::f64::consts::E));
+    module.add_constant("inf", Value::Float(f64::INFINITY));
+    module.add_constant("nan", Value::Float(f64::NAN));
+    module
+}
+
+/// The only argument as a float, converting an integer.
+fn number(name: &str, args: &[Value], loc: &Location) -> Result<f64> {
+    match args {
+        [value] => match value.as_float() {
+            Some(value) => Ok(value),
+            None => error!(
+                loc,
+                "math.{}() expects a number, got {}",
+                name,
+                value.repr()
+            ),
+        },
+        _ => error!(loc, "math.{}() takes exactly one number", name),
+    }
+}
+
+/// Defines a function of one number that gives a float.
+macro_rules! float_fn {
+    ($name:ident, $f:expr) => {
+        fn $name(
+            _interpreter: &mut Interpreter,
+            _scope: &Ref<Scope>,
+            loc: &Location,
+            args: Vec<Value>,
+        ) -> Result<Value> {
+            let value = number(stringify!($name), &args, loc)?;
+            Ok(Value::Float($f(value)))
+        }
+    };
+}
+
+float_fn!(sqrt, f64::sqrt);
+float_fn!(sin, f64::sin);
+float_fn!(cos, f64::cos);
+float_fn!(tan, f64::tan);
+float_fn!(log2, f64::log2);
+float_fn!(log10, f64::log10);
+
+/// `math.log(x)` is the natural logarithm, `math.log(x, base)` any other.
+fn log(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    Ok(match args.as_slice() {
+        [_] => Value::Float(number("log", &args, loc)?.ln()),
+        [value, base] => match (value.as_float(), base.as_float()) {
+            (Some(value), Some(base)) => Value::Float(value.log(base)),
+            _ => error!(loc, "math.log() takes a number and an optional base"),
+        },
+        _ => error!(loc, "math.log() takes a number and an optional base"),
+    })
+}
+
+/// `math.abs(x)`, which overflows for the smallest integer as the
+/// interpreter's setting says.
+fn abs(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    Ok(match args.as_slice() {
+        [Value::Integer(i)] => match (i.checked_abs(), interpreter.overflow()) {
+            (Some(abs), _) => Value::Integer(abs),
+            // only `i64::MIN` has no absolute value that fits
+            (None, Overflow::Error) => error!(loc, "Integer overflow in `abs`"),
+            (None, overflow) => Value::Integer(overflow.apply(IntegerOp::Sub, 0, *i, loc)?),
+        },
+        _ => Value::Float(number("abs", &args, loc)?.abs()),
+    })
+}
+
+/// Defines a rounding function, which gives an integer.
+macro_rules! rounding_fn {
+    ($name:ident, $f:expr) => {
+        fn $name(
+            _interpreter: &mut Interpreter,
+            _scope: &Ref<Scope>,
+            loc: &Location,
+            args: Vec<Value>,
+        ) -> Result<Value> {
+            if let [Value::Integer(i)] = args.as_slice() {
+                return Ok(Value::Integer(*i));
+            }
+            let value = $f(number(stringify!($name), &args, loc)?);
+            // i64::MAX isn't a float, but the next power of two is
+            if !(i64::MIN as f64..i64::MAX as f64).contains(&value) {
+                error!(
+                    loc,
+                    "math.{}() can't make an integer of {}",
+                    stringify!($name),
+                    args[0].repr()
+                );
+            }
+            Ok(Value::Integer(value as i64))
+        }
+    };
+}
+
+rounding_fn!(floor, f64::floor);
+rounding_fn!(ceil, f64::ceil);
+rounding_fn!(round, f64::round);
+
+/// `math.pow(a, b)`, the same as `a ** b`.
+fn pow(
+    interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [base, exponent] => base.power(exponent, interpreter.overflow(), loc),
+        _ => error!(loc, "math.pow() takes exactly two numbers"),
+    }
+}
+
+/// The value of `args`, or of the one iterable in `args`, that `replaces`
+/// says should replace each one before it.
+fn extreme(
+    interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    name: &str,
+    args: Vec<Value>,
+    replaces: fn(&Value, &Value, &Location) -> Result<Value>,
+) -> Result<Value> {
+    let values = match <[Value; 1]>::try_from(args) {
+        Ok([iterable]) => {
+            let iterator = iterator_argument(&format!("math.{}", name), iterable, loc)?;
+            let mut values = vec![];
+            while let Some(value) = iterator.next(interpreter, scope, loc)? {
+                values.push(value);
+            }
+            values
+        }
+        Err(args) => args,
+    };
+    let mut values = values.into_iter();
+    let Some(mut best) = values.next() else {
+        error!(loc, "math.{}() needs at least one value", name)
+    };
+    for value in values {
+        if let Value::Boolean(true) = replaces(&value, &best, loc)? {
+            best = value;
+        }
+    }
+    Ok(best)
+}
+
+/// `math.min(a, b, ...)` or `math.min(iterable)`: the first of the smallest.
+fn min(
+    interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    extreme(interpreter, scope, loc, "min", args, Value::less_than)
+}
+
+/// `math.max(a, b, ...)` or `math.max(iterable)`: the first of the largest.
+fn max(
+    interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    extreme(interpreter, scope, loc, "max", args, Value::greater_than)
+}
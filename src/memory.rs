@@ -0,0 +1,79 @@
+//! Counts the heap each thread holds, for [`Interpreter::set_memory_limit`].
+//!
+//! Only allocations made through [`CountingAllocator`] are counted, so it
+//! has to be the global allocator, as it is in the `rattlesnake` binary.
+//! Without it the limit only sees the results of repetitions, which are
+//! checked before they are made.
+//!
+//! The count and the limit belong to the thread, not to an interpreter:
+//! interpreters running on the same thread share one budget, what any of
+//! them allocates counts against it, and setting the limit from one of them
+//! replaces it for all of them. Hosts that want separate budgets run each
+//! interpreter on a thread of its own.
+//!
+//! [`Interpreter::set_memory_limit`]: crate::interpreter::Interpreter::set_memory_limit
+
+use crate::error::{runtime_error as error, Result};
+use crate::token::Location;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// The bytes allocated on this thread, less the ones freed on it.
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+    /// How high `ALLOCATED` may go, and the limit that was asked for.
+    static LIMIT: Cell<Option<(isize, usize)>> = const { Cell::new(None) };
+}
+
+/// The system allocator, counting what each thread allocates and frees.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn count(bytes: isize) {
+    // a thread that is exiting may have lost its counter already
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + bytes));
+}
+
+/// The bytes the running thread holds, as far as they were counted.
+pub fn allocated() -> isize {
+    ALLOCATED.with(Cell::get)
+}
+
+/// Lets the running thread hold `bytes` more than it does now, or any
+/// amount for `None`.
+pub(crate) fn set_limit(bytes: Option<usize>) {
+    let limit = bytes.map(|bytes| (allocated().saturating_add_unsigned(bytes), bytes));
+    LIMIT.with(|cell| cell.set(limit));
+}
+
+/// Fails if the running thread holds more than its limit, or would once it
+/// allocates `more` bytes.
+pub(crate) fn check(more: usize, loc: &Location) -> Result<()> {
+    if let Some((max, bytes)) = LIMIT.with(Cell::get) {
+        if allocated().saturating_add_unsigned(more) > max {
+            error!(loc, "Memory limit of {} bytes exceeded", bytes)
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,229 @@
+//! The methods that built-in types have, called as `value.name(args)`. A
+//! method is a built-in that gets the value it's called on as its first
+//! argument, looked up by the value's [`Value::type_name`] and its name.
+
+use crate::builtin::{self, iterator_argument};
+use crate::error::{runtime_error as error, Result};
+use crate::interpreter::{BuiltInFunctionType, Interpreter, Ref, Scope};
+use crate::token::Location;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub type MethodTable = HashMap<(&'static str, &'static str), BuiltInFunctionType>;
+
+macro_rules! methods {
+    ($($type:literal => [$($name:ident),+ $(,)?]),+ $(,)?) => {
+        HashMap::from([$($(
+            (($type, stringify!($name)), $name as BuiltInFunctionType),
+        )+)+])
+    };
+}
+
+pub fn table() -> MethodTable {
+    methods!(
+        "string" => [
+            len,
+            split,
+            trim,
+            replace,
+            contains,
+            starts_with,
+            ends_with,
+            to_upper,
+            to_lower,
+            find,
+            chars,
+            join,
+        ],
+    )
+}
+
+fn strings(items: impl IntoIterator<Item = impl Into<Value>>) -> Value {
+    let items = items.into_iter().map(Into::into).collect();
+    Value::List(Rc::new(RefCell::new(items)))
+}
+
+/// `string.len()`, the same as `len(string)`.
+fn len(
+    interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(loc, "len() takes no arguments");
+    }
+    builtin::len(interpreter, scope, loc, args)
+}
+
+/// `string.split(sep)`: the pieces of the string between each `sep`, or
+/// between runs of whitespace when there's no `sep`.
+fn split(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    Ok(match args.as_slice() {
+        [Value::String(string)] => strings(string.split_whitespace()),
+        [Value::String(_), Value::String(sep)] if sep.is_empty() => {
+            error!(loc, "split() can't split on an empty string")
+        }
+        [Value::String(string), Value::String(sep)] => strings(string.split(sep.as_ref())),
+        _ => error!(loc, "split() takes an optional string"),
+    })
+}
+
+/// `string.trim()`: the string without whitespace at either end.
+fn trim(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string)] => Ok(string.trim().into()),
+        _ => error!(loc, "trim() takes no arguments"),
+    }
+}
+
+/// `string.replace(from, to)`: the string with every `from` replaced by `to`.
+fn replace(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(_), Value::String(from), Value::String(_)] if from.is_empty() => {
+            error!(loc, "replace() can't replace an empty string")
+        }
+        [Value::String(string), Value::String(from), Value::String(to)] => {
+            Ok(string.replace(from.as_ref(), to).into())
+        }
+        _ => error!(loc, "replace() takes two strings"),
+    }
+}
+
+/// `string.contains(part)`, the same as `part in string`.
+fn contains(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string), Value::String(part)] => Ok(string.contains(part.as_ref()).into()),
+        _ => error!(loc, "contains() takes a string"),
+    }
+}
+
+fn starts_with(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string), Value::String(prefix)] => {
+            Ok(string.starts_with(prefix.as_ref()).into())
+        }
+        _ => error!(loc, "starts_with() takes a string"),
+    }
+}
+
+fn ends_with(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string), Value::String(suffix)] => {
+            Ok(string.ends_with(suffix.as_ref()).into())
+        }
+        _ => error!(loc, "ends_with() takes a string"),
+    }
+}
+
+fn to_upper(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string)] => Ok(string.to_uppercase().into()),
+        _ => error!(loc, "to_upper() takes no arguments"),
+    }
+}
+
+fn to_lower(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string)] => Ok(string.to_lowercase().into()),
+        _ => error!(loc, "to_lower() takes no arguments"),
+    }
+}
+
+/// `string.find(part)`: the index of the first character of the first
+/// `part` in the string, as indexing counts them, or `nothing`.
+fn find(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string), Value::String(part)] => Ok(match string.find(part.as_ref()) {
+            Some(byte) => Value::Integer(string[..byte].chars().count() as i64),
+            None => Value::Nothing,
+        }),
+        _ => error!(loc, "find() takes a string"),
+    }
+}
+
+/// `string.chars()`: a list of the string's characters, each a string.
+fn chars(
+    _interpreter: &mut Interpreter,
+    _scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    match args.as_slice() {
+        [Value::String(string)] => Ok(strings(string.chars().map(String::from))),
+        _ => error!(loc, "chars() takes no arguments"),
+    }
+}
+
+/// `sep.join(items)`: the strings of `items` with `sep` between each.
+fn join(
+    interpreter: &mut Interpreter,
+    scope: &Ref<Scope>,
+    loc: &Location,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let Ok([Value::String(sep), items]) = <[Value; 2]>::try_from(args) else {
+        error!(loc, "join() takes one iterable of strings")
+    };
+    let items = iterator_argument("join", items, loc)?;
+    let mut joined = String::new();
+    let mut first = true;
+    while let Some(item) = items.next(interpreter, scope, loc)? {
+        let Value::String(item) = item else {
+            error!(loc, "join() expects strings, got {}", item.repr())
+        };
+        if !first {
+            joined.push_str(&sep);
+        }
+        joined.push_str(&item);
+        first = false;
+    }
+    Ok(joined.into())
+}
@@ -0,0 +1,180 @@
+//! The `-O` pass, which runs what a program would compute the same way
+//! every time before running it: operators on literals become the literal
+//! they make, `if`s and `while`s with a literal condition lose the branch
+//! that can't run, as do `and`s and `or`s with a literal on the left, and
+//! indexes and slices of string literals become the string they pick.
+//!
+//! Nothing that would fail is folded, so errors still come from where they
+//! happen, when they happen.
+
+use crate::ast::AST;
+use crate::bytecode::{BinaryOp, UnaryOp};
+use crate::token::Location;
+use crate::value::{Overflow, RangeValue, Value};
+use std::sync::Arc;
+
+/// The longest string a repetition is folded into, so a program doesn't
+/// build a huge string it might never use before it starts.
+const MAX_FOLDED_LEN: usize = 1 << 12;
+
+struct Optimizer {
+    strict: bool,
+    overflow: Overflow,
+}
+
+/// Folds the constant parts of `ast`, computing them the way the
+/// interpreter would with `strict` and `overflow`.
+pub fn optimize(ast: &Arc<AST>, strict: bool, overflow: Overflow) -> Arc<AST> {
+    Optimizer { strict, overflow }.node(ast)
+}
+
+/// The value of a literal.
+fn constant(ast: &AST) -> Option<Value> {
+    Some(match ast {
+        AST::BooleanLiteral(_, value) => Value::Boolean(*value),
+        AST::IntegerLiteral(_, num) => Value::Integer(*num),
+        AST::FloatLiteral(_, num) => Value::Float(*num),
+        AST::StringLiteral(_, string) => Value::String(string.clone()),
+        AST::Nothing(_) => Value::Nothing,
+        _ => return None,
+    })
+}
+
+/// The literal for `value`, if it has one.
+fn literal(loc: &Location, value: Value) -> Option<Arc<AST>> {
+    let loc = loc.clone();
+    Some(Arc::new(match value {
+        Value::Boolean(value) => AST::BooleanLiteral(loc, value),
+        Value::Integer(num) => AST::IntegerLiteral(loc, num),
+        Value::Float(num) => AST::FloatLiteral(loc, num),
+        Value::String(string) => AST::StringLiteral(loc, string),
+        Value::Nothing => AST::Nothing(loc),
+        _ => return None,
+    }))
+}
+
+impl Optimizer {
+    fn node(&self, ast: &Arc<AST>) -> Arc<AST> {
+        if let AST::Assignment(loc, lhs, value) = ast.as_ref() {
+            let value = self.node(value);
+            return Arc::new(AST::Assignment(loc.clone(), self.target(lhs), value));
+        }
+        let node = Arc::new(ast.map_children(|child| self.node(child)));
+        self.fold(&node).unwrap_or(node)
+    }
+
+    /// Folds inside the left side of an assignment, which keeps its shape
+    /// so assigning to something that can't be assigned to still fails.
+    fn target(&self, lhs: &Arc<AST>) -> Arc<AST> {
+        match lhs.as_ref() {
+            AST::Tuple(..) => Arc::new(lhs.map_children(|target| self.target(target))),
+            _ => Arc::new(lhs.map_children(|child| self.node(child))),
+        }
+    }
+
+    /// Whether the literal `cond` of `construct` holds, unless it isn't a
+    /// literal or strict mode rejects it, which is left to run.
+    fn condition(&self, cond: &Arc<AST>, construct: &str) -> Option<bool> {
+        let value = constant(cond)?;
+        value.condition(self.strict, construct, cond.loc()).ok()
+    }
+
+    /// What `ast`, whose children are already folded, folds into.
+    fn fold(&self, ast: &Arc<AST>) -> Option<Arc<AST>> {
+        let loc = ast.loc();
+        if let Some((op, left, right)) = BinaryOp::of(ast) {
+            let (left, right) = (constant(left)?, constant(right)?);
+            if let (BinaryOp::Multiply, false) = (op, short_repetition(&left, &right)) {
+                return None;
+            }
+            let value = op
+                .apply(&left, &right, self.strict, self.overflow, loc)
+                .ok()?;
+            return literal(loc, value);
+        }
+        if let Some((op, value)) = UnaryOp::of(ast) {
+            let value = op
+                .apply(&constant(value)?, self.strict, self.overflow, loc)
+                .ok()?;
+            return literal(loc, value);
+        }
+        match ast.as_ref() {
+            AST::If(loc, cond, body, else_body) => match self.condition(cond, "if")? {
+                true => Some(body.clone()),
+                false => match else_body {
+                    Some(else_body) => Some(else_body.clone()),
+                    None => Some(Arc::new(AST::Nothing(loc.clone()))),
+                },
+            },
+            AST::And(_, left, right) | AST::Or(_, left, right) => {
+                // a literal on the left decides whether the right side runs
+                let or = matches!(ast.as_ref(), AST::Or(..));
+                let construct = if or { "or" } else { "and" };
+                match self.condition(left, construct)? == or {
+                    true => Some(left.clone()),
+                    false => Some(right.clone()),
+                }
+            }
+            AST::While(_, cond, _) => match self.condition(cond, "while")? {
+                false => Some(Arc::new(AST::Nothing(loc.clone()))),
+                true => None,
+            },
+            AST::Block(loc, stmts) => {
+                // a literal does nothing unless it's the block's value
+                let (last, rest) = stmts.split_last()?;
+                if rest.iter().all(|stmt| constant(stmt).is_none()) {
+                    return None;
+                }
+                let stmts = rest
+                    .iter()
+                    .filter(|stmt| constant(stmt).is_none())
+                    .chain([last])
+                    .cloned()
+                    .collect();
+                Some(Arc::new(AST::Block(loc.clone(), stmts)))
+            }
+            AST::Index(loc, left, right) => match constant(left)? {
+                string @ Value::String(_) => {
+                    literal(loc, string.index(&constant(right)?, loc).ok()?)
+                }
+                _ => None,
+            },
+            AST::Slice {
+                loc,
+                lhs,
+                start,
+                end,
+                step,
+            } => {
+                let string @ Value::String(_) = constant(lhs)? else {
+                    return None;
+                };
+                let bound = |bound: &Option<Arc<AST>>| match bound.as_deref() {
+                    None => Some(None),
+                    Some(AST::IntegerLiteral(_, i)) => Some(Some(*i)),
+                    Some(_) => None,
+                };
+                let range = RangeValue {
+                    start: bound(start)?,
+                    end: bound(end)?,
+                    step: bound(step)?.unwrap_or(1),
+                    inclusive: false,
+                };
+                literal(loc, string.slice(range, loc).ok()?)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether multiplying `left` by `right` makes at most a short string, if
+/// it makes a string at all.
+fn short_repetition(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::String(string), Value::Integer(times))
+        | (Value::Integer(times), Value::String(string)) => {
+            (string.len() as i128) * (*times as i128) <= MAX_FOLDED_LEN as i128
+        }
+        _ => true,
+    }
+}
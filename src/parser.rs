@@ -1,33 +1,140 @@
-use crate::ast::AST;
-use crate::error::{eof_error, parser_error as error, Result};
-use crate::token::{Token, TokenKind};
-use std::rc::Rc;
+use crate::ast::{MatchArm, Signature, TypeExpr, Variant, AST};
+use crate::edition::Edition;
+use crate::error::{eof_error, parser_error as error, Diagnostics, Error, Result};
+use crate::lexer::{unescape, Lexer};
+use crate::resolver;
+use crate::symbol::Symbol;
+use crate::token::{Location, StringPart, Token, TokenKind};
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+
+/// How many tokens the parser looks at at once: the current one, and the
+/// one after it to tell named arguments apart.
+const LOOKAHEAD: usize = 2;
+
+/// The names of a function's parameters, whether the last is a `...rest`
+/// one, and their default values and signature.
+type Parameters = (Vec<Symbol>, bool, Vec<Arc<AST>>, Option<Arc<Signature>>);
 
 pub struct Parser {
-    tokens: Vec<Token>,
-    current_index: usize,
+    /// The current token and the ones after it that have been lexed.
+    tokens: VecDeque<Token>,
+    /// Where the rest of the tokens come from, if they weren't all given up
+    /// front.
+    lexer: Option<Lexer>,
+    /// An error the lexer stopped at, which the parser sees as the end of
+    /// the input.
+    lex_error: Option<Error>,
+    /// Every distinct string literal seen so far. Repeats of a literal share
+    /// its allocation, in the tree and in every value made from it.
+    strings: HashSet<Arc<str>>,
+    edition: Edition,
+    /// Whether `break` and `continue` have a loop to act on.
+    in_loop: bool,
+    /// Whether a `yield` has a function to make a generator of, and whether
+    /// the function being parsed has one.
+    in_function: bool,
+    yields: bool,
+    /// Whether a `yield` would be inside a `try` or `match`, which a
+    /// generator can't pause partway through.
+    in_try_or_match: bool,
+    /// Where to record statements that fail to parse and carry on from the
+    /// next one, taken from the lexer.
+    diagnostics: Option<Diagnostics>,
+    /// The globals the program will find already declared, besides the
+    /// built-in ones.
+    globals: Vec<Symbol>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser {
+        Parser::with_tokens(tokens.into(), None)
+    }
+
+    /// A parser that lexes its input as it goes, instead of needing every
+    /// token up front.
+    pub fn from_lexer(lexer: Lexer) -> Parser {
+        Parser::with_tokens(VecDeque::with_capacity(LOOKAHEAD), Some(lexer))
+    }
+
+    /// Tells the resolver that the program runs where `globals` are already
+    /// declared, such as in a REPL or next to the host's globals.
+    pub fn with_globals(mut self, globals: impl IntoIterator<Item = Symbol>) -> Parser {
+        self.globals = globals.into_iter().collect();
+        self
+    }
+
+    fn with_tokens(tokens: VecDeque<Token>, lexer: Option<Lexer>) -> Parser {
+        let diagnostics = lexer.as_ref().and_then(Lexer::diagnostics).cloned();
+        let mut parser = Parser {
             tokens,
-            current_index: 0,
+            lexer,
+            lex_error: None,
+            strings: HashSet::new(),
+            edition: Edition::default_edition(),
+            in_loop: false,
+            in_function: false,
+            yields: false,
+            in_try_or_match: false,
+            diagnostics,
+            globals: vec![],
+        };
+        // lexing the first token applies the file's edition pragma
+        parser.fill();
+        if let Some(token) = parser.tokens.front() {
+            parser.edition = token.loc.edition;
+        }
+        parser
+    }
+
+    /// Lexes tokens until there are enough to look ahead at.
+    fn fill(&mut self) {
+        while self.tokens.len() < LOOKAHEAD {
+            let Some(lexer) = &mut self.lexer else {
+                return;
+            };
+            match lexer.next_token() {
+                Ok(Some(token)) => self.tokens.push_back(token),
+                Ok(None) => self.lexer = None,
+                Err(err) => {
+                    let loc = err.loc().expect("lexer errors have a location").clone();
+                    self.tokens
+                        .push_back(Token::new(TokenKind::EOF, loc, "".into(), 0..0));
+                    self.lex_error = Some(err);
+                    self.lexer = None;
+                }
+            }
+        }
+    }
+
+    fn intern(&mut self, text: &str) -> Arc<str> {
+        if let Some(string) = self.strings.get(text) {
+            return string.clone();
         }
+        let string: Arc<str> = text.into();
+        self.strings.insert(string.clone());
+        string
     }
 
     fn cur(&self) -> Token {
         self.tokens
-            .get(self.current_index)
+            .front()
             .cloned()
             .expect("should never move past end of file")
     }
 
+    /// The kind of the token after the current one.
+    fn next_kind(&self) -> Option<&TokenKind> {
+        self.tokens.get(1).map(|token| &token.kind)
+    }
+
     fn increment(&mut self) {
         match self.cur().kind {
             TokenKind::EOF => {}
             _ => {
-                self.current_index += 1;
+                self.tokens.pop_front();
+                self.fill();
             }
         }
     }
@@ -49,13 +156,82 @@ impl Parser {
         }
     }
 
-    pub fn parse(&mut self) -> Result<Rc<AST>> {
-        let res = self.parse_block(/*global*/ true);
-        self.consume(TokenKind::EOF)?;
-        res
+    /// Parses the whole input. With a lexer that has diagnostics, every
+    /// error is recorded there and the first one is returned.
+    pub fn parse(&mut self) -> Result<Arc<AST>> {
+        let res = self.parse_block(/*global*/ true).and_then(|res| {
+            self.consume(TokenKind::EOF)?;
+            Ok(res)
+        });
+        // input that the lexer cut short is what any parse error is about
+        let res = match self.lex_error.take() {
+            Some(err) => Err(err),
+            None => res,
+        };
+        let res = res.and_then(|ast| resolver::resolve(&ast, &self.globals));
+        let Some(diagnostics) = &self.diagnostics else {
+            return res;
+        };
+        let ast = res.map_err(|err| diagnostics.push(err));
+        match diagnostics.first() {
+            Some(err) => Err(err),
+            None => Ok(ast.expect("a failed parse records its error")),
+        }
     }
 
-    fn parse_block(&mut self, global: bool) -> Result<Rc<AST>> {
+    /// Desugars an interpolated string into concatenation, with `str` called
+    /// on each expression: `"a {b} c"` becomes `"a " + str(b) + " c"`.
+    fn parse_interpolation(&mut self, token: &Token) -> Result<Arc<AST>> {
+        let mut result: Option<Arc<AST>> = None;
+        for part in token.parts.iter().flat_map(|parts| parts.iter()) {
+            let node = match part {
+                StringPart::Literal(span) => {
+                    // the lexer already rejected bad escapes
+                    let text = unescape(&token.source[span.clone()]).unwrap_or_default();
+                    let text = self.intern(&text);
+                    Arc::new(AST::StringLiteral(token.loc.clone(), text))
+                }
+                StringPart::Expression(tokens) => {
+                    let expr = self.parse_embedded(tokens)?;
+                    let loc = expr.loc().clone();
+                    let str = Arc::new(AST::Variable(loc.clone(), Symbol::intern("str")));
+                    Arc::new(AST::Call(loc, str, vec![expr]))
+                }
+            };
+            result = Some(match result {
+                Some(left) => Arc::new(AST::Plus(token.loc.clone(), left, node)),
+                None => node,
+            });
+        }
+        Ok(result
+            .unwrap_or_else(|| Arc::new(AST::StringLiteral(token.loc.clone(), self.intern("")))))
+    }
+
+    /// Parses the tokens of a `{expression}` in a string, as if they were
+    /// all that's left of the input.
+    fn parse_embedded(&mut self, tokens: &[Token]) -> Result<Arc<AST>> {
+        let rest = std::mem::replace(&mut self.tokens, tokens.iter().cloned().collect());
+        let lexer = self.lexer.take();
+        let expr = self
+            .parse_expression()
+            .and_then(|expr| match self.cur().kind {
+                TokenKind::EOF => Ok(expr),
+                _ => error!(
+                    self.cur().loc,
+                    "Expected `}}` after the expression in the string"
+                ),
+            });
+        self.tokens = rest;
+        self.lexer = lexer;
+        // the string is complete, so running out of tokens in it isn't
+        // something more input would fix
+        expr.map_err(|err| match err {
+            Error::UnexpectedEOF(loc, msg) => Error::Parser(loc, msg),
+            err => err,
+        })
+    }
+
+    fn parse_block(&mut self, global: bool) -> Result<Arc<AST>> {
         let loc = self.cur().loc;
         let mut statements = vec![];
         if !global {
@@ -69,9 +245,58 @@ impl Parser {
             if global && self.cur().kind == TokenKind::EOF {
                 break;
             }
-            statements.push(self.parse_statement()?);
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(err) => self.recover(err, global)?,
+            }
+        }
+        Ok(Arc::new(AST::Block(loc, statements)))
+    }
+
+    /// Records a statement's error and skips to the next statement, when
+    /// there are diagnostics to record it in. A block that the input ended
+    /// inside of passes the error on, to be recorded once at the top.
+    fn recover(&mut self, err: Error, global: bool) -> Result<()> {
+        let Some(diagnostics) = self.diagnostics.clone() else {
+            return Err(err);
+        };
+        if self.cur().kind == TokenKind::EOF {
+            if !global {
+                return Err(err);
+            }
+            // the lexer's own error explains the input ending early
+            if self.lex_error.is_none() {
+                diagnostics.push(err);
+            }
+            return Ok(());
+        }
+        diagnostics.push(err);
+        self.synchronize(global);
+        Ok(())
+    }
+
+    /// Skips past a `;` or up to a token on a new line, not counting those
+    /// inside braces opened along the way. A `}` that closes the enclosing
+    /// block is left for it.
+    fn synchronize(&mut self, global: bool) {
+        let mut depth = 0usize;
+        loop {
+            match self.cur().kind {
+                TokenKind::EOF => return,
+                TokenKind::RightBrace if depth == 0 && !global => return,
+                TokenKind::RightBrace => depth = depth.saturating_sub(1),
+                TokenKind::LeftBrace => depth += 1,
+                TokenKind::SemiColon if depth == 0 => {
+                    self.increment();
+                    return;
+                }
+                _ => {}
+            }
+            self.increment();
+            if depth == 0 && self.cur().newline_before {
+                return;
+            }
         }
-        Ok(Rc::new(AST::Block(loc, statements)))
     }
 
     fn consume_line_end(&mut self) -> Result<()> {
@@ -90,61 +315,477 @@ impl Parser {
         Ok(())
     }
 
-    fn parse_lambda(&mut self) -> Result<Rc<AST>> {
-        let loc = self.consume(TokenKind::Pipe)?.loc;
-        let mut args = vec![];
-        while self.cur().kind != TokenKind::Pipe {
-            args.push(self.consume(TokenKind::Identifier)?.text);
-            if self.cur().kind == TokenKind::Comma {
+    /// Skips the comma after a parameter. Before edition 2026 it may be left
+    /// out.
+    fn parameter_separator(&mut self, end: TokenKind) -> Result<()> {
+        match self.cur().kind {
+            TokenKind::Comma => self.increment(),
+            kind if kind == end || self.edition < Edition::E2026 => {}
+            kind => error!(
+                self.cur().loc,
+                "Expected `,` or {:?} after parameter, but got {:?}", end, kind
+            ),
+        }
+        Ok(())
+    }
+
+    /// A block that runs in a function, where the loops around the function
+    /// can't be broken out of. A body with a `yield` in it is wrapped in a
+    /// [`AST::Generator`].
+    fn parse_function_body(&mut self) -> Result<Arc<AST>> {
+        let loc = self.cur().loc.clone();
+        let in_loop = std::mem::replace(&mut self.in_loop, false);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        let yields = std::mem::replace(&mut self.yields, false);
+        let in_try_or_match = std::mem::replace(&mut self.in_try_or_match, false);
+        let body = self.parse_block(/*global*/ false);
+        let generator = std::mem::replace(&mut self.yields, yields);
+        self.in_try_or_match = in_try_or_match;
+        self.in_function = in_function;
+        self.in_loop = in_loop;
+        match body {
+            Ok(body) if generator => Ok(Arc::new(AST::Generator(loc, body))),
+            body => body,
+        }
+    }
+
+    /// `struct Name { a, b }`, where the last field's comma is optional.
+    /// The fields may be followed by methods, `def`s whose first parameter
+    /// is the instance they are called on.
+    fn parse_struct(&mut self) -> Result<Arc<AST>> {
+        let loc = self.consume(TokenKind::Struct)?.loc;
+        let name = self.consume(TokenKind::Identifier)?.symbol();
+        self.consume(TokenKind::LeftBrace)?;
+        let mut fields = vec![];
+        while !matches!(self.cur().kind, TokenKind::RightBrace | TokenKind::Def) {
+            let field = self.consume(TokenKind::Identifier)?;
+            if fields.contains(&field.symbol()) {
+                error!(field.loc, "Field {} is declared twice", field.symbol())
+            }
+            fields.push(field.symbol());
+            match self.cur().kind {
+                TokenKind::RightBrace => {}
+                TokenKind::Def if self.cur().newline_before => {}
+                _ => {
+                    self.consume(TokenKind::Comma)?;
+                }
+            }
+        }
+        let methods = self.parse_methods(name, &fields, "field")?;
+        Ok(Arc::new(AST::Struct {
+            loc,
+            name,
+            fields,
+            methods,
+        }))
+    }
+
+    /// `enum Name { A(x, y), B }`, where a variant has fields in parentheses
+    /// or none, and the last variant's comma is optional. Methods may follow
+    /// as they do in a struct.
+    fn parse_enum(&mut self) -> Result<Arc<AST>> {
+        let loc = self.consume(TokenKind::Enum)?.loc;
+        let name = self.consume(TokenKind::Identifier)?.symbol();
+        self.consume(TokenKind::LeftBrace)?;
+        let mut variants: Vec<Variant> = vec![];
+        while !matches!(self.cur().kind, TokenKind::RightBrace | TokenKind::Def) {
+            let variant = self.consume(TokenKind::Identifier)?;
+            if variants.iter().any(|other| other.name == variant.symbol()) {
+                error!(
+                    variant.loc,
+                    "Variant {} is declared twice",
+                    variant.symbol()
+                )
+            }
+            let mut fields = vec![];
+            if self.cur().kind == TokenKind::LeftParen {
                 self.increment();
+                while self.cur().kind != TokenKind::RightParen {
+                    let field = self.consume(TokenKind::Identifier)?;
+                    if fields.contains(&field.symbol()) {
+                        error!(field.loc, "Field {} is declared twice", field.symbol())
+                    }
+                    fields.push(field.symbol());
+                    if self.cur().kind != TokenKind::RightParen {
+                        self.consume(TokenKind::Comma)?;
+                    }
+                }
+                self.increment();
+            }
+            variants.push(Variant {
+                loc: variant.loc.clone(),
+                name: variant.symbol(),
+                fields,
+            });
+            match self.cur().kind {
+                TokenKind::RightBrace => {}
+                TokenKind::Def if self.cur().newline_before => {}
+                _ => {
+                    self.consume(TokenKind::Comma)?;
+                }
+            }
+        }
+        let names = variants
+            .iter()
+            .map(|variant| variant.name)
+            .collect::<Vec<_>>();
+        let methods = self.parse_methods(name, &names, "variant")?;
+        Ok(Arc::new(AST::Enum {
+            loc,
+            name,
+            variants,
+            methods,
+        }))
+    }
+
+    /// The methods of the struct or enum `of` up to its closing brace, none
+    /// of which may be named like one of its `members`.
+    fn parse_methods(
+        &mut self,
+        of: Symbol,
+        members: &[Symbol],
+        member: &str,
+    ) -> Result<Vec<Arc<AST>>> {
+        let mut methods: Vec<Arc<AST>> = vec![];
+        while self.cur().kind != TokenKind::RightBrace {
+            let def_loc = self.cur().loc.clone();
+            let (method, method_name) = self.parse_function()?;
+            let AST::Function { args, .. } = method.as_ref() else {
+                unreachable!("parse_function parses a function")
+            };
+            if args.is_empty() {
+                error!(
+                    def_loc,
+                    "Method {} needs a first parameter for the instance it's called on",
+                    method_name
+                )
             }
+            if members.contains(&method_name) || methods.iter().any(|other| {
+                matches!(other.as_ref(), AST::Function { name, .. } if *name == Some(method_name))
+            }) {
+                error!(
+                    def_loc,
+                    "{} is already a {} or method of {}", method_name, member, of
+                )
+            }
+            methods.push(method);
         }
         self.increment();
+        self.consume_line_end()?;
+        Ok(methods)
+    }
+
+    /// Parses a `try` or `match`, where a `yield` isn't allowed.
+    fn parse_without_yield(
+        &mut self,
+        parse_fn: fn(&mut Parser) -> Result<Arc<AST>>,
+    ) -> Result<Arc<AST>> {
+        let in_try_or_match = std::mem::replace(&mut self.in_try_or_match, true);
+        let ast = parse_fn(self);
+        self.in_try_or_match = in_try_or_match;
+        ast
+    }
+
+    fn parse_loop_body(&mut self) -> Result<Arc<AST>> {
+        let in_loop = std::mem::replace(&mut self.in_loop, true);
+        let body = self.parse_block(/*global*/ false);
+        self.in_loop = in_loop;
+        body
+    }
+
+    /// `|parameters| => expression` or `|parameters| { body }`, after a
+    /// `move` if there is one.
+    fn parse_lambda(&mut self) -> Result<Arc<AST>> {
+        let moves = self.cur().kind == TokenKind::Move;
+        let loc = self.cur().loc;
+        if moves {
+            self.increment();
+        }
+        self.consume(TokenKind::Pipe)?;
+        let (args, variadic, defaults, signature) = self.parse_parameters(TokenKind::Pipe)?;
         let body = if self.cur().kind == TokenKind::FatArrow {
             self.increment();
-            Rc::new(AST::Return(loc.clone(), self.parse_expression()?))
+            Arc::new(AST::Return(loc.clone(), self.parse_expression()?))
         } else {
-            self.parse_block(/*global*/ false)?
+            self.parse_function_body()?
         };
-        Ok(Rc::new(AST::Function {
+        Ok(Arc::new(AST::Function {
             loc,
             name: None,
             args,
+            variadic,
+            defaults,
+            signature,
+            captures: vec![],
+            moves,
             body,
         }))
     }
 
-    fn parse_function(&mut self) -> Result<(Rc<AST>, String)> {
-        let loc = self.consume(TokenKind::Def)?.loc;
-        let name = self.consume(TokenKind::Identifier)?;
-        self.consume(TokenKind::LeftParen)?;
+    /// The parameters of a `def` or lambda with their annotations, up to
+    /// and including `end`, then the `-> Type` it returns if there is one.
+    /// A lambda's parameters end at a `|`, so their types can only be
+    /// unions in parentheses, and they can't have default values, which
+    /// a `def`'s last parameters can: `greeting = "hello"`. The very last
+    /// parameter may be `...rest` instead, which takes no default.
+    fn parse_parameters(&mut self, end: TokenKind) -> Result<Parameters> {
         let mut args = vec![];
-        while self.cur().kind != TokenKind::RightParen {
-            args.push(self.consume(TokenKind::Identifier)?.text);
-            if self.cur().kind == TokenKind::Comma {
+        let mut variadic = false;
+        let mut defaults = vec![];
+        let mut signature = Signature::default();
+        while self.cur().kind != end {
+            if variadic {
+                error!(
+                    self.cur().loc,
+                    "The `...{}` parameter has to be the last one",
+                    args.last().unwrap()
+                )
+            }
+            variadic = self.cur().kind == TokenKind::DotDotDot;
+            if variadic {
+                self.increment();
+            }
+            let arg = self.consume(TokenKind::Identifier)?;
+            args.push(arg.symbol());
+            let annotation = match self.cur().kind {
+                TokenKind::Colon if end == TokenKind::Pipe => {
+                    self.increment();
+                    Some(self.parse_named_type()?)
+                }
+                _ => self.parse_annotation()?,
+            };
+            signature.args.push(annotation);
+            if self.cur().kind == TokenKind::Equals && variadic {
+                error!(
+                    self.cur().loc,
+                    "The `...{}` parameter can't have a default value",
+                    arg.symbol()
+                )
+            } else if self.cur().kind == TokenKind::Equals && end == TokenKind::RightParen {
                 self.increment();
+                defaults.push(self.parse_expression()?);
+            } else if !defaults.is_empty() && !variadic {
+                error!(
+                    arg.loc,
+                    "Parameter {} needs a default value, since the ones before it have one",
+                    arg.symbol()
+                )
             }
+            self.parameter_separator(end.clone())?;
         }
         self.increment();
+        if self.cur().kind == TokenKind::Arrow {
+            self.increment();
+            signature.returns = Some(self.parse_type()?);
+        }
+        let annotated = signature.returns.is_some() || signature.args.iter().any(Option::is_some);
+        Ok((
+            args,
+            variadic,
+            defaults,
+            annotated.then(|| Arc::new(signature)),
+        ))
+    }
+
+    fn parse_function(&mut self) -> Result<(Arc<AST>, Symbol)> {
+        let loc = self.consume(TokenKind::Def)?.loc;
+        let name = self.consume(TokenKind::Identifier)?;
+        self.consume(TokenKind::LeftParen)?;
+        let (args, variadic, defaults, signature) = self.parse_parameters(TokenKind::RightParen)?;
         let body = if self.cur().kind == TokenKind::FatArrow {
             self.increment();
-            Rc::new(AST::Return(loc.clone(), self.parse_expression()?))
+            Arc::new(AST::Return(loc.clone(), self.parse_expression()?))
         } else {
-            self.parse_block(/*global*/ false)?
+            self.parse_function_body()?
         };
         self.consume_line_end()?;
         Ok((
-            Rc::new(AST::Function {
+            Arc::new(AST::Function {
                 loc,
-                name: Some(name.text.clone()),
+                name: Some(name.symbol()),
                 args,
+                variadic,
+                defaults,
+                signature,
+                captures: vec![],
+                moves: false,
                 body,
             }),
-            name.text,
+            name.symbol(),
         ))
     }
 
-    fn parse_statement(&mut self) -> Result<Rc<AST>> {
+    /// `try { block } catch name { block } finally { block }`, where either
+    /// `catch` or `finally` may be left out, but not both.
+    fn parse_try(&mut self) -> Result<Arc<AST>> {
+        let loc = self.consume(TokenKind::Try)?.loc;
+        let body = self.parse_block(/*global*/ false)?;
+        let catch = match self.cur().kind {
+            TokenKind::Catch => {
+                self.increment();
+                let name = self.consume(TokenKind::Identifier)?;
+                let handler = self.parse_block(/*global*/ false)?;
+                Some((name.symbol(), handler))
+            }
+            _ => None,
+        };
+        let finally = match self.cur().kind {
+            TokenKind::Finally => {
+                self.increment();
+                Some(self.parse_block(/*global*/ false)?)
+            }
+            _ => None,
+        };
+        if catch.is_none() && finally.is_none() {
+            error!(
+                self.cur().loc,
+                "Expected `catch` or `finally` after the `try` block, but got {:?}",
+                self.cur().kind
+            )
+        }
+        Ok(Arc::new(AST::Try {
+            loc,
+            body,
+            catch,
+            finally,
+        }))
+    }
+
+    /// `match subject { pattern if guard => expression, pattern { block } }`,
+    /// where guards are optional and the last arm's comma too.
+    fn parse_match(&mut self) -> Result<Arc<AST>> {
+        let loc = self.consume(TokenKind::Match)?.loc;
+        let subject = self.parse_expression()?;
+        self.consume(TokenKind::LeftBrace)?;
+        let mut arms = vec![];
+        while self.cur().kind != TokenKind::RightBrace {
+            let pattern = self.parse_pattern()?;
+            let guard = match self.cur().kind {
+                TokenKind::If => {
+                    self.increment();
+                    Some(self.parse_expression()?)
+                }
+                _ => None,
+            };
+            let body = if self.cur().kind == TokenKind::LeftBrace {
+                let body = self.parse_block(/*global*/ false)?;
+                if self.cur().kind == TokenKind::Comma {
+                    self.increment();
+                }
+                body
+            } else {
+                self.consume(TokenKind::FatArrow)?;
+                let body = self.parse_expression()?;
+                match self.cur().kind {
+                    TokenKind::Comma => self.increment(),
+                    TokenKind::RightBrace => {}
+                    TokenKind::EOF => {
+                        eof_error!(self.cur().loc, "Expected `}}` or ',' but got EOF")
+                    }
+                    _ => error!(
+                        self.cur().loc,
+                        "Expected `}}` or `,` after the arm, but got {:?}",
+                        self.cur().kind
+                    ),
+                }
+                body
+            };
+            let arm = MatchArm {
+                pattern,
+                guard,
+                body,
+            };
+            let mut seen = HashSet::new();
+            if let Some(name) = arm.bindings().into_iter().find(|name| !seen.insert(*name)) {
+                error!(
+                    arm.pattern.loc(),
+                    "`{}` is bound more than once in the pattern", name
+                )
+            }
+            arms.push(arm);
+        }
+        self.increment();
+        Ok(Arc::new(AST::Match(loc, subject, arms)))
+    }
+
+    fn parse_pattern(&mut self) -> Result<Arc<AST>> {
+        let loc = self.cur().loc;
+        match self.cur().kind {
+            TokenKind::LeftBracket => {
+                self.increment();
+                let items = self.parse_pattern_items(TokenKind::RightBracket, "]")?;
+                Ok(Arc::new(AST::List(loc, items)))
+            }
+            // `Name(patterns)` and `Enum.Variant(patterns)` match instances
+            TokenKind::Identifier => {
+                let mut pattern = self.parse_atom()?;
+                while self.cur().kind == TokenKind::Dot {
+                    let loc = self.cur().loc;
+                    self.increment();
+                    let name = self.consume(TokenKind::Identifier)?;
+                    pattern = Arc::new(AST::Member(loc, pattern, name.symbol()));
+                }
+                if self.cur().kind == TokenKind::LeftParen {
+                    self.increment();
+                    let items = self.parse_pattern_items(TokenKind::RightParen, ")")?;
+                    pattern = Arc::new(AST::Call(loc, pattern, items));
+                }
+                Ok(pattern)
+            }
+            TokenKind::LeftParen => {
+                self.increment();
+                self.parse_parenthesized(loc, Parser::parse_pattern)
+            }
+            // negative numbers are literals here, not negations
+            TokenKind::Minus => {
+                self.increment();
+                let number = self.parse_atom()?;
+                match number.as_ref() {
+                    AST::IntegerLiteral(_, num) => {
+                        Ok(Arc::new(AST::IntegerLiteral(loc, num.wrapping_neg())))
+                    }
+                    AST::FloatLiteral(_, num) => Ok(Arc::new(AST::FloatLiteral(loc, -num))),
+                    _ => error!(number.loc(), "Expected a number after `-` in a pattern"),
+                }
+            }
+            TokenKind::IntegerLiteralBin
+            | TokenKind::IntegerLiteralOct
+            | TokenKind::IntegerLiteralDec
+            | TokenKind::IntegerLiteralHex
+            | TokenKind::FloatLiteral
+            | TokenKind::StringLiteral
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::Nothing => self.parse_atom(),
+            TokenKind::EOF => eof_error!(loc, "Expected a pattern"),
+            kind => error!(loc, "Expected a pattern, but got {:?}", kind),
+        }
+    }
+
+    /// The patterns up to `end`, separated by commas.
+    fn parse_pattern_items(&mut self, end: TokenKind, close: &str) -> Result<Vec<Arc<AST>>> {
+        let mut items = vec![];
+        while self.cur().kind != end {
+            items.push(self.parse_pattern()?);
+            match self.cur().kind {
+                TokenKind::Comma => self.increment(),
+                kind if kind == end => {}
+                TokenKind::EOF => {
+                    eof_error!(self.cur().loc, "Expected `{}` or ',' but got EOF", close)
+                }
+                _ => error!(
+                    self.cur().loc,
+                    "Expected `{}` or `,` but got {:?}",
+                    close,
+                    self.cur().kind
+                ),
+            }
+        }
+        self.increment();
+        Ok(items)
+    }
+
+    fn parse_statement(&mut self) -> Result<Arc<AST>> {
         match self.cur() {
             Token {
                 kind: TokenKind::Let,
@@ -152,11 +793,23 @@ impl Parser {
                 ..
             } => {
                 self.increment();
-                let ident = self.consume(TokenKind::Identifier)?;
+                let target = self.parse_let_target()?;
+                let ty = match target.as_ref() {
+                    AST::Variable(..) => self.parse_annotation()?.map(Arc::new),
+                    _ => None,
+                };
+                let target = match ty {
+                    Some(_) => target,
+                    None => self.parse_tuple_rest(target, Parser::parse_let_target)?,
+                };
                 self.consume(TokenKind::Equals)?;
                 let expr = self.parse_expression()?;
+                let expr = self.parse_tuple_rest(expr, Parser::parse_comparison)?;
                 self.consume_line_end()?;
-                Ok(Rc::new(AST::VarDeclaration(loc, ident.text, expr)))
+                Ok(Arc::new(match target.as_ref() {
+                    AST::Variable(_, name) => AST::VarDeclaration(loc, *name, ty, expr),
+                    _ => AST::Unpack(loc, target, expr),
+                }))
             }
             Token {
                 kind: TokenKind::If,
@@ -169,7 +822,6 @@ impl Parser {
                 match self.cur() {
                     Token {
                         kind: TokenKind::Else,
-                        loc,
                         ..
                     } => {
                         self.increment();
@@ -177,9 +829,9 @@ impl Parser {
                             TokenKind::If => self.parse_statement()?,
                             _ => self.parse_block(/*global*/ false)?,
                         };
-                        Ok(Rc::new(AST::If(loc, cond, body, Some(else_body))))
+                        Ok(Arc::new(AST::If(loc, cond, body, Some(else_body))))
                     }
-                    _ => Ok(Rc::new(AST::If(loc, cond, body, None))),
+                    _ => Ok(Arc::new(AST::If(loc, cond, body, None))),
                 }
             }
             Token {
@@ -187,19 +839,65 @@ impl Parser {
                 ..
             } => Ok(self.parse_function()?.0),
             Token {
-                kind: TokenKind::At,
+                kind: TokenKind::Struct,
+                ..
+            } => self.parse_struct(),
+            Token {
+                kind: TokenKind::Enum,
+                ..
+            } => self.parse_enum(),
+            Token {
+                kind: TokenKind::Pub,
                 loc,
                 ..
             } => {
                 self.increment();
-                let deco = self.parse_postfix()?;
-                self.consume_line_end()?;
+                match self.cur().kind {
+                    TokenKind::Let
+                    | TokenKind::Def
+                    | TokenKind::At
+                    | TokenKind::Import
+                    | TokenKind::Struct
+                    | TokenKind::Enum => {}
+                    _ => error!(
+                        self.cur().loc,
+                        "Expected a declaration after `pub`, but got {:?}",
+                        self.cur().kind
+                    ),
+                }
+                let decl = self.parse_statement()?;
+                Ok(Arc::new(AST::Pub(loc, decl)))
+            }
+            Token {
+                kind: TokenKind::At,
+                loc,
+                ..
+            } => {
+                // decorators apply bottom-up, i.e. `@a @b def f` means `f = a(b(f))`
+                let mut decorators = vec![];
+                while self.cur().kind == TokenKind::At {
+                    let deco_loc = self.consume(TokenKind::At)?.loc;
+                    decorators.push((deco_loc, self.parse_postfix()?));
+                    self.consume_line_end()?;
+                }
+                if self.cur().kind != TokenKind::Def {
+                    error!(
+                        self.cur().loc,
+                        "Expected a function definition after decorator, but got {:?}",
+                        self.cur().kind
+                    );
+                }
                 let (func, name) = self.parse_function()?;
-                self.consume_line_end()?;
-                Ok(Rc::new(AST::Assignment(
+                let value = decorators
+                    .into_iter()
+                    .rev()
+                    .fold(func, |func, (deco_loc, deco)| {
+                        Arc::new(AST::Call(deco_loc, deco, vec![func]))
+                    });
+                Ok(Arc::new(AST::Assignment(
                     loc.clone(),
-                    Rc::new(AST::Variable(loc.clone(), name)),
-                    Rc::new(AST::Call(loc, deco, vec![func])),
+                    Arc::new(AST::Variable(loc, name)),
+                    value,
                 )))
             }
             Token {
@@ -207,18 +905,24 @@ impl Parser {
                 loc,
                 ..
             } => {
+                if !self.in_loop {
+                    error!(loc, "`continue` outside of a loop")
+                }
                 self.increment();
                 self.consume_line_end()?;
-                Ok(Rc::new(AST::Continue(loc)))
+                Ok(Arc::new(AST::Continue(loc)))
             }
             Token {
                 kind: TokenKind::Break,
                 loc,
                 ..
             } => {
+                if !self.in_loop {
+                    error!(loc, "`break` outside of a loop")
+                }
                 self.increment();
                 self.consume_line_end()?;
-                Ok(Rc::new(AST::Break(loc)))
+                Ok(Arc::new(AST::Break(loc)))
             }
             Token {
                 kind: TokenKind::While,
@@ -227,8 +931,8 @@ impl Parser {
             } => {
                 self.increment();
                 let cond = self.parse_expression()?;
-                let body = self.parse_block(/*global*/ false)?;
-                Ok(Rc::new(AST::While(loc, cond, body)))
+                let body = self.parse_loop_body()?;
+                Ok(Arc::new(AST::While(loc, cond, body)))
             }
             Token {
                 kind: TokenKind::For,
@@ -239,19 +943,62 @@ impl Parser {
                 let ident = self.consume(TokenKind::Identifier)?;
                 self.consume(TokenKind::In)?;
                 let expr = self.parse_expression()?;
-                let body = self.parse_block(/*global*/ false)?;
-                Ok(Rc::new(AST::For(loc, ident.text, expr, body)))
+                let body = self.parse_loop_body()?;
+                Ok(Arc::new(AST::For(loc, ident.symbol(), expr, body)))
+            }
+            Token {
+                kind: TokenKind::Import,
+                loc,
+                ..
+            } => {
+                self.increment();
+                let (name, path) = self.parse_import_path()?;
+                self.consume_line_end()?;
+                Ok(Arc::new(AST::Import(loc, name, path)))
             }
             Token {
                 kind: TokenKind::Return,
                 loc,
                 ..
+            } => {
+                self.increment();
+                let expr = self.parse_expression()?;
+                let expr = self.parse_tuple_rest(expr, Parser::parse_comparison)?;
+                self.consume_line_end()?;
+                Ok(Arc::new(AST::Return(loc, expr)))
+            }
+            Token {
+                kind: TokenKind::Throw,
+                loc,
+                ..
             } => {
                 self.increment();
                 let expr = self.parse_expression()?;
                 self.consume_line_end()?;
-                Ok(Rc::new(AST::Return(loc, expr)))
+                Ok(Arc::new(AST::Throw(loc, expr)))
             }
+            Token {
+                kind: TokenKind::Yield,
+                loc,
+                ..
+            } => {
+                if !self.in_function {
+                    error!(loc, "`yield` outside of a function")
+                }
+                if self.in_try_or_match {
+                    error!(loc, "Can't `yield` from inside a `try` or `match`")
+                }
+                self.increment();
+                let expr = self.parse_expression()?;
+                let expr = self.parse_tuple_rest(expr, Parser::parse_comparison)?;
+                self.consume_line_end()?;
+                self.yields = true;
+                Ok(Arc::new(AST::Yield(loc, expr)))
+            }
+            Token {
+                kind: TokenKind::Try,
+                ..
+            } => self.parse_without_yield(Parser::parse_try),
             Token {
                 kind: TokenKind::Assert,
                 loc,
@@ -261,24 +1008,180 @@ impl Parser {
                 let cond = self.parse_expression()?;
                 if self.cur().kind == TokenKind::Comma {
                     self.increment();
-                    self.consume(TokenKind::StringLiteral)?;
+                    match self.cur().kind {
+                        TokenKind::InterpolatedString => self.increment(),
+                        _ => _ = self.consume(TokenKind::StringLiteral)?,
+                    }
                 }
                 self.consume_line_end()?;
-                Ok(Rc::new(AST::Assert(loc, cond)))
+                Ok(Arc::new(AST::Assert(loc, cond)))
             }
             _ => {
-                let expr = self.parse_expression();
+                let mut expr = self.parse_expression()?;
+                if self.cur().kind == TokenKind::Comma {
+                    expr = self.parse_tuple_assignment(expr)?;
+                }
                 self.consume_line_end()?;
-                expr
+                Ok(expr)
+            }
+        }
+    }
+
+    /// A name or a parenthesized tuple of targets after `let`.
+    /// The `: Type` after a name being declared, if there is one.
+    fn parse_annotation(&mut self) -> Result<Option<TypeExpr>> {
+        if self.cur().kind != TokenKind::Colon {
+            return Ok(None);
+        }
+        self.increment();
+        self.parse_type().map(Some)
+    }
+
+    /// A type, as in `int`, `dict[str, list[int]]`, `int | nothing` or
+    /// `(int | str)`.
+    fn parse_type(&mut self) -> Result<TypeExpr> {
+        let first = self.parse_named_type()?;
+        if self.cur().kind != TokenKind::Pipe {
+            return Ok(first);
+        }
+        let mut loc = first.loc().clone();
+        let mut types = vec![first];
+        while self.cur().kind == TokenKind::Pipe {
+            self.increment();
+            types.push(self.parse_named_type()?);
+        }
+        loc.end = types.last().expect("has a type").loc().end;
+        Ok(TypeExpr::Union(loc, types))
+    }
+
+    fn parse_named_type(&mut self) -> Result<TypeExpr> {
+        let token = self.cur();
+        if token.kind == TokenKind::LeftParen {
+            self.increment();
+            let ty = self.parse_type()?;
+            self.consume(TokenKind::RightParen)?;
+            return Ok(ty);
+        }
+        let mut name = match token.kind {
+            TokenKind::Identifier => token.symbol(),
+            // a keyword, but also the name of its type
+            TokenKind::Nothing => Symbol::intern("nothing"),
+            kind => error!(token.loc, "Expected a type, but got {:?}", kind),
+        };
+        self.increment();
+        // a type from a module, as in `shapes.Point`
+        while self.cur().kind == TokenKind::Dot {
+            self.increment();
+            let member = self.consume(TokenKind::Identifier)?.symbol();
+            name = Symbol::intern(&format!("{}.{}", name, member));
+        }
+        let mut items = vec![];
+        if self.cur().kind == TokenKind::LeftBracket {
+            self.increment();
+            loop {
+                items.push(self.parse_type()?);
+                match self.cur().kind {
+                    TokenKind::Comma => self.increment(),
+                    TokenKind::RightBracket => break,
+                    kind => error!(
+                        self.cur().loc,
+                        "Expected `,` or `]` in a type, but got {:?}", kind
+                    ),
+                }
+            }
+            self.increment();
+        }
+        Ok(TypeExpr::Named(token.loc, name, items))
+    }
+
+    fn parse_let_target(&mut self) -> Result<Arc<AST>> {
+        let token = self.cur();
+        if token.kind == TokenKind::LeftParen {
+            self.increment();
+            return self.parse_parenthesized(token.loc, Parser::parse_let_target);
+        }
+        let ident = self.consume(TokenKind::Identifier)?;
+        let name = ident.symbol();
+        Ok(Arc::new(AST::Variable(ident.loc, name)))
+    }
+
+    /// The rest of a parenthesized list after its `(`: a tuple of items
+    /// that `parse` reads, unless it's a single item with no comma, as in
+    /// `(a)`.
+    fn parse_parenthesized(
+        &mut self,
+        loc: Location,
+        parse: fn(&mut Parser) -> Result<Arc<AST>>,
+    ) -> Result<Arc<AST>> {
+        let mut items = vec![];
+        let mut comma = false;
+        while self.cur().kind != TokenKind::RightParen {
+            items.push(parse(self)?);
+            match self.cur().kind {
+                TokenKind::Comma => {
+                    self.increment();
+                    comma = true;
+                }
+                TokenKind::RightParen => {}
+                TokenKind::EOF => {
+                    eof_error!(self.cur().loc, "Expected `)` or ',' but got EOF")
+                }
+                _ => error!(
+                    self.cur().loc,
+                    "Expected `)` or `,` but got {:?}",
+                    self.cur().kind
+                ),
             }
         }
+        self.increment();
+        match items.len() {
+            1 if !comma => Ok(items.remove(0)),
+            _ => Ok(Arc::new(AST::Tuple(loc, items))),
+        }
+    }
+
+    /// Continues `first` into a tuple when a comma follows it, for the
+    /// tuples that need no parentheses, like those in `a, b = b, a`.
+    fn parse_tuple_rest(
+        &mut self,
+        first: Arc<AST>,
+        parse: fn(&mut Parser) -> Result<Arc<AST>>,
+    ) -> Result<Arc<AST>> {
+        if self.cur().kind != TokenKind::Comma {
+            return Ok(first);
+        }
+        let loc = first.loc().clone();
+        let mut items = vec![first];
+        while self.cur().kind == TokenKind::Comma {
+            self.increment();
+            items.push(parse(self)?);
+        }
+        Ok(Arc::new(AST::Tuple(loc, items)))
     }
 
-    fn parse_expression(&mut self) -> Result<Rc<AST>> {
+    /// An assignment statement with a comma after `first`, either between
+    /// its targets or in its value.
+    fn parse_tuple_assignment(&mut self, first: Arc<AST>) -> Result<Arc<AST>> {
+        if let AST::Assignment(loc, target, value) = first.as_ref() {
+            let value = self.parse_tuple_rest(value.clone(), Parser::parse_comparison)?;
+            return Ok(Arc::new(AST::Assignment(
+                loc.clone(),
+                target.clone(),
+                value,
+            )));
+        }
+        let targets = self.parse_tuple_rest(first, Parser::parse_comparison)?;
+        let loc = self.consume(TokenKind::Equals)?.loc;
+        let value = self.parse_comparison()?;
+        let value = self.parse_tuple_rest(value, Parser::parse_comparison)?;
+        Ok(Arc::new(AST::Assignment(loc, targets, value)))
+    }
+
+    fn parse_expression(&mut self) -> Result<Arc<AST>> {
         self.parse_assignment()
     }
 
-    fn parse_assignment(&mut self) -> Result<Rc<AST>> {
+    fn parse_assignment(&mut self) -> Result<Arc<AST>> {
         let left = self.parse_comparison()?;
         match self.cur() {
             Token {
@@ -288,13 +1191,13 @@ impl Parser {
             } => {
                 self.increment();
                 let right = self.parse_comparison()?;
-                Ok(Rc::new(AST::Assignment(loc, left, right)))
+                Ok(Arc::new(AST::Assignment(loc, left, right)))
             }
             _ => Ok(left),
         }
     }
 
-    fn parse_comparison(&mut self) -> Result<Rc<AST>> {
+    fn parse_comparison(&mut self) -> Result<Arc<AST>> {
         let mut left = self.parse_logical_or()?;
         while let Token {
             kind:
@@ -303,7 +1206,8 @@ impl Parser {
                 | TokenKind::LessThan
                 | TokenKind::GreaterThan
                 | TokenKind::LessThanEquals
-                | TokenKind::GreaterThanEquals,
+                | TokenKind::GreaterThanEquals
+                | TokenKind::In,
             loc,
             ..
         } = self.cur()
@@ -312,19 +1216,20 @@ impl Parser {
             self.increment();
             let right = self.parse_logical_or()?;
             left = match op {
-                TokenKind::EqualsEquals => Rc::new(AST::Equals(loc, left, right)),
-                TokenKind::BangEquals => Rc::new(AST::NotEquals(loc, left, right)),
-                TokenKind::LessThan => Rc::new(AST::LessThan(loc, left, right)),
-                TokenKind::GreaterThan => Rc::new(AST::GreaterThan(loc, left, right)),
-                TokenKind::LessThanEquals => Rc::new(AST::LessThanEquals(loc, left, right)),
-                TokenKind::GreaterThanEquals => Rc::new(AST::GreaterThanEquals(loc, left, right)),
+                TokenKind::EqualsEquals => Arc::new(AST::Equals(loc, left, right)),
+                TokenKind::BangEquals => Arc::new(AST::NotEquals(loc, left, right)),
+                TokenKind::LessThan => Arc::new(AST::LessThan(loc, left, right)),
+                TokenKind::GreaterThan => Arc::new(AST::GreaterThan(loc, left, right)),
+                TokenKind::LessThanEquals => Arc::new(AST::LessThanEquals(loc, left, right)),
+                TokenKind::GreaterThanEquals => Arc::new(AST::GreaterThanEquals(loc, left, right)),
+                TokenKind::In => Arc::new(AST::In(loc, left, right)),
                 _ => unreachable!(),
             }
         }
         Ok(left)
     }
 
-    fn parse_logical_or(&mut self) -> Result<Rc<AST>> {
+    fn parse_logical_or(&mut self) -> Result<Arc<AST>> {
         let mut left = self.parse_logical_and()?;
         while let Token {
             kind: TokenKind::Or,
@@ -334,27 +1239,94 @@ impl Parser {
         {
             self.increment();
             let right = self.parse_logical_and()?;
-            left = Rc::new(AST::Or(loc, left, right));
+            left = Arc::new(AST::Or(loc, left, right));
         }
         Ok(left)
     }
 
-    fn parse_logical_and(&mut self) -> Result<Rc<AST>> {
-        let mut left = self.parse_additive()?;
+    fn parse_logical_and(&mut self) -> Result<Arc<AST>> {
+        let mut left = self.parse_bit_or()?;
         while let Token {
             kind: TokenKind::And,
             loc,
             ..
         } = self.cur()
         {
+            self.increment();
+            let right = self.parse_bit_or()?;
+            left = Arc::new(AST::And(loc, left, right));
+        }
+        Ok(left)
+    }
+
+    fn parse_bit_or(&mut self) -> Result<Arc<AST>> {
+        let mut left = self.parse_bit_xor()?;
+        // on a new line, it's a lambda starting the next statement
+        while let Token {
+            kind: TokenKind::Pipe,
+            loc,
+            newline_before: false,
+            ..
+        } = self.cur()
+        {
+            self.increment();
+            let right = self.parse_bit_xor()?;
+            left = Arc::new(AST::BitOr(loc, left, right));
+        }
+        Ok(left)
+    }
+
+    fn parse_bit_xor(&mut self) -> Result<Arc<AST>> {
+        let mut left = self.parse_bit_and()?;
+        while let Token {
+            kind: TokenKind::Caret,
+            loc,
+            ..
+        } = self.cur()
+        {
+            self.increment();
+            let right = self.parse_bit_and()?;
+            left = Arc::new(AST::BitXor(loc, left, right));
+        }
+        Ok(left)
+    }
+
+    fn parse_bit_and(&mut self) -> Result<Arc<AST>> {
+        let mut left = self.parse_shift()?;
+        while let Token {
+            kind: TokenKind::Ampersand,
+            loc,
+            ..
+        } = self.cur()
+        {
+            self.increment();
+            let right = self.parse_shift()?;
+            left = Arc::new(AST::BitAnd(loc, left, right));
+        }
+        Ok(left)
+    }
+
+    fn parse_shift(&mut self) -> Result<Arc<AST>> {
+        let mut left = self.parse_additive()?;
+        while let Token {
+            kind: TokenKind::LessThanLessThan | TokenKind::GreaterThanGreaterThan,
+            loc,
+            ..
+        } = self.cur()
+        {
+            let op = self.cur().kind;
             self.increment();
             let right = self.parse_additive()?;
-            left = Rc::new(AST::And(loc, left, right));
+            left = match op {
+                TokenKind::LessThanLessThan => Arc::new(AST::ShiftLeft(loc, left, right)),
+                TokenKind::GreaterThanGreaterThan => Arc::new(AST::ShiftRight(loc, left, right)),
+                _ => unreachable!(),
+            }
         }
         Ok(left)
     }
 
-    fn parse_additive(&mut self) -> Result<Rc<AST>> {
+    fn parse_additive(&mut self) -> Result<Arc<AST>> {
         let mut left = self.parse_multiplicative()?;
         while let Token {
             kind: TokenKind::Plus | TokenKind::Minus,
@@ -366,19 +1338,19 @@ impl Parser {
             self.increment();
             let right = self.parse_multiplicative()?;
             left = match op {
-                TokenKind::Plus => Rc::new(AST::Plus(loc, left, right)),
-                TokenKind::Minus => Rc::new(AST::Minus(loc, left, right)),
+                TokenKind::Plus => Arc::new(AST::Plus(loc, left, right)),
+                TokenKind::Minus => Arc::new(AST::Minus(loc, left, right)),
                 _ => unreachable!(),
             }
         }
         Ok(left)
     }
 
-    fn parse_multiplicative(&mut self) -> Result<Rc<AST>> {
+    fn parse_multiplicative(&mut self) -> Result<Arc<AST>> {
         let mut left = self.parse_prefix()?;
 
         while let Token {
-            kind: TokenKind::Star | TokenKind::Slash,
+            kind: TokenKind::Star | TokenKind::Slash | TokenKind::Percent,
             loc,
             ..
         } = self.cur()
@@ -387,40 +1359,73 @@ impl Parser {
             self.increment();
             let right = self.parse_prefix()?;
             left = match op {
-                TokenKind::Star => Rc::new(AST::Multiply(loc, left, right)),
-                TokenKind::Slash => Rc::new(AST::Divide(loc, left, right)),
+                TokenKind::Star => Arc::new(AST::Multiply(loc, left, right)),
+                TokenKind::Slash => Arc::new(AST::Divide(loc, left, right)),
+                TokenKind::Percent => Arc::new(AST::Modulo(loc, left, right)),
                 _ => unreachable!(),
             }
         }
         Ok(left)
     }
 
-    fn parse_slice_value(&mut self) -> Result<Option<Rc<AST>>> {
+    fn parse_slice_value(&mut self) -> Result<Option<Arc<AST>>> {
         match self.cur().kind {
             TokenKind::Colon | TokenKind::RightBracket => Ok(None),
             _ => Ok(Some(self.parse_expression()?)),
         }
     }
 
-    fn parse_prefix(&mut self) -> Result<Rc<AST>> {
+    fn parse_prefix(&mut self) -> Result<Arc<AST>> {
         match self.cur().kind {
             TokenKind::Not => {
                 let loc = self.cur().loc;
                 self.increment();
                 let expr = self.parse_prefix()?;
-                Ok(Rc::new(AST::Not(loc, expr)))
+                Ok(Arc::new(AST::Not(loc, expr)))
             }
-            _ => self.parse_postfix(),
+            TokenKind::Minus => {
+                let loc = self.cur().loc;
+                self.increment();
+                let expr = self.parse_prefix()?;
+                Ok(Arc::new(AST::Negate(loc, expr)))
+            }
+            TokenKind::Tilde => {
+                let loc = self.cur().loc;
+                self.increment();
+                let expr = self.parse_prefix()?;
+                Ok(Arc::new(AST::BitNot(loc, expr)))
+            }
+            _ => self.parse_power(),
         }
     }
 
-    fn parse_postfix(&mut self) -> Result<Rc<AST>> {
+    /// `**` binds tighter than a prefix on its left, so `-2 ** 2` is `-4`,
+    /// and groups to the right, so `2 ** 3 ** 2` is `2 ** 9`.
+    fn parse_power(&mut self) -> Result<Arc<AST>> {
+        let base = self.parse_postfix()?;
+        match self.cur() {
+            Token {
+                kind: TokenKind::StarStar,
+                loc,
+                ..
+            } => {
+                self.increment();
+                let exponent = self.parse_prefix()?;
+                Ok(Arc::new(AST::Power(loc, base, exponent)))
+            }
+            _ => Ok(base),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Arc<AST>> {
         let mut val = self.parse_atom()?;
         loop {
             match self.cur() {
+                // on a new line, it's a list starting the next statement
                 Token {
                     kind: TokenKind::LeftBracket,
                     loc,
+                    newline_before: false,
                     ..
                 } => {
                     self.increment();
@@ -429,7 +1434,7 @@ impl Parser {
                     if self.cur().kind == TokenKind::RightBracket {
                         if let Some(start) = start {
                             self.increment();
-                            val = Rc::new(AST::Index(loc.clone(), val, start));
+                            val = Arc::new(AST::Index(loc.clone(), val, start));
                             continue;
                         } else {
                             error!(loc, "Cannot have empty index");
@@ -441,7 +1446,7 @@ impl Parser {
 
                     if self.cur().kind == TokenKind::RightBracket {
                         self.increment();
-                        val = Rc::new(AST::Slice {
+                        val = Arc::new(AST::Slice {
                             loc: loc.clone(),
                             lhs: val,
                             start,
@@ -454,7 +1459,7 @@ impl Parser {
                     self.consume(TokenKind::Colon)?;
                     let step = self.parse_slice_value()?;
                     self.consume(TokenKind::RightBracket)?;
-                    val = Rc::new(AST::Slice {
+                    val = Arc::new(AST::Slice {
                         loc,
                         lhs: val,
                         start,
@@ -462,9 +1467,11 @@ impl Parser {
                         step,
                     })
                 }
+                // likewise, a tuple or a parenthesized expression
                 Token {
                     kind: TokenKind::LeftParen,
                     loc,
+                    newline_before: false,
                     ..
                 } => {
                     self.increment();
@@ -476,7 +1483,16 @@ impl Parser {
                                 break;
                             }
                             _ => {
-                                args.push(self.parse_expression()?);
+                                let arg = self.parse_argument()?;
+                                let named =
+                                    |arg: &Arc<AST>| matches!(**arg, AST::NamedArgument(..));
+                                if !named(&arg) && args.iter().any(named) {
+                                    error!(
+                                        arg.loc(),
+                                        "Positional arguments have to come before named ones"
+                                    );
+                                }
+                                args.push(arg);
                                 match self.cur().kind {
                                     TokenKind::Comma => self.increment(),
                                     TokenKind::RightParen => {}
@@ -493,16 +1509,26 @@ impl Parser {
                             }
                         }
                     }
-                    val = Rc::new(AST::Call(loc, val, args));
+                    val = Arc::new(AST::Call(loc, val, args));
+                }
+                Token {
+                    kind: TokenKind::Dot,
+                    loc,
+                    ..
+                } => {
+                    self.increment();
+                    let name = self.consume(TokenKind::Identifier)?;
+                    val = Arc::new(AST::Member(loc, val, name.symbol()));
                 }
                 Token {
-                    kind: TokenKind::DotDot,
+                    kind: kind @ (TokenKind::DotDot | TokenKind::DotDotEquals),
                     loc,
                     ..
                 } => {
                     self.increment();
                     let end = self.parse_atom()?;
-                    val = Rc::new(AST::Range(loc, val, end));
+                    let inclusive = kind == TokenKind::DotDotEquals;
+                    val = Arc::new(AST::Range(loc, val, end, inclusive));
                 }
                 _ => break,
             }
@@ -510,30 +1536,140 @@ impl Parser {
         Ok(val)
     }
 
-    fn parse_atom(&mut self) -> Result<Rc<AST>> {
+    /// What follows `import`: a name, names joined by dots, as in `a.b`,
+    /// which is `a/b` as a path, or a string with a path in it. The name
+    /// the module is bound to is the last one, or that of the file.
+    fn parse_import_path(&mut self) -> Result<(Symbol, Arc<str>)> {
+        if self.cur().kind == TokenKind::StringLiteral {
+            let token = self.cur();
+            self.increment();
+            // the lexer already rejected bad escapes
+            let path = unescape(token.text()).unwrap_or_default();
+            let stem = Path::new(path.as_ref())
+                .file_stem()
+                .and_then(|stem| stem.to_str());
+            let stem = stem.unwrap_or_default().to_string();
+            let identifier = stem.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !identifier {
+                error!(token.loc, "Can't name a module after the file {:?}", path)
+            }
+            let path = path.strip_suffix(".rat").unwrap_or(&path);
+            return Ok((Symbol::intern(&stem), self.intern(path)));
+        }
+        let mut names = vec![self.consume(TokenKind::Identifier)?.symbol()];
+        while self.cur().kind == TokenKind::Dot {
+            self.increment();
+            names.push(self.consume(TokenKind::Identifier)?.symbol());
+        }
+        let path = names.iter().map(|name| name.as_str()).collect::<Vec<_>>();
+        let path = self.intern(&path.join("/"));
+        Ok((*names.last().unwrap(), path))
+    }
+
+    /// An argument of a call, which may be named: `sep: ", "` or
+    /// `sep = ", "`, or spread: `...items`.
+    fn parse_argument(&mut self) -> Result<Arc<AST>> {
+        if self.cur().kind == TokenKind::DotDotDot {
+            let loc = self.cur().loc;
+            self.increment();
+            return Ok(Arc::new(AST::Spread(loc, self.parse_expression()?)));
+        }
+        let named = self.cur().kind == TokenKind::Identifier
+            && matches!(self.next_kind(), Some(TokenKind::Colon | TokenKind::Equals));
+        if !named {
+            return self.parse_expression();
+        }
+        let name = self.cur();
+        self.increment();
+        self.increment();
+        let value = self.parse_expression()?;
+        Ok(Arc::new(AST::NamedArgument(
+            name.loc.clone(),
+            name.symbol(),
+            value,
+        )))
+    }
+
+    fn parse_atom(&mut self) -> Result<Arc<AST>> {
         match self.cur() {
+            Token {
+                kind: TokenKind::LeftBracket,
+                loc,
+                ..
+            } => {
+                self.increment();
+                let mut items = vec![];
+                while self.cur().kind != TokenKind::RightBracket {
+                    items.push(self.parse_expression()?);
+                    match self.cur().kind {
+                        TokenKind::Comma => self.increment(),
+                        TokenKind::RightBracket => {}
+                        TokenKind::EOF => {
+                            eof_error!(self.cur().loc, "Expected `]` or ',' but got EOF")
+                        }
+                        _ => error!(
+                            self.cur().loc,
+                            "Expected `]` or `,` but got {:?}",
+                            self.cur().kind
+                        ),
+                    }
+                }
+                self.increment();
+                Ok(Arc::new(AST::List(loc, items)))
+            }
+            Token {
+                kind: TokenKind::LeftBrace,
+                loc,
+                ..
+            } => {
+                self.increment();
+                let mut entries = vec![];
+                while self.cur().kind != TokenKind::RightBrace {
+                    let key = self.parse_expression()?;
+                    self.consume(TokenKind::Colon)?;
+                    entries.push((key, self.parse_expression()?));
+                    match self.cur().kind {
+                        TokenKind::Comma => self.increment(),
+                        TokenKind::RightBrace => {}
+                        TokenKind::EOF => {
+                            eof_error!(self.cur().loc, "Expected `}}` or ',' but got EOF")
+                        }
+                        _ => error!(
+                            self.cur().loc,
+                            "Expected `}}` or `,` but got {:?}",
+                            self.cur().kind
+                        ),
+                    }
+                }
+                self.increment();
+                Ok(Arc::new(AST::Dict(loc, entries)))
+            }
             Token {
                 kind: TokenKind::LeftParen,
+                loc,
                 ..
             } => {
                 self.increment();
-                let expr = self.parse_expression()?;
-                self.consume(TokenKind::RightParen)?;
-                Ok(expr)
+                self.parse_parenthesized(loc, Parser::parse_expression)
             }
             Token {
-                kind: TokenKind::Pipe,
+                kind: TokenKind::Pipe | TokenKind::Move,
                 ..
             } => self.parse_lambda(),
+            Token {
+                kind: TokenKind::Match,
+                ..
+            } => self.parse_without_yield(Parser::parse_match),
             Token {
                 kind: TokenKind::IntegerLiteralDec,
                 loc,
-                text,
                 ..
             } => {
+                let text = self.cur().text().replace('_', "");
                 self.increment();
                 if let Ok(num) = text.parse::<i64>() {
-                    Ok(Rc::new(AST::IntegerLiteral(loc, num)))
+                    Ok(Arc::new(AST::IntegerLiteral(loc, num)))
                 } else {
                     error!(loc, "Invalid integer literal: {}", text);
                 }
@@ -541,12 +1677,12 @@ impl Parser {
             Token {
                 kind: TokenKind::IntegerLiteralBin,
                 loc,
-                text,
                 ..
             } => {
+                let text = self.cur().text().replace('_', "");
                 self.increment();
                 if let Ok(num) = i64::from_str_radix(&text, 2) {
-                    Ok(Rc::new(AST::IntegerLiteral(loc, num)))
+                    Ok(Arc::new(AST::IntegerLiteral(loc, num)))
                 } else {
                     error!(loc, "Invalid integer literal: {}", text);
                 }
@@ -554,12 +1690,12 @@ impl Parser {
             Token {
                 kind: TokenKind::IntegerLiteralOct,
                 loc,
-                text,
                 ..
             } => {
+                let text = self.cur().text().replace('_', "");
                 self.increment();
                 if let Ok(num) = i64::from_str_radix(&text, 8) {
-                    Ok(Rc::new(AST::IntegerLiteral(loc, num)))
+                    Ok(Arc::new(AST::IntegerLiteral(loc, num)))
                 } else {
                     error!(loc, "Invalid integer literal: {}", text);
                 }
@@ -567,12 +1703,12 @@ impl Parser {
             Token {
                 kind: TokenKind::IntegerLiteralHex,
                 loc,
-                text,
                 ..
             } => {
+                let text = self.cur().text().replace('_', "");
                 self.increment();
                 if let Ok(num) = i64::from_str_radix(&text, 16) {
-                    Ok(Rc::new(AST::IntegerLiteral(loc, num)))
+                    Ok(Arc::new(AST::IntegerLiteral(loc, num)))
                 } else {
                     error!(loc, "Invalid integer literal: {}", text);
                 }
@@ -580,12 +1716,12 @@ impl Parser {
             Token {
                 kind: TokenKind::FloatLiteral,
                 loc,
-                text,
                 ..
             } => {
+                let text = self.cur().text().replace('_', "");
                 self.increment();
                 if let Ok(num) = text.parse::<f64>() {
-                    Ok(Rc::new(AST::FloatLiteral(loc, num)))
+                    Ok(Arc::new(AST::FloatLiteral(loc, num)))
                 } else {
                     error!(loc, "Invalid float literal: {}", text);
                 }
@@ -593,20 +1729,31 @@ impl Parser {
             Token {
                 kind: TokenKind::StringLiteral,
                 loc,
-                text,
                 ..
             } => {
+                let token = self.cur();
+                // the lexer already rejected bad escapes
+                let text = unescape(token.text()).unwrap_or_default();
+                let text = self.intern(&text);
+                self.increment();
+                Ok(Arc::new(AST::StringLiteral(loc, text)))
+            }
+            Token {
+                kind: TokenKind::InterpolatedString,
+                ..
+            } => {
+                let token = self.cur();
                 self.increment();
-                Ok(Rc::new(AST::StringLiteral(loc, text)))
+                self.parse_interpolation(&token)
             }
             Token {
                 kind: TokenKind::Identifier,
                 loc,
-                text,
                 ..
             } => {
+                let name = self.cur().symbol();
                 self.increment();
-                Ok(Rc::new(AST::Variable(loc, text)))
+                Ok(Arc::new(AST::Variable(loc, name)))
             }
             Token {
                 kind: TokenKind::True,
@@ -614,7 +1761,7 @@ impl Parser {
                 ..
             } => {
                 self.increment();
-                Ok(Rc::new(AST::BooleanLiteral(loc, true)))
+                Ok(Arc::new(AST::BooleanLiteral(loc, true)))
             }
             Token {
                 kind: TokenKind::False,
@@ -622,7 +1769,7 @@ impl Parser {
                 ..
             } => {
                 self.increment();
-                Ok(Rc::new(AST::BooleanLiteral(loc, false)))
+                Ok(Arc::new(AST::BooleanLiteral(loc, false)))
             }
             Token {
                 kind: TokenKind::Nothing,
@@ -630,7 +1777,7 @@ impl Parser {
                 ..
             } => {
                 self.increment();
-                Ok(Rc::new(AST::Nothing(loc)))
+                Ok(Arc::new(AST::Nothing(loc)))
             }
             Token {
                 kind: TokenKind::EOF,
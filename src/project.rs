@@ -0,0 +1,136 @@
+use crate::ast::AST;
+use crate::error::{Diagnostics, Error};
+use crate::lexer::Lexer;
+use crate::manifest::Manifest;
+use crate::parser::Parser;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Where an import of `path` inside `importer` points to: `<path>.rat` next
+/// to the importing file, or else wherever the manifest resolves it. The
+/// path is a name, names joined by `/` for `import a.b`, or the path of an
+/// `import "path/to/file"`.
+pub fn resolve_import(importer: &Path, path: &str, manifest: Option<&Manifest>) -> PathBuf {
+    let dir = importer.parent().unwrap_or(Path::new(""));
+    let file = dir.join(format!("{}.rat", path));
+    if file.is_file() {
+        return file;
+    }
+    manifest
+        .and_then(|manifest| manifest.resolve(path))
+        .unwrap_or(file)
+}
+
+/// Every file reachable from an entry point through imports, parsed ahead of
+/// running it, keyed by canonical path. Errors are ordered by file.
+#[derive(Debug, Default)]
+pub struct Project {
+    pub entry: PathBuf,
+    pub modules: HashMap<PathBuf, Arc<AST>>,
+    pub errors: Vec<Error>,
+}
+
+/// Lexes and parses `entry` and everything it imports. Each wave of newly
+/// discovered files is parsed in parallel, since lexing and parsing only
+/// depend on the source text.
+pub fn parse_project(entry: &Path, manifest: Option<&Manifest>) -> Project {
+    let canonical = entry.canonicalize().unwrap_or(entry.to_path_buf());
+    let mut project = Project {
+        entry: canonical.clone(),
+        ..Project::default()
+    };
+    let mut seen = HashSet::from([canonical.clone()]);
+    // files are lexed under the path they were found by, so that locations
+    // read the same as when the interpreter imports them itself
+    let mut wave = vec![(canonical, entry.to_path_buf())];
+
+    while !wave.is_empty() {
+        let mut parsed = parse_files(&wave);
+        parsed.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        let mut next_wave = vec![];
+        for (canonical, path, result) in parsed {
+            match result {
+                Ok(ast) => {
+                    let mut names = vec![];
+                    collect_imports(&ast, &mut names);
+                    for name in names {
+                        // unresolvable names may still be host modules, so
+                        // those are left for the interpreter to report
+                        let import = resolve_import(&path, name, manifest);
+                        if let Ok(canonical) = import.canonicalize() {
+                            if seen.insert(canonical.clone()) {
+                                next_wave.push((canonical, import));
+                            }
+                        }
+                    }
+                    project.modules.insert(canonical, ast);
+                }
+                Err(errors) => project.errors.extend(errors),
+            }
+        }
+        wave = next_wave;
+    }
+    project
+}
+
+/// Parses a file, with every syntax error in it if it has any.
+fn parse_file(path: &Path) -> std::result::Result<Arc<AST>, Vec<Error>> {
+    let file = File::open(path).map_err(|err| {
+        vec![Error::Other(format!(
+            "Couldn't read {}: {}",
+            path.display(),
+            err
+        ))]
+    })?;
+    let diagnostics = Diagnostics::new();
+    let lexer = Lexer::from_reader(BufReader::new(file), path.display().to_string())
+        .with_diagnostics(diagnostics.clone());
+    Parser::from_lexer(lexer)
+        .parse()
+        .map_err(|_| diagnostics.take())
+}
+
+type Parsed = (PathBuf, PathBuf, std::result::Result<Arc<AST>, Vec<Error>>);
+
+fn parse_files(files: &[(PathBuf, PathBuf)]) -> Vec<Parsed> {
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(files.len());
+    if workers <= 1 {
+        return files
+            .iter()
+            .map(|(canonical, path)| (canonical.clone(), path.clone(), parse_file(path)))
+            .collect();
+    }
+
+    let queue = Mutex::new(files.iter());
+    let results = Mutex::new(Vec::with_capacity(files.len()));
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let Some((canonical, path)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = parse_file(path);
+                let parsed = (canonical.clone(), path.clone(), result);
+                results.lock().unwrap().push(parsed);
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+pub(crate) fn collect_imports<'a>(ast: &'a AST, names: &mut Vec<&'a str>) {
+    if let AST::Import(_, _, path) = ast {
+        names.push(path);
+    }
+    for child in ast.children() {
+        collect_imports(child, names);
+    }
+}
@@ -0,0 +1,52 @@
+//! The random numbers behind `random()` and the other random built-ins. It's
+//! xorshift64*, which is quick and good enough for scripts, but not for
+//! anything that has to be unpredictable.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// A generator that gives the same numbers every time for the same seed.
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            // xorshift gets stuck on zero
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+        }
+    }
+
+    /// A generator seeded from the clock.
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A float in `0.0..1.0`, from the top 53 bits so that every value is
+    /// equally likely.
+    pub fn float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `0..n`, rejecting the numbers that would make the low
+    /// ones more likely than the rest.
+    pub fn below(&mut self, n: u64) -> u64 {
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return value % n;
+            }
+        }
+    }
+}
@@ -1,78 +1,273 @@
 use crate::ast::AST;
-use crate::error::{Error, Result};
-use crate::interpreter::{Interpreter, Ref, Scope};
+use crate::editor::{Line, LineEditor, Terminal};
+use crate::error::{use_color, Error, Result};
+use crate::interpreter::{Interpreter, Ref, Scope, ScopeSnapshot};
+use crate::signal;
+use crate::symbol::Symbol;
+use crate::token::KEYWORDS;
 use crate::value::Value;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
 use std::io::Write;
-use std::rc::Rc;
-use std::cell::RefCell;
-use crate::value::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How many lines of history are kept across sessions.
+const HISTORY_LIMIT: usize = 1000;
 
 pub struct Repl {
     interpreter: Interpreter,
-    global_scope: Ref<Scope>,
+    editor: Box<dyn LineEditor>,
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+    /// Every statement that has run successfully, for `:save`.
+    session: Vec<String>,
+    /// The globals from before each statement in `session`, for `:undo`.
+    undo: Vec<ScopeSnapshot>,
+    /// The globals the session started with, for `:reset`.
+    start: ScopeSnapshot,
+    /// How many inputs have been parsed, to give each its own file name so
+    /// that tracebacks can still quote earlier ones.
+    execution_count: usize,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Repl {
     pub fn new() -> Repl {
+        Repl::with_editor(Box::new(Terminal))
+    }
+
+    /// A REPL that reads its input with `editor`.
+    pub fn with_editor(editor: Box<dyn LineEditor>) -> Repl {
         let interpreter = Interpreter::new();
-        let global_scope = Rc::new(RefCell::new(Scope {
-            vars: HashMap::new(),
-            parent: None,
-            in_function: false,
-        }));
+        let start = interpreter.snapshot_globals();
+        let history_path = history_path();
+        let history = history_path.as_ref().map(load_history).unwrap_or_default();
         Repl {
             interpreter,
-            global_scope,
+            editor,
+            history,
+            history_path,
+            session: vec![],
+            undo: vec![],
+            start,
+            execution_count: 0,
+        }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if line.is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        // history is best-effort, so failing to save it isn't an error
+        if let Some(path) = &self.history_path {
+            let _ = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+                .and_then(|mut file| writeln!(file, "{}", line));
         }
     }
 
-    fn run_once(&mut self) -> Result<()> {
+    /// Runs `ast` as the next statement of the session, which `:undo` can
+    /// take back if it succeeds.
+    fn run_statement(&mut self, ast: &Arc<AST>, source: String) -> Result<Value> {
+        let before = self.interpreter.snapshot_globals();
+        let value = self.interpreter.eval(ast)?;
+        if !source.trim().is_empty() {
+            self.session.push(source);
+            self.undo.push(before);
+        }
+        Ok(value)
+    }
+
+    /// Runs a REPL command: `:save <file>`, `:load <file>`, `:undo` or
+    /// `:reset`.
+    fn run_command(&mut self, command: &str) -> Result<()> {
+        let (name, path) = command.split_once(' ').unwrap_or((command, ""));
+        let path = path.trim();
+        match name {
+            ":undo" => {
+                let Some(before) = self.undo.pop() else {
+                    return Err(Error::Other("Nothing to undo".to_string()));
+                };
+                self.interpreter.restore(before);
+                let statement = self.session.pop().unwrap_or_default();
+                println!("Undid {}", statement.trim());
+            }
+            ":reset" => {
+                self.interpreter.restore(self.start.clone());
+                self.session.clear();
+                self.undo.clear();
+                println!("Reset the session");
+            }
+            ":save" | ":load" if path.is_empty() => {
+                return Err(Error::Other(format!("Usage: {} <file>", name)));
+            }
+            ":save" => {
+                fs::write(path, self.session.concat())
+                    .map_err(|err| Error::Other(format!("Couldn't write {}: {}", path, err)))?;
+                println!("Saved {} statements to {}", self.session.len(), path);
+            }
+            ":load" => {
+                let source = fs::read_to_string(path)
+                    .map_err(|err| Error::Other(format!("Couldn't read {}: {}", path, err)))?;
+                let lex = crate::lexer::Lexer::new(source.clone(), path.to_string());
+                let ast = self.interpreter.parser(lex).parse()?;
+                self.run_statement(&ast, source)?;
+            }
+            _ => {
+                return Err(Error::Other(format!(
+                    "Unknown command `{}`, expected `:save <file>`, `:load <file>`, `:undo` or `:reset`",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and runs one statement, returning false once input runs out.
+    fn run_once(&mut self) -> Result<bool> {
         let mut input = String::new();
+        let builtins = self.interpreter.builtin_names().collect::<Vec<_>>();
         let ast = loop {
-            let mut temp = String::new();
-            print!("{}", if input.is_empty() { ">>> " } else { "... " });
-            std::io::stdout().flush().expect("Failed to flush stdout");
-            std::io::stdin()
-                .read_line(&mut temp)
-                .expect("Failed to read line");
+            let prompt = if input.is_empty() { ">>> " } else { "... " };
+            let scope = &self.interpreter.globals();
+            let line = self.editor.read_line(prompt, &self.history, &|line| {
+                complete(scope, &builtins, line)
+            });
+            let temp = match line {
+                Ok(Line::Text(temp)) => temp,
+                Ok(Line::Interrupted) => return Ok(true),
+                Ok(Line::Eof) => return Ok(false),
+                // there's no more input to be had, so the session is over
+                Err(err) => {
+                    eprintln!("Couldn't read input: {}", err);
+                    return Ok(false);
+                }
+            };
+            self.add_history(temp.trim_end());
+            if input.is_empty() && temp.trim_start().starts_with(':') {
+                self.run_command(temp.trim())?;
+                return Ok(true);
+            }
             if temp.trim().is_empty() {
                 break self.try_parse(input.clone())?;
             }
             input.push_str(&temp);
             match self.try_parse(input.clone()) {
                 Ok(ast) => break ast,
-                Err(Error::UnexpectedEOF(..)) => continue,
+                Err(e) if e.is_incomplete() => continue,
                 Err(e) => return Err(e),
             }
         };
-        let val = self
-            .interpreter
-            .run_block_without_scope(&ast, self.global_scope.clone())?;
+        self.execution_count += 1;
+        // a Ctrl-C at the prompt shouldn't interrupt what's run next
+        signal::take_interrupt();
+        let val = self.run_statement(&ast, input)?;
         match val {
             Value::Nothing => {}
             _ => println!("{}", val.repr()),
         }
-        Ok(())
+        Ok(true)
     }
 
-    fn try_parse(&self, input: String) -> Result<Rc<AST>> {
-        let mut lex = crate::lexer::Lexer::new(input, "<repl>".to_string());
-        let tokens = lex.lex()?;
-        let mut parser = crate::parser::Parser::new(tokens);
-        let ast = parser.parse()?;
+    fn try_parse(&self, input: String) -> Result<Arc<AST>> {
+        let filename = format!("<repl {}>", self.execution_count + 1);
+        let lex = crate::lexer::Lexer::new(input, filename);
+        let ast = self.interpreter.parser(lex).parse()?;
         Ok(ast)
     }
 
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
     pub fn run(&mut self) {
+        signal::install();
         loop {
             match self.run_once() {
-                Ok(_) => {}
+                Ok(true) => {}
+                Ok(false) => return,
                 Err(e) => {
-                    println!("{:?}", e);
+                    print!("{}", e.render(use_color(&std::io::stdout())));
                 }
             }
         }
     }
 }
+
+/// `rattlesnake/history` in the platform's config directory.
+fn history_path() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let config = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        home()?.join("Library").join("Application Support")
+    } else {
+        match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => home()?.join(".config"),
+        }
+    };
+    Some(config.join("rattlesnake").join("history"))
+}
+
+/// The last [`HISTORY_LIMIT`] lines of history, trimming the file down to
+/// them if it has grown past that.
+fn load_history(path: &PathBuf) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    let mut history = contents.lines().map(String::from).collect::<Vec<_>>();
+    if history.len() > HISTORY_LIMIT {
+        history.drain(..history.len() - HISTORY_LIMIT);
+        let _ = fs::write(path, history.join("\n") + "\n");
+    }
+    history
+}
+
+/// Where the identifier that `text` ends with starts.
+fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Completes the word before the cursor from what's live in the session: a
+/// module's members after `module.`, otherwise variables in scope, built-ins
+/// and keywords.
+fn complete(scope: &Ref<Scope>, builtins: &[&str], line: &str) -> (usize, Vec<String>) {
+    let start = word_start(line);
+    let word = &line[start..];
+    let mut candidates = match line[..start].strip_suffix('.') {
+        Some(before) => match Symbol::lookup(&before[word_start(before)..])
+            .and_then(|name| scope.borrow().get(name))
+        {
+            Some(Value::Module(module)) => module.members.keys().cloned().collect(),
+            _ => vec![],
+        },
+        None => {
+            let mut names = vec![];
+            let mut current = Some(scope.clone());
+            while let Some(scope) = current {
+                names.extend(scope.borrow().vars().map(|(name, _)| name.to_string()));
+                current = scope.borrow().parent.clone();
+            }
+            names.extend(builtins.iter().map(|name| name.to_string()));
+            names.extend(KEYWORDS.iter().map(|(keyword, _)| keyword.to_string()));
+            names
+        }
+    };
+    candidates.retain(|candidate| candidate.starts_with(word));
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
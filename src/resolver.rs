@@ -0,0 +1,363 @@
+//! Runs after parsing to number the local variables. A scope's variables
+//! get slots in the order they are declared, and each `Variable` that reads
+//! or assigns a local becomes a `Local` with the slot, which the interpreter
+//! reads without looking the name up. Globals stay `Variable`s, since the
+//! host and earlier REPL lines can add to them.
+//!
+//! A name read before the scope it runs in declares it, with nothing of
+//! that name around to read instead, is reported here rather than when the
+//! read runs. Functions may read names their surroundings declare after
+//! them, as they usually run later.
+//!
+//! A function doesn't keep the scopes around it, only the variables of
+//! them that it uses. Each function gets a scope of its captures outside
+//! the scope of its arguments, and a local read from further out becomes a
+//! read from there, with the function noting where to capture it from.
+//! A `move` function captures the globals it reads as well, as they are
+//! when it's created, where other functions read them when they run.
+
+use crate::ast::{Capture, MatchArm, Slot, AST};
+use crate::error::{Error, Result};
+use crate::interpreter::GLOBAL_NAMES;
+use crate::symbol::Symbol;
+use crate::token::Location;
+use std::sync::Arc;
+
+struct Scope {
+    /// The names the scope will have, in the order of their slots.
+    names: Vec<Symbol>,
+    /// Which of `names` are declared at the point being resolved.
+    declared: Vec<bool>,
+    /// Whether this is the scope of a function's arguments, the outermost
+    /// scope of a call.
+    function: bool,
+    /// For the scope of a function's captures, where each of `names` is
+    /// captured from.
+    captures: Option<Vec<Capture>>,
+    /// Whether the function of a scope of captures copies globals too.
+    moves: bool,
+}
+
+struct Resolver {
+    scopes: Vec<Scope>,
+    error: Option<Error>,
+}
+
+/// Resolves the program `ast`, which runs in a global scope that already
+/// has the built-in globals and `globals`.
+pub fn resolve(ast: &Arc<AST>, globals: &[Symbol]) -> Result<Arc<AST>> {
+    let AST::Block(loc, stmts) = ast.as_ref() else {
+        unreachable!("resolve called on non-block")
+    };
+    let declared = GLOBAL_NAMES
+        .iter()
+        .map(|&name| Symbol::intern(name))
+        .chain(globals.iter().copied());
+    let mut resolver = Resolver {
+        scopes: vec![],
+        error: None,
+    };
+    resolver.push(declared, stmts, false);
+    let stmts = stmts.iter().map(|stmt| resolver.node(stmt)).collect();
+    match resolver.error {
+        Some(err) => Err(err),
+        None => Ok(Arc::new(AST::Block(loc.clone(), stmts))),
+    }
+}
+
+/// The names `stmts` declare in the scope they run in, in order.
+fn declarations(stmts: &[Arc<AST>]) -> Vec<Symbol> {
+    let mut names = vec![];
+    for stmt in stmts {
+        match stmt.as_ref() {
+            AST::Unpack(_, pattern, _) => names.extend(pattern.bindings()),
+            AST::Pub(_, decl) => names.extend(declarations(std::slice::from_ref(decl))),
+            // decorated functions are desugared to `f = deco(def f ...)`
+            AST::Assignment(_, _, value) => names.extend(decorated(value)),
+            AST::VarDeclaration(_, name, ..)
+            | AST::Import(_, name, _)
+            | AST::Struct { name, .. }
+            | AST::Enum { name, .. }
+            | AST::Function {
+                name: Some(name), ..
+            } => names.push(*name),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// The name of the function the decorators of `value` are applied to.
+fn decorated(value: &AST) -> Option<Symbol> {
+    match value {
+        AST::Call(_, _, args) => match args.as_slice() {
+            [arg] => decorated(arg),
+            _ => None,
+        },
+        AST::Function { name, .. } => *name,
+        _ => None,
+    }
+}
+
+impl Resolver {
+    /// Opens a scope that starts out with `declared`, and declares the
+    /// names of `stmts` as they run in it.
+    fn push(&mut self, declared: impl Iterator<Item = Symbol>, stmts: &[Arc<AST>], function: bool) {
+        let mut scope = Scope {
+            names: vec![],
+            declared: vec![],
+            function,
+            captures: None,
+            moves: false,
+        };
+        for name in declared {
+            if !scope.names.contains(&name) {
+                scope.names.push(name);
+                scope.declared.push(true);
+            }
+        }
+        for name in declarations(stmts) {
+            // declaring a name again replaces it in the same slot
+            if !scope.names.contains(&name) {
+                scope.names.push(name);
+                scope.declared.push(false);
+            }
+        }
+        self.scopes.push(scope);
+    }
+
+    fn declare(&mut self, name: Symbol) {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+        match scope.names.iter().position(|&other| other == name) {
+            Some(index) => scope.declared[index] = true,
+            None => {
+                scope.names.push(name);
+                scope.declared.push(true);
+            }
+        }
+    }
+
+    /// Where `name` is when read here, `None` for a global or a name that
+    /// isn't declared anywhere.
+    fn lookup(&mut self, loc: &Location, name: Symbol) -> Option<Slot> {
+        let mut declared_later = false;
+        let slot = self.find(self.scopes.len(), name, false, &mut declared_later);
+        if slot.is_none() && declared_later && self.error.is_none() {
+            let msg = format!("Variable {} is used before it's declared", name);
+            self.error = Some(Error::Parser(loc.clone(), msg));
+        }
+        slot
+    }
+
+    /// Where `name` is when read from inside the innermost `top` scopes,
+    /// capturing it into the functions it's read across. Sets
+    /// `declared_later` if it's skipped in a scope that declares it later.
+    fn find(
+        &mut self,
+        top: usize,
+        name: Symbol,
+        // scopes outside the running function have run all their
+        // declarations by the time it's called, usually
+        mut outside_function: bool,
+        declared_later: &mut bool,
+    ) -> Option<Slot> {
+        for (depth, i) in (0..top).rev().enumerate() {
+            let scope = &self.scopes[i];
+            if let Some(index) = scope.names.iter().position(|&other| other == name) {
+                if scope.declared[index] || outside_function {
+                    return (i != 0).then_some(Slot { depth, index });
+                }
+                *declared_later = true;
+            }
+            if scope.captures.is_some() {
+                let moves = scope.moves;
+                let slot = match self.find(i, name, true, declared_later) {
+                    Some(slot) => Some(slot),
+                    None if moves && self.scopes[0].names.contains(&name) => None,
+                    None => return None,
+                };
+                let scope = &mut self.scopes[i];
+                scope.names.push(name);
+                scope.declared.push(true);
+                let captures = scope.captures.as_mut().expect("a scope of captures");
+                captures.push(Capture { name, slot });
+                let index = captures.len() - 1;
+                return Some(Slot { depth, index });
+            }
+            outside_function |= scope.function;
+        }
+        None
+    }
+
+    /// Resolves the body of the function `ast` in a scope of its own.
+    fn function(&mut self, ast: &Arc<AST>) -> Arc<AST> {
+        let AST::Function {
+            loc,
+            name,
+            args,
+            variadic,
+            defaults,
+            signature,
+            moves,
+            body,
+            ..
+        } = ast.as_ref()
+        else {
+            unreachable!("function called on {}", ast.kind())
+        };
+        self.push(std::iter::empty(), &[], false);
+        let captures = self.scopes.last_mut().unwrap();
+        captures.captures = Some(vec![]);
+        captures.moves = *moves;
+        self.push(args.iter().copied(), &[], true);
+        // defaults run in the call, once the arguments before them are set
+        let defaults = defaults.iter().map(|value| self.node(value)).collect();
+        let body = self.node(body);
+        self.scopes.pop();
+        let captures = self.scopes.pop().and_then(|scope| scope.captures);
+        Arc::new(AST::Function {
+            loc: loc.clone(),
+            name: *name,
+            args: args.clone(),
+            variadic: *variadic,
+            defaults,
+            signature: signature.clone(),
+            captures: captures.unwrap_or_default(),
+            moves: *moves,
+            body,
+        })
+    }
+
+    fn node(&mut self, ast: &Arc<AST>) -> Arc<AST> {
+        let node = match ast.as_ref() {
+            AST::Variable(loc, name) => match self.lookup(loc, *name) {
+                Some(slot) => AST::Local(loc.clone(), *name, slot),
+                None => return ast.clone(),
+            },
+            AST::VarDeclaration(loc, name, ty, value) => {
+                // the value runs before the name exists
+                let value = self.node(value);
+                self.declare(*name);
+                AST::VarDeclaration(loc.clone(), *name, ty.clone(), value)
+            }
+            AST::Unpack(loc, pattern, value) => {
+                let value = self.node(value);
+                pattern
+                    .bindings()
+                    .into_iter()
+                    .for_each(|name| self.declare(name));
+                AST::Unpack(loc.clone(), pattern.clone(), value)
+            }
+            AST::Assignment(loc, lhs, value) => {
+                let value = self.node(value);
+                AST::Assignment(loc.clone(), self.target(lhs), value)
+            }
+            AST::Import(_, name, _) => {
+                self.declare(*name);
+                return ast.clone();
+            }
+            AST::Function { name, .. } => {
+                if let Some(name) = name {
+                    self.declare(*name);
+                }
+                return self.function(ast);
+            }
+            AST::Struct { name, .. } | AST::Enum { name, .. } => {
+                // methods aren't declared in the scope around the struct,
+                // which the struct is declared in before they run
+                self.declare(*name);
+                ast.map_children(|method| self.function(method))
+            }
+            AST::Block(loc, stmts) => {
+                self.push(std::iter::empty(), stmts, false);
+                let stmts = stmts.iter().map(|stmt| self.node(stmt)).collect();
+                self.scopes.pop();
+                AST::Block(loc.clone(), stmts)
+            }
+            AST::For(loc, name, iter, body) => {
+                let iter = self.node(iter);
+                self.push(std::iter::once(*name), &[], false);
+                let body = self.node(body);
+                self.scopes.pop();
+                AST::For(loc.clone(), *name, iter, body)
+            }
+            AST::Match(loc, subject, arms) => {
+                let subject = self.node(subject);
+                let arms = arms
+                    .iter()
+                    .map(|arm| {
+                        // the literals in a pattern run outside the arm
+                        let pattern = self.pattern(&arm.pattern);
+                        self.push(arm.bindings().into_iter(), &[], false);
+                        let guard = arm.guard.as_ref().map(|guard| self.node(guard));
+                        let body = self.node(&arm.body);
+                        self.scopes.pop();
+                        MatchArm {
+                            pattern,
+                            guard,
+                            body,
+                        }
+                    })
+                    .collect();
+                AST::Match(loc.clone(), subject, arms)
+            }
+            AST::Try {
+                loc,
+                body,
+                catch,
+                finally,
+            } => {
+                let body = self.node(body);
+                let catch = catch.as_ref().map(|(name, handler)| {
+                    // the handler's statements run in the scope of the name
+                    let AST::Block(handler_loc, stmts) = handler.as_ref() else {
+                        unreachable!("catch without a block")
+                    };
+                    self.push(std::iter::once(*name), stmts, false);
+                    let stmts = stmts.iter().map(|stmt| self.node(stmt)).collect();
+                    self.scopes.pop();
+                    let handler = AST::Block(handler_loc.clone(), stmts);
+                    (*name, Arc::new(handler))
+                });
+                let finally = finally.as_ref().map(|finally| self.node(finally));
+                AST::Try {
+                    loc: loc.clone(),
+                    body,
+                    catch,
+                    finally,
+                }
+            }
+            _ => ast.map_children(|child| self.node(child)),
+        };
+        Arc::new(node)
+    }
+
+    /// Resolves the left side of an assignment, where `_` discards.
+    fn target(&mut self, lhs: &Arc<AST>) -> Arc<AST> {
+        match lhs.as_ref() {
+            AST::Variable(_, name) if *name == "_" => lhs.clone(),
+            AST::Tuple(loc, targets) => {
+                let targets = targets.iter().map(|target| self.target(target)).collect();
+                Arc::new(AST::Tuple(loc.clone(), targets))
+            }
+            _ => self.node(lhs),
+        }
+    }
+
+    /// Resolves the literals and structs of a `match` pattern, leaving its
+    /// bindings.
+    fn pattern(&mut self, pattern: &Arc<AST>) -> Arc<AST> {
+        match pattern.as_ref() {
+            AST::Variable(..) => pattern.clone(),
+            AST::List(..) | AST::Tuple(..) => {
+                Arc::new(pattern.map_children(|item| self.pattern(item)))
+            }
+            AST::Call(loc, of, items) => {
+                let of = self.node(of);
+                let items = items.iter().map(|item| self.pattern(item)).collect();
+                Arc::new(AST::Call(loc.clone(), of, items))
+            }
+            _ => self.node(pattern),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! `Serialize` and `Deserialize` for [`Value`], behind the `serde` feature,
+//! so that hosts can convert script results with any serde format and hand
+//! their own data to scripts. Values map the way they do to JSON in
+//! [`crate::json`]: lists and tuples are sequences, dicts are maps, and
+//! `nothing` is the unit. Values no format has a form for, like functions,
+//! and lists or dicts that contain themselves, are errors.
+
+use crate::value::{Dict, HashKey, Value};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Nested {
+            value: self,
+            outer: &RefCell::new(Vec::new()),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// A value inside the lists and dicts in `outer`, which are being
+/// serialized further out.
+struct Nested<'a> {
+    value: &'a Value,
+    outer: &'a RefCell<Vec<*const ()>>,
+}
+
+impl Nested<'_> {
+    fn inner<'a>(&'a self, value: &'a Value) -> Nested<'a> {
+        Nested {
+            value,
+            outer: self.outer,
+        }
+    }
+
+    fn enter<E: ser::Error>(&self, ptr: *const ()) -> Result<(), E> {
+        let mut outer = self.outer.borrow_mut();
+        if outer.contains(&ptr) {
+            return Err(E::custom(
+                "A list or dict that contains itself can't be serialized",
+            ));
+        }
+        outer.push(ptr);
+        Ok(())
+    }
+
+    fn seq<S: Serializer>(&self, items: &[Value], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(items.len()))?;
+        for item in items {
+            seq.serialize_element(&self.inner(item))?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for Nested<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::Nothing => serializer.serialize_unit(),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Tuple(items) => self.seq(items, serializer),
+            Value::List(list) => {
+                self.enter(Rc::as_ptr(list).cast())?;
+                let result = self.seq(&list.borrow(), serializer);
+                self.outer.borrow_mut().pop();
+                result
+            }
+            Value::Dict(dict) => {
+                self.enter(Rc::as_ptr(dict).cast())?;
+                let dict = dict.borrow();
+                let mut map = serializer.serialize_map(Some(dict.len()))?;
+                for (key, value) in dict.entries() {
+                    map.serialize_entry(&self.inner(key), &self.inner(value))?;
+                }
+                self.outer.borrow_mut().pop();
+                map.end()
+            }
+            value => Err(ser::Error::custom(format!(
+                "{} can't be serialized",
+                value.repr()
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number, string, boolean, sequence, map or nothing")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Nothing)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Nothing)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(b))
+    }
+
+    fn visit_i64<E>(self, i: i64) -> Result<Value, E> {
+        Ok(Value::Integer(i))
+    }
+
+    /// Numbers too big for an integer become floats, as in JSON.
+    fn visit_u64<E>(self, u: u64) -> Result<Value, E> {
+        Ok(match i64::try_from(u) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::Float(u as f64),
+        })
+    }
+
+    fn visit_f64<E>(self, f: f64) -> Result<Value, E> {
+        Ok(Value::Float(f))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(s.into())
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(s.into())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(items.into())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut dict = Dict::new();
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            let Some(hash) = HashKey::of(&key) else {
+                return Err(de::Error::custom(format!(
+                    "Unhashable type: {}",
+                    key.repr()
+                )));
+            };
+            dict.insert_hashed(hash, key, value);
+        }
+        Ok(Value::Dict(Rc::new(RefCell::new(dict))))
+    }
+}
@@ -0,0 +1,45 @@
+//! Ctrl-C handling. SIGINT only sets a flag, which the interpreter checks as
+//! it runs, so a runaway script stops with an error and a traceback instead
+//! of being killed. A second Ctrl-C before the first is noticed kills the
+//! process as usual.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    pub const SIGINT: c_int = 2;
+    pub const SIG_DFL: usize = 0;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+        pub fn raise(signum: c_int) -> c_int;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: std::os::raw::c_int) {
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        // signal and raise are both safe to call from a handler
+        unsafe {
+            sys::signal(sys::SIGINT, sys::SIG_DFL);
+            sys::raise(sys::SIGINT);
+        }
+    }
+}
+
+/// Makes Ctrl-C interrupt the running script rather than the process.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        sys::signal(sys::SIGINT, on_interrupt as extern "C" fn(_) as usize);
+    }
+}
+
+/// Whether Ctrl-C was pressed since the last call.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed) && INTERRUPTED.swap(false, Ordering::Relaxed)
+}
@@ -0,0 +1,102 @@
+//! Snapshots of diagnostics, so that changes to error messages show up as a
+//! diff to review instead of slipping through.
+//!
+//! Every `*.rat` case in a directory is run through the lexer, parser and
+//! interpreter, and the diagnostic it stops with is compared to the `.snap`
+//! file next to it. Cases named `check_*` go through the type checker before
+//! they run, and cases named `strict_*` run in strict mode. Blessing writes
+//! the current output as the new snapshot.
+
+use crate::checker;
+use crate::error::{Error, Result};
+use crate::interpreter::{Interpreter, STACK_SIZE};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+/// A case whose output doesn't match its snapshot.
+pub struct Mismatch {
+    pub name: String,
+    /// `None` if the case has no snapshot yet.
+    pub expected: Option<String>,
+    pub actual: String,
+}
+
+/// Runs `source` and renders the diagnostic it stops with, as
+/// [`Error::render`] shows it without color. Programs that run to the end
+/// render as `ok`.
+pub fn render(name: &str, source: &str) -> String {
+    let run = || -> Result<()> {
+        let lexer = Lexer::new(source.to_string(), name.to_string());
+        let ast = Parser::from_lexer(lexer).parse()?;
+        if name.starts_with("check_") {
+            if let Some(error) = checker::check(&ast).into_iter().next() {
+                return Err(error);
+            }
+        }
+        let mut interpreter = Interpreter::new();
+        interpreter.set_strict(name.starts_with("strict_"));
+        interpreter.execute(&ast)?;
+        Ok(())
+    };
+    // scripts get the stack the interpreter's own binary gives them
+    let result = thread::scope(|scope| {
+        let runner = thread::Builder::new().stack_size(STACK_SIZE);
+        let runner = runner
+            .spawn_scoped(scope, run)
+            .expect("couldn't start a thread");
+        runner.join()
+    });
+    let error = match result {
+        Ok(Ok(())) => return "ok\n".to_string(),
+        Ok(Err(error)) => error,
+        Err(panic) => {
+            let message = match panic.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+            };
+            return format!("panic: {}\n", message);
+        }
+    };
+    error.render(false)
+}
+
+/// Checks every case in `dir` against its snapshot, or rewrites the
+/// snapshots that differ when `bless` is set. Returns the cases that
+/// differed, in name order.
+pub fn check(dir: &Path, bless: bool) -> Result<Vec<Mismatch>> {
+    let io_error = |path: &Path, err: std::io::Error| {
+        Error::Other(format!("Couldn't read {}: {}", path.display(), err))
+    };
+    let mut cases = fs::read_dir(dir)
+        .map_err(|err| io_error(dir, err))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rat"))
+        .collect::<Vec<_>>();
+    cases.sort();
+
+    let mut mismatches = vec![];
+    for case in cases {
+        let source = fs::read_to_string(&case).map_err(|err| io_error(&case, err))?;
+        let name = case.file_name().unwrap().to_string_lossy().to_string();
+        let actual = render(&name, &source);
+        let snapshot = case.with_extension("snap");
+        let expected = fs::read_to_string(&snapshot).ok();
+        if expected.as_deref() == Some(actual.as_str()) {
+            continue;
+        }
+        if bless {
+            fs::write(&snapshot, &actual).map_err(|err| {
+                Error::Other(format!("Couldn't write {}: {}", snapshot.display(), err))
+            })?;
+        }
+        mismatches.push(Mismatch {
+            name,
+            expected,
+            actual,
+        });
+    }
+    Ok(mismatches)
+}
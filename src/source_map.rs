@@ -0,0 +1,82 @@
+//! Every source file seen by the process, so that locations can stay small
+//! and error messages can still quote the code they point at.
+
+use std::sync::{Arc, Mutex};
+
+struct File {
+    name: Arc<str>,
+    /// `None` until it's registered or read from disk.
+    source: Option<Arc<str>>,
+}
+
+static FILES: Mutex<Vec<File>> = Mutex::new(Vec::new());
+
+/// A source file name, interned so that locations stay small and cheap to
+/// clone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(u32);
+
+impl FileId {
+    /// Registers `name`, returning the existing id if it was seen before.
+    pub fn new(name: &str) -> FileId {
+        let mut files = FILES.lock().unwrap();
+        let index = match files.iter().position(|file| &*file.name == name) {
+            Some(index) => index,
+            None => {
+                files.push(File {
+                    name: name.into(),
+                    source: None,
+                });
+                files.len() - 1
+            }
+        };
+        FileId(index as u32)
+    }
+
+    /// Registers the in-memory `source` of a file called `name`. A name
+    /// holding different text already, as every `eval_str` or REPL input
+    /// does, gets an id of its own, so that errors quote the right code.
+    pub fn with_source(name: &str, source: Arc<str>) -> FileId {
+        let mut files = FILES.lock().unwrap();
+        let same = |file: &File| {
+            &*file.name == name && file.source.as_ref().is_none_or(|text| *text == source)
+        };
+        let index = match files.iter().position(same) {
+            Some(index) => index,
+            None => {
+                files.push(File {
+                    name: name.into(),
+                    source: None,
+                });
+                files.len() - 1
+            }
+        };
+        files[index].source = Some(source);
+        FileId(index as u32)
+    }
+
+    pub fn name(self) -> Arc<str> {
+        FILES.lock().unwrap()[self.0 as usize].name.clone()
+    }
+}
+
+pub struct SourceMap;
+
+impl SourceMap {
+    /// The whole text of `file`. Files that weren't registered are read from
+    /// disk on first use.
+    pub fn source(file: FileId) -> Option<Arc<str>> {
+        let mut files = FILES.lock().unwrap();
+        let file = &mut files[file.0 as usize];
+        if file.source.is_none() {
+            file.source = Some(std::fs::read_to_string(&*file.name).ok()?.into());
+        }
+        file.source.clone()
+    }
+
+    /// The text of the 1-based `line` of `file`.
+    pub fn line(file: FileId, line: usize) -> Option<String> {
+        let source = SourceMap::source(file)?;
+        source.lines().nth(line.checked_sub(1)?).map(String::from)
+    }
+}
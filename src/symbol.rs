@@ -0,0 +1,80 @@
+//! Every identifier seen by the process, so that names are copied around as
+//! numbers and compared without looking at their text.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{LazyLock, Mutex};
+
+#[derive(Default)]
+struct Symbols {
+    /// The text of each symbol, which lives as long as the process so that
+    /// it can be handed out without holding the lock.
+    names: Vec<&'static str>,
+    index: HashMap<&'static str, Symbol>,
+}
+
+static SYMBOLS: LazyLock<Mutex<Symbols>> = LazyLock::new(Mutex::default);
+
+/// An interned identifier. Two symbols are equal exactly when their text is.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// The symbol for `name`, registering it if it wasn't seen before.
+    pub fn intern(name: &str) -> Symbol {
+        let mut symbols = SYMBOLS.lock().unwrap();
+        if let Some(&symbol) = symbols.index.get(name) {
+            return symbol;
+        }
+        let name: &'static str = Box::leak(name.into());
+        let symbol = Symbol(symbols.names.len() as u32);
+        symbols.names.push(name);
+        symbols.index.insert(name, symbol);
+        symbol
+    }
+
+    /// The symbol for `name` if it was seen before, without registering it.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        SYMBOLS.lock().unwrap().index.get(name).copied()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        SYMBOLS.lock().unwrap().names[self.0 as usize]
+    }
+
+    /// The text of `symbols` with `separator` between them.
+    pub fn join(symbols: &[Symbol], separator: &str) -> String {
+        let names = symbols.iter().map(|symbol| symbol.as_str());
+        names.collect::<Vec<_>>().join(separator)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
@@ -0,0 +1,268 @@
+//! Filling in templates, for the `format` and `printf` built-ins.
+//!
+//! A template is text with `{}` where the next argument goes, `{0}` for a
+//! given one, and `{{` and `}}` for braces. A spec after a colon, as in
+//! `{:>8.2}`, says how the argument is written: `[[fill]align][+][#][0]`
+//! `[width][.precision][type]`, where
+//!
+//! - `align` is `<`, `>` or `^` for left, right or centered in `width`
+//!   characters of `fill`, a space unless given. Numbers go to the right
+//!   and everything else to the left unless told otherwise.
+//! - `+` writes the sign of positive numbers too, `#` puts `0x`, `0o` or
+//!   `0b` in front of integers in those bases, and `0` pads numbers with
+//!   zeros after their sign.
+//! - `precision` is the digits after the point for numbers, which makes
+//!   integers floats, and the most characters shown for anything else.
+//! - `type` is `x` or `X` for hexadecimal, `o` for octal or `b` for binary
+//!   integers, `e` for numbers with an exponent, and `?` for the repr,
+//!   which shows strings quoted.
+//!
+//! Widths and precisions go up to 65535.
+
+use crate::value::{format_float, Value};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+fn align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+/// The biggest width or precision a spec may ask for, well past any table
+/// column, so that a typo can't ask for gigabytes of padding.
+const MAX_NUMBER: usize = u16::MAX as usize;
+
+/// Takes the number at the start of `rest`, if there is one.
+fn take_number(rest: &mut &str) -> Result<Option<usize>, String> {
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return Ok(None);
+    }
+    let (number, after) = rest.split_at(digits);
+    *rest = after;
+    match number.parse() {
+        Ok(number) if number <= MAX_NUMBER => Ok(Some(number)),
+        _ => Err(format!(
+            "{} is too big for a format spec, which goes up to {}",
+            number, MAX_NUMBER
+        )),
+    }
+}
+
+impl Spec {
+    fn parse(text: &str) -> Result<Spec, String> {
+        let invalid = || format!("Invalid format spec `{}`", text);
+        let mut spec = Spec::default();
+        let mut rest = text;
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next().and_then(align)) {
+            (Some(fill), Some(align)) => {
+                spec.fill = Some(fill);
+                spec.align = Some(align);
+                rest = chars.as_str();
+            }
+            (Some(c), _) if align(c).is_some() => {
+                spec.align = align(c);
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+        for (flag, set) in [
+            ('+', &mut spec.plus),
+            ('#', &mut spec.alternate),
+            ('0', &mut spec.zero),
+        ] {
+            if let Some(after) = rest.strip_prefix(flag) {
+                *set = true;
+                rest = after;
+            }
+        }
+        spec.width = take_number(&mut rest)?.unwrap_or(0);
+        if let Some(after) = rest.strip_prefix('.') {
+            rest = after;
+            spec.precision = Some(take_number(&mut rest)?.ok_or_else(invalid)?);
+        }
+        let mut chars = rest.chars();
+        spec.kind = chars.next();
+        match (spec.kind, chars.next()) {
+            (None | Some('x' | 'X' | 'o' | 'b' | 'e' | '?'), None) => Ok(spec),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Writes `value`, padded to the spec's width.
+    fn write(&self, value: &Value, out: &mut String) -> Result<(), String> {
+        let number = matches!(value, Value::Integer(_) | Value::Float(_));
+        let (sign, prefix, body) = match number {
+            true => self.number(value)?,
+            false => ("", "", self.text(value)?),
+        };
+        let len = sign.chars().count() + prefix.len() + body.chars().count();
+        let padding = self.width.saturating_sub(len);
+        if self.zero && self.align.is_none() {
+            out.push_str(sign);
+            out.push_str(prefix);
+            out.extend(std::iter::repeat_n('0', padding));
+            out.push_str(&body);
+            return Ok(());
+        }
+        let (before, after) = match self.align.unwrap_or(match number {
+            true => Align::Right,
+            false => Align::Left,
+        }) {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = self.fill.unwrap_or(' ');
+        out.extend(std::iter::repeat_n(fill, before));
+        out.push_str(sign);
+        out.push_str(prefix);
+        out.push_str(&body);
+        out.extend(std::iter::repeat_n(fill, after));
+        Ok(())
+    }
+
+    /// The sign, base prefix and digits of a number.
+    fn number(&self, value: &Value) -> Result<(&'static str, &'static str, String), String> {
+        if self.kind == Some('?') {
+            return Err("`?` doesn't apply to numbers, which show as they are".to_string());
+        }
+        let (negative, digits, prefix) = match (value, self.kind, self.precision) {
+            (Value::Integer(i), Some(kind @ ('x' | 'X' | 'o' | 'b')), _) => {
+                let n = i.unsigned_abs();
+                let (digits, prefix) = match kind {
+                    'x' => (format!("{:x}", n), "0x"),
+                    'X' => (format!("{:X}", n), "0x"),
+                    'o' => (format!("{:o}", n), "0o"),
+                    _ => (format!("{:b}", n), "0b"),
+                };
+                (*i < 0, digits, if self.alternate { prefix } else { "" })
+            }
+            (_, Some('x' | 'X' | 'o' | 'b'), _) => {
+                return Err(format!("`{}` only formats integers", self.kind.unwrap()))
+            }
+            (Value::Integer(i), None, None) => (*i < 0, i.unsigned_abs().to_string(), ""),
+            (Value::Integer(_) | Value::Float(_), _, _) => {
+                let f = value.as_float().unwrap();
+                let abs = f.abs();
+                let digits = match (self.kind, self.precision) {
+                    _ if !f.is_finite() => format_float(abs),
+                    (Some('e'), Some(precision)) => format!("{:.*e}", precision, abs),
+                    (Some('e'), None) => format!("{:e}", abs),
+                    (_, Some(precision)) => format!("{:.*}", precision, abs),
+                    _ => format_float(abs),
+                };
+                (f.is_sign_negative() && !f.is_nan(), digits, "")
+            }
+            _ => unreachable!("only numbers have a sign"),
+        };
+        let sign = match (negative, self.plus) {
+            (true, _) => "-",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        Ok((sign, prefix, digits))
+    }
+
+    /// How anything that isn't a number is written.
+    fn text(&self, value: &Value) -> Result<String, String> {
+        if self.plus || self.alternate || self.zero {
+            return Err(format!(
+                "`+`, `#` and `0` only apply to numbers, not {}",
+                value.type_name()
+            ));
+        }
+        let text = match (value, self.kind) {
+            (_, Some('?')) => value.repr(),
+            (Value::String(string), None) => string.to_string(),
+            (_, None) => value.repr(),
+            (_, Some(kind)) => {
+                return Err(format!(
+                    "`{}` only formats numbers, not {}",
+                    kind,
+                    value.type_name()
+                ))
+            }
+        };
+        Ok(match self.precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text,
+        })
+    }
+}
+
+/// `template` with its placeholders filled in from `args`, each of which
+/// has to be used.
+pub fn format(template: &str, args: &[Value]) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut used = vec![false; args.len()];
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let brace = &rest[i..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            out.push_str(&brace[..1]);
+            rest = &brace[2..];
+            continue;
+        }
+        if brace.starts_with('}') {
+            return Err(
+                "Unmatched `}` in the template, where a literal one is doubled".to_string(),
+            );
+        }
+        let Some(end) = brace.find('}') else {
+            return Err("Unclosed `{` in the template, where a literal one is doubled".to_string());
+        };
+        let placeholder = &brace[1..end];
+        rest = &brace[end + 1..];
+        let (index, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+        let index = match index {
+            "" => {
+                next += 1;
+                next - 1
+            }
+            index => match index.parse::<usize>() {
+                Ok(index) => index,
+                Err(_) => return Err(format!("Invalid placeholder `{{{}}}`", placeholder)),
+            },
+        };
+        let Some(arg) = args.get(index) else {
+            return Err(format!(
+                "`{{{}}}` has no argument, out of the {} given",
+                placeholder,
+                args.len()
+            ));
+        };
+        used[index] = true;
+        Spec::parse(spec)?.write(arg, &mut out)?;
+    }
+    out.push_str(rest);
+    if let Some(unused) = used.iter().position(|used| !used) {
+        let unused = args[unused].repr();
+        return Err(format!("The template doesn't use the argument {}", unused));
+    }
+    Ok(out)
+}
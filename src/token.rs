@@ -1,30 +1,54 @@
-use std::fmt::{Display, Error};
+use crate::edition::Edition;
+use crate::symbol::Symbol;
+use std::fmt::{Debug, Display, Error};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+pub use crate::source_map::FileId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Location {
     pub line: usize,
     pub column: usize,
-    pub filename: String,
+    pub file: FileId,
+    /// Byte offsets in the file of where the located text starts and ends,
+    /// which are the same for a point between chars.
+    pub start: usize,
+    pub end: usize,
+    /// The edition of the file, as it was when the file was lexed.
+    pub edition: Edition,
+}
+
+impl Location {
+    pub fn filename(&self) -> Arc<str> {
+        self.file.name()
+    }
 }
 
 impl Display for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
-        write!(f, "{}:{}:{}", self.filename, self.line, self.column)
+        write!(f, "{}:{}:{}", self.filename(), self.line, self.column)
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
+    Ampersand,
     And,
+    Arrow,
     Assert,
     At,
     Bang,
     BangEquals,
+    Caret,
     Colon,
     Comma,
     Def,
     Dot,
     DotDot,
+    DotDotDot,
+    DotDotEquals,
     EOF,
     Else,
     Equals,
@@ -35,25 +59,32 @@ pub enum TokenKind {
     For,
     Identifier,
     If,
+    Import,
     In,
     IntegerLiteralBin,
     IntegerLiteralOct,
     IntegerLiteralDec,
     IntegerLiteralHex,
+    InterpolatedString,
     LeftBrace,
     LeftBracket,
     LeftParen,
     LessThan,
     LessThanEquals,
+    LessThanLessThan,
     Let,
+    Match,
     Minus,
     Not,
     GreaterThan,
     GreaterThanEquals,
+    GreaterThanGreaterThan,
     Nothing,
     Or,
+    Percent,
     Pipe,
     Plus,
+    Pub,
     Return,
     RightBrace,
     RightBracket,
@@ -61,65 +92,134 @@ pub enum TokenKind {
     SemiColon,
     Slash,
     Star,
+    StarStar,
     StringLiteral,
+    Tilde,
     True,
     While,
     Continue,
     Break,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Yield,
+    Struct,
+    Enum,
+    Move,
 }
 
+/// Words that lex as keywords rather than identifiers.
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("and", TokenKind::And),
+    ("assert", TokenKind::Assert),
+    ("catch", TokenKind::Catch),
+    ("def", TokenKind::Def),
+    ("else", TokenKind::Else),
+    ("enum", TokenKind::Enum),
+    ("false", TokenKind::False),
+    ("finally", TokenKind::Finally),
+    ("if", TokenKind::If),
+    ("import", TokenKind::Import),
+    ("let", TokenKind::Let),
+    ("match", TokenKind::Match),
+    ("move", TokenKind::Move),
+    ("not", TokenKind::Not),
+    ("nothing", TokenKind::Nothing),
+    ("or", TokenKind::Or),
+    ("pub", TokenKind::Pub),
+    ("return", TokenKind::Return),
+    ("struct", TokenKind::Struct),
+    ("throw", TokenKind::Throw),
+    ("true", TokenKind::True),
+    ("try", TokenKind::Try),
+    ("while", TokenKind::While),
+    ("continue", TokenKind::Continue),
+    ("break", TokenKind::Break),
+    ("for", TokenKind::For),
+    ("in", TokenKind::In),
+    ("yield", TokenKind::Yield),
+];
+
+/// A piece of an interpolated string.
 #[derive(Debug, Clone)]
+pub enum StringPart {
+    /// Text that's kept as is, other than its escapes and doubled braces.
+    /// It's the span of the token's source, escapes left in.
+    Literal(Range<usize>),
+    /// The tokens of a `{expression}`, ending with an EOF token where the
+    /// closing brace is.
+    Expression(Vec<Token>),
+}
+
+/// A token refers back into the shared source text instead of owning a
+/// copy of its text.
+#[derive(Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub loc: Location,
-    pub text: String,
+    pub source: Rc<str>,
+    pub span: Range<usize>,
     pub newline_before: bool,
+    /// The pieces of an [`TokenKind::InterpolatedString`].
+    pub parts: Option<Rc<[StringPart]>>,
+    /// The name of an identifier, interned as it's lexed.
+    pub symbol: Option<Symbol>,
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, loc: Location, text: String) -> Token {
+    pub fn new(kind: TokenKind, mut loc: Location, source: Rc<str>, span: Range<usize>) -> Token {
+        loc.end = loc.start + span.len();
         Token {
             kind,
             loc,
-            text,
+            source,
+            span,
             newline_before: false,
+            parts: None,
+            symbol: None,
         }
     }
 
-    pub fn from_str(text: String, loc: Location) -> Token {
+    pub fn text(&self) -> &str {
+        &self.source[self.span.clone()]
+    }
+
+    pub fn identifier_or_keyword(loc: Location, source: Rc<str>, span: Range<usize>) -> Token {
+        let text = &source[span.clone()];
+        let kind = KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == text)
+            .map_or(TokenKind::Identifier, |(_, kind)| kind.clone());
+        let symbol = (kind == TokenKind::Identifier).then(|| Symbol::intern(text));
         Token {
-            kind: match text.as_ref() {
-                "and" => TokenKind::And,
-                "assert" => TokenKind::Assert,
-                "def" => TokenKind::Def,
-                "else" => TokenKind::Else,
-                "false" => TokenKind::False,
-                "if" => TokenKind::If,
-                "let" => TokenKind::Let,
-                "not" => TokenKind::Not,
-                "nothing" => TokenKind::Nothing,
-                "or" => TokenKind::Or,
-                "return" => TokenKind::Return,
-                "true" => TokenKind::True,
-                "while" => TokenKind::While,
-                "continue" => TokenKind::Continue,
-                "break" => TokenKind::Break,
-                "for" => TokenKind::For,
-                "in" => TokenKind::In,
-                _ => TokenKind::Identifier,
-            },
-            loc,
-            text,
-            newline_before: false,
+            symbol,
+            ..Token::new(kind, loc, source, span)
         }
     }
+
+    /// The name of an identifier, or of a keyword that's used as one.
+    pub fn symbol(&self) -> Symbol {
+        self.symbol.unwrap_or_else(|| Symbol::intern(self.text()))
+    }
+}
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("Token")
+            .field("kind", &self.kind)
+            .field("loc", &self.loc)
+            .field("text", &self.text())
+            .field("newline_before", &self.newline_before)
+            .finish()
+    }
 }
 
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
         write!(f, "{:?}", self.kind)?;
-        if !self.text.is_empty() {
-            write!(f, "({})", self.text)?;
+        if !self.text().is_empty() {
+            write!(f, "({})", self.text())?;
         }
         Ok(())
     }
@@ -1,17 +1,45 @@
 use crate::ast::AST;
-use crate::error::{runtime_error as error, Result};
-use crate::interpreter::{Ref, Scope};
+use crate::error::{runtime_error as error, Error, Result};
+use crate::interpreter::{BuiltInFunctionType, Interpreter, Ref, Scope};
+use crate::lexer::escape;
+use crate::memory;
+use crate::symbol::Symbol;
 use crate::token::Location;
+use std::any::Any;
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::{Arc, Weak};
 
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 
+/// The protocol that `for` loops and the iterator built-ins step through
+/// values with. Getting the next value may run script code, as `map` and
+/// `filter` do, so it can fail, and it's given what a call would need.
+pub trait Iterate {
+    fn next(
+        &mut self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>>;
+}
+
+impl<I: Iterator<Item = Value>> Iterate for I {
+    fn next(&mut self, _: &mut Interpreter, _: &Ref<Scope>, _: &Location) -> Result<Option<Value>> {
+        Ok(Iterator::next(self))
+    }
+}
+
+/// An iterator that steps through its values lazily, shared by every copy
+/// of the value.
 #[derive(Clone)]
-pub struct IteratorValue(pub Rc<RefCell<dyn Iterator<Item = Value>>>);
+pub struct IteratorValue(pub Rc<RefCell<dyn Iterate>>);
 
 struct StringIterator {
-    string: String,
+    string: Arc<str>,
+    /// Byte offset of the next char.
     index: usize,
 }
 
@@ -19,23 +47,65 @@ impl Iterator for StringIterator {
     type Item = Value;
 
     fn next(&mut self) -> Option<Value> {
-        if self.index >= self.string.len() {
-            None
-        } else {
-            let c = self.string.chars().nth(self.index).unwrap();
-            self.index += 1;
-            Some(Value::String(c.to_string()))
-        }
+        let c = self.string[self.index..].chars().next()?;
+        self.index += c.len_utf8();
+        Some(Value::String(c.to_string().into()))
     }
 }
 
 impl IteratorValue {
-    pub fn for_string(string: String) -> IteratorValue {
+    pub fn new(iterator: impl Iterate + 'static) -> IteratorValue {
+        IteratorValue(Rc::new(RefCell::new(iterator)))
+    }
+
+    /// The next value, or `None` once there are no more. Asking for one
+    /// while the iterator is producing another, as a `map` function that
+    /// steps through the iterator it's mapping over would, is an error.
+    pub fn next(
+        &self,
+        interpreter: &mut Interpreter,
+        scope: &Ref<Scope>,
+        loc: &Location,
+    ) -> Result<Option<Value>> {
+        match self.0.try_borrow_mut() {
+            Ok(mut iterator) => iterator.next(interpreter, scope, loc),
+            Err(_) => error!(loc, "The iterator is already busy producing a value"),
+        }
+    }
+
+    pub fn for_string(string: Arc<str>) -> IteratorValue {
         IteratorValue(Rc::new(RefCell::new(StringIterator { string, index: 0 })))
     }
 
-    pub fn for_range(start: i64, end: i64) -> IteratorValue {
-        IteratorValue(Rc::new(RefCell::new((start..end).map(Value::Integer))))
+    /// Iterates over a range with both ends.
+    pub fn for_range(range: RangeValue) -> IteratorValue {
+        let start = range.start.unwrap_or(0) as i128;
+        let step = range.step as i128;
+        let steps = 0..range.count().unwrap_or(0);
+        IteratorValue(Rc::new(RefCell::new(
+            steps.map(move |i| Value::Integer((start + i * step) as i64)),
+        )))
+    }
+
+    /// Iterates over the items the list has when the loop starts, so
+    /// changing the list in the loop doesn't affect it.
+    pub fn for_list(list: &Ref<Vec<Value>>) -> IteratorValue {
+        let items = list.borrow().clone();
+        IteratorValue(Rc::new(RefCell::new(items.into_iter())))
+    }
+
+    pub fn for_tuple(items: Rc<[Value]>) -> IteratorValue {
+        let indices = 0..items.len();
+        IteratorValue(Rc::new(RefCell::new(
+            indices.map(move |i| items[i].clone()),
+        )))
+    }
+
+    /// Iterates over the keys the dict has when the loop starts, like
+    /// [`IteratorValue::for_list`].
+    pub fn for_dict(dict: &Ref<Dict>) -> IteratorValue {
+        let keys = dict.borrow().keys().cloned().collect::<Vec<_>>();
+        IteratorValue(Rc::new(RefCell::new(keys.into_iter())))
     }
 }
 
@@ -45,186 +115,1646 @@ impl Debug for IteratorValue {
     }
 }
 
+/// A range of integers: `start..end`, or `start..=end` with `end` included,
+/// counting by `step`. Ranges that scripts write have both ends, but a
+/// slice, which is a range too, may leave either out to run to the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeValue {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub step: i64,
+    pub inclusive: bool,
+}
+
+impl RangeValue {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> RangeValue {
+        RangeValue {
+            start: Some(start),
+            end: Some(end),
+            step: 1,
+            inclusive,
+        }
+    }
+
+    /// How many integers the range has, if it has both ends. Stepping away
+    /// from `end` gives none.
+    pub fn count(self) -> Option<i128> {
+        let (start, end, step) = (self.start? as i128, self.end? as i128, self.step as i128);
+        let span = match self.inclusive {
+            true => end - start + step.signum(),
+            false => end - start,
+        };
+        Some(match span != 0 && (span > 0) == (step > 0) {
+            true => (span + step - step.signum()) / step,
+            false => 0,
+        })
+    }
+
+    pub fn contains(self, i: i64) -> bool {
+        let (Some(start), Some(count)) = (self.start, self.count()) else {
+            return false;
+        };
+        let offset = i as i128 - start as i128;
+        let step = self.step as i128;
+        offset % step == 0 && (0..count).contains(&(offset / step))
+    }
+
+    pub fn repr(self) -> String {
+        let bound = |bound: Option<i64>| bound.map_or(String::new(), |i| i.to_string());
+        let dots = if self.inclusive { "..=" } else { ".." };
+        let range = format!("{}{}{}", bound(self.start), dots, bound(self.end));
+        match self.step {
+            1 => range,
+            step => format!("step_by({}, {})", range, step),
+        }
+    }
+}
+
+/// The hashable subset of values, used to index into a [`Dict`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Float(u64),
+    String(Arc<str>),
+    Boolean(bool),
+    Nothing,
+    Tuple(Vec<HashKey>),
+}
+
+impl HashKey {
+    pub fn from_value(value: &Value, loc: &Location) -> Result<HashKey> {
+        match HashKey::of(value) {
+            Some(key) => Ok(key),
+            None => error!(loc, "Unhashable type: {}", value.repr()),
+        }
+    }
+
+    /// The key of `value`, or `None` if it isn't hashable.
+    pub fn of(value: &Value) -> Option<HashKey> {
+        Some(match value {
+            Value::Integer(i) => HashKey::Integer(*i),
+            // integral floats hash like their integer counterpart, since `1 == 1.0`
+            Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                HashKey::Integer(*f as i64)
+            }
+            Value::Float(f) => HashKey::Float(f.to_bits()),
+            Value::String(s) => HashKey::String(s.clone()),
+            Value::Boolean(b) => HashKey::Boolean(*b),
+            Value::Nothing => HashKey::Nothing,
+            Value::Tuple(items) => {
+                HashKey::Tuple(items.iter().map(HashKey::of).collect::<Option<_>>()?)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// An insertion-ordered map from hashable values to values.
+#[derive(Debug, Clone, Default)]
+pub struct Dict {
+    entries: Vec<(Value, Value)>,
+    index: HashMap<HashKey, usize>,
+}
+
+impl Dict {
+    pub fn new() -> Dict {
+        Dict::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, key: Value, value: Value, loc: &Location) -> Result<()> {
+        let hash = HashKey::from_value(&key, loc)?;
+        self.insert_hashed(hash, key, value);
+        Ok(())
+    }
+
+    /// Inserts `key`, whose [`HashKey`] is `hash`.
+    pub fn insert_hashed(&mut self, hash: HashKey, key: Value, value: Value) {
+        match self.index.get(&hash) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(hash, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn get(&self, key: &Value, loc: &Location) -> Result<Option<&Value>> {
+        let index = self.index.get(&HashKey::from_value(key, loc)?);
+        Ok(index.map(|&i| &self.entries[i].1))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &(Value, Value)> {
+        self.entries.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+}
+
+/// A named collection of members, brought into scope with `import`.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub members: HashMap<String, Value>,
+    /// Top-level names that weren't marked `pub`, with where they were defined.
+    pub private: HashMap<String, Location>,
+}
+
+impl Module {
+    pub fn new(name: &str) -> Module {
+        Module {
+            name: name.to_string(),
+            members: HashMap::new(),
+            private: HashMap::new(),
+        }
+    }
+
+    /// Adds a native function to the module, callable from scripts as `name.func(...)`.
+    pub fn add_function(&mut self, name: &str, func: BuiltInFunctionType) {
+        self.members.insert(
+            name.to_string(),
+            Value::NativeFunction {
+                name: format!("{}.{}", self.name, name),
+                func,
+            },
+        );
+    }
+
+    pub fn add_constant(&mut self, name: &str, value: Value) {
+        self.members.insert(name.to_string(), value);
+    }
+}
+
+/// A type declared with `struct Name { fields }`, which makes instances
+/// of itself when called. The variants of an enum are structs too.
+#[derive(Debug)]
+pub struct StructType {
+    pub name: Symbol,
+    /// The enum this is a variant of, if it is one.
+    pub variant_of: Option<Symbol>,
+    pub fields: Vec<Symbol>,
+    /// The methods of its instances, which are functions taking the
+    /// instance first. The collector empties them to break cycles.
+    pub methods: RefCell<HashMap<Symbol, Value>>,
+    pub loc: Location,
+}
+
+impl StructType {
+    /// Where the field `name` is in the instances' fields.
+    pub fn field(&self, name: Symbol) -> Option<usize> {
+        self.fields.iter().position(|&field| field == name)
+    }
+
+    pub fn method(&self, name: Symbol) -> Option<Value> {
+        self.methods.borrow().get(&name).cloned()
+    }
+}
+
+/// `Point`, or `Shape.Circle` for a variant.
+impl Display for StructType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.variant_of {
+            Some(of) => write!(f, "{}.{}", of, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A type declared with `enum Name { variants }`, whose values are
+/// instances of its variants.
+#[derive(Debug)]
+pub struct EnumType {
+    pub name: Symbol,
+    pub variants: Vec<Rc<StructType>>,
+    pub loc: Location,
+}
+
+impl EnumType {
+    pub fn variant(&self, name: Symbol) -> Option<&Rc<StructType>> {
+        self.variants.iter().find(|variant| variant.name == name)
+    }
+
+    /// The variants share their methods.
+    pub fn method(&self, name: Symbol) -> Option<Value> {
+        self.variants.first()?.method(name)
+    }
+}
+
+/// The closure behind a [`HostFunction`].
+pub type HostFn = dyn Fn(&[Value]) -> Result<Value>;
+
+/// A Rust closure that scripts can call.
+pub struct HostFunction {
+    pub name: String,
+    func: Box<HostFn>,
+}
+
+impl HostFunction {
+    pub fn new(name: String, func: impl Fn(&[Value]) -> Result<Value> + 'static) -> HostFunction {
+        HostFunction {
+            name,
+            func: Box::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[Value]) -> Result<Value> {
+        (self.func)(args)
+    }
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A function wrapped by `memoize()`, which remembers the result of each
+/// list of arguments it has been called with.
+#[derive(Debug)]
+pub struct Memoized {
+    pub func: Value,
+    pub cache: RefCell<HashMap<Vec<HashKey>, Value>>,
+}
+
+/// A host object that holds on to something outside the interpreter, such
+/// as a file or a socket, which has to be released once scripts are done
+/// with it.
+pub trait Resource: Any {
+    /// Releases what the resource holds. Called at most once.
+    fn close(&mut self) -> std::result::Result<(), String>;
+}
+
+/// A [`Resource`] as scripts see it. It's closed by the `close` builtin,
+/// when the last reference to it is dropped, or when the interpreter that
+/// created it is dropped, whichever comes first. Only an explicit `close`
+/// reports errors.
+pub struct ResourceHandle {
+    pub name: String,
+    resource: RefCell<Option<Box<dyn Resource>>>,
+}
+
+impl ResourceHandle {
+    pub fn new(name: impl Into<String>, resource: impl Resource) -> ResourceHandle {
+        ResourceHandle {
+            name: name.into(),
+            resource: RefCell::new(Some(Box::new(resource))),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.resource.borrow().is_none()
+    }
+
+    /// Closes the resource. Closing it again does nothing.
+    pub fn close(&self) -> std::result::Result<(), String> {
+        let resource = self.resource.borrow_mut().take();
+        match resource {
+            Some(mut resource) => resource.close(),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `f` on the resource, or returns `None` if it's closed or isn't a
+    /// `T`.
+    pub fn with<T: Resource, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut resource = self.resource.borrow_mut();
+        let resource: &mut dyn Any = resource.as_mut()?.as_mut();
+        resource.downcast_mut().map(f)
+    }
+}
+
+impl Drop for ResourceHandle {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+impl Debug for ResourceHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceHandle")
+            .field("name", &self.name)
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Integer(i64),
     Float(f64),
-    String(String),
+    String(Arc<str>),
     Boolean(bool),
-    BuiltInFunction(String),
+    BuiltInFunction(&'static str, BuiltInFunctionType),
     Iterator(IteratorValue),
-    Range(i64, i64),
+    Range(RangeValue),
+    List(Ref<Vec<Value>>),
+    /// A fixed `(a, b, c)` sequence, which can't be changed and so can be a
+    /// dict key when its items can.
+    Tuple(Rc<[Value]>),
+    Dict(Ref<Dict>),
+    Module(Rc<Module>),
+    Memoized(Rc<Memoized>),
+    Resource(Rc<ResourceHandle>),
+    NativeFunction {
+        name: String,
+        func: BuiltInFunctionType,
+    },
+    /// A function the host registered with [`Interpreter::register_fn`].
+    ///
+    /// [`Interpreter::register_fn`]: crate::interpreter::Interpreter::register_fn
+    HostFunction(Rc<HostFunction>),
     Function {
-        body: Rc<AST>,
-        args: Vec<String>,
+        name: Option<Symbol>,
+        loc: Location,
+        body: Arc<AST>,
+        args: Vec<Symbol>,
+        /// Whether the last argument collects the positional ones left
+        /// over into a list.
+        variadic: bool,
+        /// The values of the last arguments when a call leaves them out.
+        defaults: Vec<Arc<AST>>,
         scope: Ref<Scope>,
     },
+    Struct(Rc<StructType>),
+    Enum(Rc<EnumType>),
+    /// An instance of a struct, with its fields in the order the struct
+    /// declares them.
+    Instance(Rc<StructType>, Ref<Vec<Value>>),
     Nothing,
 }
 
+impl From<i64> for Value {
+    fn from(i: i64) -> Value {
+        Value::Integer(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Value {
+        Value::Float(f)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Boolean(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s.into())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+}
+
+/// A Rust type that a value converts to, for the arguments of
+/// [`Interpreter::register_typed_fn`].
+///
+/// [`Interpreter::register_typed_fn`]: crate::interpreter::Interpreter::register_typed_fn
+pub trait FromValue: Sized {
+    /// What the type is called in errors, with its article.
+    const EXPECTED: &'static str;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    const EXPECTED: &'static str = "a value";
+
+    fn from_value(value: &Value) -> Option<Value> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    const EXPECTED: &'static str = "an integer";
+
+    fn from_value(value: &Value) -> Option<i64> {
+        value.as_integer()
+    }
+}
+
+impl FromValue for f64 {
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<f64> {
+        value.as_float()
+    }
+}
+
+impl FromValue for bool {
+    const EXPECTED: &'static str = "a boolean";
+
+    fn from_value(value: &Value) -> Option<bool> {
+        value.as_bool()
+    }
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: &Value) -> Option<String> {
+        value.as_str().map(String::from)
+    }
+}
+
+impl FromValue for Vec<Value> {
+    const EXPECTED: &'static str = "a list";
+
+    fn from_value(value: &Value) -> Option<Vec<Value>> {
+        value.as_list()
+    }
+}
+
+/// `nothing` is `None`, anything else has to convert to `T`.
+impl<T: FromValue> FromValue for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_value(value: &Value) -> Option<Option<T>> {
+        match value {
+            Value::Nothing => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+macro_rules! try_from_value {
+    ($($ty:ty),*) => {$(
+        impl TryFrom<Value> for $ty {
+            type Error = Error;
+
+            fn try_from(value: Value) -> Result<$ty> {
+                <$ty>::from_value(&value).ok_or_else(|| {
+                    Error::Other(format!("Expected {}, got {}", <$ty>::EXPECTED, value.repr()))
+                })
+            }
+        }
+    )*};
+}
+
+try_from_value!(i64, f64, bool, String, Vec<Value>);
+
+/// A Rust type that converts to a value, for the results of
+/// [`Interpreter::register_typed_fn`]. Anything that is `Into<Value>` is.
+///
+/// [`Interpreter::register_typed_fn`]: crate::interpreter::Interpreter::register_typed_fn
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl<T: Into<Value>> IntoValue for T {
+    fn into_value(self) -> Value {
+        self.into()
+    }
+}
+
+/// The arguments of a call as a tuple of [`FromValue`] types, checking their
+/// number and types. Errors are the message for the caller.
+pub trait FromArgs: Sized {
+    fn from_args(args: &[Value]) -> std::result::Result<Self, String>;
+}
+
+macro_rules! from_args {
+    ($count:literal $(, $arg:ident $index:tt)*) => {
+        impl<$($arg: FromValue),*> FromArgs for ($($arg,)*) {
+            fn from_args(args: &[Value]) -> std::result::Result<Self, String> {
+                if args.len() != $count {
+                    let plural = if $count == 1 { "" } else { "s" };
+                    return Err(format!(
+                        "takes {} argument{}, got {}",
+                        $count,
+                        plural,
+                        args.len()
+                    ));
+                }
+                Ok(($(
+                    $arg::from_value(&args[$index]).ok_or_else(|| {
+                        format!(
+                            "expects {} for argument {}, got {}",
+                            $arg::EXPECTED,
+                            $index + 1,
+                            args[$index].repr()
+                        )
+                    })?,
+                )*))
+            }
+        }
+    };
+}
+
+from_args!(0);
+from_args!(1, A 0);
+from_args!(2, A 0, B 1);
+from_args!(3, A 0, B 1, C 2);
+from_args!(4, A 0, B 1, C 2, D 3);
+
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::Nothing
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Nothing, Into::into)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Value {
+        Value::Integer(i.into())
+    }
+}
+
+/// The operands of a binary numeric operation, with mixed integer/float
+/// pairs promoted to floats.
+enum Numeric {
+    Integers(i64, i64),
+    Floats(f64, f64),
+}
+
+/// Resolves the bounds of a slice of `len` items, with an exclusive end.
+/// Negative bounds count from the end, and bounds past either end are
+/// clamped, so stepping from `start` towards `end` stays in range. A
+/// negative step goes backwards, from the last item by default.
+fn slice_bounds(range: RangeValue, len: usize, loc: &Location) -> Result<(i64, i64, i64)> {
+    let len = len as i64;
+    // an inclusive end is moved one step further to exclude what's after it
+    let past = |direction: i64| if range.inclusive { direction } else { 0 };
+    let bound = |bound: Option<i64>, default: i64, past: i64, min: i64, max: i64| match bound {
+        None => default,
+        Some(i) if i < 0 => i.saturating_add(len).saturating_add(past).clamp(min, max),
+        Some(i) => i.saturating_add(past).clamp(min, max),
+    };
+    Ok(match range.step {
+        0 => error!(loc, "Step cannot be 0"),
+        step if step > 0 => (
+            bound(range.start, 0, 0, 0, len),
+            bound(range.end, len, past(1), 0, len),
+            step,
+        ),
+        step => {
+            // -1 stands for "before the first item", since the end is exclusive
+            let last = len - 1;
+            (
+                bound(range.start, last, 0, -1, last),
+                bound(range.end, -1, past(-1), -1, last),
+                step,
+            )
+        }
+    })
+}
+
+/// How many copies `*` makes of `len` items to repeat them `times` times.
+/// Repeating nothing makes no copies, however many times. The copies are
+/// known to fit in a `usize`, though not that there's memory for them.
+fn repetitions(len: usize, times: i64, loc: &Location) -> Result<usize> {
+    if times < 0 {
+        error!(loc, "{times} is not a positive integer.")
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    match len.checked_mul(times as usize) {
+        Some(_) => Ok(times as usize),
+        None => error!(loc, "Result of `*` is too long"),
+    }
+}
+
+/// The repr of the list or dict at `ptr`, made by `repr` unless it's one of
+/// the `outer` ones it's inside of, in which case it's `cycle`.
+fn nested_repr(
+    outer: &mut Vec<*const ()>,
+    ptr: *const (),
+    cycle: &str,
+    repr: impl FnOnce(&mut Vec<*const ()>) -> String,
+) -> String {
+    if outer.contains(&ptr) {
+        return cycle.to_string();
+    }
+    outer.push(ptr);
+    let repr = repr(outer);
+    outer.pop();
+    repr
+}
+
+thread_local! {
+    /// The char starts of the string indexed or sliced last, which is
+    /// usually the one indexed next, as in a loop over its positions.
+    static LAST_INDEXED: RefCell<Option<(Weak<str>, CharStarts)>> = const { RefCell::new(None) };
+}
+
+/// Where each char of a string starts, so that its chars can be picked
+/// out by position without walking the string from the start.
+#[derive(Clone)]
+enum CharStarts {
+    /// Every char is a byte, and there are this many.
+    Ascii(usize),
+    /// The byte offset of each char, then the length of the string.
+    Offsets(Rc<[usize]>),
+}
+
+impl CharStarts {
+    fn of(string: &Arc<str>) -> CharStarts {
+        LAST_INDEXED.with(|last| {
+            let mut last = last.borrow_mut();
+            // the weak reference keeps the address from being reused
+            if let Some((indexed, starts)) = &*last {
+                if std::ptr::addr_eq(indexed.as_ptr(), Arc::as_ptr(string)) {
+                    return starts.clone();
+                }
+            }
+            let starts = match string.is_ascii() {
+                true => CharStarts::Ascii(string.len()),
+                false => CharStarts::Offsets(
+                    string
+                        .char_indices()
+                        .map(|(i, _)| i)
+                        .chain([string.len()])
+                        .collect(),
+                ),
+            };
+            *last = Some((Arc::downgrade(string), starts.clone()));
+            starts
+        })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            CharStarts::Ascii(len) => *len,
+            CharStarts::Offsets(offsets) => offsets.len() - 1,
+        }
+    }
+
+    /// The byte offset of char `i`, or of the end of the string when `i` is
+    /// the number of chars.
+    fn byte(&self, i: usize) -> usize {
+        match self {
+            CharStarts::Ascii(_) => i,
+            CharStarts::Offsets(offsets) => offsets[i],
+        }
+    }
+}
+
+/// The number of chars in `string`, which indexes and slices count in.
+pub(crate) fn char_count(string: &Arc<str>) -> usize {
+    CharStarts::of(string).len()
+}
+
+/// Resolves index `i` of `len` items, where a negative index counts from
+/// the end.
+fn position(i: i64, len: usize, loc: &Location) -> Result<usize> {
+    let len = len as i64;
+    let position = if i < 0 { i.saturating_add(len) } else { i };
+    match position >= 0 && position < len {
+        true => Ok(position as usize),
+        false => error!(loc, "Index {} out of bounds for length {}", i, len),
+    }
+}
+
+/// The indices a slice with the given bounds visits, in order.
+fn slice_indices((start, end, step): (i64, i64, i64)) -> impl Iterator<Item = usize> {
+    let mut i = Some(start);
+    std::iter::from_fn(move || {
+        let current = i.filter(|&i| (step > 0 && i < end) || (step < 0 && i > end))?;
+        i = current.checked_add(step);
+        Some(current as usize)
+    })
+}
+
+/// What integer arithmetic does when its result doesn't fit in an i64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wraps around, so `i64::MAX + 1` is `i64::MIN`.
+    Wrap,
+    /// Stops at `i64::MIN` or `i64::MAX`.
+    Saturate,
+    /// Raises a runtime error.
+    #[default]
+    Error,
+}
+
+impl Overflow {
+    pub const ALL: [Overflow; 3] = [Overflow::Wrap, Overflow::Saturate, Overflow::Error];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Overflow::Wrap => "wrap",
+            Overflow::Saturate => "saturate",
+            Overflow::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Overflow> {
+        Overflow::ALL
+            .into_iter()
+            .find(|overflow| overflow.name() == name)
+    }
+
+    /// The names of all behaviors, for error messages.
+    pub fn names() -> String {
+        Overflow::ALL.map(Overflow::name).join(", ")
+    }
+
+    pub fn apply(self, op: IntegerOp, left: i64, right: i64, loc: &Location) -> Result<i64> {
+        op.check_operand(right, loc)?;
+        Ok(match self {
+            Overflow::Wrap => op.wrapping(left, right),
+            Overflow::Saturate => op.saturating(left, right),
+            Overflow::Error => match op.checked(left, right) {
+                Some(result) => result,
+                None => error!(loc, "Integer overflow in `{}`", op.symbol()),
+            },
+        })
+    }
+}
+
+/// An integer operation that can overflow.
+#[derive(Debug, Clone, Copy)]
+pub enum IntegerOp {
+    Add,
+    Sub,
+    Mul,
+    /// Only `i64::MIN / -1` overflows.
+    Div,
+    /// Only `i64::MIN % -1` overflows, though its result of 0 fits.
+    Rem,
+    Pow,
+    /// Overflows when bits other than copies of the sign bit are shifted
+    /// out.
+    Shl,
+}
+
+impl IntegerOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            IntegerOp::Add => "+",
+            IntegerOp::Sub => "-",
+            IntegerOp::Mul => "*",
+            IntegerOp::Div => "/",
+            IntegerOp::Rem => "%",
+            IntegerOp::Pow => "**",
+            IntegerOp::Shl => "<<",
+        }
+    }
+
+    /// Rules out the right operands the operation has no result for, which
+    /// [`IntegerOp::checked`] and the others have to be spared.
+    pub fn check_operand(self, right: i64, loc: &Location) -> Result<()> {
+        match self {
+            IntegerOp::Div if right == 0 => error!(loc, "Division by zero"),
+            IntegerOp::Rem if right == 0 => error!(loc, "Modulo by zero"),
+            IntegerOp::Pow if right < 0 => {
+                let msg = format!("Negative exponent {} in integer `**`", right);
+                Err(Error::Runtime(loc.clone(), msg)
+                    .with_help("use a float base, such as `2.0 ** -1`, for a fraction"))
+            }
+            IntegerOp::Shl if right < 0 => error!(loc, "Negative shift amount {}", right),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn checked(self, left: i64, right: i64) -> Option<i64> {
+        match self {
+            IntegerOp::Add => left.checked_add(right),
+            IntegerOp::Sub => left.checked_sub(right),
+            IntegerOp::Mul => left.checked_mul(right),
+            IntegerOp::Div => left.checked_div(right),
+            IntegerOp::Rem => left.checked_rem(right),
+            IntegerOp::Pow => match u32::try_from(right) {
+                Ok(exponent) => left.checked_pow(exponent),
+                // only these bases stay in range for exponents this big
+                Err(_) => match left {
+                    0 | 1 => Some(left),
+                    -1 => Some(if right % 2 == 0 { 1 } else { -1 }),
+                    _ => None,
+                },
+            },
+            IntegerOp::Shl if left == 0 => Some(0),
+            IntegerOp::Shl => {
+                let shifted = left.checked_shl(u32::try_from(right).ok()?)?;
+                (shifted >> right == left).then_some(shifted)
+            }
+        }
+    }
+
+    pub fn wrapping(self, left: i64, right: i64) -> i64 {
+        match self {
+            IntegerOp::Add => left.wrapping_add(right),
+            IntegerOp::Sub => left.wrapping_sub(right),
+            IntegerOp::Mul => left.wrapping_mul(right),
+            IntegerOp::Div => left.wrapping_div(right),
+            IntegerOp::Rem => left.wrapping_rem(right),
+            IntegerOp::Pow => wrapping_pow(left, right as u64),
+            IntegerOp::Shl => match u32::try_from(right) {
+                Ok(right) if right < i64::BITS => left << right,
+                _ => 0,
+            },
+        }
+    }
+
+    pub fn saturating(self, left: i64, right: i64) -> i64 {
+        match self {
+            IntegerOp::Add => left.saturating_add(right),
+            IntegerOp::Sub => left.saturating_sub(right),
+            IntegerOp::Mul => left.saturating_mul(right),
+            IntegerOp::Div => left.saturating_div(right),
+            IntegerOp::Rem => left.wrapping_rem(right),
+            IntegerOp::Pow => match IntegerOp::Pow.checked(left, right) {
+                Some(result) => result,
+                None if left < 0 && right % 2 == 1 => i64::MIN,
+                None => i64::MAX,
+            },
+            IntegerOp::Shl => match IntegerOp::Shl.checked(left, right) {
+                Some(result) => result,
+                None if left < 0 => i64::MIN,
+                None => i64::MAX,
+            },
+        }
+    }
+}
+
+/// How two lists or tuples order: by their first items that aren't equal,
+/// or by their lengths if one is the start of the other.
+/// The pairs of lists, dicts and instances being compared, by address, which
+/// a list that contains itself comes back to.
+type Pairs = Vec<(*const (), *const ())>;
+
+/// Runs `compare` on the pair `left` and `right`, unless they're being
+/// compared already further out, in which case they're as `cycle` says:
+/// whatever of them differs is found out there.
+fn nested_pair<T>(
+    pairs: &mut Pairs,
+    left: *const (),
+    right: *const (),
+    cycle: T,
+    compare: impl FnOnce(&mut Pairs) -> T,
+) -> T {
+    if pairs.contains(&(left, right)) {
+        return cycle;
+    }
+    pairs.push((left, right));
+    let result = compare(pairs);
+    pairs.pop();
+    result
+}
+
+fn compare_items(
+    left: &[Value],
+    right: &[Value],
+    pairs: &mut Pairs,
+    loc: &Location,
+) -> Result<Option<Ordering>> {
+    for (left, right) in left.iter().zip(right) {
+        if let (Value::List(l), Value::List(r)) = (left, right) {
+            if pairs.contains(&(Rc::as_ptr(l).cast(), Rc::as_ptr(r).cast())) {
+                continue;
+            }
+        }
+        if !left.is_equal(right) {
+            return left.compare_inside(right, pairs, loc);
+        }
+    }
+    Ok(Some(left.len().cmp(&right.len())))
+}
+
+/// `base ** exponent` wrapped around, for exponents too big for
+/// `i64::wrapping_pow`.
+fn wrapping_pow(mut base: i64, mut exponent: u64) -> i64 {
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
 impl Value {
-    pub fn plus(self, other: Value, loc: &Location) -> Result<Value> {
-        Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left + right),
-            (Value::Integer(left), Value::Float(right)) => Value::Float(left as f64 + right),
-            (Value::Float(left), Value::Float(right)) => Value::Float(left + right),
-            (Value::Float(left), Value::Integer(right)) => Value::Float(left + right as f64),
-            (Value::String(left), Value::String(right)) => Value::String(left + &right),
-            _ => error!(loc, "Invalid types for addition"),
+    /// Classifies a pair of operands by discriminant alone, without moving or
+    /// cloning payloads. Integer and float pairs come first, since tight
+    /// arithmetic loops almost only see those.
+    #[inline]
+    fn numeric(&self, other: &Value) -> Option<Numeric> {
+        Some(match (self, other) {
+            (Value::Integer(left), Value::Integer(right)) => Numeric::Integers(*left, *right),
+            (Value::Float(left), Value::Float(right)) => Numeric::Floats(*left, *right),
+            (Value::Integer(left), Value::Float(right)) => Numeric::Floats(*left as f64, *right),
+            (Value::Float(left), Value::Integer(right)) => Numeric::Floats(*left, *right as f64),
+            _ => return None,
         })
     }
 
-    pub fn minus(self, other: Value, loc: &Location) -> Result<Value> {
+    /// Rejects the conversions that strict mode disallows for the binary
+    /// operator `symbol`.
+    pub fn check_strict(&self, other: &Value, symbol: &str, loc: &Location) -> Result<()> {
+        match (self, other) {
+            (Value::Integer(_), Value::Float(_)) | (Value::Float(_), Value::Integer(_)) => error!(
+                loc,
+                "Strict mode doesn't mix integers and floats in `{}`", symbol
+            ),
+            (Value::String(_), Value::Integer(_)) if symbol == "*" => {
+                error!(loc, "Strict mode doesn't repeat strings with `*`")
+            }
+            (Value::List(_), Value::Integer(_)) if symbol == "*" => {
+                error!(loc, "Strict mode doesn't repeat lists with `*`")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn plus(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Add, left, right, loc)?)
+            }
+            Some(Numeric::Floats(left, right)) => Value::Float(left + right),
+            None => match (self, other) {
+                (Value::String(left), Value::String(right)) => {
+                    let mut result = String::with_capacity(left.len() + right.len());
+                    result.push_str(left);
+                    result.push_str(right);
+                    Value::String(result.into())
+                }
+                (Value::List(left), Value::List(right)) => {
+                    let mut result = left.borrow().clone();
+                    result.extend(right.borrow().iter().cloned());
+                    Value::List(Rc::new(RefCell::new(result)))
+                }
+                (Value::Tuple(left), Value::Tuple(right)) => {
+                    Value::Tuple(left.iter().chain(right.iter()).cloned().collect())
+                }
+                _ => error!(loc, "Invalid types for addition"),
+            },
+        })
+    }
+
+    pub fn minus(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Sub, left, right, loc)?)
+            }
+            Some(Numeric::Floats(left, right)) => Value::Float(left - right),
+            None => error!(loc, "Invalid types for subtraction"),
+        })
+    }
+
+    pub fn multiply(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Mul, left, right, loc)?)
+            }
+            Some(Numeric::Floats(left, right)) => Value::Float(left * right),
+            None => match (self, other) {
+                (Value::String(left), Value::Integer(right)) => {
+                    let times = repetitions(left.len(), *right, loc)?;
+                    memory::check(left.len() * times, loc)?;
+                    let mut result = String::new();
+                    if result.try_reserve_exact(left.len() * times).is_err() {
+                        error!(loc, "Not enough memory for the result of `*`")
+                    }
+                    (0..times).for_each(|_| result.push_str(left));
+                    Value::String(result.into())
+                }
+                (Value::List(left), Value::Integer(right)) => {
+                    let left = left.borrow();
+                    let times = repetitions(left.len(), *right, loc)?;
+                    memory::check(left.len() * times * std::mem::size_of::<Value>(), loc)?;
+                    let mut result = Vec::new();
+                    if result.try_reserve_exact(left.len() * times).is_err() {
+                        error!(loc, "Not enough memory for the result of `*`")
+                    }
+                    (0..times).for_each(|_| result.extend(left.iter().cloned()));
+                    Value::List(Rc::new(RefCell::new(result)))
+                }
+                _ => error!(loc, "Invalid types for multiplication"),
+            },
+        })
+    }
+
+    /// `self / other`, rounded toward zero for integers. Dividing an integer
+    /// by zero is an error, but floats follow IEEE 754 instead: a float
+    /// divided by zero is `inf` or `-inf`, and `0.0 / 0` is `nan`.
+    pub fn divide(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Div, left, right, loc)?)
+            }
+            Some(Numeric::Floats(left, right)) => Value::Float(left / right),
+            None => error!(loc, "Invalid types for division"),
+        })
+    }
+
+    /// The remainder of `self / other`, which has the sign of `self`. Like
+    /// with `/`, a zero divisor is an error for integers, while for floats
+    /// the remainder is `nan`.
+    pub fn modulo(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Rem, left, right, loc)?)
+            }
+            Some(Numeric::Floats(left, right)) => Value::Float(left % right),
+            None => error!(loc, "Invalid types for modulo"),
+        })
+    }
+
+    pub fn shift_left(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
         Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left - right),
-            (Value::Integer(left), Value::Float(right)) => Value::Float(left as f64 - right),
-            (Value::Float(left), Value::Float(right)) => Value::Float(left - right),
-            (Value::Float(left), Value::Integer(right)) => Value::Float(left - right as f64),
-            _ => error!(loc, "Invalid types for subtraction"),
+            (Value::Integer(left), Value::Integer(right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Shl, *left, *right, loc)?)
+            }
+            _ => error!(loc, "Invalid types for `<<`"),
         })
     }
 
-    pub fn multiply(self, other: Value, loc: &Location) -> Result<Value> {
+    /// Shifts keeping the sign, so the result is rounded down, and can't
+    /// overflow.
+    pub fn shift_right(&self, other: &Value, loc: &Location) -> Result<Value> {
         Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left * right),
-            (Value::Integer(left), Value::Float(right)) => Value::Float(left as f64 * right),
-            (Value::Float(left), Value::Float(right)) => Value::Float(left * right),
-            (Value::Float(left), Value::Integer(right)) => Value::Float(left * right as f64),
-            (Value::String(left), Value::Integer(right)) => {
-                if right < 0 {
-                    error!(loc, "{right} is not a positive integer.")
-                }
-                Value::String(left.repeat(right as usize))
+            (Value::Integer(_), Value::Integer(right)) if *right < 0 => {
+                error!(loc, "Negative shift amount {}", right)
             }
-            _ => error!(loc, "Invalid types for multiplication"),
+            (Value::Integer(left), Value::Integer(right)) => {
+                Value::Integer(left >> (*right).min(i64::BITS as i64 - 1))
+            }
+            _ => error!(loc, "Invalid types for `>>`"),
         })
     }
 
-    pub fn divide(self, other: Value, loc: &Location) -> Result<Value> {
+    pub fn bit_and(&self, other: &Value, loc: &Location) -> Result<Value> {
         Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left / right),
-            (Value::Integer(left), Value::Float(right)) => Value::Float(left as f64 / right),
-            (Value::Float(left), Value::Float(right)) => Value::Float(left / right),
-            (Value::Float(left), Value::Integer(right)) => Value::Float(left / right as f64),
-            _ => error!(loc, "Invalid types for division"),
+            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left & right),
+            _ => error!(loc, "Invalid types for `&`"),
         })
     }
 
-    pub fn slice(
-        self,
-        start: Option<Value>,
-        end: Option<Value>,
-        step: Option<Value>,
-        loc: &Location,
-    ) -> Result<Value> {
-        let start = start.unwrap_or(Value::Integer(0));
-        let step = step.unwrap_or(Value::Integer(1));
+    pub fn bit_or(&self, other: &Value, loc: &Location) -> Result<Value> {
+        Ok(match (self, other) {
+            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left | right),
+            _ => error!(loc, "Invalid types for `|`"),
+        })
+    }
+
+    pub fn bit_xor(&self, other: &Value, loc: &Location) -> Result<Value> {
+        Ok(match (self, other) {
+            (Value::Integer(left), Value::Integer(right)) => Value::Integer(left ^ right),
+            _ => error!(loc, "Invalid types for `^`"),
+        })
+    }
+
+    pub fn bit_not(&self, loc: &Location) -> Result<Value> {
+        Ok(match self {
+            Value::Integer(i) => Value::Integer(!i),
+            _ => error!(loc, "Invalid type for `~`"),
+        })
+    }
+
+    pub fn power(&self, other: &Value, overflow: Overflow, loc: &Location) -> Result<Value> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => {
+                Value::Integer(overflow.apply(IntegerOp::Pow, left, right, loc)?)
+            }
+            Some(Numeric::Floats(left, right)) => Value::Float(left.powf(right)),
+            None => error!(loc, "Invalid types for power"),
+        })
+    }
+
+    /// `self[index]`, where a negative index into a string, list or tuple
+    /// counts from the end.
+    pub fn index(&self, index: &Value, loc: &Location) -> Result<Value> {
+        Ok(match (self, index) {
+            (Value::String(string), Value::Integer(i)) => {
+                let starts = CharStarts::of(string);
+                let i = position(*i, starts.len(), loc)?;
+                Value::String(string[starts.byte(i)..starts.byte(i + 1)].into())
+            }
+            (Value::List(list), Value::Integer(i)) => {
+                let list = list.borrow();
+                list[position(*i, list.len(), loc)?].clone()
+            }
+            (Value::String(_) | Value::List(_) | Value::Tuple(_), Value::Range(range)) => {
+                self.clone().slice(*range, loc)?
+            }
+            (Value::Tuple(items), Value::Integer(i)) => {
+                items[position(*i, items.len(), loc)?].clone()
+            }
+            (Value::Dict(dict), key) => match dict.borrow().get(key, loc)? {
+                Some(value) => value.clone(),
+                None => error!(loc, "Key {} not found", key.repr()),
+            },
+            _ => error!(loc, "Can't index {} with {}", self.repr(), index.repr()),
+        })
+    }
+
+    /// `self[index] = value`, which adds the key if `self` is a dict that
+    /// doesn't have it.
+    pub fn set_index(&self, index: Value, value: Value, loc: &Location) -> Result<()> {
+        match (self, &index) {
+            (Value::List(list), Value::Integer(i)) => {
+                let mut list = list.borrow_mut();
+                let position = position(*i, list.len(), loc)?;
+                list[position] = value;
+            }
+            (Value::Dict(dict), _) => {
+                // hashed before borrowing, since the error for an unhashable
+                // key shows it, and it may be this very dict
+                let hash = HashKey::from_value(&index, loc)?;
+                dict.borrow_mut().insert_hashed(hash, index, value);
+            }
+            _ => error!(loc, "Can't assign to {}[{}]", self.repr(), index.repr()),
+        }
+        Ok(())
+    }
+
+    /// `item in self`: whether a list or tuple has an equal item, a dict has the key,
+    /// a string has the substring or a range has the integer.
+    pub fn contains(&self, item: &Value, loc: &Location) -> Result<Value> {
+        Ok(Value::Boolean(match (self, item) {
+            (Value::List(list), _) => list.borrow().iter().any(|x| x.is_equal(item)),
+            (Value::Tuple(items), _) => items.iter().any(|x| x.is_equal(item)),
+            (Value::Dict(dict), _) => dict.borrow().get(item, loc)?.is_some(),
+            (Value::String(string), Value::String(item)) => string.contains(item.as_ref()),
+            (Value::Range(range), Value::Integer(i)) => range.contains(*i),
+            _ => error!(loc, "Can't look for {} in {}", item.repr(), self.repr()),
+        }))
+    }
+
+    /// The items of a string, list or tuple that `range` picks out, as a
+    /// value of the same type.
+    pub fn slice(self, range: RangeValue, loc: &Location) -> Result<Value> {
         match self {
             Value::String(s) => {
-                let end = end.unwrap_or(Value::Integer(s.len() as i64));
-                match (start, end, step) {
-                    (Value::Integer(start), Value::Integer(end), Value::Integer(step)) => {
-                        if step == 0 {
-                            error!(loc, "Step cannot be 0")
-                        }
-                        let mut result = String::new();
-                        let mut i = start;
-                        while i < end {
-                            result.push(s.chars().nth(i as usize).unwrap());
-                            i += step;
-                        }
-                        Ok(Value::String(result))
+                let starts = CharStarts::of(&s);
+                let bounds = slice_bounds(range, starts.len(), loc)?;
+                Ok(Value::String(match bounds {
+                    // the chars of a slice without gaps are next to each other
+                    (start, end, 1) => {
+                        let (start, end) = (start as usize, end.max(start) as usize);
+                        s[starts.byte(start)..starts.byte(end)].into()
                     }
-                    _ => error!(loc, "Invalid types for slice"),
-                }
+                    _ => slice_indices(bounds)
+                        .map(|i| &s[starts.byte(i)..starts.byte(i + 1)])
+                        .collect::<String>()
+                        .into(),
+                }))
             }
-            _ => error!(loc, "Can only slice strings"),
+            Value::List(list) => {
+                let list = list.borrow();
+                let bounds = slice_bounds(range, list.len(), loc)?;
+                let result = slice_indices(bounds).map(|i| list[i].clone()).collect();
+                Ok(Value::List(Rc::new(RefCell::new(result))))
+            }
+            Value::Tuple(items) => {
+                let bounds = slice_bounds(range, items.len(), loc)?;
+                Ok(Value::Tuple(
+                    slice_indices(bounds).map(|i| items[i].clone()).collect(),
+                ))
+            }
+            _ => error!(loc, "Can only slice strings, lists and tuples"),
         }
     }
 
-    pub fn not(self, loc: &Location) -> Result<Value> {
+    pub fn negate(&self, overflow: Overflow, loc: &Location) -> Result<Value> {
         Ok(match self {
-            Value::Boolean(b) => Value::Boolean(!b),
-            _ => error!(loc, "Invalid type for not"),
+            Value::Integer(i) => Value::Integer(overflow.apply(IntegerOp::Sub, 0, *i, loc)?),
+            Value::Float(f) => Value::Float(-f),
+            _ => error!(loc, "Invalid type for negation"),
         })
     }
-    pub fn and(self, other: Value, loc: &Location) -> Result<Value> {
-        Ok(match (self, other) {
-            (Value::Boolean(left), Value::Boolean(right)) => Value::Boolean(left && right),
-            _ => error!(loc, "Invalid types for and"),
-        })
+
+    /// Whether the value counts as true for `and`, `or`, `not` and the
+    /// conditions of `if` and `while`. `false`, `nothing`, zero, and empty
+    /// strings, lists, tuples, dicts and ranges are false, and everything
+    /// else is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Boolean(b) => *b,
+            Value::Nothing => false,
+            Value::Integer(i) => *i != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::String(string) => !string.is_empty(),
+            Value::List(list) => !list.borrow().is_empty(),
+            Value::Tuple(items) => !items.is_empty(),
+            Value::Dict(dict) => !dict.borrow().is_empty(),
+            Value::Range(range) => range.count() != Some(0),
+            Value::Iterator(_)
+            | Value::Module(_)
+            | Value::Memoized(_)
+            | Value::Resource(_)
+            | Value::BuiltInFunction(..)
+            | Value::NativeFunction { .. }
+            | Value::HostFunction(_)
+            | Value::Function { .. }
+            | Value::Struct(_)
+            | Value::Enum(_)
+            | Value::Instance(..) => true,
+        }
     }
-    pub fn or(self, other: Value, loc: &Location) -> Result<Value> {
-        Ok(match (self, other) {
-            (Value::Boolean(left), Value::Boolean(right)) => Value::Boolean(left || right),
-            _ => error!(loc, "Invalid types for or"),
-        })
+
+    /// [`Value::is_truthy`] for the condition of `construct`, which strict
+    /// mode only lets be a boolean.
+    pub fn condition(&self, strict: bool, construct: &str, loc: &Location) -> Result<bool> {
+        match (self, strict) {
+            (Value::Boolean(b), _) => Ok(*b),
+            (value, true) => error!(
+                loc,
+                "Strict mode needs a boolean in `{}`, not {}",
+                construct,
+                value.type_name()
+            ),
+            (value, false) => Ok(value.is_truthy()),
+        }
     }
 
-    pub fn equals(self, other: Value, _loc: &Location) -> Result<Value> {
-        Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Boolean(left == right),
-            (Value::Integer(left), Value::Float(right)) => Value::Boolean(left as f64 == right),
-            (Value::Float(left), Value::Float(right)) => Value::Boolean(left == right),
-            (Value::Float(left), Value::Integer(right)) => Value::Boolean(left == right as f64),
-            (Value::String(left), Value::String(right)) => Value::Boolean(left == right),
-            (Value::Boolean(left), Value::Boolean(right)) => Value::Boolean(left == right),
-            _ => Value::Boolean(false),
-        })
+    pub fn not(&self, strict: bool, loc: &Location) -> Result<Value> {
+        Ok(Value::Boolean(!self.condition(strict, "not", loc)?))
+    }
+
+    pub fn equals(&self, other: &Value, _loc: &Location) -> Result<Value> {
+        Ok(Value::Boolean(self.is_equal(other)))
+    }
+
+    /// `==`, which is false rather than an error for values of different
+    /// types. Lists and tuples are equal when their items are, and dicts when they map
+    /// the same keys to equal values, in any order. Lists that contain
+    /// themselves are equal when nothing along the way differs.
+    pub(crate) fn is_equal(&self, other: &Value) -> bool {
+        self.is_equal_inside(other, &mut Vec::new())
     }
-    pub fn not_equals(self, other: Value, loc: &Location) -> Result<Value> {
+
+    /// `==` inside the pairs of lists, dicts and instances in `pairs`.
+    fn is_equal_inside(&self, other: &Value, pairs: &mut Pairs) -> bool {
+        match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => left == right,
+            Some(Numeric::Floats(left, right)) => left == right,
+            None => match (self, other) {
+                (Value::String(left), Value::String(right)) => left == right,
+                (Value::Boolean(left), Value::Boolean(right)) => left == right,
+                (Value::Range(left), Value::Range(right)) => left == right,
+                // a list that contains itself is equal to itself
+                (Value::List(left), Value::List(right)) if Rc::ptr_eq(left, right) => true,
+                (Value::Dict(left), Value::Dict(right)) if Rc::ptr_eq(left, right) => true,
+                (Value::Instance(_, left), Value::Instance(_, right))
+                    if Rc::ptr_eq(left, right) =>
+                {
+                    true
+                }
+                (Value::List(l), Value::List(r)) => nested_pair(
+                    pairs,
+                    Rc::as_ptr(l).cast(),
+                    Rc::as_ptr(r).cast(),
+                    true,
+                    |pairs| {
+                        let (left, right) = (l.borrow(), r.borrow());
+                        left.len() == right.len()
+                            && (left.iter().zip(right.iter()))
+                                .all(|(l, r)| l.is_equal_inside(r, pairs))
+                    },
+                ),
+                (Value::Tuple(left), Value::Tuple(right)) => {
+                    left.len() == right.len()
+                        && (left.iter().zip(right.iter())).all(|(l, r)| l.is_equal_inside(r, pairs))
+                }
+                (Value::Dict(l), Value::Dict(r)) => nested_pair(
+                    pairs,
+                    Rc::as_ptr(l).cast(),
+                    Rc::as_ptr(r).cast(),
+                    true,
+                    |pairs| {
+                        let (left, right) = (l.borrow(), r.borrow());
+                        left.len() == right.len()
+                            && left.index.iter().all(|(key, &i)| {
+                                let other = right.index.get(key).map(|&j| &right.entries[j].1);
+                                other.is_some_and(|other| {
+                                    left.entries[i].1.is_equal_inside(other, pairs)
+                                })
+                            })
+                    },
+                ),
+                (Value::Struct(left), Value::Struct(right)) => Rc::ptr_eq(left, right),
+                (Value::Enum(left), Value::Enum(right)) => Rc::ptr_eq(left, right),
+                // instances are equal when they are of the same struct and
+                // their fields are
+                (Value::Instance(of, l), Value::Instance(other_of, r)) => nested_pair(
+                    pairs,
+                    Rc::as_ptr(l).cast(),
+                    Rc::as_ptr(r).cast(),
+                    true,
+                    |pairs| {
+                        let (left, right) = (l.borrow(), r.borrow());
+                        Rc::ptr_eq(of, other_of)
+                            && (left.iter().zip(right.iter()))
+                                .all(|(l, r)| l.is_equal_inside(r, pairs))
+                    },
+                ),
+                _ => false,
+            },
+        }
+    }
+    pub fn not_equals(&self, other: &Value, loc: &Location) -> Result<Value> {
         Ok(match self.equals(other, loc)? {
             Value::Boolean(b) => Value::Boolean(!b),
             _ => unreachable!("equals should always return a boolean"),
         })
     }
-    pub fn less_than(self, other: Value, loc: &Location) -> Result<Value> {
-        Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Boolean(left < right),
-            (Value::Integer(left), Value::Float(right)) => Value::Boolean((left as f64) < right),
-            (Value::Float(left), Value::Float(right)) => Value::Boolean(left < right),
-            (Value::Float(left), Value::Integer(right)) => Value::Boolean(left < right as f64),
-            (Value::String(left), Value::String(right)) => Value::Boolean(left < right),
-            _ => error!(loc, "Invalid types for less than"),
+    /// How `self` orders against `other` for `<`, `<=`, `>` and `>=`.
+    /// Numbers compare by value, strings by their text, `false` comes
+    /// before `true`, and lists and tuples compare item by item, a shorter
+    /// one first when it's the start of the other. It's `None` when a NaN
+    /// is involved, and an error for values that have no order.
+    pub fn compare(&self, other: &Value, loc: &Location) -> Result<Option<Ordering>> {
+        self.compare_inside(other, &mut Vec::new(), loc)
+    }
+
+    /// [`Value::compare`] inside the pairs of lists in `pairs`, which are
+    /// the same as far as the comparison further out goes.
+    fn compare_inside(
+        &self,
+        other: &Value,
+        pairs: &mut Pairs,
+        loc: &Location,
+    ) -> Result<Option<Ordering>> {
+        Ok(match self.numeric(other) {
+            Some(Numeric::Integers(left, right)) => Some(left.cmp(&right)),
+            Some(Numeric::Floats(left, right)) => left.partial_cmp(&right),
+            None => match (self, other) {
+                (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+                (Value::Boolean(left), Value::Boolean(right)) => Some(left.cmp(right)),
+                (Value::List(l), Value::List(r)) => {
+                    let (left, right) = (Rc::as_ptr(l).cast(), Rc::as_ptr(r).cast());
+                    nested_pair(pairs, left, right, Ok(Some(Ordering::Equal)), |pairs| {
+                        compare_items(&l.borrow(), &r.borrow(), pairs, loc)
+                    })?
+                }
+                (Value::Tuple(left), Value::Tuple(right)) => {
+                    compare_items(left, right, pairs, loc)?
+                }
+                _ => error!(
+                    loc,
+                    "Can't compare {} and {}",
+                    self.type_name(),
+                    other.type_name()
+                ),
+            },
         })
     }
 
-    pub fn greater_than(self, other: Value, loc: &Location) -> Result<Value> {
+    pub fn less_than(&self, other: &Value, loc: &Location) -> Result<Value> {
+        let ordering = self.compare(other, loc)?;
+        Ok(Value::Boolean(ordering == Some(Ordering::Less)))
+    }
+    pub fn greater_than(&self, other: &Value, loc: &Location) -> Result<Value> {
         other.less_than(self, loc)
     }
-    pub fn less_than_equals(self, other: Value, loc: &Location) -> Result<Value> {
-        Ok(match (self, other) {
-            (Value::Integer(left), Value::Integer(right)) => Value::Boolean(left <= right),
-            (Value::Integer(left), Value::Float(right)) => Value::Boolean((left as f64) <= right),
-            (Value::Float(left), Value::Float(right)) => Value::Boolean(left <= right),
-            (Value::Float(left), Value::Integer(right)) => Value::Boolean(left <= right as f64),
-            (Value::String(left), Value::String(right)) => Value::Boolean(left <= right),
-            _ => error!(loc, "Invalid types for less than"),
-        })
+    pub fn less_than_equals(&self, other: &Value, loc: &Location) -> Result<Value> {
+        let ordering = self.compare(other, loc)?;
+        Ok(Value::Boolean(ordering.is_some_and(Ordering::is_le)))
     }
-    pub fn greater_than_equals(self, other: Value, loc: &Location) -> Result<Value> {
+    pub fn greater_than_equals(&self, other: &Value, loc: &Location) -> Result<Value> {
         other.less_than_equals(self, loc)
     }
 
     pub fn iterator(self, _loc: &Location) -> Value {
         match self {
             Value::String(s) => Value::Iterator(IteratorValue::for_string(s)),
-            Value::Range(start, end) => Value::Iterator(IteratorValue::for_range(start, end)),
+            Value::Range(range) if range.count().is_some() => {
+                Value::Iterator(IteratorValue::for_range(range))
+            }
+            Value::List(list) => Value::Iterator(IteratorValue::for_list(&list)),
+            Value::Tuple(items) => Value::Iterator(IteratorValue::for_tuple(items)),
+            Value::Dict(dict) => Value::Iterator(IteratorValue::for_dict(&dict)),
             _ => self,
         }
     }
 
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// The value as a float, converting integers as arithmetic does.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            Value::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// A copy of the list's items, which the script may go on changing.
+    pub fn as_list(&self) -> Option<Vec<Value>> {
+        match self {
+            Value::List(list) => Some(list.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_nothing(&self) -> bool {
+        matches!(self, Value::Nothing)
+    }
+
+    /// The name of the value's type, which its methods are looked up by.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Iterator(_) => "iterator",
+            Value::Range(_) => "range",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Dict(_) => "dict",
+            Value::Module(_) => "module",
+            Value::Resource(_) => "resource",
+            Value::Struct(_) => "struct",
+            Value::Enum(_) => "enum",
+            // an instance's type is its struct, or the enum of its variant
+            Value::Instance(of, _) => of.variant_of.unwrap_or(of.name).as_str(),
+            Value::BuiltInFunction(..)
+            | Value::NativeFunction { .. }
+            | Value::HostFunction(_)
+            | Value::Memoized(_)
+            | Value::Function { .. } => "function",
+            Value::Nothing => "nothing",
+        }
+    }
+
     pub fn repr(&self) -> String {
+        self.repr_inside(&mut Vec::new())
+    }
+
+    /// The repr of a value inside the lists and dicts in `outer`, which
+    /// shows a list or dict that contains itself as `[...]` or `{...}`.
+    fn repr_inside(&self, outer: &mut Vec<*const ()>) -> String {
+        match self {
+            Value::List(list) => nested_repr(outer, Rc::as_ptr(list).cast(), "[...]", |outer| {
+                let items = list
+                    .borrow()
+                    .iter()
+                    .map(|item| item.repr_inside(outer))
+                    .collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }),
+            Value::Dict(dict) => nested_repr(outer, Rc::as_ptr(dict).cast(), "{...}", |outer| {
+                let entries = dict
+                    .borrow()
+                    .entries()
+                    .map(|(key, value)| format!("{}: {}", key.repr(), value.repr_inside(outer)))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", entries.join(", "))
+            }),
+            // a variant without fields is written like it's read
+            Value::Instance(of, _) if of.variant_of.is_some() && of.fields.is_empty() => {
+                of.to_string()
+            }
+            Value::Instance(of, fields) => {
+                let cycle = format!("{}(...)", of);
+                nested_repr(outer, Rc::as_ptr(fields).cast(), &cycle, |outer| {
+                    let fields = of
+                        .fields
+                        .iter()
+                        .zip(fields.borrow().iter())
+                        .map(|(name, value)| format!("{} = {}", name, value.repr_inside(outer)))
+                        .collect::<Vec<_>>();
+                    format!("{}({})", of, fields.join(", "))
+                })
+            }
+            Value::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.repr_inside(outer))
+                    .collect::<Vec<_>>();
+                match items.len() {
+                    1 => format!("({},)", items[0]),
+                    _ => format!("({})", items.join(", ")),
+                }
+            }
+            _ => self.repr_flat(),
+        }
+    }
+
+    /// The repr of anything but a list, tuple or dict.
+    fn repr_flat(&self) -> String {
         match self {
             Value::Integer(i) => i.to_string(),
-            Value::Float(f) => f.to_string(),
-            Value::String(s) => format!("\"{}\"", s),
+            Value::Float(f) => format_float(*f),
+            Value::String(s) => format!("\"{}\"", escape(s)),
             Value::Boolean(b) => b.to_string(),
-            Value::Range(start, end) => format!("{}..{}", start, end),
+            Value::Range(range) => range.repr(),
             Value::Iterator(_) => "<iterator>".to_string(),
+            Value::List(_) | Value::Tuple(_) | Value::Dict(_) | Value::Instance(..) => {
+                self.repr_inside(&mut Vec::new())
+            }
             Value::Function { .. } => "<function>".to_string(),
-            Value::BuiltInFunction(name) => format!("<built-in function {}>", name),
+            Value::BuiltInFunction(name, _) => format!("<built-in function {}>", name),
+            Value::NativeFunction { name, .. } => format!("<built-in function {}>", name),
+            Value::HostFunction(host) => format!("<host function {}>", host.name),
+            Value::Module(module) => format!("<module {}>", module.name),
+            Value::Struct(of) => format!("<struct {}>", of),
+            Value::Enum(of) => format!("<enum {}>", of.name),
+            Value::Memoized(memo) => format!("<memoized {}>", memo.func.repr()),
+            Value::Resource(handle) if handle.is_closed() => format!("<closed {}>", handle.name),
+            Value::Resource(handle) => format!("<{}>", handle.name),
             Value::Nothing => "nothing".to_string(),
         }
     }
 }
+
+/// Formats a float with the fewest digits that parse back to the same
+/// value, always with a `.` or an exponent so it can't be mistaken for an
+/// integer. Infinities and NaN are `inf`, `-inf` and `nan`, which is also
+/// what [`parse_float`] accepts for them.
+pub fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        "nan".to_string()
+    } else if f.is_infinite() {
+        if f > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        // Debug, unlike Display, keeps the `.0` and switches to an exponent
+        // for very large and very small numbers
+        format!("{:?}", f)
+    }
+}
+
+/// Parses an integer the way it's written in a script, as in `-12`,
+/// `1_000` or `0xff`, with an optional sign in front.
+pub fn parse_int(text: &str) -> Option<i64> {
+    let text = text.trim().replace('_', "");
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(&text)),
+    };
+    let (radix, digits) = match text.get(..2) {
+        Some("0b") => (2, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0x") => (16, &text[2..]),
+        _ => (10, text),
+    };
+    // from_str_radix would take a second sign
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let magnitude = i128::from_str_radix(digits, radix).ok()?;
+    i64::try_from(if negative { -magnitude } else { magnitude }).ok()
+}
+
+/// Parses a float the way [`format_float`] writes them, ignoring
+/// surrounding whitespace and `_` separators.
+pub fn parse_float(text: &str) -> Option<f64> {
+    let text = text.trim().replace('_', "");
+    match text.as_str() {
+        "nan" => Some(f64::NAN),
+        "inf" | "+inf" => Some(f64::INFINITY),
+        "-inf" => Some(f64::NEG_INFINITY),
+        // std's parser also takes `infinity` and any capitalization of them
+        _ if text
+            .bytes()
+            .any(|b| b.is_ascii_alphabetic() && !b"eE".contains(&b)) =>
+        {
+            None
+        }
+        _ => text.parse().ok(),
+    }
+}
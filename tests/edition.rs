@@ -0,0 +1,19 @@
+//! Editions, which the default changing later mustn't change for code that
+//! was already parsed. This runs in a process of its own, since the default
+//! edition is global.
+
+use rattlesnake::edition::Edition;
+use rattlesnake::interpreter::Interpreter;
+use rattlesnake::value::Value;
+
+#[test]
+fn len_follows_the_edition_it_was_parsed_in() {
+    let mut interpreter = Interpreter::new();
+    Edition::set_default(Edition::E2024);
+    interpreter.eval_str("def count(s) => len(s)\n").unwrap();
+    Edition::set_default(Edition::E2026);
+    let bytes = interpreter.eval_str("count(\"héllo\")\n").unwrap();
+    let chars = interpreter.eval_str("len(\"héllo\")\n").unwrap();
+    assert!(matches!(bytes, Value::Integer(6)), "{}", bytes.repr());
+    assert!(matches!(chars, Value::Integer(5)), "{}", chars.repr());
+}
@@ -0,0 +1,14 @@
+//! Runs scripts the way a host program does, through `Interpreter`'s
+//! embedding API.
+
+use rattlesnake::interpreter::Interpreter;
+
+#[test]
+fn eval_str_errors_quote_their_own_source() {
+    let mut first = Interpreter::new();
+    let mut second = Interpreter::new();
+    let err = first.eval_str("let a = 1\nlet b = a + nope\n").unwrap_err();
+    second.eval_str("let c = 2\nlet d = c * 3\n").unwrap();
+    let rendered = err.render(false);
+    assert!(rendered.contains("let b = a + nope"), "{}", rendered);
+}
@@ -0,0 +1,25 @@
+//! A bounded run of the fuzz checks of `cargo run --example fuzz`, over the
+//! first few seeds, so that changes which break them fail `cargo test`.
+
+use rattlesnake::generator::{check_round_trip, check_runs, unparse, Generator};
+use std::panic;
+
+const SEEDS: u64 = 200;
+const MAX_STEPS: u64 = 10_000;
+
+#[test]
+fn random_programs_round_trip_and_run_the_same_everywhere() {
+    // the checks report panics themselves
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let failures = (0..SEEDS)
+        .filter_map(|seed| {
+            let ast = Generator::new(seed).program();
+            let result = check_round_trip(&ast).and_then(|()| check_runs(&ast, MAX_STEPS));
+            let err = result.err()?;
+            Some(format!("seed {}: {}\n{}\n", seed, err, unparse(&ast)))
+        })
+        .collect::<String>();
+    panic::set_hook(hook);
+    assert!(failures.is_empty(), "{}", failures);
+}
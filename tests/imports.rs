@@ -0,0 +1,59 @@
+//! Imports of modules by name, by dotted name and by path, from a project
+//! written out to a temporary directory.
+
+use rattlesnake::interpreter::Interpreter;
+use rattlesnake::manifest::Manifest;
+use rattlesnake::value::Value;
+use std::fs;
+use std::path::PathBuf;
+
+/// A fresh directory holding `files`, each a path and its contents.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rattlesnake-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn dotted_and_path_imports_resolve_next_to_the_importer_and_in_roots() {
+    let dir = project(
+        "imports",
+        &[
+            ("snake.toml", "roots = [\"lib\"]\n"),
+            ("a/b.rat", "pub let value = 40\n"),
+            ("lib/geo/circle.rat", "pub def area(r) => 3 * r * r\n"),
+            (
+                "main.rat",
+                "import a.b\nimport \"geo/circle\"\nb.value + circle.area(1) - 1\n",
+            ),
+        ],
+    );
+    let mut interpreter = Interpreter::new();
+    interpreter.set_manifest(Manifest::load(&dir.join("snake.toml")).unwrap());
+    let result = interpreter.eval_file(dir.join("main.rat")).unwrap();
+    assert!(matches!(result, Value::Integer(42)), "{}", result.repr());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn importing_a_module_again_is_a_no_op() {
+    let dir = project(
+        "reimport",
+        &[
+            ("counter.rat", "pub let runs = [1]\n"),
+            (
+                "main.rat",
+                "import counter\nimport counter\nimport \"counter.rat\"\nlen(counter.runs)\n",
+            ),
+        ],
+    );
+    let mut interpreter = Interpreter::new();
+    let result = interpreter.eval_file(dir.join("main.rat")).unwrap();
+    assert!(matches!(result, Value::Integer(1)), "{}", result.repr());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,26 @@
+//! The listings of `--emit=ir`.
+
+use rattlesnake::ir;
+use rattlesnake::lexer::Lexer;
+use rattlesnake::parser::Parser;
+
+#[test]
+fn bytecode_disassembles_the_script_and_every_function() {
+    let code = "let x = 1\ndef double(n) => n * 2\nprint(double(x))\n";
+    let lexer = Lexer::new(code.to_string(), "<test>".to_string());
+    let ast = Parser::from_lexer(lexer).parse().unwrap();
+    let listing = ir::bytecode(&ast);
+    assert!(listing.starts_with("; script\n"), "{}", listing);
+    assert!(listing.contains("Declare      x  ; line 1"), "{}", listing);
+    assert!(
+        listing.contains("Eval         Function double"),
+        "{}",
+        listing
+    );
+    assert!(
+        listing.contains("; function double at line 2"),
+        "{}",
+        listing
+    );
+    assert!(listing.contains("Binary       *  ; line 2"), "{}", listing);
+}
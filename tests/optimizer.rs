@@ -0,0 +1,67 @@
+//! The `-O` pass, which must leave what a program computes, and how it
+//! fails, the same as running it unoptimized.
+
+use rattlesnake::interpreter::Interpreter;
+use rattlesnake::value::Overflow;
+
+/// What `source` evaluates to, or the error it fails with.
+fn run(source: &str, optimize: bool, strict: bool, overflow: Overflow) -> String {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_optimize(optimize);
+    interpreter.set_strict(strict);
+    interpreter.set_overflow(overflow);
+    match interpreter.eval_str(source) {
+        Ok(value) => value.repr(),
+        Err(err) => err.render(false),
+    }
+}
+
+const PROGRAMS: &[&str] = &[
+    "1 + 2 * 3 - 4",
+    "2 ** 10 % 1000",
+    "-7 % 3",
+    "7 / 2",
+    "1.5 * 4 + 0.25",
+    "~5 ^ 3 | 8 & 12",
+    "1 << 62",
+    "1 == 1.0",
+    "\"b\" < \"a\"",
+    "not 0",
+    "-(-9223372036854775807 - 1)",
+    "9223372036854775807 + 1",
+    "9223372036854775807 * 2",
+    "1 / 0",
+    "1 % 0",
+    "1 + 1.5",
+    "\"x\" - 1",
+    "\"ab\" * 3",
+    "\"ab\" * 100000",
+    "\"hello\"[1]",
+    "\"hello\"[1:4]",
+    "\"hello\"[10]",
+    "true and 0",
+    "nothing or \"x\"",
+    "0 and missing",
+    "1 or missing",
+    "if 0 {\n    1\n} else {\n    2\n}\n",
+    "if \"\" {\n    missing\n}\n",
+    "while 0 {\n    missing\n}\n",
+    "let x = 2 + 3\nx * 4\n",
+];
+
+#[test]
+fn folding_computes_what_running_does() {
+    for program in PROGRAMS {
+        for strict in [false, true] {
+            for overflow in Overflow::ALL {
+                let plain = run(program, false, strict, overflow);
+                let optimized = run(program, true, strict, overflow);
+                assert_eq!(
+                    plain, optimized,
+                    "{:?} with strict {} and overflow {:?}",
+                    program, strict, overflow
+                );
+            }
+        }
+    }
+}
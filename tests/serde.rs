@@ -0,0 +1,30 @@
+//! `Value` through serde, here with JSON as the format.
+
+use rattlesnake::interpreter::Interpreter;
+use rattlesnake::value::Value;
+
+#[test]
+fn values_round_trip_through_json() {
+    let mut interpreter = Interpreter::new();
+    let value = interpreter
+        .eval_str("{\"name\": \"bob\", \"scores\": [1, 2.5, (true, nothing)]}\n")
+        .unwrap();
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(json, r#"{"name":"bob","scores":[1,2.5,[true,null]]}"#);
+    let back: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        back.repr(),
+        r#"{"name": "bob", "scores": [1, 2.5, [true, nothing]]}"#
+    );
+}
+
+#[test]
+fn functions_and_cycles_are_errors() {
+    let mut interpreter = Interpreter::new();
+    let function = interpreter.eval_str("len\n").unwrap();
+    let err = serde_json::to_string(&function).unwrap_err();
+    assert!(err.to_string().contains("can't be serialized"), "{}", err);
+    let cycle = interpreter.eval_str("let a = [1]\na[0] = a\na\n").unwrap();
+    let err = serde_json::to_string(&cycle).unwrap_err();
+    assert!(err.to_string().contains("contains itself"), "{}", err);
+}
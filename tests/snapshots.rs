@@ -0,0 +1,29 @@
+//! Runs the programs in `snapshots/` and checks their diagnostics against
+//! the recorded ones, as `cargo run --example snapshot` does. Rerun that
+//! with `--bless` to accept changes.
+
+use rattlesnake::snapshot::check;
+use std::panic;
+use std::path::Path;
+
+#[test]
+fn diagnostics_match_their_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots");
+    // panics are part of the snapshots
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mismatches = check(&dir, false);
+    panic::set_hook(hook);
+    let report = mismatches
+        .unwrap()
+        .iter()
+        .map(|mismatch| match &mismatch.expected {
+            Some(expected) => format!(
+                "{} changed\n--- expected\n{}+++ actual\n{}",
+                mismatch.name, expected, mismatch.actual
+            ),
+            None => format!("{} is new\n{}", mismatch.name, mismatch.actual),
+        })
+        .collect::<String>();
+    assert!(report.is_empty(), "{}", report);
+}
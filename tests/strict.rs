@@ -0,0 +1,57 @@
+//! Strict mode, which takes only booleans as conditions, on the
+//! tree-walker and the VM alike.
+
+use rattlesnake::interpreter::Interpreter;
+
+/// What `source` evaluates to, or the error it fails with.
+fn run(source: &str, vm: bool, strict: bool) -> String {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_vm(vm);
+    interpreter.set_strict(strict);
+    match interpreter.eval_str(source) {
+        Ok(value) => value.repr(),
+        Err(err) => err.render(false),
+    }
+}
+
+const CONDITIONS: &[(&str, &str)] = &[
+    ("if 1 {\n    2\n}\n", "`if`, not integer"),
+    ("let n = 0\nwhile \"\" {\n    n = 1\n}\n", "`while`, not string"),
+    ("1 and 2", "`and`, not integer"),
+    ("\"\" or \"x\"", "`or`, not string"),
+    ("not 0", "`not`, not integer"),
+    (
+        "// edition: 2026\ndef count() {\n    while [] {\n        yield 1\n    }\n}\nlist(count())\n",
+        "`while`, not list",
+    ),
+    (
+        "// edition: 2026\ndef count() {\n    if nothing {\n        yield 1\n    }\n}\nlist(count())\n",
+        "`if`, not nothing",
+    ),
+];
+
+#[test]
+fn strict_mode_rejects_conditions_that_arent_booleans() {
+    for (source, error) in CONDITIONS {
+        for vm in [false, true] {
+            let rejected = run(source, vm, true);
+            assert!(
+                rejected.contains(&format!("Strict mode needs a boolean in {}", error)),
+                "{} with vm: {}\n{}",
+                source,
+                vm,
+                rejected
+            );
+            let accepted = run(source, vm, false);
+            assert!(!accepted.contains("error"), "{}\n{}", source, accepted);
+        }
+    }
+}
+
+#[test]
+fn strict_mode_takes_booleans() {
+    let source = "let n = 0\nwhile (n < 3) and not false {\n    if (n == 1) or false {\n        n = n + 10\n    }\n    n = n + 1\n}\nn\n";
+    for vm in [false, true] {
+        assert_eq!(run(source, vm, true), "12");
+    }
+}